@@ -0,0 +1,95 @@
+use std::{fmt::Display, io};
+
+use bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{
+    parser::{ProtocolLimits, RedisType, RespParseError, parse_resp},
+    store::ProtocolVersion,
+};
+
+/// Frames the RESP traffic of a connection: decodes the commands the peer sends, within the
+/// protocol limits, and encodes frames for the RESP version the peer negotiated
+#[derive(Clone, Debug)]
+pub struct RespCodec {
+    limits: ProtocolLimits,
+    protocol: ProtocolVersion,
+}
+
+#[derive(Debug)]
+pub enum CodecError {
+    /// the peer broke the protocol, it can't be read from anymore
+    Protocol(RespParseError),
+    Io(io::Error),
+}
+
+impl RespCodec {
+    pub fn new(limits: ProtocolLimits, protocol: ProtocolVersion) -> Self {
+        RespCodec { limits, protocol }
+    }
+}
+
+impl Decoder for RespCodec {
+    type Item = RedisType;
+    type Error = CodecError;
+
+    fn decode(&mut self, buffer: &mut BytesMut) -> Result<Option<RedisType>, CodecError> {
+        match parse_resp(buffer, &self.limits) {
+            Ok(frame) => Ok(Some(frame)),
+            // the rest of the frame is still on its way
+            Err(RespParseError::NeedMoreData) => Ok(None),
+            Err(err) => Err(CodecError::Protocol(err)),
+        }
+    }
+
+    /// A peer which disconnects in the middle of a frame only loses that frame
+    fn decode_eof(&mut self, buffer: &mut BytesMut) -> Result<Option<RedisType>, CodecError> {
+        self.decode(buffer)
+    }
+}
+
+impl Encoder<RedisType> for RespCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, frame: RedisType, out: &mut BytesMut) -> Result<(), CodecError> {
+        frame.encode(out, self.protocol.get());
+        Ok(())
+    }
+}
+
+impl From<io::Error> for CodecError {
+    fn from(error: io::Error) -> Self {
+        CodecError::Io(error)
+    }
+}
+
+impl Display for CodecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodecError::Protocol(error) => write!(f, "Protocol error: {}", error),
+            CodecError::Io(error) => write!(f, "IO error: {:?}", error),
+        }
+    }
+}
+
+#[test]
+fn test_codec_decodes_frames_as_they_complete() {
+    let protocol = ProtocolVersion::default();
+    let mut codec = RespCodec::new(ProtocolLimits::default(), protocol.clone());
+    let mut buffer = BytesMut::from("*1\r\n$4\r\nPI");
+    assert!(matches!(codec.decode(&mut buffer), Ok(None)));
+    buffer.extend_from_slice(b"NG\r\n*1\r\n");
+    assert!(matches!(
+        codec.decode(&mut buffer),
+        Ok(Some(RedisType::Array(Some(_))))
+    ));
+    // the client went away before finishing its second command
+    assert!(matches!(codec.decode_eof(&mut buffer), Ok(None)));
+
+    protocol.set(3);
+    let mut out = BytesMut::new();
+    codec
+        .encode(RedisType::Push(vec![RedisType::Integer(1)]), &mut out)
+        .unwrap();
+    assert_eq!(out, BytesMut::from(">1\r\n:1\r\n"));
+}
@@ -0,0 +1,622 @@
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+
+use super::{
+    CommandError, command_label, table,
+    utils::{argument_as_bytes, argument_as_str},
+};
+use crate::{
+    parser::RedisType,
+    store::{ReplyMode, Store, TrackingOptions},
+};
+
+pub fn handle_client(
+    arguments: &[RedisType],
+    store: &mut Store,
+    client_id: u64,
+) -> Result<RedisType, CommandError> {
+    let subcommand = argument_as_str(arguments, 0)?.to_ascii_uppercase();
+    let arguments = &arguments[1..];
+
+    match subcommand.as_str() {
+        "ID" => Ok(RedisType::Integer(client_id as i128)),
+        "SETNAME" => {
+            if arguments.len() != 1 {
+                return Err(CommandError::InvalidInput(
+                    "wrong number of arguments for 'client|setname' command".into(),
+                ));
+            }
+            let name = argument_as_bytes(arguments, 0)?;
+            if !is_valid_name(name) {
+                return Err(CommandError::InvalidInput(INVALID_NAME_ERROR.into()));
+            }
+            store.client_mut(client_id).name = (!name.is_empty()).then(|| name.clone());
+            Ok(RedisType::SimpleString(Bytes::from("OK")))
+        }
+        "GETNAME" => Ok(match &store.client(client_id).name {
+            Some(name) => RedisType::BulkString(name.clone()),
+            None => RedisType::NullBulkString,
+        }),
+        "INFO" => Ok(RedisType::BulkString(Bytes::from(format!(
+            "{}\n",
+            store.client(client_id).info_line(Instant::now())
+        )))),
+        "LIST" => handle_client_list(arguments, store),
+        "PAUSE" => handle_client_pause(arguments, store),
+        "REPLY" => {
+            if arguments.len() != 1 {
+                return Err(CommandError::InvalidInput("syntax error".into()));
+            }
+            let mode = match argument_as_str(arguments, 0)?.to_ascii_uppercase().as_str() {
+                "ON" => ReplyMode::On,
+                "OFF" => ReplyMode::Off,
+                "SKIP" => ReplyMode::Skip,
+                _ => return Err(CommandError::InvalidInput("syntax error".into())),
+            };
+            store.client_mut(client_id).reply_mode = mode;
+            // only seen with ON, the reply is dropped otherwise
+            Ok(RedisType::SimpleString(Bytes::from("OK")))
+        }
+        "TRACKING" => handle_client_tracking(arguments, store, client_id),
+        "CACHING" => handle_client_caching(arguments, store, client_id),
+        "GETREDIRECT" => Ok(RedisType::Integer(
+            match &store.client(client_id).tracking {
+                Some(options) => options.redirect.map_or(0, |id| id as i128),
+                None => -1,
+            },
+        )),
+        // clients aren't evicted yet, the setting only shows up in the flags
+        "NO-EVICT" => {
+            store.client_mut(client_id).no_evict = switch(arguments)?;
+            Ok(RedisType::SimpleString(Bytes::from("OK")))
+        }
+        "NO-TOUCH" => {
+            store.client_mut(client_id).no_touch = switch(arguments)?;
+            Ok(RedisType::SimpleString(Bytes::from("OK")))
+        }
+        "UNPAUSE" => {
+            store.unpause_clients();
+            Ok(RedisType::SimpleString(Bytes::from("OK")))
+        }
+        _ => Err(CommandError::UnknownCommand(format!(
+            "unknown subcommand '{}' for 'client' command",
+            subcommand
+        ))),
+    }
+}
+
+const INVALID_NAME_ERROR: &str =
+    "Client names cannot contain spaces, newlines or special characters.";
+
+/// The name shows up in CLIENT LIST, where fields are separated by spaces
+fn is_valid_name(name: &[u8]) -> bool {
+    name.iter().all(|byte| (b'!'..=b'~').contains(byte))
+}
+
+/// HELLO [protover [AUTH username password] [SETNAME clientname]], switches the connection to
+/// the RESP version and replies with the server and connection details
+pub fn handle_hello(
+    arguments: &[RedisType],
+    store: &mut Store,
+    client_id: u64,
+) -> Result<RedisType, CommandError> {
+    let mut protocol = None;
+    let mut name = None;
+    if !arguments.is_empty() {
+        match argument_as_str(arguments, 0)?.parse::<u8>() {
+            Ok(version @ (2 | 3)) => protocol = Some(version),
+            Ok(_) => {
+                return Err(CommandError::WithCode(
+                    "NOPROTO",
+                    "unsupported protocol version".into(),
+                ));
+            }
+            Err(_) => {
+                return Err(CommandError::InvalidInput(
+                    "Protocol version is not an integer or out of range".into(),
+                ));
+            }
+        }
+    }
+    let mut index = 1;
+    while index < arguments.len() {
+        let option = argument_as_str(arguments, index)?.to_ascii_uppercase();
+        match option.as_str() {
+            "AUTH" if index + 2 < arguments.len() => {
+                // there are no users besides the default one, which needs no password
+                if argument_as_bytes(arguments, index + 1)?.as_ref() != b"default" {
+                    return Err(CommandError::WithCode(
+                        "WRONGPASS",
+                        "invalid username-password pair or user is disabled.".into(),
+                    ));
+                }
+                index += 3;
+            }
+            "SETNAME" if index + 1 < arguments.len() => {
+                let requested = argument_as_bytes(arguments, index + 1)?;
+                if !is_valid_name(requested) {
+                    return Err(CommandError::InvalidInput(INVALID_NAME_ERROR.into()));
+                }
+                name = Some(requested.clone());
+                index += 2;
+            }
+            _ => {
+                return Err(CommandError::InvalidInput(format!(
+                    "Syntax error in HELLO option '{}'",
+                    option.to_ascii_lowercase()
+                )));
+            }
+        }
+    }
+
+    let mode = if store.cluster_enabled() {
+        "cluster"
+    } else {
+        "standalone"
+    };
+    let client = store.client_mut(client_id);
+    if let Some(protocol) = protocol {
+        client.protocol.set(protocol);
+    }
+    if let Some(name) = name {
+        client.name = (!name.is_empty()).then_some(name);
+    }
+    let field =
+        |name: &'static str, value: RedisType| (RedisType::BulkString(Bytes::from(name)), value);
+    Ok(RedisType::Map(vec![
+        field("server", RedisType::BulkString(Bytes::from("redis"))),
+        field(
+            "version",
+            RedisType::BulkString(Bytes::from(env!("CARGO_PKG_VERSION"))),
+        ),
+        field("proto", RedisType::Integer(client.protocol.get() as i128)),
+        field("id", RedisType::Integer(client_id as i128)),
+        field("mode", RedisType::BulkString(Bytes::from(mode))),
+        field("role", RedisType::BulkString(Bytes::from("master"))),
+        field("modules", RedisType::Array(Some(Vec::new()))),
+    ]))
+}
+
+/// The ON | OFF argument of NO-EVICT and NO-TOUCH
+fn switch(arguments: &[RedisType]) -> Result<bool, CommandError> {
+    if arguments.len() != 1 {
+        return Err(CommandError::InvalidInput("syntax error".into()));
+    }
+    match argument_as_str(arguments, 0)?.to_ascii_uppercase().as_str() {
+        "ON" => Ok(true),
+        "OFF" => Ok(false),
+        _ => Err(CommandError::InvalidInput("syntax error".into())),
+    }
+}
+
+/// CLIENT TRACKING ON|OFF [REDIRECT client-id] [PREFIX prefix ...] [BCAST] [OPTIN] [OPTOUT]
+/// [NOLOOP]
+fn handle_client_tracking(
+    arguments: &[RedisType],
+    store: &mut Store,
+    client_id: u64,
+) -> Result<RedisType, CommandError> {
+    let enable = match argument_as_str(arguments, 0)?.to_ascii_uppercase().as_str() {
+        "ON" => true,
+        "OFF" => false,
+        _ => return Err(CommandError::InvalidInput("syntax error".into())),
+    };
+    let mut options = TrackingOptions::default();
+    let mut index = 1;
+    while index < arguments.len() {
+        let option = argument_as_str(arguments, index)?.to_ascii_uppercase();
+        match option.as_str() {
+            "REDIRECT" => {
+                let Ok(redirect) = argument_as_str(arguments, index + 1)?.parse::<u64>() else {
+                    return Ok(RedisType::SimpleError(Bytes::from("ERR Invalid client ID")));
+                };
+                options.redirect = Some(redirect);
+                index += 1;
+            }
+            "PREFIX" => {
+                options
+                    .prefixes
+                    .push(argument_as_bytes(arguments, index + 1)?.clone());
+                index += 1;
+            }
+            "BCAST" => options.bcast = true,
+            "OPTIN" => options.optin = true,
+            "OPTOUT" => options.optout = true,
+            "NOLOOP" => options.noloop = true,
+            _ => return Err(CommandError::InvalidInput("syntax error".into())),
+        }
+        index += 1;
+    }
+
+    if !enable {
+        store.disable_tracking(client_id);
+        return Ok(RedisType::SimpleString(Bytes::from("OK")));
+    }
+    let error = if !options.prefixes.is_empty() && !options.bcast {
+        Some("ERR PREFIX option requires BCAST mode to be enabled")
+    } else if options.optin && options.optout {
+        Some("ERR You can't use both OPTIN and OPTOUT")
+    } else if options.bcast && (options.optin || options.optout) {
+        Some("ERR OPTIN and OPTOUT are not compatible with BCAST")
+    } else if options
+        .redirect
+        .is_some_and(|redirect| store.clients().iter().all(|client| client.id != redirect))
+    {
+        Some("ERR The client ID you want redirect to does not exist")
+    } else {
+        None
+    };
+    if let Some(error) = error {
+        return Ok(RedisType::SimpleError(Bytes::from(error)));
+    }
+    store.enable_tracking(client_id, options);
+    Ok(RedisType::SimpleString(Bytes::from("OK")))
+}
+
+/// CLIENT CACHING YES|NO, whether the keys the next command reads are tracked in OPTIN
+/// (OPTOUT) mode
+fn handle_client_caching(
+    arguments: &[RedisType],
+    store: &mut Store,
+    client_id: u64,
+) -> Result<RedisType, CommandError> {
+    if arguments.len() != 1 {
+        return Err(CommandError::InvalidInput("syntax error".into()));
+    }
+    let caching = match argument_as_str(arguments, 0)?.to_ascii_uppercase().as_str() {
+        "YES" => true,
+        "NO" => false,
+        _ => return Err(CommandError::InvalidInput("syntax error".into())),
+    };
+    let client = store.client_mut(client_id);
+    let error = match &client.tracking {
+        Some(options) if caching && !options.optin => {
+            "ERR CLIENT CACHING YES is only valid when tracking is enabled in OPTIN mode."
+        }
+        Some(options) if !caching && !options.optout => {
+            "ERR CLIENT CACHING NO is only valid when tracking is enabled in OPTOUT mode."
+        }
+        Some(_) => {
+            client.caching = Some(caching);
+            return Ok(RedisType::SimpleString(Bytes::from("OK")));
+        }
+        None => {
+            "ERR CLIENT CACHING can be called only when the client is in tracking mode with OPTIN or OPTOUT mode enabled"
+        }
+    };
+    Ok(RedisType::SimpleError(Bytes::from(error)))
+}
+
+/// CLIENT PAUSE timeout [WRITE | ALL], the timeout is in milliseconds
+fn handle_client_pause(
+    arguments: &[RedisType],
+    store: &mut Store,
+) -> Result<RedisType, CommandError> {
+    let Ok(timeout) = argument_as_str(arguments, 0)?.parse::<u64>() else {
+        return Ok(RedisType::SimpleError(Bytes::from(
+            "ERR timeout is not an integer or out of range",
+        )));
+    };
+    let writes_only = match arguments.len() {
+        1 => false,
+        2 => match argument_as_str(arguments, 1)?.to_ascii_uppercase().as_str() {
+            "WRITE" => true,
+            "ALL" => false,
+            _ => return Err(CommandError::InvalidInput("syntax error".into())),
+        },
+        _ => return Err(CommandError::InvalidInput("syntax error".into())),
+    };
+    store.pause_clients(Instant::now() + Duration::from_millis(timeout), writes_only);
+    Ok(RedisType::SimpleString(Bytes::from("OK")))
+}
+
+/// Whether the command has to wait for the clients to be unpaused. CLIENT UNPAUSE itself is
+/// never held, and EXEC only when writes are paused and the transaction writes.
+pub fn is_held_by_pause(input: &RedisType, store: &Store, client_id: u64) -> bool {
+    let Some(pause) = store.pause() else {
+        return false;
+    };
+    let RedisType::Array(Some(elements)) = input else {
+        return false;
+    };
+    let command = command_label(elements);
+    if command == "client|unpause" {
+        return false;
+    }
+    if !pause.writes_only {
+        return true;
+    }
+    let writes = |elements: &[RedisType]| {
+        argument_as_str(elements, 0)
+            .ok()
+            .and_then(|command| table::lookup(&command.to_ascii_uppercase()))
+            .is_some_and(|spec| spec.has(table::WRITE | table::MAY_REPLICATE))
+    };
+    match (command.as_str(), &store.client(client_id).transaction) {
+        ("exec", Some(queue)) => queue.iter().any(|queued| match queued {
+            RedisType::Array(Some(elements)) => writes(elements),
+            _ => false,
+        }),
+        _ => writes(elements),
+    }
+}
+
+/// CLIENT LIST [TYPE normal|master|replica|pubsub] [ID client-id ...]
+fn handle_client_list(arguments: &[RedisType], store: &Store) -> Result<RedisType, CommandError> {
+    let mut client_type = None;
+    let mut ids = None;
+    let mut index = 0;
+    while index < arguments.len() {
+        let option = argument_as_str(arguments, index)?.to_ascii_uppercase();
+        match option.as_str() {
+            "TYPE" => {
+                let name = argument_as_str(arguments, index + 1)?.to_ascii_lowercase();
+                if !matches!(name.as_str(), "normal" | "master" | "replica" | "pubsub") {
+                    return Ok(RedisType::SimpleError(Bytes::from(format!(
+                        "ERR Unknown client type '{}'",
+                        name
+                    ))));
+                }
+                client_type = Some(name);
+                index += 2;
+            }
+            "ID" => {
+                let mut wanted = Vec::new();
+                index += 1;
+                while index < arguments.len() {
+                    match argument_as_str(arguments, index)?.parse::<u64>() {
+                        Ok(id) => wanted.push(id),
+                        Err(_) => {
+                            return Ok(RedisType::SimpleError(Bytes::from(
+                                "ERR Invalid client ID",
+                            )));
+                        }
+                    }
+                    index += 1;
+                }
+                if wanted.is_empty() {
+                    return Err(CommandError::InvalidInput("syntax error".into()));
+                }
+                ids = Some(wanted);
+            }
+            _ => return Err(CommandError::InvalidInput("syntax error".into())),
+        }
+    }
+
+    let now = Instant::now();
+    let mut list = String::new();
+    for client in store.clients() {
+        // there is no replication, every client is either a normal or a pubsub one
+        let matches_type = match client_type.as_deref() {
+            Some("normal") => !client.subscriptions.is_subscribed(),
+            Some("pubsub") => client.subscriptions.is_subscribed(),
+            Some(_) => false,
+            None => true,
+        };
+        let matches_id = ids.as_ref().is_none_or(|ids| ids.contains(&client.id));
+        if matches_type && matches_id {
+            list.push_str(&client.info_line(now));
+            list.push('\n');
+        }
+    }
+    Ok(RedisType::BulkString(Bytes::from(list)))
+}
+
+#[test]
+fn test_client_setname_and_getname() {
+    use crate::{commands::utils::test_arguments, store::test_client};
+
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 3);
+
+    assert_eq!(
+        handle_client(&test_arguments(&["GETNAME"]), &mut store, 3).unwrap(),
+        RedisType::NullBulkString
+    );
+    handle_client(&test_arguments(&["SETNAME", "worker"]), &mut store, 3).unwrap();
+    assert_eq!(
+        handle_client(&test_arguments(&["getname"]), &mut store, 3).unwrap(),
+        RedisType::BulkString(Bytes::from("worker"))
+    );
+    assert!(handle_client(&test_arguments(&["SETNAME", "a b"]), &mut store, 3).is_err());
+    assert_eq!(
+        handle_client(&test_arguments(&["ID"]), &mut store, 3).unwrap(),
+        RedisType::Integer(3)
+    );
+    let RedisType::BulkString(info) =
+        handle_client(&test_arguments(&["INFO"]), &mut store, 3).unwrap()
+    else {
+        panic!("expected the client description");
+    };
+    assert!(info.starts_with(b"id=3 addr=127.0.0.1:4000 laddr=127.0.0.1:6379 name=worker "));
+}
+
+#[test]
+fn test_client_list_filters_by_type_and_id() {
+    use crate::{commands::utils::test_arguments, store::test_client};
+
+    let mut store = Store::new();
+    let _inboxes = [1, 2].map(|id| test_client(&mut store, id));
+    let list = |parts: &[&str], store: &mut Store| {
+        let RedisType::BulkString(list) = handle_client(&test_arguments(parts), store, 1).unwrap()
+        else {
+            panic!("expected the client list");
+        };
+        String::from_utf8(list.to_vec()).unwrap()
+    };
+
+    let all = list(&["LIST"], &mut store);
+    assert_eq!(all.lines().count(), 2);
+    assert!(all.starts_with("id=1 "));
+    assert!(all.contains(" flags=N "));
+    handle_client(&test_arguments(&["NO-TOUCH", "on"]), &mut store, 1).unwrap();
+    handle_client(&test_arguments(&["NO-EVICT", "ON"]), &mut store, 1).unwrap();
+    assert!(list(&["LIST", "ID", "1"], &mut store).contains(" flags=eT "));
+    handle_client(&test_arguments(&["NO-EVICT", "off"]), &mut store, 1).unwrap();
+    assert!(list(&["LIST", "ID", "1"], &mut store).contains(" flags=T "));
+    assert!(handle_client(&test_arguments(&["NO-EVICT"]), &mut store, 1).is_err());
+    assert!(handle_client(&test_arguments(&["NO-TOUCH", "maybe"]), &mut store, 1).is_err());
+    let two = list(&["LIST", "ID", "2", "7"], &mut store);
+    assert_eq!(two.lines().count(), 1);
+    assert!(two.starts_with("id=2 "));
+    assert_eq!(list(&["LIST", "TYPE", "pubsub"], &mut store), "");
+    assert_eq!(
+        list(&["LIST", "TYPE", "normal"], &mut store)
+            .lines()
+            .count(),
+        2
+    );
+    assert!(matches!(
+        handle_client(&test_arguments(&["LIST", "TYPE", "nope"]), &mut store, 1).unwrap(),
+        RedisType::SimpleError(_)
+    ));
+}
+
+#[test]
+fn test_paused_writes_are_held() {
+    use crate::{commands::utils::test_command, store::test_client};
+
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 1);
+    let set = test_command(&["SET", "k", "v"]);
+    let get = test_command(&["GET", "k"]);
+
+    assert!(!is_held_by_pause(&set, &store, 1));
+    let RedisType::Array(Some(pause)) = test_command(&["CLIENT", "PAUSE", "10000", "WRITE"]) else {
+        unreachable!();
+    };
+    handle_client(&pause[1..], &mut store, 1).unwrap();
+    assert!(is_held_by_pause(&set, &store, 1));
+    assert!(!is_held_by_pause(&get, &store, 1));
+    store.client_mut(1).transaction = Some([get.clone()].into());
+    assert!(!is_held_by_pause(&test_command(&["EXEC"]), &store, 1));
+    store.client_mut(1).transaction = Some([set.clone()].into());
+    assert!(is_held_by_pause(&test_command(&["EXEC"]), &store, 1));
+
+    store.pause_clients(Instant::now(), false);
+    assert!(is_held_by_pause(&get, &store, 1));
+    assert!(!is_held_by_pause(
+        &test_command(&["CLIENT", "UNPAUSE"]),
+        &store,
+        1
+    ));
+}
+
+#[test]
+fn test_client_tracking_optin_tracks_after_caching_yes() {
+    use crate::commands::handle_command;
+    use crate::{commands::utils::test_command, store::test_client};
+
+    let mut store = Store::new();
+    let mut inbox = test_client(&mut store, 1);
+    store.client(1).protocol.set(3);
+    let run = |parts: &[&str], store: &mut Store| {
+        handle_command(test_command(parts), store, 1).unwrap();
+    };
+
+    run(&["CLIENT", "TRACKING", "ON", "OPTIN"], &mut store);
+    run(&["GET", "a"], &mut store);
+    run(&["CLIENT", "CACHING", "YES"], &mut store);
+    run(&["GET", "b"], &mut store);
+    run(&["SET", "a", "1"], &mut store);
+    assert!(inbox.try_recv().is_err());
+    run(&["SET", "b", "1"], &mut store);
+    assert_eq!(
+        inbox.try_recv().unwrap(),
+        RedisType::Push(vec![
+            RedisType::BulkString(Bytes::from("invalidate")),
+            RedisType::Array(Some(vec![RedisType::BulkString(Bytes::from("b"))])),
+        ])
+    );
+    let RedisType::Array(Some(optin_bcast)) =
+        test_command(&["CLIENT", "TRACKING", "ON", "BCAST", "OPTIN"])
+    else {
+        unreachable!();
+    };
+    assert!(matches!(
+        handle_client(&optin_bcast[1..], &mut store, 1).unwrap(),
+        RedisType::SimpleError(_)
+    ));
+}
+
+#[test]
+fn test_hello_switches_the_protocol() {
+    use crate::{commands::utils::test_arguments, store::test_client};
+
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 1);
+
+    let error = |arguments: &[&str], store: &mut Store| {
+        handle_hello(&test_arguments(arguments), store, 1)
+            .unwrap_err()
+            .to_string()
+    };
+    assert_eq!(
+        error(&["4"], &mut store),
+        "NOPROTO unsupported protocol version"
+    );
+    assert_eq!(
+        error(&["3", "AUTH", "admin", "secret"], &mut store),
+        "WRONGPASS invalid username-password pair or user is disabled."
+    );
+    assert!(error(&["3", "SETNAME", "my app"], &mut store).starts_with("ERR "));
+    assert_eq!(store.client(1).protocol.get(), 2);
+
+    let RedisType::Map(reply) = handle_hello(
+        &test_arguments(&["3", "AUTH", "default", "x", "SETNAME", "app"]),
+        &mut store,
+        1,
+    )
+    .unwrap() else {
+        panic!("expected the server details");
+    };
+    assert_eq!(reply[2].1, RedisType::Integer(3));
+    assert_eq!(store.client(1).protocol.get(), 3);
+    assert_eq!(store.client(1).name, Some(Bytes::from("app")));
+}
+
+#[test]
+fn test_clients_can_start_in_resp3() {
+    use crate::{commands::utils::test_arguments, store::ProtocolVersion, store::test_client};
+
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 1);
+    // like connections do with REDIS_DEFAULT_RESP3=yes
+    store.client_mut(1).protocol = ProtocolVersion::new(3);
+
+    let RedisType::Map(reply) = handle_hello(&test_arguments(&[]), &mut store, 1).unwrap() else {
+        panic!("expected the server details as a map");
+    };
+    assert_eq!(reply[2].1, RedisType::Integer(3));
+}
+
+#[test]
+fn test_client_tracking_tracks_reads_inside_transactions() {
+    use crate::commands::handle_command;
+    use crate::{commands::utils::test_command, store::test_client};
+
+    let mut store = Store::new();
+    let mut inbox = test_client(&mut store, 1);
+    let _other = test_client(&mut store, 2);
+    store.client(1).protocol.set(3);
+    let run = |parts: &[&str], store: &mut Store, client_id| {
+        handle_command(test_command(parts), store, client_id).unwrap();
+    };
+
+    run(&["CLIENT", "TRACKING", "ON"], &mut store, 1);
+    run(&["MULTI"], &mut store, 1);
+    run(&["GET", "k"], &mut store, 1);
+    // nothing is read while the command is only queued
+    run(&["SET", "k", "1"], &mut store, 2);
+    assert!(inbox.try_recv().is_err());
+    run(&["EXEC"], &mut store, 1);
+    run(&["SET", "k", "2"], &mut store, 2);
+    assert_eq!(
+        inbox.try_recv().unwrap(),
+        RedisType::Push(vec![
+            RedisType::BulkString(Bytes::from("invalidate")),
+            RedisType::Array(Some(vec![RedisType::BulkString(Bytes::from("k"))])),
+        ])
+    );
+}
@@ -0,0 +1,47 @@
+use super::{
+    CommandError,
+    utils::{argument_as_number, argument_as_str},
+};
+use crate::{
+    parser::RedisType,
+    store::{CLUSTER_SLOTS, Store},
+};
+
+pub fn handle_cluster(arguments: &[RedisType], store: &Store) -> Result<RedisType, CommandError> {
+    if !store.cluster_enabled() {
+        return Ok(RedisType::SimpleError(
+            "ERR This instance has cluster support disabled".into(),
+        ));
+    }
+
+    let subcommand = argument_as_str(arguments, 0)?.to_ascii_uppercase();
+    match subcommand.as_str() {
+        "COUNTKEYSINSLOT" => {
+            let slot = extract_slot(arguments, 1)?;
+            Ok(RedisType::Integer(store.count_keys_in_slot(slot) as i128))
+        }
+        "GETKEYSINSLOT" => {
+            let slot = extract_slot(arguments, 1)?;
+            let count: usize = argument_as_number(arguments, 2)
+                .map_err(|_| CommandError::InvalidInput("Invalid number of keys".into()))?;
+            Ok(RedisType::Array(Some(
+                store
+                    .get_keys_in_slot(slot, count)
+                    .into_iter()
+                    .map(RedisType::BulkString)
+                    .collect(),
+            )))
+        }
+        _ => Err(CommandError::UnknownCommand(format!(
+            "unknown subcommand '{}' for 'cluster' command",
+            subcommand
+        ))),
+    }
+}
+
+fn extract_slot(arguments: &[RedisType], index: usize) -> Result<u16, CommandError> {
+    argument_as_number(arguments, index)
+        .ok()
+        .filter(|slot| *slot < CLUSTER_SLOTS)
+        .ok_or_else(|| CommandError::InvalidInput("Invalid slot".into()))
+}
@@ -0,0 +1,205 @@
+use bytes::Bytes;
+
+use super::{
+    CommandError,
+    scan::{ScanOptions, scan_reply},
+    utils::{
+        argument_as_bytes, argument_as_number, argument_as_str, extract_key, redis_type_as_bytes,
+        reply_from_store,
+    },
+};
+use crate::{
+    parser::RedisType,
+    store::{ExpiryCondition, Store, now_millis},
+};
+
+pub fn handle_hset(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let fields_and_values = &arguments[1..];
+    if fields_and_values.is_empty() || !fields_and_values.len().is_multiple_of(2) {
+        return Err(CommandError::InvalidInput(
+            "wrong number of arguments for 'hset' command".into(),
+        ));
+    }
+
+    let pairs = fields_and_values
+        .chunks_exact(2)
+        .map(|pair| {
+            Ok((
+                redis_type_as_bytes(&pair[0])?.clone(),
+                redis_type_as_bytes(&pair[1])?.clone(),
+            ))
+        })
+        .collect::<Result<Vec<(Bytes, Bytes)>, CommandError>>()?;
+
+    reply_from_store(store.hset(key, pairs), |added| {
+        RedisType::Integer(added as i128)
+    })
+}
+
+pub fn handle_hget(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let field = argument_as_bytes(arguments, 1)?;
+
+    reply_from_store(store.hget(key, field), |value| match value {
+        Some(value) => RedisType::BulkString(value),
+        None => RedisType::NullBulkString,
+    })
+}
+
+pub fn handle_hscan(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let options = ScanOptions::parse(&arguments[1..], false, true)?;
+
+    reply_from_store(
+        store.hscan(key, options.cursor, options.count),
+        |(cursor, fields)| {
+            let elements = fields
+                .into_iter()
+                .filter(|(field, _)| options.matches(field))
+                .flat_map(|(field, value)| {
+                    if options.novalues {
+                        vec![field]
+                    } else {
+                        vec![field, value]
+                    }
+                })
+                .collect();
+            scan_reply(cursor, elements)
+        },
+    )
+}
+
+/// HEXPIRE and HPEXPIRE, `unit_factor` converts the given time to milliseconds
+pub fn handle_hexpire(
+    arguments: &[RedisType],
+    store: &mut Store,
+    unit_factor: u128,
+) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let time: u128 = argument_as_number(arguments, 1)?;
+
+    let mut fields_index = 2;
+    let condition = match argument_as_str(arguments, 2)?.to_ascii_uppercase().as_str() {
+        "FIELDS" => ExpiryCondition::Always,
+        "NX" => ExpiryCondition::Nx,
+        "XX" => ExpiryCondition::Xx,
+        "GT" => ExpiryCondition::Gt,
+        "LT" => ExpiryCondition::Lt,
+        other => {
+            return Err(CommandError::InvalidInput(format!(
+                "Unsupported option {}",
+                other
+            )));
+        }
+    };
+    if condition != ExpiryCondition::Always {
+        fields_index += 1;
+    }
+    let fields = extract_fields(arguments, fields_index)?;
+
+    let now = now_millis().map_err(CommandError::StoreError)?;
+    let command = if unit_factor == 1 {
+        "hpexpire"
+    } else {
+        "hexpire"
+    };
+    let deadline = time
+        .checked_mul(unit_factor)
+        .and_then(|time| now.checked_add(time))
+        .ok_or_else(|| {
+            CommandError::InvalidInput(format!("invalid expire time in '{}' command", command))
+        })?;
+    reply_from_store(
+        store.hexpire(key, deadline, condition, &fields),
+        integer_array,
+    )
+}
+
+/// HTTL and HPTTL, `unit_factor` converts milliseconds to the reply unit
+pub fn handle_httl(
+    arguments: &[RedisType],
+    store: &mut Store,
+    unit_factor: i128,
+) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let fields = extract_fields(arguments, 1)?;
+
+    reply_from_store(store.httl(key, &fields), |ttls| {
+        integer_array(
+            ttls.into_iter()
+                .map(|ttl| {
+                    if ttl < 0 {
+                        ttl
+                    } else {
+                        // round to the closest unit, like TTL does
+                        (ttl + unit_factor / 2) / unit_factor
+                    }
+                })
+                .collect(),
+        )
+    })
+}
+
+pub fn handle_hpersist(
+    arguments: &[RedisType],
+    store: &mut Store,
+) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let fields = extract_fields(arguments, 1)?;
+
+    reply_from_store(store.hpersist(key, &fields), integer_array)
+}
+
+/// Extracts the `FIELDS numfields field [field ...]` block starting at `index`
+fn extract_fields(arguments: &[RedisType], index: usize) -> Result<Vec<Bytes>, CommandError> {
+    if !argument_as_str(arguments, index)?.eq_ignore_ascii_case("FIELDS") {
+        return Err(CommandError::InvalidInput(
+            "Mandatory argument FIELDS is missing or not at the right position".into(),
+        ));
+    }
+    let numfields: usize = argument_as_number(arguments, index + 1)?;
+    let fields = &arguments[index + 2..];
+    if numfields == 0 || numfields != fields.len() {
+        return Err(CommandError::InvalidInput(
+            "The `numfields` parameter must match the number of arguments".into(),
+        ));
+    }
+
+    fields
+        .iter()
+        .map(|field| redis_type_as_bytes(field).cloned())
+        .collect()
+}
+
+fn integer_array(values: Vec<i128>) -> RedisType {
+    RedisType::Array(Some(values.into_iter().map(RedisType::Integer).collect()))
+}
+
+#[test]
+fn test_hexpire_refuses_times_which_overflow() {
+    use crate::commands::utils::test_arguments;
+
+    let mut store = Store::new();
+    store
+        .hset(&Bytes::from("hash"), vec![("f".into(), "v".into())])
+        .unwrap();
+    let huge = u128::MAX.to_string();
+    let hexpire = |arguments: &[&str], store: &mut Store, unit_factor| {
+        handle_hexpire(&test_arguments(arguments), store, unit_factor)
+            .unwrap_err()
+            .to_string()
+    };
+    assert_eq!(
+        hexpire(&["hash", &huge, "FIELDS", "1", "f"], &mut store, 1000),
+        "ERR invalid expire time in 'hexpire' command"
+    );
+    assert_eq!(
+        hexpire(&["hash", &huge, "FIELDS", "1", "f"], &mut store, 1),
+        "ERR invalid expire time in 'hpexpire' command"
+    );
+    assert_eq!(
+        store.httl(&Bytes::from("hash"), &["f".into()]).unwrap(),
+        vec![-1]
+    );
+}
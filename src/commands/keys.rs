@@ -2,7 +2,8 @@ use bytes::Bytes;
 
 use super::{
     CommandError,
-    utils::{argument_as_bytes, argument_as_number, argument_as_str, extract_key},
+    scan::{ScanOptions, scan_reply},
+    utils::{OptionParser, argument_as_bytes, argument_as_number, extract_key, wrong_type_error},
 };
 use crate::{
     parser::RedisType,
@@ -15,57 +16,40 @@ pub fn handle_get(arguments: &[RedisType], store: &Store) -> Result<RedisType, C
     let value = store.get(key.clone());
     match value {
         Ok(value) => Ok(RedisType::BulkString(value.clone())),
-        Err(StoreError::KeyExpired) => Ok(RedisType::NullBulkString), // we handle key expiration and return a null bulk string
-
         Err(StoreError::KeyNotFound) => Ok(RedisType::NullBulkString),
-        Err(StoreError::TimeError) => Err(CommandError::InvalidInput(
-            "Unable to convert expiry to unix timestamp".into(),
-        )),
-
-        Err(StoreError::StreamIdSmallerThanLast) => Err(CommandError::InvalidInput(
-            "Stream ID is smaller than the last ID".into(),
-        )),
-        Err(StoreError::StreamIdNotGreaterThan0) => Err(CommandError::InvalidInput(
-            "Stream ID must be greater than 0-0".into(),
-        )),
-        Err(StoreError::ValueError) => Err(CommandError::InvalidInput("Invalid value".into())),
+        Err(StoreError::WrongType) => Ok(wrong_type_error()),
+        Err(error) => Err(CommandError::StoreError(error)),
     }
 }
 
 pub fn handle_set(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
-    if arguments.len() != 2 && arguments.len() != 4 {
-        // either it's a simple SET, or it's a SET with an expiry
-        return Err(CommandError::InvalidInput(
-            "Invalid input: expected 2 or 4 arguments".into(),
-        ));
-    }
-
     let key = extract_key(arguments)?;
     let value = argument_as_bytes(arguments, 1)?;
 
+    let invalid_expire_time =
+        || CommandError::InvalidInput("invalid expire time in 'set' command".into());
+    let mut options = OptionParser::new(&arguments[2..]);
     let mut expiry: Option<u128> = None;
-    if arguments.len() == 4 {
-        let expiry_unit = argument_as_str(arguments, 2)?;
-        let expiry_value: u128 = argument_as_number(arguments, 3)?;
-
-        let unit_factor = match expiry_unit {
+    while let Some(option) = options.next_option()? {
+        let unit_factor = match option.as_str() {
             "EX" => 1000,
             "PX" => 1,
-            _ => {
-                return Err(CommandError::InvalidInput(
-                    "Invalid input: expiry unit of SET must be either 'EX' or 'PX'".into(),
-                ));
-            }
+            _ => return Err(CommandError::InvalidInput("syntax error".into())),
         };
-        expiry = Some(expiry_value * unit_factor);
+        let expiry_value: u128 = options.value_as_number()?;
+        expiry = Some(
+            expiry_value
+                .checked_mul(unit_factor)
+                .filter(|expiry| *expiry > 0)
+                .ok_or_else(invalid_expire_time)?,
+        );
     }
+    options.exclusive(&["EX", "PX"])?;
 
     store
         .set_with_expiry(key.clone(), value.clone(), expiry)
         .map_err(|store_error| match store_error {
-            StoreError::TimeError => {
-                CommandError::InvalidInput("Unable to convert expiry to unix timestamp".into())
-            }
+            StoreError::TimeError => invalid_expire_time(),
             _ => CommandError::StoreError(store_error),
         })?;
     Ok(RedisType::SimpleString(Bytes::from_static(b"OK")))
@@ -87,6 +71,25 @@ pub fn handle_incr(arguments: &[RedisType], store: &mut Store) -> Result<RedisTy
             "ERR value is not an integer or out of range".into(),
         )),
 
-        Err(_) => Err(CommandError::StoreError(StoreError::KeyNotFound)),
+        Err(StoreError::WrongType) => Ok(wrong_type_error()),
+        Err(error) => Err(CommandError::StoreError(error)),
     }
 }
+
+pub fn handle_scan(arguments: &[RedisType], store: &Store) -> Result<RedisType, CommandError> {
+    let options = ScanOptions::parse(arguments, true, false)?;
+
+    let (cursor, keys) = store.scan(options.cursor, options.count);
+    let keys = keys
+        .into_iter()
+        .filter(|key| options.matches(key))
+        .filter(|key| {
+            options.key_type.as_ref().is_none_or(|key_type| {
+                store
+                    .get_type(key)
+                    .is_ok_and(|actual| actual == key_type.as_bytes())
+            })
+        })
+        .collect();
+    Ok(scan_reply(cursor, keys))
+}
@@ -3,11 +3,14 @@ use tokio::sync::oneshot;
 
 use super::{
     CommandError, CommandResponse,
-    utils::{argument_as_number, extract_key},
+    utils::{
+        argument_as_bytes, argument_as_number, argument_as_str, extract_key, parse_timeout,
+        redis_type_as_bytes, reply_from_store, wrong_type_error,
+    },
 };
 use crate::{
     parser::RedisType,
-    store::{Store, StoreError},
+    store::{ListEnd, Store, StoreError},
 };
 
 pub fn handle_rpush(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
@@ -50,16 +53,15 @@ pub fn handle_lrange(arguments: &[RedisType], store: &Store) -> Result<RedisType
     let start: i128 = argument_as_number(arguments, 1)?;
     let end: i128 = argument_as_number(arguments, 2)?;
 
-    let result = store.lrange(key.clone(), start, end);
-
-    let response = if let Ok(values) = result {
+    let values = match store.lrange(key.clone(), start, end) {
+        Err(StoreError::KeyNotFound) => Ok(Vec::new()),
+        result => result,
+    };
+    reply_from_store(values, |values| {
         RedisType::Array(Some(
             values.into_iter().map(RedisType::BulkString).collect(),
         ))
-    } else {
-        RedisType::Array(Some(vec![]))
-    };
-    Ok(response)
+    })
 }
 
 pub fn handle_llen(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
@@ -70,65 +72,487 @@ pub fn handle_llen(arguments: &[RedisType], store: &mut Store) -> Result<RedisTy
     Ok(RedisType::Integer(len as i128))
 }
 
-pub fn handle_lpop(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+pub fn handle_lindex(arguments: &[RedisType], store: &Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let index: i128 = argument_as_number(arguments, 1)?;
+
+    reply_from_store(store.lindex(key, index), |element| match element {
+        Some(element) => RedisType::BulkString(element),
+        None => RedisType::NullBulkString,
+    })
+}
+
+pub fn handle_lset(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let index: i128 = argument_as_number(arguments, 1)?;
+    let value = argument_as_bytes(arguments, 2)?;
+
+    match store.lset(key, index, value.clone()) {
+        Err(StoreError::KeyNotFound) => Ok(RedisType::SimpleError("ERR no such key".into())),
+        Err(StoreError::IndexOutOfRange) => {
+            Ok(RedisType::SimpleError("ERR index out of range".into()))
+        }
+        result => reply_from_store(result, |_| RedisType::SimpleString("OK".into())),
+    }
+}
+
+pub fn handle_linsert(
+    arguments: &[RedisType],
+    store: &mut Store,
+) -> Result<RedisType, CommandError> {
     let key = extract_key(arguments)?;
-    let mut amount = 1;
+    let before = match argument_as_str(arguments, 1)?.to_ascii_uppercase().as_str() {
+        "BEFORE" => true,
+        "AFTER" => false,
+        _ => return Err(CommandError::InvalidInput("syntax error".into())),
+    };
+    let pivot = argument_as_bytes(arguments, 2)?;
+    let value = argument_as_bytes(arguments, 3)?;
+
+    reply_from_store(
+        store.linsert(key, before, pivot, value.clone()),
+        RedisType::Integer,
+    )
+}
+
+pub fn handle_lrem(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let count: i128 = argument_as_number(arguments, 1)?;
+    let value = argument_as_bytes(arguments, 2)?;
+
+    reply_from_store(store.lrem(key, count, value), |removed| {
+        RedisType::Integer(removed as i128)
+    })
+}
+
+pub fn handle_ltrim(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let start: i128 = argument_as_number(arguments, 1)?;
+    let stop: i128 = argument_as_number(arguments, 2)?;
+
+    reply_from_store(store.ltrim(key, start, stop), |_| {
+        RedisType::SimpleString("OK".into())
+    })
+}
+
+pub fn handle_lmove(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let source = extract_key(arguments)?;
+    let destination = argument_as_bytes(arguments, 1)?;
+    let from = parse_list_end(arguments, 2)?;
+    let to = parse_list_end(arguments, 3)?;
+
+    move_reply(store.lmove(source, destination, from, to))
+}
+
+pub fn handle_rpoplpush(
+    arguments: &[RedisType],
+    store: &mut Store,
+) -> Result<RedisType, CommandError> {
+    let source = extract_key(arguments)?;
+    let destination = argument_as_bytes(arguments, 1)?;
+
+    move_reply(store.lmove(source, destination, ListEnd::Right, ListEnd::Left))
+}
 
-    if arguments.len() > 1 {
-        amount = argument_as_number(arguments, 1)?;
+fn parse_list_end(arguments: &[RedisType], index: usize) -> Result<ListEnd, CommandError> {
+    match argument_as_str(arguments, index)?
+        .to_ascii_uppercase()
+        .as_str()
+    {
+        "LEFT" => Ok(ListEnd::Left),
+        "RIGHT" => Ok(ListEnd::Right),
+        _ => Err(CommandError::InvalidInput("syntax error".into())),
     }
+}
+
+/// The moved element, or a null reply if the source was empty
+fn move_reply(result: Result<Option<Bytes>, StoreError>) -> Result<RedisType, CommandError> {
+    reply_from_store(result, |element| match element {
+        Some(element) => RedisType::BulkString(element),
+        None => RedisType::NullBulkString,
+    })
+}
 
-    let removed_elements = store.lpop(key.clone(), amount);
-
-    match removed_elements {
-        Ok(removed_elements) => {
-            if removed_elements.is_empty() {
-                Ok(RedisType::NullBulkString)
-            } else if removed_elements.len() == 1 {
-                let element = &removed_elements[0];
-                Ok(RedisType::BulkString(element.clone()))
-            } else {
-                let resp = RedisType::Array(Some(
-                    removed_elements
-                        .into_iter()
-                        .map(RedisType::BulkString)
-                        .collect(),
+pub fn handle_lpos(arguments: &[RedisType], store: &Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let value = argument_as_bytes(arguments, 1)?;
+
+    let mut rank: i128 = 1;
+    let mut count = None;
+    let mut maxlen = 0;
+    let mut index = 2;
+    while index < arguments.len() {
+        let option = argument_as_str(arguments, index)?.to_ascii_uppercase();
+        let number: i128 = argument_as_number(arguments, index + 1)?;
+        match option.as_str() {
+            "RANK" if number == 0 => {
+                return Ok(RedisType::SimpleError(
+                    "ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list".into(),
                 ));
-                Ok(resp)
             }
+            "RANK" => rank = number,
+            "COUNT" if number < 0 => {
+                return Ok(RedisType::SimpleError("ERR COUNT can't be negative".into()));
+            }
+            "COUNT" => count = Some(number as usize),
+            "MAXLEN" if number < 0 => {
+                return Ok(RedisType::SimpleError(
+                    "ERR MAXLEN can't be negative".into(),
+                ));
+            }
+            "MAXLEN" => maxlen = number as usize,
+            _ => return Err(CommandError::InvalidInput("syntax error".into())),
         }
-        Err(StoreError::KeyNotFound) => Ok(RedisType::NullBulkString),
-        Err(err) => Err(CommandError::StoreError(err)),
+        index += 2;
+    }
+
+    let positions = store.lpos(key, value, rank, count.unwrap_or(1), maxlen);
+    // without COUNT the reply is the single position, with COUNT always an array
+    reply_from_store(positions, |positions| match count {
+        Some(_) => RedisType::Array(Some(
+            positions
+                .into_iter()
+                .map(|position| RedisType::Integer(position as i128))
+                .collect(),
+        )),
+        None => match positions.first() {
+            Some(position) => RedisType::Integer(*position as i128),
+            None => RedisType::NullBulkString,
+        },
+    })
+}
+
+pub fn handle_lpop(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    handle_pop(arguments, store, Store::lpop)
+}
+
+pub fn handle_rpop(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    handle_pop(arguments, store, Store::rpop)
+}
+
+/// LPOP and RPOP: a single popped element is replied as bulk string, several as array
+fn handle_pop(
+    arguments: &[RedisType],
+    store: &mut Store,
+    pop: fn(&mut Store, Bytes, usize) -> Result<Vec<Bytes>, StoreError>,
+) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    // with a count the reply is an array, however many elements were popped
+    let count = if arguments.len() > 1 {
+        Some(argument_as_number(arguments, 1)?)
+    } else {
+        None
+    };
+
+    match (pop(store, key.clone(), count.unwrap_or(1)), count) {
+        (Ok(removed_elements), Some(_)) => Ok(RedisType::Array(Some(
+            removed_elements
+                .into_iter()
+                .map(RedisType::BulkString)
+                .collect(),
+        ))),
+        (Ok(removed_elements), None) => Ok(removed_elements
+            .into_iter()
+            .next()
+            .map_or(RedisType::NullBulkString, RedisType::BulkString)),
+        (Err(StoreError::KeyNotFound), Some(_)) => Ok(RedisType::Array(None)),
+        (Err(StoreError::KeyNotFound), None) => Ok(RedisType::NullBulkString),
+        (Err(StoreError::WrongType), _) => Ok(wrong_type_error()),
+        (Err(err), _) => Err(CommandError::StoreError(err)),
     }
 }
 
 pub fn handle_blpop(
     arguments: &[RedisType],
     store: &mut Store,
+    can_block: bool,
 ) -> Result<CommandResponse, CommandError> {
-    let key = extract_key(arguments)?;
-    let timeout: f64 = argument_as_number(arguments, 1)?;
+    handle_blocking_pop(arguments, store, ListEnd::Left, can_block)
+}
 
-    // Check if data available first
-    if let Some(values) = store.lpop_for_blpop(key) {
-        // Data available - send immediately
-        let response = RedisType::Array(Some(
-            values.into_iter().map(RedisType::BulkString).collect(),
+pub fn handle_brpop(
+    arguments: &[RedisType],
+    store: &mut Store,
+    can_block: bool,
+) -> Result<CommandResponse, CommandError> {
+    handle_blocking_pop(arguments, store, ListEnd::Right, can_block)
+}
+
+/// BLPOP and BRPOP `key [key ...] timeout`: pop right away from the first key with elements,
+/// otherwise wait for a push to any of the keys
+fn handle_blocking_pop(
+    arguments: &[RedisType],
+    store: &mut Store,
+    from: ListEnd,
+    can_block: bool,
+) -> Result<CommandResponse, CommandError> {
+    if arguments.len() < 2 {
+        return Err(CommandError::InvalidInput(
+            "wrong number of arguments for 'blpop|brpop' command".into(),
         ));
-        return Ok(CommandResponse::Immediate(response));
+    }
+    let timeout = parse_timeout(arguments, arguments.len() - 1)?;
+    let keys = arguments[..arguments.len() - 1]
+        .iter()
+        .map(|key| redis_type_as_bytes(key).cloned())
+        .collect::<Result<Vec<Bytes>, CommandError>>()?;
+
+    pop_or_wait(store, keys, from, 1, timeout, can_block, |key, popped| {
+        let elements = std::iter::once(key).chain(popped);
+        RedisType::Array(Some(elements.map(RedisType::BulkString).collect()))
+    })
+}
+
+pub fn handle_lmpop(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let (keys, from, count) = parse_lmpop(arguments)?;
+
+    reply_from_store(store.lmpop(&keys, from, count), lmpop_reply)
+}
+
+pub fn handle_blmpop(
+    arguments: &[RedisType],
+    store: &mut Store,
+    can_block: bool,
+) -> Result<CommandResponse, CommandError> {
+    let timeout = parse_timeout(arguments, 0)?;
+    let (keys, from, count) = parse_lmpop(&arguments[1..])?;
+
+    pop_or_wait(
+        store,
+        keys,
+        from,
+        count,
+        timeout,
+        can_block,
+        |key, popped| lmpop_reply(Some((key, popped))),
+    )
+}
+
+/// Parses the arguments shared by LMPOP and BLMPOP, returns the keys, the end to pop from
+/// and the count
+fn parse_lmpop(arguments: &[RedisType]) -> Result<(Vec<Bytes>, ListEnd, usize), CommandError> {
+    let numkeys: usize = argument_as_number(arguments, 0)?;
+    if numkeys == 0 {
+        return Err(CommandError::InvalidInput(
+            "numkeys should be greater than 0".into(),
+        ));
+    }
+    let keys = (1..=numkeys)
+        .map(|index| argument_as_bytes(arguments, index).cloned())
+        .collect::<Result<Vec<Bytes>, CommandError>>()?;
+
+    let from = parse_list_end(arguments, numkeys + 1)?;
+    let count = match &arguments[numkeys + 2..] {
+        [] => 1,
+        [_, _] if argument_as_str(arguments, numkeys + 2)?.eq_ignore_ascii_case("COUNT") => {
+            match argument_as_number(arguments, numkeys + 3) {
+                Ok(count) if count > 0 => count,
+                _ => {
+                    return Err(CommandError::InvalidInput(
+                        "count should be greater than 0".into(),
+                    ));
+                }
+            }
+        }
+        _ => return Err(CommandError::InvalidInput("syntax error".into())),
+    };
+    Ok((keys, from, count))
+}
+
+/// The key and its popped elements as `[key, [element, ...]]`, null if nothing was popped
+fn lmpop_reply(popped: Option<(Bytes, Vec<Bytes>)>) -> RedisType {
+    let Some((key, elements)) = popped else {
+        return RedisType::Array(None);
+    };
+    RedisType::Array(Some(vec![
+        RedisType::BulkString(key),
+        RedisType::Array(Some(
+            elements.into_iter().map(RedisType::BulkString).collect(),
+        )),
+    ]))
+}
+
+/// Pops right away if one of the keys holds elements, otherwise blocks the client until
+/// a push to one of the keys serves it. A client which can't block (in a transaction) gets
+/// a null reply instead.
+fn pop_or_wait(
+    store: &mut Store,
+    keys: Vec<Bytes>,
+    from: ListEnd,
+    count: usize,
+    timeout: f64,
+    can_block: bool,
+    reply: fn(Bytes, Vec<Bytes>) -> RedisType,
+) -> Result<CommandResponse, CommandError> {
+    let popped = match store.lmpop(&keys, from, count) {
+        Err(StoreError::WrongType) => return Ok(CommandResponse::Immediate(wrong_type_error())),
+        result => result.map_err(CommandError::StoreError)?,
+    };
+    if let Some((key, popped)) = popped {
+        return Ok(CommandResponse::Immediate(reply(key, popped)));
+    }
+    if !can_block {
+        return Ok(CommandResponse::Immediate(RedisType::Array(None)));
     }
 
-    // No data - register for waiting
+    Ok(wait_for_push(
+        store, keys, from, count, None, timeout, reply,
+    ))
+}
+
+pub fn handle_blmove(
+    arguments: &[RedisType],
+    store: &mut Store,
+    can_block: bool,
+) -> Result<CommandResponse, CommandError> {
+    let source = extract_key(arguments)?;
+    let destination = argument_as_bytes(arguments, 1)?;
+    let from = parse_list_end(arguments, 2)?;
+    let to = parse_list_end(arguments, 3)?;
+    let timeout = parse_timeout(arguments, 4)?;
+
+    blocking_move(store, source, destination, from, to, timeout, can_block)
+}
+
+pub fn handle_brpoplpush(
+    arguments: &[RedisType],
+    store: &mut Store,
+    can_block: bool,
+) -> Result<CommandResponse, CommandError> {
+    let source = extract_key(arguments)?;
+    let destination = argument_as_bytes(arguments, 1)?;
+    let timeout = parse_timeout(arguments, 2)?;
+
+    blocking_move(
+        store,
+        source,
+        destination,
+        ListEnd::Right,
+        ListEnd::Left,
+        timeout,
+        can_block,
+    )
+}
+
+/// BLMOVE and BRPOPLPUSH: move right away if the source has elements, otherwise wait for a
+/// push. The store moves the element for a waiting client before it replies.
+fn blocking_move(
+    store: &mut Store,
+    source: &Bytes,
+    destination: &Bytes,
+    from: ListEnd,
+    to: ListEnd,
+    timeout: f64,
+    can_block: bool,
+) -> Result<CommandResponse, CommandError> {
+    match store.lmove(source, destination, from, to) {
+        Ok(None) if !can_block => Ok(CommandResponse::Immediate(RedisType::NullBulkString)),
+        Ok(None) => Ok(wait_for_push(
+            store,
+            vec![source.clone()],
+            from,
+            1,
+            Some((destination.clone(), to)),
+            timeout,
+            |_, moved| match moved.into_iter().next() {
+                Some(element) => RedisType::BulkString(element),
+                None => RedisType::NullBulkString,
+            },
+        )),
+        result => Ok(CommandResponse::Immediate(move_reply(result)?)),
+    }
+}
+
+fn wait_for_push(
+    store: &mut Store,
+    keys: Vec<Bytes>,
+    from: ListEnd,
+    count: usize,
+    destination: Option<(Bytes, ListEnd)>,
+    timeout: f64,
+    reply: fn(Bytes, Vec<Bytes>) -> RedisType,
+) -> CommandResponse {
     let (tx, rx) = oneshot::channel();
-    let identifier = store.register_blpop_waiting_client(key.clone(), tx);
-    println!(
-        "Waiting with timeout {} for client: {}",
-        timeout, identifier
-    );
-    Ok(CommandResponse::WaitForBLPOP {
+    let identifier = store.register_blpop_waiting_client(keys, from, count, destination, reply, tx);
+    CommandResponse::WaitForBLPOP {
         timeout,
         receiver: rx,
-        key: key.clone(),
         client_id: identifier,
-    })
+    }
+}
+
+#[test]
+fn test_blocking_pop_without_blocking_replies_null() {
+    let mut store = Store::new();
+    let arguments = [
+        RedisType::BulkString("list".into()),
+        RedisType::BulkString("0".into()),
+    ];
+
+    let response = handle_blpop(&arguments, &mut store, false).unwrap();
+    assert!(matches!(
+        response,
+        CommandResponse::Immediate(RedisType::Array(None))
+    ));
+
+    // no client was left waiting for the element
+    store.rpush("list".into(), vec!["a".into()]).unwrap();
+    store.serve_blocked_clients();
+    assert_eq!(store.llen(&"list".into()).unwrap(), 1);
+}
+
+#[test]
+fn test_blocking_commands_reject_negative_timeouts() {
+    use crate::{
+        commands::{handle_command, utils::test_command},
+        store::test_client,
+    };
+
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 1);
+    for command in [
+        &["BLPOP", "list", "-1"][..],
+        &["BRPOP", "list", "-1"],
+        &["BLMPOP", "-1", "1", "list", "LEFT"],
+        &["BLMOVE", "list", "other", "LEFT", "RIGHT", "-1"],
+        &["BRPOPLPUSH", "list", "other", "-1"],
+    ] {
+        let error = handle_command(test_command(command), &mut store, 1).unwrap_err();
+        assert_eq!(error.to_string(), "ERR timeout is negative");
+    }
+    let error = handle_command(test_command(&["BLPOP", "list", "nan"]), &mut store, 1).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "ERR timeout is not a float or out of range"
+    );
+}
+
+#[test]
+fn test_pop_with_a_count_replies_an_array() {
+    use crate::{
+        commands::{handle_command, utils::test_command},
+        store::test_client,
+    };
+
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 1);
+    store
+        .rpush("list".into(), vec!["a".into(), "b".into()])
+        .unwrap();
+    let mut pop = |command: &[&str]| match handle_command(test_command(command), &mut store, 1) {
+        Ok(CommandResponse::Immediate(reply)) => reply,
+        _ => panic!("{:?} should reply right away", command),
+    };
+
+    assert_eq!(pop(&["LPOP", "list", "0"]), RedisType::Array(Some(vec![])));
+    assert_eq!(
+        pop(&["LPOP", "list", "1"]),
+        RedisType::Array(Some(vec![RedisType::BulkString("a".into())]))
+    );
+    assert_eq!(
+        pop(&["RPOP", "list", "5"]),
+        RedisType::Array(Some(vec![RedisType::BulkString("b".into())]))
+    );
+    assert_eq!(pop(&["LPOP", "list", "5"]), RedisType::Array(None));
+    assert_eq!(pop(&["LPOP", "list"]), RedisType::NullBulkString);
 }
@@ -1,12 +1,28 @@
 use bytes::Bytes;
 
-use super::{CommandError, utils::extract_key};
+use super::{
+    CommandError,
+    table::{self, BeginSearch, COMMANDS, CommandSpec, FindKeys, KeySpec},
+    utils::{argument_as_str, extract_key, glob_match, reply_from_store},
+};
 use crate::{
+    metrics::client_buffer_bytes,
     parser::RedisType,
     store::{Store, StoreError},
 };
 
-pub fn handle_ping(arguments: &[RedisType]) -> Result<RedisType, CommandError> {
+/// In subscriber mode PING replies like a published message, `pong` and the argument
+pub fn handle_ping(arguments: &[RedisType], subscribed: bool) -> Result<RedisType, CommandError> {
+    if subscribed {
+        let message = match arguments.first() {
+            Some(_) => extract_key(arguments)?.clone(),
+            None => Bytes::new(),
+        };
+        return Ok(RedisType::Array(Some(vec![
+            RedisType::BulkString(Bytes::from_static(b"pong")),
+            RedisType::BulkString(message),
+        ])));
+    }
     if !arguments.is_empty() {
         // as per https://redis.io/docs/latest/commands/ping/, ping should return the arguments passed to it
         return handle_echo(arguments);
@@ -32,3 +48,392 @@ pub fn handle_type(arguments: &[RedisType], store: &mut Store) -> Result<RedisTy
         },
     }
 }
+
+pub fn handle_object(arguments: &[RedisType], store: &Store) -> Result<RedisType, CommandError> {
+    let subcommand = argument_as_str(arguments, 0)?.to_ascii_uppercase();
+    let key = extract_key(&arguments[1..])?;
+
+    match subcommand.as_str() {
+        "HISTOGRAM" => match store.element_histogram(key) {
+            Err(StoreError::KeyNotFound) => Ok(RedisType::NullBulkString),
+            result => reply_from_store(result, |histogram| {
+                RedisType::Array(Some(vec![
+                    RedisType::BulkString("encoding".into()),
+                    RedisType::BulkString(histogram.encoding.into()),
+                    RedisType::BulkString("elements".into()),
+                    RedisType::Integer(histogram.elements as i128),
+                    RedisType::BulkString("min-size".into()),
+                    RedisType::Integer(histogram.min_size as i128),
+                    RedisType::BulkString("avg-size".into()),
+                    RedisType::BulkString(format!("{:.2}", histogram.avg_size()).into()),
+                    RedisType::BulkString("max-size".into()),
+                    RedisType::Integer(histogram.max_size as i128),
+                ]))
+            }),
+        },
+        "ENCODING" => match store.object_encoding(key) {
+            Err(StoreError::KeyNotFound) => Ok(RedisType::NullBulkString),
+            result => reply_from_store(result, |encoding| RedisType::BulkString(encoding.into())),
+        },
+        "IDLETIME" => match store.object_idletime(key) {
+            Err(StoreError::KeyNotFound) => Ok(RedisType::NullBulkString),
+            result => reply_from_store(result, |idle| RedisType::Integer(idle as i128)),
+        },
+        _ => Err(CommandError::UnknownCommand(format!(
+            "unknown subcommand '{}' for 'object' command",
+            subcommand
+        ))),
+    }
+}
+
+/// Only DEBUG PROTOCOL, which replies with a sample of the given RESP type so clients can test
+/// their parsers
+pub fn handle_debug(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let subcommand = argument_as_str(arguments, 0)?.to_ascii_uppercase();
+    if subcommand != "PROTOCOL" {
+        return Err(CommandError::UnknownCommand(format!(
+            "unknown subcommand '{}'. Try DEBUG HELP.",
+            argument_as_str(arguments, 0)?
+        )));
+    }
+    if arguments.len() != 2 {
+        return Err(CommandError::InvalidInput(
+            "wrong number of arguments for 'debug|protocol' command".into(),
+        ));
+    }
+    let integers = || (0..3).map(RedisType::Integer).collect::<Vec<_>>();
+    let reply = match argument_as_str(arguments, 1)?.to_ascii_lowercase().as_str() {
+        "string" => RedisType::BulkString(Bytes::from_static(b"Hello World")),
+        "integer" => RedisType::Integer(12345),
+        // the same sample redis replies with, not meant to be pi
+        #[allow(clippy::approx_constant)]
+        "double" => RedisType::Double(3.141),
+        "bignum" => {
+            RedisType::BigNumber(Bytes::from_static(b"1234567999999999999999999999999999999"))
+        }
+        "null" => RedisType::Null,
+        "array" => RedisType::Array(Some(integers())),
+        "set" => RedisType::Set(integers()),
+        "map" => RedisType::Map(
+            integers()
+                .into_iter()
+                .zip([false, true, false])
+                .map(|(key, value)| (key, RedisType::Boolean(value)))
+                .collect(),
+        ),
+        "attrib" => {
+            store.add_reply_attribute(
+                "key-popularity",
+                RedisType::Array(Some(vec![
+                    RedisType::BulkString(Bytes::from_static(b"key:123")),
+                    RedisType::Integer(90),
+                ])),
+            );
+            RedisType::BulkString(Bytes::from_static(
+                b"Some real reply following the attribute",
+            ))
+        }
+        "verbatim" => RedisType::VerbatimString {
+            format: *b"txt",
+            text: Bytes::from_static(b"This is a verbatim\nstring"),
+        },
+        "true" => RedisType::Boolean(true),
+        "false" => RedisType::Boolean(false),
+        _ => {
+            return Err(CommandError::InvalidInput(
+                "Wrong protocol type name. Please use one of the following: \
+                 string|integer|double|bignum|null|array|set|map|attrib|verbatim|true|false"
+                    .into(),
+            ));
+        }
+    };
+    Ok(reply)
+}
+
+pub fn handle_info(arguments: &[RedisType]) -> Result<RedisType, CommandError> {
+    let section = if arguments.is_empty() {
+        "default".to_string()
+    } else {
+        argument_as_str(arguments, 0)?.to_ascii_lowercase()
+    };
+
+    let mut info = String::new();
+    if matches!(
+        section.as_str(),
+        "default" | "all" | "everything" | "memory"
+    ) {
+        info.push_str("# Memory\r\n");
+        info.push_str(&format!("mem_clients_normal:{}\r\n", client_buffer_bytes()));
+    }
+    Ok(RedisType::BulkString(info.into()))
+}
+
+/// SAVE, writes the dataset to the RDB file before replying
+pub fn handle_save(store: &Store) -> Result<RedisType, CommandError> {
+    store
+        .save()
+        .map_err(|err| CommandError::InvalidInput(format!("Failed saving the DB: {}", err)))?;
+    Ok(RedisType::SimpleString(Bytes::from_static(b"OK")))
+}
+
+/// CONFIG GET parameter [parameter ...], each parameter a glob-style pattern of the names
+pub fn handle_config(arguments: &[RedisType], store: &Store) -> Result<RedisType, CommandError> {
+    let subcommand = argument_as_str(arguments, 0)?.to_ascii_uppercase();
+    if subcommand != "GET" {
+        return Err(CommandError::UnknownCommand(format!(
+            "unknown subcommand '{}'. Try CONFIG HELP.",
+            argument_as_str(arguments, 0)?
+        )));
+    }
+    if arguments.len() < 2 {
+        return Err(CommandError::InvalidInput(
+            "wrong number of arguments for 'config|get' command".into(),
+        ));
+    }
+    let patterns = (1..arguments.len())
+        .map(|index| argument_as_str(arguments, index).map(str::to_ascii_lowercase))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let config = store.rdb_config();
+    let parameters = [
+        ("dir", config.dir.to_string_lossy().into_owned()),
+        ("dbfilename", config.dbfilename.clone()),
+    ];
+    let matching = parameters
+        .into_iter()
+        .filter(|(name, _)| {
+            patterns
+                .iter()
+                .any(|pattern| glob_match(pattern.as_bytes(), name.as_bytes()))
+        })
+        .map(|(name, value)| {
+            (
+                RedisType::BulkString(Bytes::from_static(name.as_bytes())),
+                RedisType::BulkString(Bytes::from(value)),
+            )
+        })
+        .collect();
+    Ok(RedisType::Map(matching))
+}
+
+/// COMMAND [COUNT | INFO [command ...] | DOCS [command ...] | GETKEYS command [arg ...]],
+/// without a subcommand it describes every command
+pub fn handle_command_info(arguments: &[RedisType]) -> Result<RedisType, CommandError> {
+    if arguments.is_empty() {
+        return Ok(RedisType::Array(Some(
+            COMMANDS.iter().map(command_info).collect(),
+        )));
+    }
+    let subcommand = argument_as_str(arguments, 0)?.to_ascii_uppercase();
+    let names = &arguments[1..];
+
+    match subcommand.as_str() {
+        "COUNT" if names.is_empty() => Ok(RedisType::Integer(COMMANDS.len() as i128)),
+        "INFO" => {
+            if names.is_empty() {
+                return handle_command_info(&[]);
+            }
+            let infos = (0..names.len())
+                .map(|index| {
+                    let name = argument_as_str(names, index)?;
+                    Ok(table::lookup(&name.to_ascii_uppercase())
+                        .map(command_info)
+                        .unwrap_or(RedisType::Array(None)))
+                })
+                .collect::<Result<Vec<RedisType>, CommandError>>()?;
+            Ok(RedisType::Array(Some(infos)))
+        }
+        "GETKEYS" if !names.is_empty() => {
+            let Some(spec) = table::lookup(&argument_as_str(names, 0)?.to_ascii_uppercase()) else {
+                return Err(CommandError::InvalidInput(
+                    "Invalid command specified".into(),
+                ));
+            };
+            if !table::arity_matches(spec.arity, names.len()) {
+                return Err(CommandError::InvalidInput(
+                    "Invalid number of arguments specified for command".into(),
+                ));
+            }
+            let keys = spec.key_positions(names);
+            if keys.is_empty() {
+                return Err(CommandError::InvalidInput(
+                    "The command has no key arguments".into(),
+                ));
+            }
+            Ok(RedisType::Array(Some(
+                keys.into_iter()
+                    .map(|position| names[position].clone())
+                    .collect(),
+            )))
+        }
+        "DOCS" => {
+            let specs = if names.is_empty() {
+                COMMANDS.iter().collect()
+            } else {
+                (0..names.len())
+                    .map(|index| argument_as_str(names, index))
+                    .collect::<Result<Vec<&str>, CommandError>>()?
+                    .into_iter()
+                    .filter_map(|name| table::lookup(&name.to_ascii_uppercase()))
+                    .collect::<Vec<&CommandSpec>>()
+            };
+            // unknown commands are left out
+            Ok(RedisType::Map(
+                specs
+                    .into_iter()
+                    .map(|spec| {
+                        (
+                            RedisType::BulkString(spec.name.to_ascii_lowercase().into()),
+                            RedisType::Map(vec![(
+                                RedisType::BulkString("group".into()),
+                                RedisType::BulkString(spec.group.into()),
+                            )]),
+                        )
+                    })
+                    .collect(),
+            ))
+        }
+        _ => Err(CommandError::UnknownCommand(format!(
+            "unknown subcommand '{}'. Try COMMAND HELP.",
+            argument_as_str(arguments, 0)?
+        ))),
+    }
+}
+
+/// What COMMAND INFO tells about a command: its name, arity, flags, key positions, ACL
+/// categories, tips, key specifications and subcommands
+fn command_info(spec: &CommandSpec) -> RedisType {
+    let status_set = |names: Vec<&str>| {
+        RedisType::Set(
+            names
+                .into_iter()
+                .map(|name| RedisType::SimpleString(Bytes::copy_from_slice(name.as_bytes())))
+                .collect(),
+        )
+    };
+    let (first_key, last_key, key_step) = spec.legacy_key_range();
+    RedisType::Array(Some(vec![
+        RedisType::BulkString(spec.name.to_ascii_lowercase().into()),
+        RedisType::Integer(spec.arity as i128),
+        status_set(spec.flag_names().collect()),
+        RedisType::Integer(first_key as i128),
+        RedisType::Integer(last_key as i128),
+        RedisType::Integer(key_step as i128),
+        status_set(spec.acl_categories()),
+        RedisType::Array(Some(Vec::new())),
+        RedisType::Array(Some(spec.key_specs.iter().map(key_spec_info).collect())),
+        RedisType::Array(Some(Vec::new())),
+    ]))
+}
+
+/// A key specification the way COMMAND INFO describes it
+fn key_spec_info(spec: &KeySpec) -> RedisType {
+    let field = |name: &str| RedisType::BulkString(Bytes::copy_from_slice(name.as_bytes()));
+    let number = |value: i128| RedisType::Integer(value);
+    let described = |kind: &str, fields: Vec<(RedisType, RedisType)>| {
+        RedisType::Map(vec![
+            (field("type"), field(kind)),
+            (field("spec"), RedisType::Map(fields)),
+        ])
+    };
+
+    let begin_search = match spec.begin_search {
+        BeginSearch::Index(index) => {
+            described("index", vec![(field("index"), number(index as i128))])
+        }
+        BeginSearch::Keyword {
+            keyword,
+            start_from,
+        } => described(
+            "keyword",
+            vec![
+                (field("keyword"), field(keyword)),
+                (field("startfrom"), number(start_from as i128)),
+            ],
+        ),
+    };
+    let find_keys = match spec.find_keys {
+        FindKeys::Range {
+            last_key,
+            key_step,
+            limit,
+        } => described(
+            "range",
+            vec![
+                (field("lastkey"), number(last_key as i128)),
+                (field("keystep"), number(key_step as i128)),
+                (field("limit"), number(limit as i128)),
+            ],
+        ),
+        FindKeys::KeyNum {
+            numkeys,
+            first_key,
+            key_step,
+        } => described(
+            "keynum",
+            vec![
+                (field("keynumidx"), number(numkeys as i128)),
+                (field("firstkey"), number(first_key as i128)),
+                (field("keystep"), number(key_step as i128)),
+            ],
+        ),
+    };
+    RedisType::Map(vec![
+        (field("begin_search"), begin_search),
+        (field("find_keys"), find_keys),
+    ])
+}
+
+#[test]
+fn test_command_info_describes_the_table() {
+    let info = |name: &str| {
+        handle_command_info(&[
+            RedisType::BulkString("INFO".into()),
+            RedisType::BulkString(Bytes::copy_from_slice(name.as_bytes())),
+        ])
+        .unwrap()
+    };
+    let RedisType::Array(Some(infos)) = info("get") else {
+        panic!("expected the infos");
+    };
+    let RedisType::Array(Some(get)) = &infos[0] else {
+        panic!("expected the info of GET");
+    };
+    assert_eq!(get[0], RedisType::BulkString("get".into()));
+    assert_eq!(get[1], RedisType::Integer(2));
+    assert_eq!(
+        get[2],
+        RedisType::Set(vec![
+            RedisType::SimpleString("readonly".into()),
+            RedisType::SimpleString("fast".into()),
+        ])
+    );
+    assert_eq!(&get[3..6], &[1, 1, 1].map(RedisType::Integer));
+    assert_eq!(
+        info("nosuchcommand"),
+        RedisType::Array(Some(vec![RedisType::Array(None)]))
+    );
+    assert_eq!(
+        handle_command_info(&[RedisType::BulkString("count".into())]).unwrap(),
+        RedisType::Integer(COMMANDS.len() as i128)
+    );
+
+    let getkeys = |command: &str| {
+        let arguments: Vec<RedisType> = format!("GETKEYS {}", command)
+            .split(' ')
+            .map(|argument| RedisType::BulkString(Bytes::copy_from_slice(argument.as_bytes())))
+            .collect();
+        handle_command_info(&arguments)
+    };
+    assert_eq!(
+        getkeys("ZUNIONSTORE dest 2 a b").unwrap(),
+        RedisType::Array(Some(vec![
+            RedisType::BulkString("dest".into()),
+            RedisType::BulkString("a".into()),
+            RedisType::BulkString("b".into()),
+        ]))
+    );
+    assert!(getkeys("PING").is_err());
+    assert!(getkeys("GET").is_err());
+    assert!(getkeys("NOSUCHCOMMAND key").is_err());
+}
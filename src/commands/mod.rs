@@ -1,40 +1,57 @@
-use std::{collections::VecDeque, fmt::Display};
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
 
 use bytes::Bytes;
 use tokio::sync::oneshot;
 
-use crate::{commands::keys::handle_incr, parser::RedisType, store::Store};
+use crate::{parser::RedisType, store::Store};
 
+mod client;
+mod cluster;
+mod hashes;
 mod keys;
 mod lists;
 mod misc;
+mod pubsub;
+mod scan;
+mod sets;
 mod streams;
+mod table;
+mod transactions;
 pub mod utils;
+mod zsets;
 
-use keys::{handle_get, handle_set};
-use lists::{handle_blpop, handle_llen, handle_lpop, handle_lpush, handle_lrange, handle_rpush};
-use misc::{handle_echo, handle_ping, handle_type};
-use streams::{handle_xadd, handle_xrange, handle_xread};
+pub use client::is_held_by_pause;
+pub use pubsub::Subscriptions;
+use table::{CommandSpec, Invocation};
 use utils::argument_as_str;
 
-use crate::store::StoreError;
+use crate::store::{ReplyMode, StoreError};
 
 #[derive(Debug)]
 pub enum CommandError {
     InvalidInput(String),
     UnknownCommand(String),
     StoreError(StoreError),
+    /// an error with a code of its own instead of `ERR`, like `NOPROTO` or `WRONGPASS`
+    WithCode(&'static str, String),
 }
 
 #[derive(Debug)]
 pub enum CommandResponse {
     Immediate(RedisType),
-    StartTransaction,
-    ExecTransaction(RedisType),
+    /// the replies were put into the outbox of the client already
+    Pushed,
+    /// QUIT, the connection is closed once the client got its OK
+    Quit,
+    /// the command ran, but the client turned its replies off (CLIENT REPLY OFF or SKIP). A
+    /// blocking command is still waited for, its reply dropped.
+    Silent(Box<CommandResponse>),
     WaitForBLPOP {
         timeout: f64,
         receiver: oneshot::Receiver<RedisType>,
-        key: Bytes,
         client_id: u64,
     },
     WaitForXREAD {
@@ -42,13 +59,191 @@ pub enum CommandResponse {
         receiver: oneshot::Receiver<RedisType>,
         client_id: u64,
     },
+    WaitForZPOP {
+        timeout: f64,
+        receiver: oneshot::Receiver<RedisType>,
+        client_id: u64,
+    },
+}
+
+/// Longer timeouts (up to infinity) are cut to this, a deadline further out can't be represented
+const MAX_BLOCKING_TIMEOUT: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+impl CommandResponse {
+    /// The blocked client and how long it waits at most, `None` if it does not block or
+    /// waits forever (a timeout of 0)
+    pub fn blocking_timeout(&self) -> Option<(u64, Duration)> {
+        let (client_id, timeout) = match self {
+            CommandResponse::WaitForBLPOP {
+                timeout, client_id, ..
+            }
+            | CommandResponse::WaitForZPOP {
+                timeout, client_id, ..
+            } if *timeout > 0.0 => (
+                *client_id,
+                Duration::try_from_secs_f64(*timeout).unwrap_or(MAX_BLOCKING_TIMEOUT),
+            ),
+            CommandResponse::WaitForXREAD {
+                timeout, client_id, ..
+            } if *timeout > 0 => (
+                *client_id,
+                Duration::from_millis(u64::try_from(*timeout).unwrap_or(u64::MAX)),
+            ),
+            // a silenced blocking command still waits at most for its timeout
+            CommandResponse::Silent(response) => return response.blocking_timeout(),
+            _ => return None,
+        };
+        Some((client_id, timeout.min(MAX_BLOCKING_TIMEOUT)))
+    }
+}
+
+/// Commands a client in subscriber mode (subscribed to any channel) may still issue
+const SUBSCRIBER_COMMANDS: [&str; 9] = [
+    "SUBSCRIBE",
+    "UNSUBSCRIBE",
+    "PSUBSCRIBE",
+    "PUNSUBSCRIBE",
+    "SSUBSCRIBE",
+    "SUNSUBSCRIBE",
+    "PING",
+    "QUIT",
+    "RESET",
+];
+
+/// Commands MONITOR doesn't show, they may carry credentials
+const UNMONITORED_COMMANDS: [&str; 3] = ["AUTH", "HELLO", "MONITOR"];
+
+/// Commands which look at keys without counting as access to them
+const UNTOUCHED_COMMANDS: [&str; 2] = ["OBJECT", "TYPE"];
+
+/// Commands which act on the transaction itself instead of being queued in it
+const TRANSACTION_COMMANDS: [&str; 6] = ["MULTI", "EXEC", "DISCARD", "WATCH", "RESET", "QUIT"];
+
+/// Checks the name and the number of arguments of the command without running it, as done
+/// before running it and for the commands queued in a transaction
+fn check_command(input: &RedisType) -> Result<&'static CommandSpec, CommandError> {
+    let RedisType::Array(Some(elements)) = input else {
+        return Err(CommandError::InvalidInput(
+            "The supplied input has an invalid format or redis type: Input needs to be of RedisType::Array".to_string(),
+        ));
+    };
+    let command = argument_as_str(elements, 0)?.to_ascii_uppercase();
+    let Some(spec) = table::lookup(&command) else {
+        return Err(unknown_command(elements));
+    };
+    if !table::arity_matches(spec.arity, elements.len()) {
+        return Err(CommandError::InvalidInput(format!(
+            "wrong number of arguments for '{}' command",
+            command.to_ascii_lowercase()
+        )));
+    }
+    Ok(spec)
 }
 
+/// Commands whose first argument is a subcommand, reported as `container|subcommand`
+const CONTAINER_COMMANDS: [&str; 8] = [
+    "CLIENT", "CLUSTER", "COMMAND", "CONFIG", "OBJECT", "PUBSUB", "XGROUP", "XINFO",
+];
+
+/// Lower case name of the command as CLIENT INFO reports it, e.g. `get` or `client|info`
+fn command_label(elements: &[RedisType]) -> String {
+    let Ok(command) = argument_as_str(elements, 0) else {
+        return String::new();
+    };
+    let command = command.to_ascii_lowercase();
+    match argument_as_str(elements, 1) {
+        Ok(subcommand) if CONTAINER_COMMANDS.contains(&command.to_ascii_uppercase().as_str()) => {
+            format!("{}|{}", command, subcommand.to_ascii_lowercase())
+        }
+        _ => command,
+    }
+}
+
+/// Runs the command the client sent, its reply is dropped if the client turned replies off
 pub fn handle_command(
     input: RedisType,
     store: &mut Store,
-    transaction: Option<VecDeque<RedisType>>,
+    client_id: u64,
+) -> Result<CommandResponse, CommandError> {
+    let client = store.client_mut(client_id);
+    if let RedisType::Array(Some(elements)) = &input {
+        client.last_command = command_label(elements);
+        client.last_interaction = Instant::now();
+    }
+    store.set_current_client(Some(client_id));
+    let response = queue_or_execute(input, store, client_id);
+    store.set_current_client(None);
+
+    let client = store.client_mut(client_id);
+    let attributes = std::mem::take(&mut client.reply_attributes);
+    let response = match response {
+        Ok(CommandResponse::Immediate(reply)) if !attributes.is_empty() => {
+            Ok(CommandResponse::Immediate(RedisType::Attribute {
+                attributes,
+                reply: Box::new(reply),
+            }))
+        }
+        response => response,
+    };
+    if client.last_command != "client|caching" {
+        client.caching = None;
+    }
+    let silent = client.reply_mode != ReplyMode::On;
+    // SKIP only mutes the command after CLIENT REPLY SKIP
+    if client.reply_mode == ReplyMode::Skip && client.last_command != "client|reply" {
+        client.reply_mode = ReplyMode::On;
+    }
+    if !silent {
+        return response;
+    }
+    let response = response.unwrap_or_else(|err| CommandResponse::Immediate(err.to_reply()));
+    Ok(CommandResponse::Silent(Box::new(response)))
+}
+
+/// Inside a transaction the command is only checked and queued for EXEC, a command which is
+/// refused makes EXEC discard the transaction. Otherwise it runs right away.
+fn queue_or_execute(
+    input: RedisType,
+    store: &mut Store,
+    client_id: u64,
+) -> Result<CommandResponse, CommandError> {
+    let client = store.client_mut(client_id);
+    if let Some(queue) = client.transaction.as_mut()
+        && let RedisType::Array(Some(elements)) = &input
+        && let Ok(command) = argument_as_str(elements, 0)
+        && !TRANSACTION_COMMANDS.contains(&command.to_ascii_uppercase().as_str())
+    {
+        return match check_command(&input) {
+            Ok(spec) if spec.has(table::NO_MULTI) => {
+                client.transaction_failed = true;
+                Err(CommandError::InvalidInput(
+                    "Command not allowed inside a transaction".into(),
+                ))
+            }
+            Ok(_) => {
+                queue.push_back(input);
+                Ok(CommandResponse::Immediate(RedisType::SimpleString(
+                    Bytes::from("QUEUED"),
+                )))
+            }
+            Err(err) => {
+                client.transaction_failed = true;
+                Err(err)
+            }
+        };
+    }
+    execute_command(input, store, client_id, true)
+}
+
+/// Runs one command. Blocking commands only block if `can_block` is set, inside a transaction
+/// they act like their non-blocking counterparts and reply null if there is nothing to pop.
+fn execute_command(
+    input: RedisType,
+    store: &mut Store,
+    client_id: u64,
+    can_block: bool,
 ) -> Result<CommandResponse, CommandError> {
+    let spec = check_command(&input)?;
     let RedisType::Array(Some(elements)) = input else {
         return Err(CommandError::InvalidInput(
             "The supplied input has an invalid format or redis type: Input needs to be of RedisType::Array".to_string(),
@@ -59,59 +254,392 @@ pub fn handle_command(
 
     let arguments = &elements[1..];
 
-    match command.as_str() {
-        "PING" => Ok(CommandResponse::Immediate(handle_ping(arguments)?)),
-        "ECHO" => Ok(CommandResponse::Immediate(handle_echo(arguments)?)),
-        "LRANGE" => Ok(CommandResponse::Immediate(handle_lrange(arguments, store)?)),
-        "RPUSH" => Ok(CommandResponse::Immediate(handle_rpush(arguments, store)?)),
-        "LPUSH" => Ok(CommandResponse::Immediate(handle_lpush(arguments, store)?)),
-        "GET" => Ok(CommandResponse::Immediate(handle_get(arguments, store)?)),
-        "SET" => Ok(CommandResponse::Immediate(handle_set(arguments, store)?)),
-        "LLEN" => Ok(CommandResponse::Immediate(handle_llen(arguments, store)?)),
-        "LPOP" => Ok(CommandResponse::Immediate(handle_lpop(arguments, store)?)),
-        "TYPE" => Ok(CommandResponse::Immediate(handle_type(arguments, store)?)),
-        "XADD" => Ok(CommandResponse::Immediate(handle_xadd(arguments, store)?)),
-        "XRANGE" => Ok(CommandResponse::Immediate(handle_xrange(arguments, store)?)),
-        "INCR" => Ok(CommandResponse::Immediate(handle_incr(arguments, store)?)),
-        "XREAD" => handle_xread(arguments, store),
-        "BLPOP" => handle_blpop(arguments, store),
-        "MULTI" => Ok(CommandResponse::StartTransaction),
-        "EXEC" => {
-            if let Some(transaction) = transaction {
-                let mut responses = Vec::new();
-                for comm in transaction {
-                    let response = handle_command(comm, store, None)?;
-                    let f = match response {
-                        CommandResponse::Immediate(redis_type) => redis_type,
-                        _ => todo!(),
-                    };
-                    responses.push(f);
-                }
-                Ok(CommandResponse::ExecTransaction(RedisType::Array(Some(
-                    responses,
-                ))))
-            } else {
-                println!("No transaction in progress");
-
-                Ok(CommandResponse::ExecTransaction(RedisType::SimpleError(
-                    Bytes::from("ERR EXEC without MULTI"),
-                )))
-            }
-        }
+    if !UNMONITORED_COMMANDS.contains(&command.as_str()) {
+        store.feed_monitors(client_id, &elements);
+    }
+    let keys: Vec<Bytes> = spec
+        .key_positions(&elements)
+        .into_iter()
+        .filter_map(|index| elements.get(index))
+        .filter_map(|key| utils::redis_type_as_bytes(key).ok().cloned())
+        .collect();
+    if !store.client(client_id).no_touch && !UNTOUCHED_COMMANDS.contains(&command.as_str()) {
+        store.access_keys(&keys);
+    }
+
+    // RESP3 tells pushes and replies apart, subscribed clients may run any command there
+    let client = store.client(client_id);
+    let subscribed = client.protocol.get() < 3 && client.subscriptions.is_subscribed();
+    if subscribed && !SUBSCRIBER_COMMANDS.contains(&command.as_str()) {
+        return Err(CommandError::InvalidInput(format!(
+            "Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+            command.to_ascii_lowercase()
+        )));
+    }
+
+    let response = (spec.handler)(Invocation {
+        arguments,
+        store,
+        client_id,
+        can_block,
+        subscribed,
+    });
+    // the keys a read-only command read are tracked for clients which cache them, this covers
+    // the commands EXEC runs as well
+    if spec.has(table::READONLY) {
+        store.track_keys(client_id, keys);
+    }
+    response
+}
 
-        _ => Err(CommandError::UnknownCommand(format!(
-            "redis command {} not supported",
-            command
-        ))),
+/// The error for a command redis doesn't know, quoting the command and its first arguments
+fn unknown_command(elements: &[RedisType]) -> CommandError {
+    let quoted = |element: &RedisType| match utils::redis_type_as_bytes(element) {
+        Ok(bytes) => format!("'{}'", String::from_utf8_lossy(bytes)),
+        Err(_) => "''".to_string(),
+    };
+    let arguments: String = elements
+        .iter()
+        .skip(1)
+        .take(16)
+        .map(|argument| quoted(argument) + " ")
+        .collect();
+    CommandError::UnknownCommand(format!(
+        "unknown command {}, with args beginning with: {}",
+        elements.first().map(quoted).unwrap_or_default(),
+        arguments
+    ))
+}
+
+impl CommandError {
+    /// The error as sent to the client
+    pub fn to_reply(&self) -> RedisType {
+        RedisType::SimpleError(Bytes::from(self.to_string()))
     }
 }
 
+/// The error the way redis replies with it: an error code like `ERR` or `WRONGTYPE`, which
+/// clients categorize errors by, followed by the message
 impl Display for CommandError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            CommandError::InvalidInput(message) => write!(f, "Invalid Input: {}", message),
-            CommandError::UnknownCommand(message) => write!(f, "Invalid Input: {}", message),
-            CommandError::StoreError(store_error) => write!(f, "Store Error: {}", store_error),
+            CommandError::InvalidInput(message) => write!(f, "ERR {}", message),
+            CommandError::UnknownCommand(message) => write!(f, "ERR {}", message),
+            CommandError::WithCode(code, message) => write!(f, "{} {}", code, message),
+            CommandError::StoreError(store_error) => {
+                write!(f, "{} {}", store_error.code(), store_error)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_subscriber_mode_only_allows_subscription_commands() {
+    use crate::{commands::utils::test_command, store::test_client};
+
+    let mut store = Store::new();
+    let mut inbox = test_client(&mut store, 1);
+    let command = |name: &str| test_command(&[name, "news"]);
+
+    assert!(matches!(
+        handle_command(command("SUBSCRIBE"), &mut store, 1),
+        Ok(CommandResponse::Pushed)
+    ));
+    assert!(inbox.try_recv().is_ok());
+    assert!(handle_command(command("GET"), &mut store, 1).is_err());
+    assert!(matches!(
+        handle_command(command("PING"), &mut store, 1),
+        Ok(CommandResponse::Immediate(RedisType::Array(_)))
+    ));
+    assert!(matches!(
+        handle_command(command("UNSUBSCRIBE"), &mut store, 1),
+        Ok(CommandResponse::Pushed)
+    ));
+    assert!(inbox.try_recv().is_ok());
+    assert!(handle_command(command("GET"), &mut store, 1).is_ok());
+}
+
+#[test]
+fn test_exec_runs_the_queued_commands() {
+    use crate::{commands::utils::test_command, store::test_client};
+
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 1);
+    let mut run = |parts: &[&str]| match handle_command(test_command(parts), &mut store, 1) {
+        Ok(CommandResponse::Immediate(reply)) => reply,
+        Ok(_) => panic!("expected an immediate reply"),
+        Err(err) => err.to_reply(),
+    };
+
+    assert_eq!(run(&["MULTI"]), RedisType::SimpleString(Bytes::from("OK")));
+    assert_eq!(
+        run(&["SET", "k", "1"]),
+        RedisType::SimpleString(Bytes::from("QUEUED"))
+    );
+    assert_eq!(
+        run(&["INCR", "k"]),
+        RedisType::SimpleString(Bytes::from("QUEUED"))
+    );
+    assert_eq!(
+        run(&["EXEC"]),
+        RedisType::Array(Some(vec![
+            RedisType::SimpleString(Bytes::from("OK")),
+            RedisType::Integer(2),
+        ]))
+    );
+    assert!(matches!(run(&["EXEC"]), RedisType::SimpleError(_)));
+}
+
+#[test]
+fn test_exec_aborts_if_a_watched_key_changed() {
+    use crate::{commands::utils::test_command, store::test_client};
+
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 1);
+
+    handle_command(test_command(&["WATCH", "k"]), &mut store, 1).unwrap();
+    handle_command(test_command(&["SET", "k", "1"]), &mut store, 1).unwrap();
+    handle_command(test_command(&["MULTI"]), &mut store, 1).unwrap();
+    assert!(handle_command(test_command(&["WATCH", "k"]), &mut store, 1).is_err());
+    handle_command(test_command(&["INCR", "k"]), &mut store, 1).unwrap();
+    let Ok(CommandResponse::Immediate(reply)) =
+        handle_command(test_command(&["EXEC"]), &mut store, 1)
+    else {
+        panic!("expected the reply of the transaction");
+    };
+    assert_eq!(reply, RedisType::Array(None));
+    assert!(store.client(1).watched.is_empty());
+}
+
+#[test]
+fn test_no_op_writes_leave_watched_keys_alone() {
+    use crate::{commands::utils::test_command, store::test_client};
+
+    let mut store = Store::new();
+    let _first = test_client(&mut store, 1);
+    let _second = test_client(&mut store, 2);
+    for command in [
+        &["SADD", "set", "a"][..],
+        &["RPUSH", "list", "a"],
+        &["XADD", "stream", "1-1", "f", "v"],
+        &["HSET", "hash", "f", "v"],
+    ] {
+        handle_command(test_command(command), &mut store, 2).unwrap();
+    }
+
+    handle_command(
+        test_command(&["WATCH", "set", "list", "stream", "hash"]),
+        &mut store,
+        1,
+    )
+    .unwrap();
+    // writes which find nothing to change, and reads
+    for command in [
+        &["SREM", "set", "missing"][..],
+        &["SADD", "set", "a"],
+        &["LREM", "list", "0", "missing"],
+        &["XDEL", "stream", "9-9"],
+        &["XTRIM", "stream", "MAXLEN", "5"],
+        &["HGET", "hash", "f"],
+        &["HPERSIST", "hash", "FIELDS", "1", "f"],
+    ] {
+        handle_command(test_command(command), &mut store, 2).unwrap();
+    }
+    handle_command(test_command(&["MULTI"]), &mut store, 1).unwrap();
+    handle_command(test_command(&["SCARD", "set"]), &mut store, 1).unwrap();
+    let Ok(CommandResponse::Immediate(reply)) =
+        handle_command(test_command(&["EXEC"]), &mut store, 1)
+    else {
+        panic!("expected the reply of the transaction");
+    };
+    assert_eq!(reply, RedisType::Array(Some(vec![RedisType::Integer(1)])));
+}
+
+#[test]
+fn test_client_reply_off_and_skip_silence_replies() {
+    use crate::{commands::utils::test_command, store::test_client};
+
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 1);
+    let mut silent = |parts: &[&str]| {
+        matches!(
+            handle_command(test_command(parts), &mut store, 1),
+            Ok(CommandResponse::Silent(_))
+        )
+    };
+
+    assert!(silent(&["CLIENT", "REPLY", "SKIP"]));
+    assert!(silent(&["SET", "k", "1"]));
+    assert!(!silent(&["GET", "k"]));
+    assert!(silent(&["CLIENT", "REPLY", "OFF"]));
+    assert!(silent(&["INCR", "k"]));
+    assert!(silent(&["NOPE"]));
+    assert!(!silent(&["CLIENT", "REPLY", "ON"]));
+    assert_eq!(store.get(Bytes::from("k")).unwrap(), Bytes::from("2"));
+}
+
+#[test]
+fn test_check_command() {
+    use crate::commands::utils::test_command;
+    assert!(check_command(&test_command(&["get", "k"])).is_ok());
+    assert!(check_command(&test_command(&["GET"])).is_err());
+    assert!(check_command(&test_command(&["NOPE", "k"])).is_err());
+    assert!(check_command(&test_command(&["RPUSH", "k", "a", "b"])).is_ok());
+}
+
+#[test]
+fn test_errors_are_replied_like_redis() {
+    use crate::store::test_client;
+
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 1);
+    let mut error = |parts: &[&str]| {
+        let command = RedisType::Array(Some(
+            parts
+                .iter()
+                .map(|part| RedisType::BulkString(Bytes::copy_from_slice(part.as_bytes())))
+                .collect(),
+        ));
+        match handle_command(command, &mut store, 1) {
+            Err(err) => err.to_reply(),
+            Ok(CommandResponse::Immediate(reply)) => reply,
+            Ok(_) => unreachable!(),
         }
+    };
+
+    assert_eq!(
+        error(&["GET"]),
+        RedisType::SimpleError(Bytes::from(
+            "ERR wrong number of arguments for 'get' command"
+        ))
+    );
+    assert_eq!(
+        error(&["nope", "a"]),
+        RedisType::SimpleError(Bytes::from(
+            "ERR unknown command 'nope', with args beginning with: 'a' "
+        ))
+    );
+    error(&["RPUSH", "list", "a"]);
+    assert_eq!(
+        error(&["INCR", "list"]),
+        RedisType::SimpleError(Bytes::from(
+            "WRONGTYPE Operation against a key holding the wrong kind of value"
+        ))
+    );
+    assert_eq!(
+        CommandError::StoreError(StoreError::BusyGroup).to_reply(),
+        RedisType::SimpleError(Bytes::from("BUSYGROUP Consumer Group name already exists"))
+    );
+}
+
+#[test]
+fn test_huge_blocking_timeouts_are_clamped() {
+    use crate::{commands::utils::test_command, store::test_client};
+
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 1);
+    for timeout in ["1e20", "inf"] {
+        let response = handle_command(test_command(&["BZPOPMIN", "k", timeout]), &mut store, 1);
+        let (client_id, timeout) = response.unwrap().blocking_timeout().unwrap();
+        assert_eq!(timeout, MAX_BLOCKING_TIMEOUT);
+        store.set_waiting_timeout(client_id, timeout);
+        store.remove_waiting_client(client_id);
     }
+
+    let error = |timeout: &str, store: &mut Store| {
+        handle_command(test_command(&["BZPOPMIN", "k", timeout]), store, 1)
+            .unwrap_err()
+            .to_string()
+    };
+    assert_eq!(
+        error("nan", &mut store),
+        "ERR timeout is not a float or out of range"
+    );
+    assert_eq!(error("-1", &mut store), "ERR timeout is negative");
+}
+
+#[test]
+fn test_silenced_blocking_commands_keep_their_timeout() {
+    use crate::{commands::utils::test_command, store::test_client};
+
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 1);
+    handle_command(test_command(&["CLIENT", "REPLY", "SKIP"]), &mut store, 1).unwrap();
+    let response = handle_command(test_command(&["BLPOP", "empty", "0.2"]), &mut store, 1);
+    let response = response.unwrap();
+    assert!(matches!(response, CommandResponse::Silent(_)));
+    let (_, timeout) = response.blocking_timeout().unwrap();
+    assert_eq!(timeout, Duration::from_millis(200));
+}
+
+#[test]
+fn test_transactions_refuse_subscriptions_and_never_block() {
+    use crate::{commands::utils::test_command, store::test_client};
+
+    let mut store = Store::new();
+    let mut inbox = test_client(&mut store, 1);
+    let mut run = |parts: &[&str]| match handle_command(test_command(parts), &mut store, 1) {
+        Ok(CommandResponse::Immediate(reply)) => reply,
+        Ok(_) => panic!("expected an immediate reply"),
+        Err(err) => err.to_reply(),
+    };
+
+    run(&["MULTI"]);
+    assert_eq!(
+        run(&["BLPOP", "list", "0"]),
+        RedisType::SimpleString(Bytes::from("QUEUED"))
+    );
+    assert_eq!(
+        run(&["EXEC"]),
+        RedisType::Array(Some(vec![RedisType::Array(None)]))
+    );
+
+    run(&["MULTI"]);
+    assert_eq!(
+        run(&["SUBSCRIBE", "news"]),
+        RedisType::SimpleError(Bytes::from("ERR Command not allowed inside a transaction"))
+    );
+    run(&["SET", "k", "1"]);
+    assert!(
+        matches!(run(&["EXEC"]), RedisType::SimpleError(error) if error.starts_with(b"EXECABORT"))
+    );
+    assert!(inbox.try_recv().is_err());
+    assert!(!store.client(1).subscriptions.is_subscribed());
+}
+
+#[test]
+fn test_reply_attributes_wrap_the_reply_of_their_command_only() {
+    use crate::{commands::utils::test_command, store::test_client};
+
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 1);
+    // without a command being run there is no reply to attach it to
+    store.add_reply_attribute("ignored", RedisType::Integer(1));
+
+    let Ok(CommandResponse::Immediate(reply)) = handle_command(
+        test_command(&["DEBUG", "PROTOCOL", "attrib"]),
+        &mut store,
+        1,
+    ) else {
+        panic!("DEBUG PROTOCOL should reply right away");
+    };
+    assert_eq!(
+        reply,
+        RedisType::Attribute {
+            attributes: vec![(
+                RedisType::BulkString(Bytes::from("key-popularity")),
+                RedisType::Array(Some(vec![
+                    RedisType::BulkString(Bytes::from("key:123")),
+                    RedisType::Integer(90),
+                ]))
+            )],
+            reply: Box::new(RedisType::BulkString(Bytes::from(
+                "Some real reply following the attribute"
+            ))),
+        }
+    );
+    assert!(matches!(
+        handle_command(test_command(&["PING"]), &mut store, 1),
+        Ok(CommandResponse::Immediate(RedisType::SimpleString(_)))
+    ));
 }
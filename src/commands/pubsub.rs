@@ -0,0 +1,355 @@
+use std::collections::HashSet;
+
+use bytes::Bytes;
+
+use super::{
+    CommandError, CommandResponse,
+    utils::{argument_as_bytes, argument_as_str, extract_key},
+};
+use crate::{
+    parser::RedisType,
+    store::{Store, SubscriptionKind},
+};
+
+/// Channels, patterns and shard channels a connection is subscribed to
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    channels: HashSet<Bytes>,
+    patterns: HashSet<Bytes>,
+    shard_channels: HashSet<Bytes>,
+}
+
+impl Subscriptions {
+    fn names_mut(&mut self, kind: SubscriptionKind) -> &mut HashSet<Bytes> {
+        match kind {
+            SubscriptionKind::Channel => &mut self.channels,
+            SubscriptionKind::Pattern => &mut self.patterns,
+            SubscriptionKind::ShardChannel => &mut self.shard_channels,
+        }
+    }
+
+    /// The channels or patterns of the kind the connection is subscribed to
+    pub fn names(&self, kind: SubscriptionKind) -> Vec<Bytes> {
+        let names = match kind {
+            SubscriptionKind::Channel => &self.channels,
+            SubscriptionKind::Pattern => &self.patterns,
+            SubscriptionKind::ShardChannel => &self.shard_channels,
+        };
+        names.iter().cloned().collect()
+    }
+
+    /// Number of channels (patterns, shard channels) the connection is subscribed to
+    pub fn len_of(&self, kind: SubscriptionKind) -> usize {
+        match kind {
+            SubscriptionKind::Channel => self.channels.len(),
+            SubscriptionKind::Pattern => self.patterns.len(),
+            SubscriptionKind::ShardChannel => self.shard_channels.len(),
+        }
+    }
+
+    /// Number of subscriptions confirmations of the kind report: shard channels are counted on
+    /// their own, channels and patterns together
+    pub fn count(&self, kind: SubscriptionKind) -> usize {
+        match kind {
+            SubscriptionKind::ShardChannel => self.shard_channels.len(),
+            _ => self.channels.len() + self.patterns.len(),
+        }
+    }
+
+    /// Whether the connection is in subscriber mode, i.e. subscribed to anything
+    pub fn is_subscribed(&self) -> bool {
+        !(self.channels.is_empty() && self.patterns.is_empty() && self.shard_channels.is_empty())
+    }
+
+    /// Adds the subscriptions, returns the confirmations for the client
+    pub fn subscribe(&mut self, kind: SubscriptionKind, names: &[Bytes]) -> Vec<RedisType> {
+        names
+            .iter()
+            .map(|name| {
+                self.names_mut(kind).insert(name.clone());
+                subscription_reply(kind.subscribe_reply(), Some(name.clone()), self.count(kind))
+            })
+            .collect()
+    }
+
+    /// Removes the subscriptions, returns the confirmations for the client. Unsubscribing from
+    /// nothing is confirmed with a null name.
+    pub fn unsubscribe(&mut self, kind: SubscriptionKind, names: &[Bytes]) -> Vec<RedisType> {
+        if names.is_empty() {
+            return vec![subscription_reply(
+                kind.unsubscribe_reply(),
+                None,
+                self.count(kind),
+            )];
+        }
+        names
+            .iter()
+            .map(|name| {
+                self.names_mut(kind).remove(name);
+                subscription_reply(
+                    kind.unsubscribe_reply(),
+                    Some(name.clone()),
+                    self.count(kind),
+                )
+            })
+            .collect()
+    }
+}
+
+/// The channels or patterns a (un)subscribe command names
+fn names(arguments: &[RedisType]) -> Result<Vec<Bytes>, CommandError> {
+    (0..arguments.len())
+        .map(|index| argument_as_bytes(arguments, index).cloned())
+        .collect()
+}
+
+/// Confirms each subscription through the outbox of the client before it is registered, so no
+/// published message overtakes its confirmation
+pub fn handle_subscribe(
+    arguments: &[RedisType],
+    store: &mut Store,
+    client_id: u64,
+    kind: SubscriptionKind,
+) -> Result<CommandResponse, CommandError> {
+    if arguments.is_empty() {
+        return Err(CommandError::InvalidInput(format!(
+            "wrong number of arguments for '{}' command",
+            kind.subscribe_reply()
+        )));
+    }
+    let names = names(arguments)?;
+    let client = store.client_mut(client_id);
+    for confirmation in client.subscriptions.subscribe(kind, &names) {
+        client.push_reply(confirmation);
+    }
+    let outbox = client.outbox.clone();
+    for name in names {
+        store.subscribe(kind, name, client_id, outbox.clone());
+    }
+    Ok(CommandResponse::Pushed)
+}
+
+/// Without names the client leaves all of its channels (or patterns)
+pub fn handle_unsubscribe(
+    arguments: &[RedisType],
+    store: &mut Store,
+    client_id: u64,
+    kind: SubscriptionKind,
+) -> Result<CommandResponse, CommandError> {
+    let mut names = names(arguments)?;
+    if names.is_empty() {
+        names = store.client(client_id).subscriptions.names(kind);
+    }
+    for name in &names {
+        store.unsubscribe(kind, name, client_id);
+    }
+    let client = store.client_mut(client_id);
+    for confirmation in client.subscriptions.unsubscribe(kind, &names) {
+        client.push_reply(confirmation);
+    }
+    Ok(CommandResponse::Pushed)
+}
+
+pub fn handle_publish(
+    arguments: &[RedisType],
+    store: &mut Store,
+) -> Result<RedisType, CommandError> {
+    if arguments.len() != 2 {
+        return Err(CommandError::InvalidInput(
+            "wrong number of arguments for 'publish' command".into(),
+        ));
+    }
+    let channel = extract_key(arguments)?;
+    let message = argument_as_bytes(arguments, 1)?;
+    Ok(RedisType::Integer(store.publish(channel, message) as i128))
+}
+
+pub fn handle_spublish(
+    arguments: &[RedisType],
+    store: &mut Store,
+) -> Result<RedisType, CommandError> {
+    if arguments.len() != 2 {
+        return Err(CommandError::InvalidInput(
+            "wrong number of arguments for 'spublish' command".into(),
+        ));
+    }
+    let channel = extract_key(arguments)?;
+    let message = argument_as_bytes(arguments, 1)?;
+    Ok(RedisType::Integer(store.spublish(channel, message) as i128))
+}
+
+pub fn handle_pubsub(arguments: &[RedisType], store: &Store) -> Result<RedisType, CommandError> {
+    let subcommand = argument_as_str(arguments, 0)?.to_ascii_uppercase();
+    let arguments = &arguments[1..];
+
+    match subcommand.as_str() {
+        "CHANNELS" => channels_reply(arguments, store, SubscriptionKind::Channel),
+        "SHARDCHANNELS" => channels_reply(arguments, store, SubscriptionKind::ShardChannel),
+        "NUMSUB" => numsub_reply(arguments, store, SubscriptionKind::Channel),
+        "SHARDNUMSUB" => numsub_reply(arguments, store, SubscriptionKind::ShardChannel),
+        "NUMPAT" => Ok(RedisType::Integer(store.pattern_count() as i128)),
+        _ => Err(CommandError::UnknownCommand(format!(
+            "unknown subcommand '{}' for 'pubsub' command",
+            subcommand
+        ))),
+    }
+}
+
+/// The active channels (or shard channels), optionally only those matching a pattern
+fn channels_reply(
+    arguments: &[RedisType],
+    store: &Store,
+    kind: SubscriptionKind,
+) -> Result<RedisType, CommandError> {
+    let pattern = arguments
+        .first()
+        .map(|_| extract_key(arguments))
+        .transpose()?;
+    let channels = store.active_channels(kind, pattern);
+    Ok(RedisType::Array(Some(
+        channels.into_iter().map(RedisType::BulkString).collect(),
+    )))
+}
+
+/// Each of the channels (or shard channels) followed by its number of subscribers
+fn numsub_reply(
+    arguments: &[RedisType],
+    store: &Store,
+    kind: SubscriptionKind,
+) -> Result<RedisType, CommandError> {
+    let mut reply = Vec::new();
+    for channel in names(arguments)? {
+        let count = store.channel_subscribers(kind, &channel);
+        reply.push(RedisType::BulkString(channel));
+        reply.push(RedisType::Integer(count as i128));
+    }
+    Ok(RedisType::Array(Some(reply)))
+}
+
+/// Confirms a (un)subscription to the client, along with the number of subscriptions it has
+/// afterwards
+fn subscription_reply(kind: &'static str, name: Option<Bytes>, count: usize) -> RedisType {
+    RedisType::Push(vec![
+        RedisType::BulkString(Bytes::from_static(kind.as_bytes())),
+        name.map_or(RedisType::NullBulkString, RedisType::BulkString),
+        RedisType::Integer(count as i128),
+    ])
+}
+
+#[test]
+fn test_subscriptions_count_channels_and_patterns() {
+    let mut subscriptions = Subscriptions::default();
+    let news = Bytes::from("news");
+    let pattern = Bytes::from("news.*");
+
+    subscriptions.subscribe(SubscriptionKind::Channel, &[news.clone(), news.clone()]);
+    let confirmations =
+        subscriptions.subscribe(SubscriptionKind::Pattern, std::slice::from_ref(&pattern));
+    assert_eq!(
+        confirmations,
+        vec![subscription_reply("psubscribe", Some(pattern.clone()), 2)]
+    );
+
+    let confirmations =
+        subscriptions.unsubscribe(SubscriptionKind::Channel, std::slice::from_ref(&news));
+    assert_eq!(
+        confirmations,
+        vec![subscription_reply("unsubscribe", Some(news), 1)]
+    );
+    assert_eq!(
+        subscriptions.names(SubscriptionKind::Pattern),
+        vec![pattern]
+    );
+    assert_eq!(
+        subscriptions.unsubscribe(SubscriptionKind::Channel, &[]),
+        vec![subscription_reply("unsubscribe", None, 1)]
+    );
+}
+
+#[test]
+fn test_resp3_subscribers_get_pushes_and_may_run_any_command() {
+    use crate::{
+        commands::{handle_command, utils::test_command},
+        store::{ProtocolVersion, test_client},
+    };
+
+    let mut store = Store::new();
+    let mut resp2_inbox = test_client(&mut store, 1);
+    let mut resp3_inbox = test_client(&mut store, 2);
+    store.client_mut(2).protocol = ProtocolVersion::new(3);
+    for client_id in [1, 2] {
+        assert!(matches!(
+            handle_command(test_command(&["SUBSCRIBE", "news"]), &mut store, client_id),
+            Ok(CommandResponse::Pushed)
+        ));
+    }
+    let confirmation = subscription_reply("subscribe", Some(Bytes::from("news")), 1);
+    assert_eq!(resp2_inbox.try_recv().unwrap(), confirmation);
+    assert_eq!(resp3_inbox.try_recv().unwrap(), confirmation);
+
+    // only RESP2 subscribers are limited to the subscriber commands
+    assert!(handle_command(test_command(&["GET", "a"]), &mut store, 1).is_err());
+    assert!(matches!(
+        handle_command(test_command(&["GET", "a"]), &mut store, 2),
+        Ok(CommandResponse::Immediate(RedisType::NullBulkString))
+    ));
+
+    assert_eq!(
+        store.publish(&Bytes::from("news"), &Bytes::from("hello")),
+        2
+    );
+    let message = resp3_inbox.try_recv().unwrap();
+    assert_eq!(
+        message,
+        RedisType::Push(vec![
+            RedisType::BulkString(Bytes::from("message")),
+            RedisType::BulkString(Bytes::from("news")),
+            RedisType::BulkString(Bytes::from("hello")),
+        ])
+    );
+    assert_eq!(resp2_inbox.try_recv().unwrap(), message);
+}
+
+#[test]
+fn test_muted_clients_get_published_messages_but_no_confirmations() {
+    use crate::{
+        commands::{handle_command, utils::test_command},
+        store::test_client,
+    };
+
+    let mut store = Store::new();
+    let mut inboxes = [test_client(&mut store, 1), test_client(&mut store, 2)];
+    for (client_id, mode) in [(1, "OFF"), (2, "SKIP")] {
+        handle_command(
+            test_command(&["CLIENT", "REPLY", mode]),
+            &mut store,
+            client_id,
+        )
+        .unwrap();
+        handle_command(test_command(&["SUBSCRIBE", "news"]), &mut store, client_id).unwrap();
+    }
+    assert!(inboxes.iter_mut().all(|inbox| inbox.try_recv().is_err()));
+
+    assert_eq!(
+        store.publish(&Bytes::from("news"), &Bytes::from("hello")),
+        2
+    );
+    let message = RedisType::Push(vec![
+        RedisType::BulkString(Bytes::from("message")),
+        RedisType::BulkString(Bytes::from("news")),
+        RedisType::BulkString(Bytes::from("hello")),
+    ]);
+    for inbox in &mut inboxes {
+        assert_eq!(inbox.try_recv().unwrap(), message);
+    }
+
+    // SKIP only muted the SUBSCRIBE
+    for client_id in [1, 2] {
+        handle_command(test_command(&["UNSUBSCRIBE"]), &mut store, client_id).unwrap();
+    }
+    assert!(inboxes[0].try_recv().is_err());
+    assert_eq!(
+        inboxes[1].try_recv().unwrap(),
+        subscription_reply("unsubscribe", Some(Bytes::from("news")), 0)
+    );
+}
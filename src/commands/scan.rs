@@ -0,0 +1,73 @@
+use bytes::Bytes;
+
+use super::{
+    CommandError,
+    utils::{OptionParser, argument_as_number, glob_match},
+};
+use crate::parser::RedisType;
+
+const DEFAULT_COUNT: usize = 10;
+
+/// Options shared by the SCAN family of commands
+pub struct ScanOptions {
+    pub cursor: u64,
+    pub count: usize,
+    pub pattern: Option<Bytes>,
+    pub key_type: Option<String>,
+    pub novalues: bool,
+}
+
+impl ScanOptions {
+    /// Parses `cursor [MATCH pattern] [COUNT count]` followed by the command specific flags.
+    /// `TYPE` is only accepted by SCAN, `NOVALUES` only by HSCAN.
+    pub fn parse(
+        arguments: &[RedisType],
+        allow_type: bool,
+        allow_novalues: bool,
+    ) -> Result<Self, CommandError> {
+        let cursor = argument_as_number(arguments, 0)
+            .map_err(|_| CommandError::InvalidInput("invalid cursor".into()))?;
+        let mut options = ScanOptions {
+            cursor,
+            count: DEFAULT_COUNT,
+            pattern: None,
+            key_type: None,
+            novalues: false,
+        };
+
+        let mut parser = OptionParser::new(&arguments[1..]);
+        while let Some(option) = parser.next_option()? {
+            match option.as_str() {
+                "MATCH" => options.pattern = Some(parser.value()?.clone()),
+                "COUNT" => {
+                    options.count = parser.value_as_number()?;
+                    if options.count == 0 {
+                        return Err(CommandError::InvalidInput("syntax error".into()));
+                    }
+                }
+                "TYPE" if allow_type => {
+                    options.key_type = Some(parser.value_as_str()?.to_ascii_lowercase());
+                }
+                "NOVALUES" if allow_novalues => options.novalues = true,
+                _ => return Err(CommandError::InvalidInput("syntax error".into())),
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn matches(&self, element: &[u8]) -> bool {
+        self.pattern
+            .as_ref()
+            .is_none_or(|pattern| glob_match(pattern, element))
+    }
+}
+
+/// Builds the `[cursor, [elements...]]` reply of the SCAN family
+pub fn scan_reply(cursor: u64, elements: Vec<Bytes>) -> RedisType {
+    RedisType::Array(Some(vec![
+        RedisType::BulkString(Bytes::from(cursor.to_string())),
+        RedisType::Array(Some(
+            elements.into_iter().map(RedisType::BulkString).collect(),
+        )),
+    ]))
+}
@@ -0,0 +1,277 @@
+use std::collections::HashSet;
+
+use bytes::Bytes;
+
+use super::{
+    CommandError,
+    scan::{ScanOptions, scan_reply},
+    utils::{
+        argument_as_bytes, argument_as_number, extract_key, redis_type_as_bytes, reply_from_store,
+    },
+};
+use crate::{
+    parser::RedisType,
+    store::{Store, StoreError},
+};
+
+pub fn handle_sadd(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let members = extract_members(&arguments[1..])?;
+
+    reply_from_store(store.sadd(key, members), |added| {
+        RedisType::Integer(added as i128)
+    })
+}
+
+pub fn handle_srem(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let members = extract_members(&arguments[1..])?;
+
+    reply_from_store(store.srem(key, &members), |removed| {
+        RedisType::Integer(removed as i128)
+    })
+}
+
+pub fn handle_smembers(arguments: &[RedisType], store: &Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+
+    reply_from_store(store.smembers(key), |members| {
+        RedisType::Set(members.into_iter().map(RedisType::BulkString).collect())
+    })
+}
+
+pub fn handle_scard(arguments: &[RedisType], store: &Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+
+    reply_from_store(store.scard(key), |len| RedisType::Integer(len as i128))
+}
+
+pub fn handle_sscan(arguments: &[RedisType], store: &Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let options = ScanOptions::parse(&arguments[1..], false, false)?;
+
+    reply_from_store(
+        store.sscan(key, options.cursor, options.count),
+        |(cursor, members)| {
+            let members = members
+                .into_iter()
+                .filter(|member| options.matches(member))
+                .collect();
+            scan_reply(cursor, members)
+        },
+    )
+}
+
+pub fn handle_sismember(arguments: &[RedisType], store: &Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let member = argument_as_bytes(arguments, 1)?.clone();
+
+    reply_from_store(store.smismember(key, &[member]), |found| {
+        RedisType::Integer(found[0] as i128)
+    })
+}
+
+pub fn handle_smismember(
+    arguments: &[RedisType],
+    store: &Store,
+) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let members = extract_members(&arguments[1..])?;
+
+    reply_from_store(store.smismember(key, &members), |found| {
+        RedisType::Array(Some(
+            found
+                .into_iter()
+                .map(|found| RedisType::Integer(found as i128))
+                .collect(),
+        ))
+    })
+}
+
+pub fn handle_smove(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let source = extract_key(arguments)?;
+    let destination = argument_as_bytes(arguments, 1)?;
+    let member = argument_as_bytes(arguments, 2)?;
+
+    reply_from_store(store.smove(source, destination, member), |moved| {
+        RedisType::Integer(moved as i128)
+    })
+}
+
+pub fn handle_spop(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+
+    if arguments.len() == 1 {
+        return reply_from_store(store.spop(key, 1), single_member);
+    }
+    let count: usize = argument_as_number(arguments, 1).map_err(|_| {
+        CommandError::InvalidInput("value is out of range, must be positive".into())
+    })?;
+    reply_from_store(store.spop(key, count), bulk_string_array)
+}
+
+pub fn handle_srandmember(
+    arguments: &[RedisType],
+    store: &Store,
+) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+
+    if arguments.len() == 1 {
+        return reply_from_store(store.srandmember(key, 1), single_member);
+    }
+    let count: i128 = argument_as_number(arguments, 1)?;
+    reply_from_store(store.srandmember(key, count), bulk_string_array)
+}
+
+/// Reply for the count-less forms of SPOP and SRANDMEMBER
+fn single_member(members: Vec<Bytes>) -> RedisType {
+    members
+        .into_iter()
+        .next()
+        .map(RedisType::BulkString)
+        .unwrap_or(RedisType::NullBulkString)
+}
+
+/// Extracts at least one member from the arguments
+fn extract_members(arguments: &[RedisType]) -> Result<Vec<Bytes>, CommandError> {
+    if arguments.is_empty() {
+        return Err(CommandError::InvalidInput(
+            "Invalid input: at least one member is required".into(),
+        ));
+    }
+    arguments
+        .iter()
+        .map(|member| redis_type_as_bytes(member).cloned())
+        .collect()
+}
+
+fn bulk_string_array(values: Vec<Bytes>) -> RedisType {
+    RedisType::Array(Some(
+        values.into_iter().map(RedisType::BulkString).collect(),
+    ))
+}
+
+/// SINTER, SUNION and SDIFF
+pub fn handle_set_algebra(
+    arguments: &[RedisType],
+    store: &Store,
+    operation: SetOperation,
+) -> Result<RedisType, CommandError> {
+    let keys = extract_members(arguments)?;
+
+    reply_from_store(operation.apply(store, &keys), |members| {
+        bulk_string_array(members.into_iter().collect())
+    })
+}
+
+pub fn handle_sintercard(
+    arguments: &[RedisType],
+    store: &Store,
+) -> Result<RedisType, CommandError> {
+    let numkeys: usize = argument_as_number(arguments, 0)?;
+    if numkeys == 0 {
+        return Err(CommandError::InvalidInput(
+            "numkeys should be greater than 0".into(),
+        ));
+    }
+    let Some(keys) = arguments.get(1..=numkeys) else {
+        return Err(CommandError::InvalidInput(
+            "Number of keys can't be greater than number of args".into(),
+        ));
+    };
+    let keys = extract_members(keys)?;
+
+    let limit = match &arguments[numkeys + 1..] {
+        [] => 0,
+        [option, _] if redis_type_as_bytes(option)?.eq_ignore_ascii_case(b"LIMIT") => {
+            argument_as_number(arguments, numkeys + 2)
+                .map_err(|_| CommandError::InvalidInput("LIMIT can't be negative".into()))?
+        }
+        _ => return Err(CommandError::InvalidInput("syntax error".into())),
+    };
+
+    reply_from_store(store.sintercard(&keys, limit), |cardinality| {
+        RedisType::Integer(cardinality as i128)
+    })
+}
+
+/// SINTERSTORE, SUNIONSTORE and SDIFFSTORE
+pub fn handle_set_algebra_store(
+    arguments: &[RedisType],
+    store: &mut Store,
+    operation: SetOperation,
+) -> Result<RedisType, CommandError> {
+    let destination = extract_key(arguments)?;
+    let keys = extract_members(&arguments[1..])?;
+
+    let members = match operation.apply(store, &keys) {
+        Ok(members) => members,
+        result => return reply_from_store(result, |_| RedisType::NullBulkString),
+    };
+    let len = store.sstore(destination, members);
+    Ok(RedisType::Integer(len as i128))
+}
+
+#[derive(Clone, Copy)]
+pub enum SetOperation {
+    Intersection,
+    Union,
+    Difference,
+}
+
+impl SetOperation {
+    fn apply(self, store: &Store, keys: &[Bytes]) -> Result<HashSet<Bytes>, StoreError> {
+        match self {
+            SetOperation::Intersection => store.sinter(keys),
+            SetOperation::Union => store.sunion(keys),
+            SetOperation::Difference => store.sdiff(keys),
+        }
+    }
+}
+
+#[test]
+fn test_sintercard_stops_at_the_limit() {
+    use crate::commands::utils::test_arguments;
+
+    let mut store = Store::new();
+    store
+        .sadd(&"a".into(), vec!["1".into(), "2".into(), "3".into()])
+        .unwrap();
+    store
+        .sadd(
+            &"b".into(),
+            vec!["1".into(), "2".into(), "3".into(), "4".into()],
+        )
+        .unwrap();
+    let sintercard = |arguments: &[&str]| handle_sintercard(&test_arguments(arguments), &store);
+
+    assert_eq!(sintercard(&["2", "a", "b"]).unwrap(), RedisType::Integer(3));
+    // a limit of 0 means no limit
+    assert_eq!(
+        sintercard(&["2", "a", "b", "LIMIT", "0"]).unwrap(),
+        RedisType::Integer(3)
+    );
+    assert_eq!(
+        sintercard(&["2", "a", "b", "limit", "2"]).unwrap(),
+        RedisType::Integer(2)
+    );
+    assert_eq!(
+        sintercard(&["2", "a", "missing"]).unwrap(),
+        RedisType::Integer(0)
+    );
+
+    for (arguments, error) in [
+        (&["0", "a"][..], "ERR numkeys should be greater than 0"),
+        (
+            &["3", "a", "b"],
+            "ERR Number of keys can't be greater than number of args",
+        ),
+        (
+            &["2", "a", "b", "LIMIT", "-1"],
+            "ERR LIMIT can't be negative",
+        ),
+        (&["1", "a", "LIMIT"], "ERR syntax error"),
+    ] {
+        assert_eq!(sintercard(arguments).unwrap_err().to_string(), error);
+    }
+}
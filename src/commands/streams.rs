@@ -1,23 +1,62 @@
+use std::ops::Bound::{self, Excluded, Included, Unbounded};
+
 use bytes::Bytes;
 use tokio::sync::oneshot;
 
 use super::{
     CommandError, CommandResponse,
-    utils::{argument_as_number, argument_as_str, extract_key, redis_type_as_bytes},
+    utils::{
+        OptionParser, argument_as_bytes, argument_as_number, argument_as_str, extract_key,
+        redis_type_as_bytes, reply_from_store, wrong_type_error,
+    },
 };
 use crate::{
     commands::utils::xread_output_to_redis_type,
     parser::RedisType,
-    store::{Store, StoreError, StreamId},
+    store::{
+        ClaimOptions, Store, StoreError, StreamEntry, StreamId, StreamReadPosition, StreamTrim,
+        TrimThreshold, now_millis,
+    },
 };
 
+/// XADD key [NOMKSTREAM] [MAXLEN|MINID [=|~] threshold [LIMIT count]] id|* field value
+/// [field value ...]
 pub fn handle_xadd(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
     let key = extract_key(arguments)?;
 
-    let (ms, seq) = extract_stream_id_values(&arguments[1])?;
+    let mut options = OptionParser::new(&arguments[1..]);
+    let mut trim = None;
+    let mut make_stream = true;
+    loop {
+        if options.eat("NOMKSTREAM") {
+            make_stream = false;
+        } else if matches!(options.peek().as_deref(), Some("MAXLEN" | "MINID")) {
+            trim = Some(parse_stream_trim(&mut options)?);
+        } else {
+            break;
+        }
+    }
+    // an id followed by field value pairs
+    let entry = options.rest();
+    if entry.len() < 3 || !(entry.len() - 1).is_multiple_of(2) {
+        return Err(CommandError::InvalidInput(
+            "wrong number of arguments for 'xadd' command".into(),
+        ));
+    }
+
+    let (ms, seq) = extract_stream_id_values(&entry[0])?;
+    if !make_stream && store.get_type(key).is_err() {
+        return Ok(RedisType::NullBulkString);
+    }
 
-    match store.xadd(key, seq, ms, &arguments[2..]) {
-        Ok(id) => Ok(id.into()),
+    match store.xadd(key, seq, ms, &entry[1..]) {
+        Ok(id) => {
+            if let Some(trim) = trim {
+                store.xtrim(key, trim).map_err(CommandError::StoreError)?;
+            }
+            Ok(id.into())
+        }
+        Err(StoreError::WrongType) => Ok(wrong_type_error()),
         Err(StoreError::StreamIdSmallerThanLast) => Ok(RedisType::SimpleError(
             "ERR The ID specified in XADD is equal or smaller than the target stream top item"
                 .into(),
@@ -25,155 +64,954 @@ pub fn handle_xadd(arguments: &[RedisType], store: &mut Store) -> Result<RedisTy
         Err(StoreError::StreamIdNotGreaterThan0) => Ok(RedisType::SimpleError(
             "ERR The ID specified in XADD must be greater than 0-0".into(),
         )),
-        Err(other) => Err(CommandError::InvalidInput(format!(
-            "Unable to add to stream: {:?}",
-            other
-        ))),
+        Err(other) => Err(CommandError::StoreError(other)),
+    }
+}
+
+/// XTRIM key MAXLEN|MINID [=|~] threshold [LIMIT count]
+pub fn handle_xtrim(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let mut options = OptionParser::new(&arguments[1..]);
+    let trim = parse_stream_trim(&mut options)?;
+    if !options.is_done() {
+        return Err(CommandError::InvalidInput("syntax error".into()));
+    }
+
+    reply_from_store(store.xtrim(key, trim), |removed| {
+        RedisType::Integer(removed as i128)
+    })
+}
+
+/// XSETID key last-id [ENTRIESADDED entries-added] [MAXDELETEDID max-deleted-id]
+pub fn handle_xsetid(
+    arguments: &[RedisType],
+    store: &mut Store,
+) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let last_id = parse_stream_id(arguments.get(1).ok_or_else(|| {
+        CommandError::InvalidInput("wrong number of arguments for 'xsetid' command".into())
+    })?)?;
+
+    let mut entries_added = None;
+    let mut max_deleted_id = None;
+    let mut index = 2;
+    while index < arguments.len() {
+        let option = argument_as_str(arguments, index)?.to_ascii_uppercase();
+        let value = arguments
+            .get(index + 1)
+            .ok_or_else(|| CommandError::InvalidInput("syntax error".into()))?;
+        match option.as_str() {
+            "ENTRIESADDED" => match argument_as_number::<i64>(arguments, index + 1)? {
+                count if count < 0 => {
+                    return Err(CommandError::InvalidInput(
+                        "entries_added must be positive".into(),
+                    ));
+                }
+                count => entries_added = Some(count as u64),
+            },
+            "MAXDELETEDID" => {
+                let id = parse_stream_id(value)?;
+                if last_id < id {
+                    return Err(CommandError::InvalidInput(
+                        "The ID specified in XSETID is smaller than the provided max_deleted_entry_id"
+                            .into(),
+                    ));
+                }
+                max_deleted_id = Some(id);
+            }
+            _ => return Err(CommandError::InvalidInput("syntax error".into())),
+        }
+        index += 2;
+    }
+
+    match store.xsetid(key, last_id, entries_added, max_deleted_id) {
+        Ok(()) => Ok(RedisType::SimpleString("OK".into())),
+        Err(StoreError::WrongType) => Ok(wrong_type_error()),
+        Err(StoreError::KeyNotFound) => Ok(RedisType::SimpleError("ERR no such key".into())),
+        Err(StoreError::StreamIdSmallerThanLast) => Ok(RedisType::SimpleError(
+            "ERR The ID specified in XSETID is smaller than the target stream top item".into(),
+        )),
+        Err(StoreError::EntriesAddedSmallerThanLength) => Ok(RedisType::SimpleError(
+            "ERR The entries_added specified in XSETID is smaller than the target stream length"
+                .into(),
+        )),
+        Err(other) => Err(CommandError::StoreError(other)),
+    }
+}
+
+/// Parses `MAXLEN|MINID [=|~] threshold [LIMIT count]`
+fn parse_stream_trim(options: &mut OptionParser) -> Result<StreamTrim, CommandError> {
+    let strategy = options.next_option()?.unwrap_or_default();
+    let approximate = options.eat("~");
+    if !approximate {
+        options.eat("=");
+    }
+
+    let threshold = match strategy.as_str() {
+        "MAXLEN" => match options.value_as_number::<i64>()? {
+            max_len if max_len < 0 => {
+                return Err(CommandError::InvalidInput(
+                    "The MAXLEN argument must be >= 0.".into(),
+                ));
+            }
+            max_len => TrimThreshold::MaxLen(max_len as usize),
+        },
+        "MINID" => TrimThreshold::MinId(parse_stream_id(options.argument()?)?),
+        _ => return Err(CommandError::InvalidInput("syntax error".into())),
+    };
+
+    let mut limit = None;
+    if options.eat("LIMIT") {
+        if !approximate {
+            return Err(CommandError::InvalidInput(
+                "syntax error, LIMIT cannot be used without the special ~ option".into(),
+            ));
+        }
+        match options.value_as_number::<i64>()? {
+            count if count < 0 => {
+                return Err(CommandError::InvalidInput(
+                    "The LIMIT argument must be >= 0.".into(),
+                ));
+            }
+            count => limit = Some(count as usize),
+        }
+    }
+
+    Ok(StreamTrim {
+        threshold,
+        approximate,
+        limit,
+    })
+}
+
+/// XDEL key id [id ...]
+pub fn handle_xdel(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    if arguments.len() < 2 {
+        return Err(CommandError::InvalidInput(
+            "wrong number of arguments for 'xdel' command".into(),
+        ));
     }
+    let ids = arguments[1..]
+        .iter()
+        .map(parse_stream_id)
+        .collect::<Result<Vec<StreamId>, CommandError>>()?;
+
+    reply_from_store(store.xdel(key, &ids), |deleted| {
+        RedisType::Integer(deleted as i128)
+    })
 }
 
+/// XRANGE key start end [COUNT count]
 pub fn handle_xrange(
     arguments: &[RedisType],
     store: &mut Store,
 ) -> Result<RedisType, CommandError> {
+    let (stream_key, start, end, count) = parse_range(arguments, "xrange")?;
+
+    reply_from_store(store.xrange(stream_key, start, end, count), |entries| {
+        entries_reply(&entries)
+    })
+}
+
+/// XREVRANGE key end start [COUNT count]
+pub fn handle_xrevrange(
+    arguments: &[RedisType],
+    store: &mut Store,
+) -> Result<RedisType, CommandError> {
+    let (stream_key, end, start, count) = parse_range(arguments, "xrevrange")?;
+
+    reply_from_store(store.xrevrange(stream_key, start, end, count), |entries| {
+        entries_reply(&entries)
+    })
+}
+
+/// Key, first bound, last bound and count of XRANGE and XREVRANGE
+type RangeArguments<'a> = (&'a Bytes, Bound<StreamId>, Bound<StreamId>, Option<usize>);
+
+/// Parses `key first last [COUNT count]` of XRANGE and XREVRANGE, the first bound is the
+/// start for XRANGE and the end for XREVRANGE
+fn parse_range<'a>(
+    arguments: &'a [RedisType],
+    command: &str,
+) -> Result<RangeArguments<'a>, CommandError> {
     let stream_key = extract_key(arguments)?;
-    let (start_ms, start_sq) = extract_stream_id_values(&arguments[1])?;
-    let (end_ms, end_sq) = extract_stream_id_values(&arguments[2])?;
-
-    let start_stream_id = start_ms
-        .map(|start_ms| {
-            Some(StreamId {
-                ms: start_ms,
-                seq: start_sq.unwrap_or(0),
-            })
-        })
-        .unwrap_or(None);
+    if arguments.len() != 3 && arguments.len() != 5 {
+        return Err(CommandError::InvalidInput(format!(
+            "wrong number of arguments for '{}' command",
+            command
+        )));
+    }
+    let reverse = command == "xrevrange";
+    let first = parse_range_bound(&arguments[1], reverse)?;
+    let last = parse_range_bound(&arguments[2], !reverse)?;
+    let count = match arguments.get(3) {
+        None => None,
+        Some(_) if argument_as_str(arguments, 3)?.eq_ignore_ascii_case("COUNT") => {
+            // a negative count returns nothing, like 0
+            Some(argument_as_number::<i64>(arguments, 4)?.max(0) as usize)
+        }
+        Some(_) => return Err(CommandError::InvalidInput("syntax error".into())),
+    };
+    Ok((stream_key, first, last, count))
+}
+
+/// A bound of XRANGE or XREVRANGE: `-` for the smallest id, `+` for the largest one, or an id
+/// which `(` makes exclusive. An id without a sequence number covers its whole millisecond,
+/// so it starts at the first and ends at the last sequence number.
+fn parse_range_bound(argument: &RedisType, is_end: bool) -> Result<Bound<StreamId>, CommandError> {
+    let bound = redis_type_as_bytes(argument)?;
+    match bound.as_ref() {
+        b"-" => return Ok(Included(StreamId::default())),
+        b"+" => {
+            return Ok(Included(StreamId {
+                ms: u128::MAX,
+                seq: u128::MAX,
+            }));
+        }
+        _ => {}
+    }
+    let default_seq = if is_end { u128::MAX } else { 0 };
+    match bound.strip_prefix(b"(") {
+        Some(id) => Ok(Excluded(parse_id(id, default_seq)?)),
+        None => Ok(Included(parse_id(bound, default_seq)?)),
+    }
+}
+
+/// Entries as `[[id, [field, value, ...]], ...]`
+fn entries_reply(entries: &[(StreamId, StreamEntry)]) -> RedisType {
+    RedisType::Array(Some(entries.iter().map(entry_reply).collect()))
+}
+
+/// An entry as `[id, [field, value, ...]]`
+fn entry_reply((id, map): &(StreamId, StreamEntry)) -> RedisType {
+    RedisType::Array(Some(vec![
+        id.into(),
+        RedisType::Array(Some(
+            map.iter()
+                .flat_map(|(key, value)| [key.clone().into(), value.clone().into()])
+                .collect(),
+        )),
+    ]))
+}
+
+/// XINFO STREAM key [FULL [COUNT count]] | XINFO GROUPS key | XINFO CONSUMERS key group
+pub fn handle_xinfo(arguments: &[RedisType], store: &Store) -> Result<RedisType, CommandError> {
+    let subcommand = argument_as_str(arguments, 0)?.to_ascii_uppercase();
+    let key = extract_key(&arguments[1..])?;
+
+    let info = match store.xinfo_stream(key) {
+        Err(StoreError::KeyNotFound) => {
+            return Ok(RedisType::SimpleError("ERR no such key".into()));
+        }
+        Err(StoreError::WrongType) => return Ok(wrong_type_error()),
+        result => result.map_err(CommandError::StoreError)?,
+    };
+    match subcommand.as_str() {
+        "STREAM" => {
+            let full = match arguments.len() {
+                2 => None,
+                3 if argument_as_str(arguments, 2)?.eq_ignore_ascii_case("FULL") => Some(10),
+                5 if argument_as_str(arguments, 2)?.eq_ignore_ascii_case("FULL")
+                    && argument_as_str(arguments, 3)?.eq_ignore_ascii_case("COUNT") =>
+                {
+                    Some(argument_as_number::<i64>(arguments, 4)?)
+                }
+                _ => return Err(CommandError::InvalidInput("syntax error".into())),
+            };
+            let mut reply = vec![
+                ("length", RedisType::Integer(info.length as i128)),
+                ("last-generated-id", info.last_generated_id.into()),
+                ("max-deleted-entry-id", info.max_deleted_id.into()),
+                (
+                    "entries-added",
+                    RedisType::Integer(info.entries_added as i128),
+                ),
+                (
+                    "recorded-first-entry-id",
+                    info.first_entry
+                        .as_ref()
+                        .map(|(id, _)| *id)
+                        .unwrap_or_default()
+                        .into(),
+                ),
+            ];
+            match full {
+                Some(count) => {
+                    // a count of 0 (or less) lists all entries
+                    let count = (count > 0).then_some(count as usize);
+                    let entries = store
+                        .xrange(key, Unbounded, Unbounded, count)
+                        .map_err(CommandError::StoreError)?;
+                    reply.extend([
+                        ("entries", entries_reply(&entries)),
+                        (
+                            "groups",
+                            RedisType::Array(Some(full_groups_reply(key, store)?)),
+                        ),
+                    ]);
+                }
+                None => {
+                    let entry = |entry: Option<(StreamId, StreamEntry)>| match entry {
+                        Some(entry) => entry_reply(&entry),
+                        None => RedisType::Null,
+                    };
+                    reply.extend([
+                        ("groups", RedisType::Integer(info.groups as i128)),
+                        ("first-entry", entry(info.first_entry)),
+                        ("last-entry", entry(info.last_entry)),
+                    ]);
+                }
+            }
+            Ok(info_reply(reply))
+        }
+        "GROUPS" => {
+            let groups = store.xinfo_groups(key).map_err(CommandError::StoreError)?;
+            Ok(RedisType::Array(Some(
+                groups
+                    .iter()
+                    .map(|group| {
+                        info_reply(vec![
+                            ("name", RedisType::BulkString(group.name.clone())),
+                            ("consumers", RedisType::Integer(group.consumers as i128)),
+                            ("pending", RedisType::Integer(group.pending as i128)),
+                            ("last-delivered-id", group.last_delivered_id.into()),
+                            ("entries-read", optional_integer(group.entries_read)),
+                            ("lag", optional_integer(group.lag)),
+                        ])
+                    })
+                    .collect(),
+            )))
+        }
+        "CONSUMERS" => {
+            let group = argument_as_bytes(arguments, 2)?;
+            let consumers = match store.xinfo_consumers(key, group) {
+                Err(StoreError::NoGroup) => return Ok(no_group_error(key, group)),
+                result => result.map_err(CommandError::StoreError)?,
+            };
+            let now = now_millis().map_err(CommandError::StoreError)?;
+            Ok(RedisType::Array(Some(
+                consumers
+                    .iter()
+                    .map(|consumer| {
+                        let inactive = consumer
+                            .active_time
+                            .map(|active_time| now.saturating_sub(active_time) as i128)
+                            .unwrap_or(-1);
+                        let idle = now.saturating_sub(consumer.seen_time) as i128;
+                        info_reply(vec![
+                            ("name", RedisType::BulkString(consumer.name.clone())),
+                            ("pending", RedisType::Integer(consumer.pending as i128)),
+                            ("idle", RedisType::Integer(idle)),
+                            ("inactive", RedisType::Integer(inactive)),
+                        ])
+                    })
+                    .collect(),
+            )))
+        }
+        _ => Err(CommandError::UnknownCommand(format!(
+            "unknown subcommand '{}' for 'xinfo' command",
+            subcommand
+        ))),
+    }
+}
 
-    let end_stream_id = end_ms
-        .map(|end_ms| {
-            Some(StreamId {
-                ms: end_ms,
-                seq: end_sq.unwrap_or(0),
+/// The fields of an XINFO reply in their order, a map for RESP3 clients
+fn info_reply(fields: Vec<(&'static str, RedisType)>) -> RedisType {
+    RedisType::Map(
+        fields
+            .into_iter()
+            .map(|(name, value)| {
+                (
+                    RedisType::BulkString(Bytes::from_static(name.as_bytes())),
+                    value,
+                )
             })
-        })
-        .unwrap_or(None);
+            .collect(),
+    )
+}
 
-    let result: Vec<RedisType> = store
-        .xrange(stream_key, start_stream_id, end_stream_id)
+/// The groups of XINFO STREAM FULL, with their consumers but without the pending entries
+fn full_groups_reply(key: &Bytes, store: &Store) -> Result<Vec<RedisType>, CommandError> {
+    let groups = store.xinfo_groups(key).map_err(CommandError::StoreError)?;
+    groups
         .iter()
-        .map(|(id, map)| {
-            RedisType::Array(Some(vec![
-                id.into(),
-                RedisType::Array(Some(
-                    map.iter()
-                        .flat_map(|(key, value)| [key.clone().into(), value.clone().into()])
-                        .collect(),
-                )),
+        .map(|group| {
+            let consumers = store
+                .xinfo_consumers(key, &group.name)
+                .map_err(CommandError::StoreError)?;
+            let consumers = consumers
+                .iter()
+                .map(|consumer| {
+                    let active_time = consumer.active_time.map_or(-1, |time| time as i128);
+                    info_reply(vec![
+                        ("name", RedisType::BulkString(consumer.name.clone())),
+                        ("seen-time", RedisType::Integer(consumer.seen_time as i128)),
+                        ("active-time", RedisType::Integer(active_time)),
+                        ("pel-count", RedisType::Integer(consumer.pending as i128)),
+                    ])
+                })
+                .collect();
+            Ok(info_reply(vec![
+                ("name", RedisType::BulkString(group.name.clone())),
+                ("last-delivered-id", group.last_delivered_id.into()),
+                ("entries-read", optional_integer(group.entries_read)),
+                ("lag", optional_integer(group.lag)),
+                ("pel-count", RedisType::Integer(group.pending as i128)),
+                ("consumers", RedisType::Array(Some(consumers))),
             ]))
         })
-        .collect();
-    Ok(RedisType::Array(Some(result)))
+        .collect()
 }
 
-fn handle_xread_immediate(
-    keys_and_ids: &[RedisType],
+fn optional_integer(value: Option<u64>) -> RedisType {
+    match value {
+        Some(value) => RedisType::Integer(value as i128),
+        None => RedisType::Null,
+    }
+}
+
+fn no_group_error(key: &Bytes, group: &Bytes) -> RedisType {
+    RedisType::SimpleError(
+        format!(
+            "NOGROUP No such consumer group '{}' for key name '{}'",
+            String::from_utf8_lossy(group),
+            String::from_utf8_lossy(key)
+        )
+        .into(),
+    )
+}
+
+fn no_key_or_group_error(key: &Bytes, group: &Bytes) -> RedisType {
+    RedisType::SimpleError(
+        format!(
+            "NOGROUP No such key '{}' or consumer group '{}'",
+            String::from_utf8_lossy(key),
+            String::from_utf8_lossy(group)
+        )
+        .into(),
+    )
+}
+
+/// XGROUP CREATE key group id|$ [MKSTREAM] [ENTRIESREAD entries-read]
+/// | XGROUP SETID key group id|$ [ENTRIESREAD entries-read] | XGROUP DESTROY key group
+/// | XGROUP CREATECONSUMER key group consumer | XGROUP DELCONSUMER key group consumer
+pub fn handle_xgroup(
+    arguments: &[RedisType],
     store: &mut Store,
 ) -> Result<RedisType, CommandError> {
-    let (stream_keys, stream_ids) = keys_and_ids.split_at(keys_and_ids.len() / 2);
+    let subcommand = argument_as_str(arguments, 0)?.to_ascii_uppercase();
+    let key = extract_key(&arguments[1..])?;
+    let group = argument_as_bytes(arguments, 2)?;
 
-    let keys: Vec<&Bytes> = stream_keys
-        .iter()
-        .map(redis_type_as_bytes) // -> Result<&Bytes, CommandError>
-        .collect::<Result<Vec<_>, _>>()?;
+    let result = match subcommand.as_str() {
+        "CREATE" | "SETID" => {
+            let id = match argument_as_bytes(arguments, 3)?.as_ref() {
+                b"$" => store
+                    .last_stream_id(key)
+                    .map_err(CommandError::StoreError)?,
+                _ => parse_stream_id(&arguments[3])?,
+            };
+            let mut make_stream = false;
+            let mut entries_read = None;
+            let mut index = 4;
+            while index < arguments.len() {
+                let option = argument_as_str(arguments, index)?.to_ascii_uppercase();
+                match option.as_str() {
+                    "MKSTREAM" if subcommand == "CREATE" => {
+                        make_stream = true;
+                        index += 1;
+                    }
+                    "ENTRIESREAD" => {
+                        // -1 leaves the number of read entries unknown, it is derived if possible
+                        match argument_as_number::<i64>(arguments, index + 1)? {
+                            -1 => {}
+                            read if read < 0 => {
+                                return Err(CommandError::InvalidInput(
+                                    "value for ENTRIESREAD must be positive or -1".into(),
+                                ));
+                            }
+                            read => entries_read = Some(read as u64),
+                        }
+                        index += 2;
+                    }
+                    _ => return Err(CommandError::InvalidInput("syntax error".into())),
+                }
+            }
+            if subcommand == "CREATE" {
+                store.xgroup_create(key, group, id, entries_read, make_stream)
+            } else {
+                store.xgroup_setid(key, group, id, entries_read)
+            }
+            .map(|()| RedisType::SimpleString("OK".into()))
+        }
+        "DESTROY" => store
+            .xgroup_destroy(key, group)
+            .map(|destroyed| RedisType::Integer(destroyed as i128)),
+        "CREATECONSUMER" => store
+            .xgroup_createconsumer(key, group, argument_as_bytes(arguments, 3)?)
+            .map(|created| RedisType::Integer(created as i128)),
+        "DELCONSUMER" => store
+            .xgroup_delconsumer(key, group, argument_as_bytes(arguments, 3)?)
+            .map(|pending| RedisType::Integer(pending as i128)),
+        _ => {
+            return Err(CommandError::UnknownCommand(format!(
+                "unknown subcommand '{}' for 'xgroup' command",
+                subcommand
+            )));
+        }
+    };
+
+    match result {
+        Ok(reply) => Ok(reply),
+        Err(StoreError::WrongType) => Ok(wrong_type_error()),
+        Err(StoreError::KeyNotFound) => Ok(RedisType::SimpleError(
+            "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically."
+                .into(),
+        )),
+        Err(StoreError::BusyGroup) => Ok(RedisType::SimpleError(
+            "BUSYGROUP Consumer Group name already exists".into(),
+        )),
+        Err(StoreError::NoGroup) => Ok(no_group_error(key, group)),
+        Err(other) => Err(CommandError::StoreError(other)),
+    }
+}
+
+/// XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]
+pub fn handle_xread(
+    arguments: &[RedisType],
+    store: &mut Store,
+    can_block: bool,
+) -> Result<CommandResponse, CommandError> {
+    let mut count = None;
+    let mut block = None;
+    let mut options = OptionParser::new(arguments);
+    loop {
+        match options.next_option()?.as_deref() {
+            Some("COUNT") => {
+                // a count of 0 (or less) reads all entries
+                let max = options.value_as_number::<i64>()?;
+                count = (max > 0).then_some(max as usize);
+            }
+            Some("BLOCK") => block = Some(options.value_as_number::<u128>()?),
+            Some("STREAMS") => break,
+            _ => return Err(CommandError::InvalidInput("syntax error".into())),
+        }
+    }
+    let keys_and_ids = options.rest();
+    if keys_and_ids.is_empty() || !keys_and_ids.len().is_multiple_of(2) {
+        return Err(CommandError::InvalidInput(
+            "Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be specified."
+                .into(),
+        ));
+    }
 
-    let ids: Vec<StreamId> = stream_ids
+    let (stream_keys, stream_ids) = keys_and_ids.split_at(keys_and_ids.len() / 2);
+    let keys = stream_keys
         .iter()
-        .map(extract_stream_id_values) // -> Result<&Bytes, CommandError>
-        .collect::<Result<Vec<_>, _>>()?
+        .map(|key| redis_type_as_bytes(key).cloned())
+        .collect::<Result<Vec<Bytes>, CommandError>>()?;
+    // `$` is resolved right away, a blocked client only gets entries added after it blocked
+    let ids = keys
         .iter()
-        .map(|(ms, seq)| StreamId {
-            ms: ms.unwrap_or(0),
-            seq: seq.unwrap_or(0),
-        })
-        .collect();
-    let keys_and_ids: Vec<(&Bytes, StreamId)> = keys.into_iter().zip(ids).collect();
-    let result = keys_and_ids
-        .into_iter()
-        .map(|(key, stream)| {
-            xread_output_to_redis_type(key.clone(), store.xread(key, stream, false))
+        .zip(stream_ids)
+        .map(|(key, id)| match redis_type_as_bytes(id)?.as_ref() {
+            b"$" => store.last_stream_id(key).map_err(CommandError::StoreError),
+            _ => parse_stream_id(id),
         })
-        .collect();
+        .collect::<Result<Vec<StreamId>, CommandError>>()?;
+
+    let mut streams = Vec::new();
+    for (key, id) in keys.iter().zip(&ids) {
+        let entries = store
+            .xread(key, *id, false, count)
+            .map_err(CommandError::StoreError)?;
+        if !entries.is_empty() {
+            streams.push(xread_output_to_redis_type(key.clone(), entries));
+        }
+    }
+    if !streams.is_empty() {
+        return Ok(CommandResponse::Immediate(RedisType::Array(Some(streams))));
+    }
 
-    Ok(RedisType::Array(Some(result)))
+    match block {
+        // in a transaction XREAD BLOCK doesn't wait for data
+        Some(timeout) if can_block => {
+            let (tx, rx) = oneshot::channel();
+            let from = StreamReadPosition::After(ids);
+            let identifier = store.register_xread_waiting_client(keys, from, count, tx);
+
+            Ok(CommandResponse::WaitForXREAD {
+                timeout,
+                receiver: rx,
+                client_id: identifier,
+            })
+        }
+        _ => Ok(CommandResponse::Immediate(RedisType::Array(None))),
+    }
 }
 
-pub fn handle_xread(
+/// XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key
+/// [key ...] id [id ...]
+///
+/// `>` delivers the entries the group hasn't delivered to any consumer yet, an id replays the
+/// entries after it which are pending for the consumer. NOACK doesn't add the delivered entries
+/// to the pending entries.
+pub fn handle_xreadgroup(
     arguments: &[RedisType],
     store: &mut Store,
+    can_block: bool,
 ) -> Result<CommandResponse, CommandError> {
-    let possible_block = argument_as_str(arguments, 0)?;
-
-    if possible_block.to_uppercase() == "BLOCK" {
-        let timeout: u128 = argument_as_number(arguments, 1)?;
-        let last_argument = argument_as_str(arguments, arguments.len() - 1)?;
-        let keys_and_ids = &arguments[3..];
-
-        let resp = handle_xread_immediate(keys_and_ids, store)?;
-        if let RedisType::Array(Some(array)) = &resp
-            && !array.is_empty()
-        {
-            // data structure is [[id, [field, value]]] -> [field, value] is empty -> no data
-            let has_some_content = array
-                .first()
-                .and_then(|first_inner| {
-                    if let RedisType::Array(Some(some_inner)) = &first_inner {
-                        Some(some_inner)
-                    } else {
-                        None
-                    }
-                })
-                .map(|first_inner| {
-                    first_inner.iter().any(
-                        |item| matches!(item, RedisType::Array(Some(inner)) if !inner.is_empty()),
+    let mut options = OptionParser::new(arguments);
+    options.expect("GROUP")?;
+    let group = options.value()?;
+    let consumer = options.value()?;
+
+    let mut count = None;
+    let mut block = None;
+    let mut no_ack = false;
+    loop {
+        match options.next_option()?.as_deref() {
+            Some("COUNT") => {
+                // a count of 0 (or less) reads all entries
+                let max = options.value_as_number::<i64>()?;
+                count = (max > 0).then_some(max as usize);
+            }
+            Some("BLOCK") => block = Some(options.value_as_number::<u128>()?),
+            Some("NOACK") => no_ack = true,
+            Some("STREAMS") => break,
+            _ => return Err(CommandError::InvalidInput("syntax error".into())),
+        }
+    }
+    let keys_and_ids = options.rest();
+    if keys_and_ids.is_empty() || !keys_and_ids.len().is_multiple_of(2) {
+        return Err(CommandError::InvalidInput(
+            "Unbalanced 'xreadgroup' list of streams: for each stream key an ID or '>' must be specified."
+                .into(),
+        ));
+    }
+
+    let (stream_keys, stream_ids) = keys_and_ids.split_at(keys_and_ids.len() / 2);
+    let mut reads = Vec::with_capacity(stream_keys.len());
+    for (key, id) in stream_keys.iter().zip(stream_ids) {
+        let key = redis_type_as_bytes(key)?;
+        // `None` reads new entries, an id the pending ones
+        let id = match redis_type_as_bytes(id)?.as_ref() {
+            b">" => None,
+            b"$" => {
+                return Err(CommandError::InvalidInput(
+                    "The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set."
+                        .into(),
+                ));
+            }
+            _ => Some(parse_stream_id(id)?),
+        };
+        match store.has_consumer_group(key, group) {
+            Ok(true) => {}
+            Ok(false) => {
+                return Ok(CommandResponse::Immediate(RedisType::SimpleError(
+                    format!(
+                        "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                        String::from_utf8_lossy(key),
+                        String::from_utf8_lossy(group)
                     )
-                })
-                .unwrap_or(false);
+                    .into(),
+                )));
+            }
+            Err(StoreError::WrongType) => {
+                return Ok(CommandResponse::Immediate(wrong_type_error()));
+            }
+            Err(error) => return Err(CommandError::StoreError(error)),
+        }
+        reads.push((key, id));
+    }
 
-            if has_some_content && last_argument != "$" {
-                Ok(CommandResponse::Immediate(resp))
-            } else {
-                // No data - register for waiting
-                let keys_only = keys_and_ids.split_at(keys_and_ids.len() / 2).0.to_vec();
-                let key_as_bytes: Vec<Bytes> = keys_only
+    let mut streams = Vec::new();
+    for &(key, id) in &reads {
+        match id {
+            None => {
+                let entries = store
+                    .xreadgroup(key, group, consumer, count, no_ack)
+                    .map_err(CommandError::StoreError)?;
+                if !entries.is_empty() {
+                    streams.push(xread_output_to_redis_type(key.clone(), entries));
+                }
+            }
+            Some(id) => {
+                let entries = store
+                    .xreadgroup_pending(key, group, consumer, id, count)
+                    .map_err(CommandError::StoreError)?;
+                // the history is listed even if there is none
+                let entries = entries
                     .iter()
-                    .map(redis_type_as_bytes)
-                    .collect::<Result<Vec<&Bytes>, _>>()?
-                    .into_iter()
-                    .cloned()
+                    .map(|(id, entry)| match entry {
+                        Some(entry) => entry_reply(&(*id, entry.clone())),
+                        None => RedisType::Array(Some(vec![id.into(), RedisType::Array(None)])),
+                    })
                     .collect();
+                streams.push(RedisType::Array(Some(vec![
+                    RedisType::BulkString(key.clone()),
+                    RedisType::Array(Some(entries)),
+                ])));
+            }
+        }
+    }
+    if !streams.is_empty() {
+        return Ok(CommandResponse::Immediate(RedisType::Array(Some(streams))));
+    }
+
+    // nothing to deliver means every id is `>`, the history is listed even if it is empty
+    match block {
+        // in a transaction XREADGROUP BLOCK doesn't wait for data
+        Some(timeout) if can_block => {
+            let (tx, rx) = oneshot::channel();
+            let keys = reads.into_iter().map(|(key, _)| key.clone()).collect();
+            let from = StreamReadPosition::Group {
+                group: group.clone(),
+                consumer: consumer.clone(),
+                no_ack,
+            };
+            let identifier = store.register_xread_waiting_client(keys, from, count, tx);
+
+            Ok(CommandResponse::WaitForXREAD {
+                timeout,
+                receiver: rx,
+                client_id: identifier,
+            })
+        }
+        _ => Ok(CommandResponse::Immediate(RedisType::Array(None))),
+    }
+}
+
+/// XPENDING key group [[IDLE min-idle-time] start end count [consumer]]
+pub fn handle_xpending(
+    arguments: &[RedisType],
+    store: &mut Store,
+) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let group = argument_as_bytes(arguments, 1)?;
+
+    if arguments.len() == 2 {
+        return match store.xpending_summary(key, group) {
+            Ok(summary) => {
+                let consumers = summary
+                    .consumers
+                    .into_iter()
+                    .map(|(name, count)| {
+                        RedisType::Array(Some(vec![
+                            RedisType::BulkString(name),
+                            RedisType::BulkString(count.to_string().into()),
+                        ]))
+                    })
+                    .collect::<Vec<_>>();
+                let optional_id = |id: Option<StreamId>| match id {
+                    Some(id) => id.into(),
+                    None => RedisType::NullBulkString,
+                };
+                Ok(RedisType::Array(Some(vec![
+                    RedisType::Integer(summary.count as i128),
+                    optional_id(summary.first),
+                    optional_id(summary.last),
+                    if consumers.is_empty() {
+                        RedisType::Array(None)
+                    } else {
+                        RedisType::Array(Some(consumers))
+                    },
+                ])))
+            }
+            Err(StoreError::KeyNotFound | StoreError::NoGroup) => {
+                Ok(no_key_or_group_error(key, group))
+            }
+            Err(StoreError::WrongType) => Ok(wrong_type_error()),
+            Err(error) => Err(CommandError::StoreError(error)),
+        };
+    }
+
+    let mut index = 2;
+    let mut min_idle = None;
+    if argument_as_str(arguments, index)?.eq_ignore_ascii_case("IDLE") {
+        // a negative idle time filters nothing
+        min_idle = Some(argument_as_number::<i64>(arguments, index + 1)?.max(0) as u128);
+        index += 2;
+    }
+    if arguments.len() != index + 3 && arguments.len() != index + 4 {
+        return Err(CommandError::InvalidInput("syntax error".into()));
+    }
+    let start = parse_range_bound(&arguments[index], false)?;
+    let end = parse_range_bound(&arguments[index + 1], true)?;
+    // a negative count returns nothing, like 0
+    let count = argument_as_number::<i64>(arguments, index + 2)?.max(0) as usize;
+    let consumer = match arguments.get(index + 3) {
+        Some(consumer) => Some(redis_type_as_bytes(consumer)?),
+        None => None,
+    };
 
-                let (tx, rx) = oneshot::channel();
-                let identifier = store.register_xread_waiting_client(key_as_bytes, tx);
-                println!(
-                    "XREAD Waiting with timeout {} for client: {}",
-                    timeout, identifier
-                );
-
-                Ok(CommandResponse::WaitForXREAD {
-                    timeout,
-                    receiver: rx,
-                    client_id: identifier,
+    match store.xpending(key, group, (start, end), count, consumer, min_idle) {
+        Ok(pending) => Ok(RedisType::Array(Some(
+            pending
+                .into_iter()
+                .map(|pending| {
+                    RedisType::Array(Some(vec![
+                        pending.id.into(),
+                        RedisType::BulkString(pending.consumer),
+                        RedisType::Integer(pending.idle as i128),
+                        RedisType::Integer(pending.delivery_count as i128),
+                    ]))
                 })
+                .collect(),
+        ))),
+        Err(StoreError::KeyNotFound | StoreError::NoGroup) => Ok(no_key_or_group_error(key, group)),
+        Err(StoreError::WrongType) => Ok(wrong_type_error()),
+        Err(error) => Err(CommandError::StoreError(error)),
+    }
+}
+
+/// XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-time-milliseconds]
+/// [RETRYCOUNT count] [FORCE] [JUSTID] [LASTID lastid]
+pub fn handle_xclaim(
+    arguments: &[RedisType],
+    store: &mut Store,
+) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let group = argument_as_bytes(arguments, 1)?;
+    let consumer = argument_as_bytes(arguments, 2)?;
+    let min_idle = argument_as_number::<i64>(arguments, 3).map_err(|_| {
+        CommandError::InvalidInput("Invalid min-idle-time argument for XCLAIM".into())
+    })?;
+    let mut options = ClaimOptions {
+        min_idle: min_idle.max(0) as u128,
+        ..Default::default()
+    };
+
+    // the ids run up to the first option
+    let mut index = 4;
+    let mut ids = Vec::new();
+    while let Some(argument) = arguments.get(index)
+        && let Ok(id) = parse_stream_id(argument)
+    {
+        ids.push(id);
+        index += 1;
+    }
+    if ids.is_empty() {
+        return Err(CommandError::InvalidInput(
+            "wrong number of arguments for 'xclaim' command".into(),
+        ));
+    }
+
+    let now = now_millis().map_err(CommandError::StoreError)?;
+    while index < arguments.len() {
+        let option = argument_as_str(arguments, index)?.to_ascii_uppercase();
+        match option.as_str() {
+            "FORCE" => options.force = true,
+            "JUSTID" => options.just_id = true,
+            "IDLE" | "TIME" | "RETRYCOUNT" => {
+                let value = argument_as_number::<i64>(arguments, index + 1)
+                    .map_err(|_| {
+                        CommandError::InvalidInput(format!(
+                            "Invalid {} option argument for XCLAIM",
+                            option
+                        ))
+                    })?
+                    .max(0);
+                match option.as_str() {
+                    "IDLE" => options.delivery_time = Some(now.saturating_sub(value as u128)),
+                    "TIME" => options.delivery_time = Some(value as u128),
+                    _ => options.retry_count = Some(value as u64),
+                }
+                index += 1;
+            }
+            "LASTID" => {
+                let last_id = arguments
+                    .get(index + 1)
+                    .ok_or_else(|| CommandError::InvalidInput("syntax error".into()))?;
+                options.last_id = Some(parse_stream_id(last_id)?);
+                index += 1;
+            }
+            _ => {
+                return Err(CommandError::InvalidInput(format!(
+                    "Unrecognized XCLAIM option '{}'",
+                    option
+                )));
             }
+        }
+        index += 1;
+    }
 
-            // May be not enough to just check the outmost array for data.
-        } else {
-            Ok(CommandResponse::Immediate(resp))
+    match store.xclaim(key, group, consumer, &ids, &options) {
+        Ok(claimed) if options.just_id => Ok(RedisType::Array(Some(
+            claimed.iter().map(|(id, _)| id.into()).collect(),
+        ))),
+        Ok(claimed) => Ok(entries_reply(&claimed)),
+        Err(StoreError::KeyNotFound | StoreError::NoGroup) => Ok(no_key_or_group_error(key, group)),
+        Err(StoreError::WrongType) => Ok(wrong_type_error()),
+        Err(error) => Err(CommandError::StoreError(error)),
+    }
+}
+
+/// XAUTOCLAIM key group consumer min-idle-time start [COUNT count] [JUSTID]
+pub fn handle_xautoclaim(
+    arguments: &[RedisType],
+    store: &mut Store,
+) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let group = argument_as_bytes(arguments, 1)?;
+    let consumer = argument_as_bytes(arguments, 2)?;
+    let min_idle = argument_as_number::<i64>(arguments, 3).map_err(|_| {
+        CommandError::InvalidInput("Invalid min-idle-time argument for XAUTOCLAIM".into())
+    })?;
+    let start = parse_range_bound(
+        arguments.get(4).ok_or_else(|| {
+            CommandError::InvalidInput("wrong number of arguments for 'xautoclaim' command".into())
+        })?,
+        false,
+    )?;
+    let mut options = ClaimOptions {
+        min_idle: min_idle.max(0) as u128,
+        ..Default::default()
+    };
+
+    let mut count = 100;
+    let mut index = 5;
+    while index < arguments.len() {
+        let option = argument_as_str(arguments, index)?.to_ascii_uppercase();
+        match option.as_str() {
+            "COUNT" => {
+                count = argument_as_number::<usize>(arguments, index + 1)
+                    .ok()
+                    .filter(|count| *count > 0)
+                    .ok_or_else(|| CommandError::InvalidInput("COUNT must be > 0".into()))?;
+                index += 1;
+            }
+            "JUSTID" => options.just_id = true,
+            _ => return Err(CommandError::InvalidInput("syntax error".into())),
         }
-    } else {
-        let keys_and_ids = &arguments[1..];
-        let resp = handle_xread_immediate(keys_and_ids, store)?;
-        Ok(CommandResponse::Immediate(resp))
+        index += 1;
+    }
+
+    match store.xautoclaim(key, group, consumer, start, count, &options) {
+        Ok(auto_claimed) => {
+            let claimed = if options.just_id {
+                RedisType::Array(Some(
+                    auto_claimed
+                        .claimed
+                        .iter()
+                        .map(|(id, _)| id.into())
+                        .collect(),
+                ))
+            } else {
+                entries_reply(&auto_claimed.claimed)
+            };
+            Ok(RedisType::Array(Some(vec![
+                auto_claimed.next.into(),
+                claimed,
+                RedisType::Array(Some(
+                    auto_claimed.deleted.iter().map(|id| id.into()).collect(),
+                )),
+            ])))
+        }
+        Err(StoreError::KeyNotFound | StoreError::NoGroup) => Ok(no_key_or_group_error(key, group)),
+        Err(StoreError::WrongType) => Ok(wrong_type_error()),
+        Err(error) => Err(CommandError::StoreError(error)),
     }
 }
 
@@ -226,3 +1064,146 @@ fn extract_stream_id_values(
     };
     Ok((ms, seq))
 }
+
+/// Parses a complete stream id, `ms-seq` or just `ms` for sequence 0
+fn parse_stream_id(argument: &RedisType) -> Result<StreamId, CommandError> {
+    parse_id(redis_type_as_bytes(argument)?, 0)
+}
+
+/// Parses `ms-seq`, or just `ms` with the given sequence number
+fn parse_id(id: &[u8], default_seq: u128) -> Result<StreamId, CommandError> {
+    let invalid = || {
+        CommandError::InvalidInput("Invalid stream ID specified as stream command argument".into())
+    };
+    let id = str::from_utf8(id).map_err(|_| invalid())?;
+    let (ms, seq) = match id.split_once('-') {
+        Some((ms, seq)) => (ms, seq.parse().map_err(|_| invalid())?),
+        None => (id, default_seq),
+    };
+    Ok(StreamId {
+        ms: ms.parse().map_err(|_| invalid())?,
+        seq,
+    })
+}
+
+#[test]
+fn test_xinfo_replies_with_maps() {
+    use crate::{
+        commands::{CommandResponse, handle_command, utils::test_command},
+        store::test_client,
+    };
+
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 1);
+    let mut reply = |command: &[&str]| match handle_command(test_command(command), &mut store, 1) {
+        Ok(CommandResponse::Immediate(reply)) => reply,
+        _ => panic!("{:?} should reply right away", command),
+    };
+    reply(&["XADD", "s", "1-1", "f", "v"]);
+    reply(&["XGROUP", "CREATE", "s", "g", "0"]);
+
+    let RedisType::Map(stream) = reply(&["XINFO", "STREAM", "s"]) else {
+        panic!("expected the stream details as a map");
+    };
+    assert_eq!(
+        stream[0],
+        (
+            RedisType::BulkString("length".into()),
+            RedisType::Integer(1)
+        )
+    );
+    let RedisType::Array(Some(groups)) = reply(&["XINFO", "GROUPS", "s"]) else {
+        panic!("expected an array of groups");
+    };
+    let RedisType::Map(group) = &groups[0] else {
+        panic!("expected the group details as a map");
+    };
+    assert_eq!(group[0].1, RedisType::BulkString("g".into()));
+    // RESP2 clients get the fields and values in a flat array
+    assert!(
+        RedisType::Map(group.clone())
+            .to_bytes()
+            .starts_with(b"*12\r\n$4\r\nname\r\n$1\r\ng\r\n")
+    );
+}
+
+#[test]
+fn test_xadd_nomkstream_only_appends_to_existing_streams() {
+    use crate::commands::utils::test_arguments;
+
+    let mut store = Store::new();
+    let mut xadd = |arguments: &[&str]| handle_xadd(&test_arguments(arguments), &mut store).ok();
+    assert_eq!(
+        xadd(&["s", "NOMKSTREAM", "1-1", "f", "v"]),
+        Some(RedisType::NullBulkString)
+    );
+    assert_eq!(
+        xadd(&["s", "1-1", "f", "v"]),
+        Some(RedisType::BulkString("1-1".into()))
+    );
+    assert_eq!(
+        xadd(&["s", "nomkstream", "MAXLEN", "1", "1-2", "f", "v"]),
+        Some(RedisType::BulkString("1-2".into()))
+    );
+    assert_eq!(
+        store.get_type(&Bytes::from("s")).ok(),
+        Some(Bytes::from("stream"))
+    );
+    assert!(store.get_type(&Bytes::from("missing")).is_err());
+}
+
+#[test]
+fn test_xrange_with_exclusive_bounds_and_count() {
+    use crate::commands::utils::test_arguments;
+
+    let mut store = Store::new();
+    for id in ["1-1", "1-2", "2-0", "3-5"] {
+        handle_xadd(&test_arguments(&["s", id, "f", "v"]), &mut store).unwrap();
+    }
+    let ids = |reply: Result<RedisType, CommandError>| -> Vec<String> {
+        let Ok(RedisType::Array(Some(entries))) = reply else {
+            panic!("expected an array of entries, got {:?}", reply);
+        };
+        entries
+            .iter()
+            .map(|entry| match entry {
+                RedisType::Array(Some(entry)) => match &entry[0] {
+                    RedisType::BulkString(id) => String::from_utf8_lossy(id).into_owned(),
+                    other => panic!("expected the id, got {:?}", other),
+                },
+                other => panic!("expected an entry, got {:?}", other),
+            })
+            .collect()
+    };
+
+    let xrange = |arguments: &[&str], store: &mut Store| {
+        ids(handle_xrange(&test_arguments(arguments), store))
+    };
+    assert_eq!(
+        xrange(&["s", "(1-1", "+"], &mut store),
+        ["1-2", "2-0", "3-5"]
+    );
+    assert_eq!(
+        xrange(&["s", "-", "(3-5"], &mut store),
+        ["1-1", "1-2", "2-0"]
+    );
+    // an id without a sequence number covers its whole millisecond
+    assert_eq!(xrange(&["s", "1", "1"], &mut store), ["1-1", "1-2"]);
+    assert_eq!(xrange(&["s", "-", "(2-0"], &mut store), ["1-1", "1-2"]);
+    assert_eq!(
+        xrange(&["s", "(1-1", "+", "COUNT", "2"], &mut store),
+        ["1-2", "2-0"]
+    );
+
+    let xrevrange = |arguments: &[&str], store: &mut Store| {
+        ids(handle_xrevrange(&test_arguments(arguments), store))
+    };
+    assert_eq!(
+        xrevrange(&["s", "(3-5", "-", "COUNT", "1"], &mut store),
+        ["2-0"]
+    );
+    assert_eq!(xrevrange(&["s", "+", "(1-2"], &mut store), ["3-5", "2-0"]);
+
+    assert!(handle_xrange(&test_arguments(&["s", "(x", "+"]), &mut store).is_err());
+    assert!(handle_xrange(&test_arguments(&["s", "-", "+", "LIMIT", "1"]), &mut store).is_err());
+}
@@ -0,0 +1,1306 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use bytes::Bytes;
+
+use super::{
+    CommandError, CommandResponse,
+    client::{handle_client, handle_hello},
+    cluster::handle_cluster,
+    hashes::{
+        handle_hexpire, handle_hget, handle_hpersist, handle_hscan, handle_hset, handle_httl,
+    },
+    keys::{handle_get, handle_incr, handle_scan, handle_set},
+    lists::{
+        handle_blmove, handle_blmpop, handle_blpop, handle_brpop, handle_brpoplpush, handle_lindex,
+        handle_linsert, handle_llen, handle_lmove, handle_lmpop, handle_lpop, handle_lpos,
+        handle_lpush, handle_lrange, handle_lrem, handle_lset, handle_ltrim, handle_rpop,
+        handle_rpoplpush, handle_rpush,
+    },
+    misc::{
+        handle_command_info, handle_config, handle_debug, handle_echo, handle_info, handle_object,
+        handle_ping, handle_save, handle_type,
+    },
+    pubsub::{
+        handle_publish, handle_pubsub, handle_spublish, handle_subscribe, handle_unsubscribe,
+    },
+    sets::{
+        SetOperation, handle_sadd, handle_scard, handle_set_algebra, handle_set_algebra_store,
+        handle_sintercard, handle_sismember, handle_smembers, handle_smismember, handle_smove,
+        handle_spop, handle_srandmember, handle_srem, handle_sscan,
+    },
+    streams::{
+        handle_xadd, handle_xautoclaim, handle_xclaim, handle_xdel, handle_xgroup, handle_xinfo,
+        handle_xpending, handle_xrange, handle_xread, handle_xreadgroup, handle_xrevrange,
+        handle_xsetid, handle_xtrim,
+    },
+    transactions::{handle_discard, handle_exec, handle_multi, handle_unwatch, handle_watch},
+    utils::{argument_as_number, argument_as_str},
+    zsets::{
+        handle_bzmpop, handle_bzpop, handle_legacy_zrange, handle_zadd, handle_zcard,
+        handle_zcount, handle_zincrby, handle_zmpop, handle_zpop, handle_zrandmember,
+        handle_zrange, handle_zrangestore, handle_zrank, handle_zrem, handle_zscan, handle_zscore,
+        handle_zset_algebra, handle_zset_algebra_store,
+    },
+};
+use crate::{
+    parser::RedisType,
+    store::{Store, SubscriptionKind, ZSetOperation},
+};
+
+/// The command modifies the keyspace
+pub const WRITE: u16 = 1 << 0;
+/// The command only reads the keyspace
+pub const READONLY: u16 = 1 << 1;
+/// The command may grow the memory used, refused once out of memory
+pub const DENYOOM: u16 = 1 << 2;
+/// The command runs in constant or logarithmic time
+pub const FAST: u16 = 1 << 3;
+/// The command may block the client
+pub const BLOCKING: u16 = 1 << 4;
+/// The command doesn't write to the keyspace but is propagated anyway, like PUBLISH
+pub const MAY_REPLICATE: u16 = 1 << 5;
+/// The command changes how the connection is served, it can't be queued in a transaction
+pub const NO_MULTI: u16 = 1 << 6;
+
+/// Names COMMAND INFO reports the flags with
+const FLAG_NAMES: [(u16, &str); 7] = [
+    (WRITE, "write"),
+    (READONLY, "readonly"),
+    (DENYOOM, "denyoom"),
+    (FAST, "fast"),
+    (BLOCKING, "blocking"),
+    (MAY_REPLICATE, "may_replicate"),
+    (NO_MULTI, "no_multi"),
+];
+
+/// What a command runs with
+pub struct Invocation<'a> {
+    /// the arguments, the command name left out
+    pub arguments: &'a [RedisType],
+    pub store: &'a mut Store,
+    pub client_id: u64,
+    pub can_block: bool,
+    /// whether the client is in the RESP2 subscriber mode
+    pub subscribed: bool,
+}
+
+type Handler = fn(Invocation) -> Result<CommandResponse, CommandError>;
+
+/// Where the search for the keys of a command begins
+pub enum BeginSearch {
+    /// at the argument, the command name being argument 0
+    Index(usize),
+    /// right after the first argument which is the keyword, searching from `start_from` on
+    Keyword {
+        keyword: &'static str,
+        start_from: usize,
+    },
+}
+
+/// Which arguments are keys, starting where the search began
+pub enum FindKeys {
+    /// The keys up to the last one, relative to where the search began. A negative last key
+    /// counts from the end, -1 is the last argument. A limit of 2 or more only takes that
+    /// fraction of the range, like the keys before their ids in XREAD.
+    Range {
+        last_key: isize,
+        key_step: usize,
+        limit: usize,
+    },
+    /// The number of keys is an argument, the keys follow it. Both positions are relative to
+    /// where the search began.
+    KeyNum {
+        numkeys: usize,
+        first_key: usize,
+        key_step: usize,
+    },
+}
+
+/// Tells where some of the keys of a command are, like a key specification of redis
+pub struct KeySpec {
+    pub begin_search: BeginSearch,
+    pub find_keys: FindKeys,
+}
+
+impl KeySpec {
+    /// The keys from `index` to `last_key`, relative to `index` or from the end if negative
+    pub const fn range(index: usize, last_key: isize, key_step: usize) -> Self {
+        KeySpec {
+            begin_search: BeginSearch::Index(index),
+            find_keys: FindKeys::Range {
+                last_key,
+                key_step,
+                limit: 0,
+            },
+        }
+    }
+
+    /// The number of keys at `index`, followed by the keys
+    pub const fn numkeys(index: usize) -> Self {
+        KeySpec {
+            begin_search: BeginSearch::Index(index),
+            find_keys: FindKeys::KeyNum {
+                numkeys: 0,
+                first_key: 1,
+                key_step: 1,
+            },
+        }
+    }
+
+    /// Positions of the keys among the arguments, the command name being argument 0. Keys past
+    /// the arguments are left out.
+    fn key_positions(&self, arguments: &[RedisType]) -> Vec<usize> {
+        let count = arguments.len();
+        let begin = match self.begin_search {
+            BeginSearch::Index(index) => index,
+            BeginSearch::Keyword {
+                keyword,
+                start_from,
+            } => match (start_from..count).find(|index| {
+                argument_as_str(arguments, *index)
+                    .is_ok_and(|argument| argument.eq_ignore_ascii_case(keyword))
+            }) {
+                Some(index) => index + 1,
+                None => return Vec::new(),
+            },
+        };
+        match self.find_keys {
+            FindKeys::Range {
+                last_key,
+                key_step,
+                limit,
+            } => {
+                let last = if last_key < 0 {
+                    count as isize + last_key
+                } else {
+                    begin as isize + last_key
+                };
+                let last = match limit {
+                    0 | 1 => last,
+                    limit => begin as isize + (last - begin as isize + 1) / limit as isize - 1,
+                };
+                if last < begin as isize {
+                    return Vec::new();
+                }
+                (begin..=last as usize)
+                    .step_by(key_step.max(1))
+                    .take_while(|position| *position < count)
+                    .collect()
+            }
+            FindKeys::KeyNum {
+                numkeys,
+                first_key,
+                key_step,
+            } => {
+                let numkeys = argument_as_number::<usize>(arguments, begin + numkeys).unwrap_or(0);
+                (begin + first_key..count)
+                    .step_by(key_step.max(1))
+                    .take(numkeys)
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Keys of a command taking none
+const NO_KEYS: &[KeySpec] = &[];
+/// Keys of a command whose only key is its first argument
+const FIRST_KEY: &[KeySpec] = &[KeySpec::range(1, 0, 1)];
+/// Keys of ZUNIONSTORE and friends, the destination and the keys counted by numkeys
+const STORE_AND_NUMKEYS: &[KeySpec] = &[KeySpec::range(1, 0, 1), KeySpec::numkeys(2)];
+/// Keys of XREAD and XREADGROUP, as many keys as ids follow STREAMS. The keyword is searched
+/// from `start_from` on, past the group and consumer names of XREADGROUP.
+const fn after_streams(start_from: usize) -> KeySpec {
+    KeySpec {
+        begin_search: BeginSearch::Keyword {
+            keyword: "STREAMS",
+            start_from,
+        },
+        find_keys: FindKeys::Range {
+            last_key: -1,
+            key_step: 1,
+            limit: 2,
+        },
+    }
+}
+
+/// A command the server knows, with the metadata redis keeps about it
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// group COMMAND DOCS files the command under, like `list` or `sorted-set`
+    pub group: &'static str,
+    /// Number of arguments the command takes, its name included. Like in redis a negative
+    /// arity is a minimum, -2 means at least 2.
+    pub arity: i32,
+    pub flags: u16,
+    pub key_specs: &'static [KeySpec],
+    pub handler: Handler,
+}
+
+impl CommandSpec {
+    /// Whether the command has any of the flags
+    pub fn has(&self, flag: u16) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Whether the keys can't be found by position alone, they depend on other arguments
+    pub fn has_movable_keys(&self) -> bool {
+        self.key_specs.iter().any(|spec| {
+            matches!(spec.begin_search, BeginSearch::Keyword { .. })
+                || matches!(spec.find_keys, FindKeys::KeyNum { .. })
+        })
+    }
+
+    pub fn flag_names(&self) -> impl Iterator<Item = &'static str> {
+        FLAG_NAMES
+            .into_iter()
+            .filter(|(flag, _)| self.has(*flag))
+            .map(|(_, name)| name)
+            .chain(self.has_movable_keys().then_some("movablekeys"))
+    }
+
+    /// The ACL categories of the command, derived from its flags and its group
+    pub fn acl_categories(&self) -> Vec<&'static str> {
+        let mut categories = Vec::new();
+        if self.has(WRITE) {
+            categories.push("@write");
+        }
+        if self.has(READONLY) {
+            categories.push("@read");
+        }
+        categories.push(if self.has(FAST) { "@fast" } else { "@slow" });
+        if self.has(BLOCKING) {
+            categories.push("@blocking");
+        }
+        let group = match self.group {
+            "generic" => "@keyspace",
+            "string" => "@string",
+            "list" => "@list",
+            "hash" => "@hash",
+            "set" => "@set",
+            "sorted-set" => "@sortedset",
+            "stream" => "@stream",
+            "pubsub" => "@pubsub",
+            "connection" => "@connection",
+            "transactions" => "@transaction",
+            _ => return categories,
+        };
+        categories.push(group);
+        categories
+    }
+
+    /// First key, last key and step as COMMAND INFO reports them, taken from the first key
+    /// specification if it finds the keys by position. (0, 0, 0) otherwise.
+    pub fn legacy_key_range(&self) -> (usize, isize, usize) {
+        match self.key_specs.first() {
+            Some(KeySpec {
+                begin_search: BeginSearch::Index(index),
+                find_keys:
+                    FindKeys::Range {
+                        last_key,
+                        key_step,
+                        limit: 0,
+                    },
+            }) => {
+                let last_key = if *last_key < 0 {
+                    *last_key
+                } else {
+                    *index as isize + last_key
+                };
+                (*index, last_key, *key_step)
+            }
+            _ => (0, 0, 0),
+        }
+    }
+
+    /// Positions of the keys in the command, its name being argument 0, in the order of the key
+    /// specifications
+    pub fn key_positions(&self, elements: &[RedisType]) -> Vec<usize> {
+        self.key_specs
+            .iter()
+            .flat_map(|spec| spec.key_positions(elements))
+            .collect()
+    }
+}
+
+const fn command(
+    name: &'static str,
+    group: &'static str,
+    arity: i32,
+    flags: u16,
+    key_specs: &'static [KeySpec],
+    handler: Handler,
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        group,
+        arity,
+        flags,
+        key_specs,
+        handler,
+    }
+}
+
+fn immediate(reply: Result<RedisType, CommandError>) -> Result<CommandResponse, CommandError> {
+    reply.map(CommandResponse::Immediate)
+}
+
+fn simple_string(reply: &'static str) -> Result<CommandResponse, CommandError> {
+    Ok(CommandResponse::Immediate(RedisType::SimpleString(
+        Bytes::from_static(reply.as_bytes()),
+    )))
+}
+
+/// Every command the server knows
+pub static COMMANDS: &[CommandSpec] = &[
+    command("PING", "connection", -1, FAST, NO_KEYS, |call| {
+        immediate(handle_ping(call.arguments, call.subscribed))
+    }),
+    command("ECHO", "connection", 2, FAST, NO_KEYS, |call| {
+        immediate(handle_echo(call.arguments))
+    }),
+    command("INFO", "server", -1, 0, NO_KEYS, |call| {
+        immediate(handle_info(call.arguments))
+    }),
+    command(
+        "OBJECT",
+        "generic",
+        -2,
+        READONLY,
+        &[KeySpec::range(2, 0, 1)],
+        |call| immediate(handle_object(call.arguments, call.store)),
+    ),
+    command("TYPE", "generic", 2, READONLY | FAST, FIRST_KEY, |call| {
+        immediate(handle_type(call.arguments, call.store))
+    }),
+    command("SAVE", "server", 1, 0, NO_KEYS, |call| {
+        immediate(handle_save(call.store))
+    }),
+    command("CONFIG", "server", -2, 0, NO_KEYS, |call| {
+        immediate(handle_config(call.arguments, call.store))
+    }),
+    command("COMMAND", "server", -1, 0, NO_KEYS, |call| {
+        immediate(handle_command_info(call.arguments))
+    }),
+    command("DEBUG", "server", -2, 0, NO_KEYS, |call| {
+        immediate(handle_debug(call.arguments, call.store))
+    }),
+    command("CLUSTER", "cluster", -2, 0, NO_KEYS, |call| {
+        immediate(handle_cluster(call.arguments, call.store))
+    }),
+    // strings and keys
+    command("GET", "string", 2, READONLY | FAST, FIRST_KEY, |call| {
+        immediate(handle_get(call.arguments, call.store))
+    }),
+    command("SET", "string", -3, WRITE | DENYOOM, FIRST_KEY, |call| {
+        immediate(handle_set(call.arguments, call.store))
+    }),
+    command(
+        "INCR",
+        "string",
+        2,
+        WRITE | DENYOOM | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_incr(call.arguments, call.store)),
+    ),
+    command("SCAN", "generic", -2, READONLY, NO_KEYS, |call| {
+        immediate(handle_scan(call.arguments, call.store))
+    }),
+    // lists
+    command("LRANGE", "list", 4, READONLY, FIRST_KEY, |call| {
+        immediate(handle_lrange(call.arguments, call.store))
+    }),
+    command(
+        "RPUSH",
+        "list",
+        -3,
+        WRITE | DENYOOM | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_rpush(call.arguments, call.store)),
+    ),
+    command(
+        "LPUSH",
+        "list",
+        -3,
+        WRITE | DENYOOM | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_lpush(call.arguments, call.store)),
+    ),
+    command("LLEN", "list", 2, READONLY | FAST, FIRST_KEY, |call| {
+        immediate(handle_llen(call.arguments, call.store))
+    }),
+    command("LPOP", "list", -2, WRITE | FAST, FIRST_KEY, |call| {
+        immediate(handle_lpop(call.arguments, call.store))
+    }),
+    command("RPOP", "list", -2, WRITE | FAST, FIRST_KEY, |call| {
+        immediate(handle_rpop(call.arguments, call.store))
+    }),
+    command("LINDEX", "list", 3, READONLY, FIRST_KEY, |call| {
+        immediate(handle_lindex(call.arguments, call.store))
+    }),
+    command("LSET", "list", 4, WRITE | DENYOOM, FIRST_KEY, |call| {
+        immediate(handle_lset(call.arguments, call.store))
+    }),
+    command("LINSERT", "list", 5, WRITE | DENYOOM, FIRST_KEY, |call| {
+        immediate(handle_linsert(call.arguments, call.store))
+    }),
+    command("LREM", "list", 4, WRITE, FIRST_KEY, |call| {
+        immediate(handle_lrem(call.arguments, call.store))
+    }),
+    command("LTRIM", "list", 4, WRITE, FIRST_KEY, |call| {
+        immediate(handle_ltrim(call.arguments, call.store))
+    }),
+    command("LPOS", "list", -3, READONLY, FIRST_KEY, |call| {
+        immediate(handle_lpos(call.arguments, call.store))
+    }),
+    command("LMPOP", "list", -4, WRITE, &[KeySpec::numkeys(1)], |call| {
+        immediate(handle_lmpop(call.arguments, call.store))
+    }),
+    command(
+        "LMOVE",
+        "list",
+        5,
+        WRITE | DENYOOM,
+        &[KeySpec::range(1, 1, 1)],
+        |call| immediate(handle_lmove(call.arguments, call.store)),
+    ),
+    command(
+        "RPOPLPUSH",
+        "list",
+        3,
+        WRITE | DENYOOM,
+        &[KeySpec::range(1, 1, 1)],
+        |call| immediate(handle_rpoplpush(call.arguments, call.store)),
+    ),
+    command(
+        "BLPOP",
+        "list",
+        -3,
+        WRITE | BLOCKING,
+        &[KeySpec::range(1, -2, 1)],
+        |call| handle_blpop(call.arguments, call.store, call.can_block),
+    ),
+    command(
+        "BRPOP",
+        "list",
+        -3,
+        WRITE | BLOCKING,
+        &[KeySpec::range(1, -2, 1)],
+        |call| handle_brpop(call.arguments, call.store, call.can_block),
+    ),
+    command(
+        "BLMOVE",
+        "list",
+        6,
+        WRITE | DENYOOM | BLOCKING,
+        &[KeySpec::range(1, 1, 1)],
+        |call| handle_blmove(call.arguments, call.store, call.can_block),
+    ),
+    command(
+        "BLMPOP",
+        "list",
+        -5,
+        WRITE | BLOCKING,
+        &[KeySpec::numkeys(2)],
+        |call| handle_blmpop(call.arguments, call.store, call.can_block),
+    ),
+    command(
+        "BRPOPLPUSH",
+        "list",
+        4,
+        WRITE | DENYOOM | BLOCKING,
+        &[KeySpec::range(1, 1, 1)],
+        |call| handle_brpoplpush(call.arguments, call.store, call.can_block),
+    ),
+    // hashes
+    command(
+        "HSET",
+        "hash",
+        -4,
+        WRITE | DENYOOM | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_hset(call.arguments, call.store)),
+    ),
+    command("HGET", "hash", 3, READONLY | FAST, FIRST_KEY, |call| {
+        immediate(handle_hget(call.arguments, call.store))
+    }),
+    command("HSCAN", "hash", -3, READONLY, FIRST_KEY, |call| {
+        immediate(handle_hscan(call.arguments, call.store))
+    }),
+    command("HEXPIRE", "hash", -6, WRITE | FAST, FIRST_KEY, |call| {
+        immediate(handle_hexpire(call.arguments, call.store, 1000))
+    }),
+    command("HPEXPIRE", "hash", -6, WRITE | FAST, FIRST_KEY, |call| {
+        immediate(handle_hexpire(call.arguments, call.store, 1))
+    }),
+    command("HTTL", "hash", -5, READONLY | FAST, FIRST_KEY, |call| {
+        immediate(handle_httl(call.arguments, call.store, 1000))
+    }),
+    command("HPTTL", "hash", -5, READONLY | FAST, FIRST_KEY, |call| {
+        immediate(handle_httl(call.arguments, call.store, 1))
+    }),
+    command("HPERSIST", "hash", -5, WRITE | FAST, FIRST_KEY, |call| {
+        immediate(handle_hpersist(call.arguments, call.store))
+    }),
+    // sets
+    command(
+        "SADD",
+        "set",
+        -3,
+        WRITE | DENYOOM | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_sadd(call.arguments, call.store)),
+    ),
+    command("SREM", "set", -3, WRITE | FAST, FIRST_KEY, |call| {
+        immediate(handle_srem(call.arguments, call.store))
+    }),
+    command("SMEMBERS", "set", 2, READONLY, FIRST_KEY, |call| {
+        immediate(handle_smembers(call.arguments, call.store))
+    }),
+    command("SSCAN", "set", -3, READONLY, FIRST_KEY, |call| {
+        immediate(handle_sscan(call.arguments, call.store))
+    }),
+    command("SCARD", "set", 2, READONLY | FAST, FIRST_KEY, |call| {
+        immediate(handle_scard(call.arguments, call.store))
+    }),
+    command("SISMEMBER", "set", 3, READONLY | FAST, FIRST_KEY, |call| {
+        immediate(handle_sismember(call.arguments, call.store))
+    }),
+    command(
+        "SMISMEMBER",
+        "set",
+        -3,
+        READONLY | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_smismember(call.arguments, call.store)),
+    ),
+    command(
+        "SMOVE",
+        "set",
+        4,
+        WRITE | FAST,
+        &[KeySpec::range(1, 1, 1)],
+        |call| immediate(handle_smove(call.arguments, call.store)),
+    ),
+    command("SPOP", "set", -2, WRITE | FAST, FIRST_KEY, |call| {
+        immediate(handle_spop(call.arguments, call.store))
+    }),
+    command("SRANDMEMBER", "set", -2, READONLY, FIRST_KEY, |call| {
+        immediate(handle_srandmember(call.arguments, call.store))
+    }),
+    command(
+        "SINTER",
+        "set",
+        -2,
+        READONLY,
+        &[KeySpec::range(1, -1, 1)],
+        |call| {
+            immediate(handle_set_algebra(
+                call.arguments,
+                call.store,
+                SetOperation::Intersection,
+            ))
+        },
+    ),
+    command(
+        "SUNION",
+        "set",
+        -2,
+        READONLY,
+        &[KeySpec::range(1, -1, 1)],
+        |call| {
+            immediate(handle_set_algebra(
+                call.arguments,
+                call.store,
+                SetOperation::Union,
+            ))
+        },
+    ),
+    command(
+        "SDIFF",
+        "set",
+        -2,
+        READONLY,
+        &[KeySpec::range(1, -1, 1)],
+        |call| {
+            immediate(handle_set_algebra(
+                call.arguments,
+                call.store,
+                SetOperation::Difference,
+            ))
+        },
+    ),
+    command(
+        "SINTERCARD",
+        "set",
+        -3,
+        READONLY,
+        &[KeySpec::numkeys(1)],
+        |call| immediate(handle_sintercard(call.arguments, call.store)),
+    ),
+    command(
+        "SINTERSTORE",
+        "set",
+        -3,
+        WRITE | DENYOOM,
+        &[KeySpec::range(1, -1, 1)],
+        |call| {
+            immediate(handle_set_algebra_store(
+                call.arguments,
+                call.store,
+                SetOperation::Intersection,
+            ))
+        },
+    ),
+    command(
+        "SUNIONSTORE",
+        "set",
+        -3,
+        WRITE | DENYOOM,
+        &[KeySpec::range(1, -1, 1)],
+        |call| {
+            immediate(handle_set_algebra_store(
+                call.arguments,
+                call.store,
+                SetOperation::Union,
+            ))
+        },
+    ),
+    command(
+        "SDIFFSTORE",
+        "set",
+        -3,
+        WRITE | DENYOOM,
+        &[KeySpec::range(1, -1, 1)],
+        |call| {
+            immediate(handle_set_algebra_store(
+                call.arguments,
+                call.store,
+                SetOperation::Difference,
+            ))
+        },
+    ),
+    // sorted sets
+    command(
+        "ZADD",
+        "sorted-set",
+        -4,
+        WRITE | DENYOOM | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_zadd(call.arguments, call.store)),
+    ),
+    command(
+        "ZINCRBY",
+        "sorted-set",
+        4,
+        WRITE | DENYOOM | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_zincrby(call.arguments, call.store)),
+    ),
+    command(
+        "ZSCORE",
+        "sorted-set",
+        3,
+        READONLY | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_zscore(call.arguments, call.store)),
+    ),
+    command(
+        "ZCARD",
+        "sorted-set",
+        2,
+        READONLY | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_zcard(call.arguments, call.store)),
+    ),
+    command(
+        "ZRANK",
+        "sorted-set",
+        -3,
+        READONLY | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_zrank(call.arguments, call.store, false)),
+    ),
+    command(
+        "ZREVRANK",
+        "sorted-set",
+        -3,
+        READONLY | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_zrank(call.arguments, call.store, true)),
+    ),
+    command("ZRANGE", "sorted-set", -4, READONLY, FIRST_KEY, |call| {
+        immediate(handle_zrange(call.arguments, call.store))
+    }),
+    command(
+        "ZRANGEBYSCORE",
+        "sorted-set",
+        -4,
+        READONLY,
+        FIRST_KEY,
+        |call| {
+            immediate(handle_legacy_zrange(
+                call.arguments,
+                call.store,
+                &["BYSCORE"],
+            ))
+        },
+    ),
+    command(
+        "ZREVRANGEBYSCORE",
+        "sorted-set",
+        -4,
+        READONLY,
+        FIRST_KEY,
+        |call| {
+            immediate(handle_legacy_zrange(
+                call.arguments,
+                call.store,
+                &["BYSCORE", "REV"],
+            ))
+        },
+    ),
+    command(
+        "ZRANGEBYLEX",
+        "sorted-set",
+        -4,
+        READONLY,
+        FIRST_KEY,
+        |call| immediate(handle_legacy_zrange(call.arguments, call.store, &["BYLEX"])),
+    ),
+    command(
+        "ZREVRANGEBYLEX",
+        "sorted-set",
+        -4,
+        READONLY,
+        FIRST_KEY,
+        |call| {
+            immediate(handle_legacy_zrange(
+                call.arguments,
+                call.store,
+                &["BYLEX", "REV"],
+            ))
+        },
+    ),
+    command("ZREVRANGE", "sorted-set", -4, READONLY, FIRST_KEY, |call| {
+        immediate(handle_legacy_zrange(call.arguments, call.store, &["REV"]))
+    }),
+    command(
+        "ZCOUNT",
+        "sorted-set",
+        4,
+        READONLY | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_zcount(call.arguments, call.store, "BYSCORE")),
+    ),
+    command(
+        "ZLEXCOUNT",
+        "sorted-set",
+        4,
+        READONLY | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_zcount(call.arguments, call.store, "BYLEX")),
+    ),
+    command(
+        "ZRANGESTORE",
+        "sorted-set",
+        -5,
+        WRITE | DENYOOM,
+        &[KeySpec::range(1, 1, 1)],
+        |call| immediate(handle_zrangestore(call.arguments, call.store)),
+    ),
+    command(
+        "ZPOPMIN",
+        "sorted-set",
+        -2,
+        WRITE | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_zpop(call.arguments, call.store, false)),
+    ),
+    command(
+        "ZPOPMAX",
+        "sorted-set",
+        -2,
+        WRITE | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_zpop(call.arguments, call.store, true)),
+    ),
+    command(
+        "ZMPOP",
+        "sorted-set",
+        -4,
+        WRITE,
+        &[KeySpec::numkeys(1)],
+        |call| immediate(handle_zmpop(call.arguments, call.store)),
+    ),
+    command(
+        "BZPOPMIN",
+        "sorted-set",
+        -3,
+        WRITE | FAST | BLOCKING,
+        &[KeySpec::range(1, -2, 1)],
+        |call| handle_bzpop(call.arguments, call.store, false, call.can_block),
+    ),
+    command(
+        "BZPOPMAX",
+        "sorted-set",
+        -3,
+        WRITE | FAST | BLOCKING,
+        &[KeySpec::range(1, -2, 1)],
+        |call| handle_bzpop(call.arguments, call.store, true, call.can_block),
+    ),
+    command(
+        "BZMPOP",
+        "sorted-set",
+        -5,
+        WRITE | BLOCKING,
+        &[KeySpec::numkeys(2)],
+        |call| handle_bzmpop(call.arguments, call.store, call.can_block),
+    ),
+    command(
+        "ZUNION",
+        "sorted-set",
+        -3,
+        READONLY,
+        &[KeySpec::numkeys(1)],
+        |call| {
+            immediate(handle_zset_algebra(
+                call.arguments,
+                call.store,
+                ZSetOperation::Union,
+            ))
+        },
+    ),
+    command(
+        "ZINTER",
+        "sorted-set",
+        -3,
+        READONLY,
+        &[KeySpec::numkeys(1)],
+        |call| {
+            immediate(handle_zset_algebra(
+                call.arguments,
+                call.store,
+                ZSetOperation::Intersection,
+            ))
+        },
+    ),
+    command(
+        "ZDIFF",
+        "sorted-set",
+        -3,
+        READONLY,
+        &[KeySpec::numkeys(1)],
+        |call| {
+            immediate(handle_zset_algebra(
+                call.arguments,
+                call.store,
+                ZSetOperation::Difference,
+            ))
+        },
+    ),
+    command(
+        "ZUNIONSTORE",
+        "sorted-set",
+        -4,
+        WRITE | DENYOOM,
+        STORE_AND_NUMKEYS,
+        |call| {
+            immediate(handle_zset_algebra_store(
+                call.arguments,
+                call.store,
+                ZSetOperation::Union,
+            ))
+        },
+    ),
+    command(
+        "ZINTERSTORE",
+        "sorted-set",
+        -4,
+        WRITE | DENYOOM,
+        STORE_AND_NUMKEYS,
+        |call| {
+            immediate(handle_zset_algebra_store(
+                call.arguments,
+                call.store,
+                ZSetOperation::Intersection,
+            ))
+        },
+    ),
+    command(
+        "ZDIFFSTORE",
+        "sorted-set",
+        -4,
+        WRITE | DENYOOM,
+        STORE_AND_NUMKEYS,
+        |call| {
+            immediate(handle_zset_algebra_store(
+                call.arguments,
+                call.store,
+                ZSetOperation::Difference,
+            ))
+        },
+    ),
+    command(
+        "ZRANDMEMBER",
+        "sorted-set",
+        -2,
+        READONLY,
+        FIRST_KEY,
+        |call| immediate(handle_zrandmember(call.arguments, call.store)),
+    ),
+    command("ZSCAN", "sorted-set", -3, READONLY, FIRST_KEY, |call| {
+        immediate(handle_zscan(call.arguments, call.store))
+    }),
+    command("ZREM", "sorted-set", -3, WRITE | FAST, FIRST_KEY, |call| {
+        immediate(handle_zrem(call.arguments, call.store))
+    }),
+    // streams
+    command(
+        "XADD",
+        "stream",
+        -5,
+        WRITE | DENYOOM | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_xadd(call.arguments, call.store)),
+    ),
+    command("XRANGE", "stream", -4, READONLY, FIRST_KEY, |call| {
+        immediate(handle_xrange(call.arguments, call.store))
+    }),
+    command("XREVRANGE", "stream", -4, READONLY, FIRST_KEY, |call| {
+        immediate(handle_xrevrange(call.arguments, call.store))
+    }),
+    command("XDEL", "stream", -3, WRITE | FAST, FIRST_KEY, |call| {
+        immediate(handle_xdel(call.arguments, call.store))
+    }),
+    command("XTRIM", "stream", -4, WRITE, FIRST_KEY, |call| {
+        immediate(handle_xtrim(call.arguments, call.store))
+    }),
+    command(
+        "XSETID",
+        "stream",
+        -3,
+        WRITE | DENYOOM | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_xsetid(call.arguments, call.store)),
+    ),
+    command(
+        "XINFO",
+        "stream",
+        -2,
+        READONLY,
+        &[KeySpec::range(2, 0, 1)],
+        |call| immediate(handle_xinfo(call.arguments, call.store)),
+    ),
+    command(
+        "XGROUP",
+        "stream",
+        -2,
+        WRITE,
+        &[KeySpec::range(2, 0, 1)],
+        |call| immediate(handle_xgroup(call.arguments, call.store)),
+    ),
+    command(
+        "XREAD",
+        "stream",
+        -4,
+        READONLY | BLOCKING,
+        &[after_streams(1)],
+        |call| handle_xread(call.arguments, call.store, call.can_block),
+    ),
+    command(
+        "XREADGROUP",
+        "stream",
+        -7,
+        WRITE | BLOCKING,
+        &[after_streams(4)],
+        |call| handle_xreadgroup(call.arguments, call.store, call.can_block),
+    ),
+    command("XPENDING", "stream", -3, READONLY, FIRST_KEY, |call| {
+        immediate(handle_xpending(call.arguments, call.store))
+    }),
+    command("XCLAIM", "stream", -6, WRITE | FAST, FIRST_KEY, |call| {
+        immediate(handle_xclaim(call.arguments, call.store))
+    }),
+    command(
+        "XAUTOCLAIM",
+        "stream",
+        -6,
+        WRITE | FAST,
+        FIRST_KEY,
+        |call| immediate(handle_xautoclaim(call.arguments, call.store)),
+    ),
+    // pub/sub
+    command("SUBSCRIBE", "pubsub", -2, NO_MULTI, NO_KEYS, |call| {
+        handle_subscribe(
+            call.arguments,
+            call.store,
+            call.client_id,
+            SubscriptionKind::Channel,
+        )
+    }),
+    command("UNSUBSCRIBE", "pubsub", -1, NO_MULTI, NO_KEYS, |call| {
+        handle_unsubscribe(
+            call.arguments,
+            call.store,
+            call.client_id,
+            SubscriptionKind::Channel,
+        )
+    }),
+    command("PSUBSCRIBE", "pubsub", -2, NO_MULTI, NO_KEYS, |call| {
+        handle_subscribe(
+            call.arguments,
+            call.store,
+            call.client_id,
+            SubscriptionKind::Pattern,
+        )
+    }),
+    command("PUNSUBSCRIBE", "pubsub", -1, NO_MULTI, NO_KEYS, |call| {
+        handle_unsubscribe(
+            call.arguments,
+            call.store,
+            call.client_id,
+            SubscriptionKind::Pattern,
+        )
+    }),
+    command("SSUBSCRIBE", "pubsub", -2, NO_MULTI, NO_KEYS, |call| {
+        handle_subscribe(
+            call.arguments,
+            call.store,
+            call.client_id,
+            SubscriptionKind::ShardChannel,
+        )
+    }),
+    command("SUNSUBSCRIBE", "pubsub", -1, NO_MULTI, NO_KEYS, |call| {
+        handle_unsubscribe(
+            call.arguments,
+            call.store,
+            call.client_id,
+            SubscriptionKind::ShardChannel,
+        )
+    }),
+    command(
+        "PUBLISH",
+        "pubsub",
+        3,
+        FAST | MAY_REPLICATE,
+        NO_KEYS,
+        |call| immediate(handle_publish(call.arguments, call.store)),
+    ),
+    command(
+        "SPUBLISH",
+        "pubsub",
+        3,
+        FAST | MAY_REPLICATE,
+        NO_KEYS,
+        |call| immediate(handle_spublish(call.arguments, call.store)),
+    ),
+    command("PUBSUB", "pubsub", -2, 0, NO_KEYS, |call| {
+        immediate(handle_pubsub(call.arguments, call.store))
+    }),
+    // connections
+    command("CLIENT", "connection", -2, 0, NO_KEYS, |call| {
+        immediate(handle_client(call.arguments, call.store, call.client_id))
+    }),
+    command("HELLO", "connection", -1, FAST, NO_KEYS, |call| {
+        immediate(handle_hello(call.arguments, call.store, call.client_id))
+    }),
+    command("RESET", "connection", 1, FAST, NO_KEYS, |call| {
+        call.store.reset_client(call.client_id);
+        simple_string("RESET")
+    }),
+    command("QUIT", "connection", -1, FAST, NO_KEYS, |_| {
+        Ok(CommandResponse::Quit)
+    }),
+    command("MONITOR", "server", 1, NO_MULTI, NO_KEYS, |call| {
+        call.store.start_monitor(call.client_id);
+        simple_string("OK")
+    }),
+    // transactions
+    command("MULTI", "transactions", 1, FAST, NO_KEYS, |call| {
+        Ok(CommandResponse::Immediate(handle_multi(
+            call.store,
+            call.client_id,
+        )))
+    }),
+    command("EXEC", "transactions", 1, 0, NO_KEYS, |call| {
+        Ok(CommandResponse::Immediate(handle_exec(
+            call.store,
+            call.client_id,
+        )))
+    }),
+    command("DISCARD", "transactions", 1, FAST, NO_KEYS, |call| {
+        Ok(CommandResponse::Immediate(handle_discard(
+            call.store,
+            call.client_id,
+        )))
+    }),
+    command(
+        "WATCH",
+        "transactions",
+        -2,
+        FAST,
+        &[KeySpec::range(1, -1, 1)],
+        |call| immediate(handle_watch(call.arguments, call.store, call.client_id)),
+    ),
+    command("UNWATCH", "transactions", 1, FAST, NO_KEYS, |call| {
+        Ok(CommandResponse::Immediate(handle_unwatch(
+            call.store,
+            call.client_id,
+        )))
+    }),
+];
+
+static BY_NAME: LazyLock<HashMap<&'static str, &'static CommandSpec>> =
+    LazyLock::new(|| COMMANDS.iter().map(|spec| (spec.name, spec)).collect());
+
+/// The command named (in upper case) `name`, `None` for unknown commands
+pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    BY_NAME.get(name).copied()
+}
+
+/// Whether the command takes `count` arguments, its name included
+pub fn arity_matches(arity: i32, count: usize) -> bool {
+    if arity < 0 {
+        count >= arity.unsigned_abs() as usize
+    } else {
+        count == arity as usize
+    }
+}
+
+/// Positions of the keys in the command with the arguments, as the table finds them
+#[cfg(test)]
+fn key_positions(name: &str, arguments: &[&str]) -> Vec<usize> {
+    let elements: Vec<RedisType> = std::iter::once(name)
+        .chain(arguments.iter().copied())
+        .map(|argument| RedisType::BulkString(Bytes::copy_from_slice(argument.as_bytes())))
+        .collect();
+    lookup(name).unwrap().key_positions(&elements)
+}
+
+#[test]
+fn test_arity() {
+    assert_eq!(lookup("GET").map(|spec| spec.arity), Some(2));
+    assert!(lookup("FOO").is_none());
+    assert!(arity_matches(2, 2));
+    assert!(!arity_matches(2, 3));
+    assert!(arity_matches(-3, 4));
+    assert!(!arity_matches(-3, 2));
+    assert!(lookup("SET").unwrap().has(WRITE));
+    assert!(!lookup("GET").unwrap().has(WRITE));
+    assert!(lookup("BLPOP").unwrap().has(BLOCKING));
+}
+
+#[test]
+fn test_key_positions() {
+    assert_eq!(key_positions("GET", &["a"]), vec![1]);
+    assert_eq!(key_positions("SINTER", &["a", "b", "c"]), vec![1, 2, 3]);
+    // the timeout comes after the keys
+    assert_eq!(key_positions("BLPOP", &["a", "b", "0"]), vec![1, 2]);
+    assert_eq!(key_positions("RPOPLPUSH", &["a", "b"]), vec![1, 2]);
+    assert!(key_positions("PING", &[]).is_empty());
+    assert_eq!(
+        key_positions("XREAD", &["COUNT", "2", "streams", "a", "b", "0", "0"]),
+        vec![4, 5]
+    );
+    assert_eq!(
+        key_positions("ZUNIONSTORE", &["d", "2", "a", "b", "WEIGHTS", "1", "2"]),
+        vec![1, 3, 4]
+    );
+    assert_eq!(
+        key_positions("BLMPOP", &["0", "2", "a", "b", "LEFT"]),
+        vec![3, 4]
+    );
+    // more keys announced than given
+    assert_eq!(key_positions("LMPOP", &["3", "a", "LEFT"]), vec![2, 3]);
+    assert!(key_positions("SINTERCARD", &["x", "a"]).is_empty());
+
+    assert_eq!(lookup("SINTER").unwrap().legacy_key_range(), (1, -1, 1));
+    assert_eq!(lookup("LMOVE").unwrap().legacy_key_range(), (1, 2, 1));
+    assert_eq!(lookup("XREAD").unwrap().legacy_key_range(), (0, 0, 0));
+    assert!(lookup("XREAD").unwrap().has_movable_keys());
+}
+
+#[test]
+fn test_key_positions_of_lmpop_blmpop_and_xreadgroup() {
+    assert_eq!(
+        key_positions("LMPOP", &["2", "a", "b", "LEFT", "COUNT", "3"]),
+        vec![2, 3]
+    );
+    assert_eq!(key_positions("LMPOP", &["1", "a", "RIGHT"]), vec![2]);
+    assert!(key_positions("LMPOP", &["x", "a", "LEFT"]).is_empty());
+    assert_eq!(
+        key_positions("BLMPOP", &["0.5", "1", "a", "LEFT", "COUNT", "2"]),
+        vec![3]
+    );
+    assert_eq!(
+        key_positions("BLMPOP", &["0", "3", "a", "b", "c", "RIGHT"]),
+        vec![3, 4, 5]
+    );
+
+    assert_eq!(
+        key_positions(
+            "XREADGROUP",
+            &[
+                "GROUP", "g", "c", "COUNT", "1", "STREAMS", "a", "b", ">", ">"
+            ]
+        ),
+        vec![7, 8]
+    );
+    // group and consumer names come before the keyword is searched for
+    assert_eq!(
+        key_positions(
+            "XREADGROUP",
+            &["GROUP", "streams", "streams", "STREAMS", "a", "0"]
+        ),
+        vec![5]
+    );
+    assert!(key_positions("XREADGROUP", &["GROUP", "g", "c", "COUNT", "1"]).is_empty());
+
+    for name in ["LMPOP", "BLMPOP", "XREADGROUP"] {
+        let spec = lookup(name).unwrap();
+        assert!(spec.has_movable_keys());
+        assert_eq!(spec.legacy_key_range(), (0, 0, 0));
+    }
+}
+
+#[test]
+fn test_legacy_range_shims_look_like_zrange() {
+    let shims = [
+        ("ZRANGEBYSCORE", -4, &["z", "0", "1", "WITHSCORES"][..]),
+        ("ZREVRANGEBYSCORE", -4, &["z", "1", "0", "LIMIT", "0", "1"]),
+        ("ZRANGEBYLEX", -4, &["z", "-", "+"]),
+        ("ZREVRANGEBYLEX", -4, &["z", "+", "-"]),
+        ("ZREVRANGE", -4, &["z", "0", "-1"]),
+        ("ZCOUNT", 4, &["z", "0", "1"]),
+        ("ZLEXCOUNT", 4, &["z", "-", "+"]),
+    ];
+    for (name, arity, arguments) in shims {
+        let spec = lookup(name).unwrap();
+        assert_eq!(spec.arity, arity, "{}", name);
+        assert!(arity_matches(spec.arity, arguments.len() + 1), "{}", name);
+        assert!(spec.has(READONLY) && !spec.has(WRITE), "{}", name);
+        assert_eq!(spec.has(FAST), arity == 4, "{}", name);
+        assert!(!spec.has_movable_keys(), "{}", name);
+
+        // the legacy range COMMAND reports covers the same keys as the key specifications
+        let positions = key_positions(name, arguments);
+        assert_eq!(positions, vec![1], "{}", name);
+        let (first, last, step) = spec.legacy_key_range();
+        let last = if last < 0 {
+            (arguments.len() as isize + 1 + last) as usize
+        } else {
+            last as usize
+        };
+        assert_eq!(
+            (first..=last).step_by(step).collect::<Vec<_>>(),
+            positions,
+            "{}",
+            name
+        );
+        assert_eq!(
+            spec.legacy_key_range(),
+            lookup("ZRANGE").unwrap().legacy_key_range()
+        );
+    }
+}
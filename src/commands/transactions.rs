@@ -0,0 +1,91 @@
+use std::collections::VecDeque;
+
+use bytes::Bytes;
+
+use super::{CommandError, CommandResponse, execute_command, utils::argument_as_bytes};
+use crate::{parser::RedisType, store::Store};
+
+pub fn handle_multi(store: &mut Store, client_id: u64) -> RedisType {
+    let client = store.client_mut(client_id);
+    if client.transaction.is_some() {
+        return RedisType::SimpleError(Bytes::from("ERR MULTI calls can not be nested"));
+    }
+    client.transaction = Some(VecDeque::new());
+    RedisType::SimpleString(Bytes::from("OK"))
+}
+
+/// Runs the queued commands at once, unless a command was refused while queuing or a watched
+/// key was modified. Either way the client leaves the transaction and unwatches its keys.
+pub fn handle_exec(store: &mut Store, client_id: u64) -> RedisType {
+    let client = store.client_mut(client_id);
+    let Some(transaction) = client.transaction.take() else {
+        return RedisType::SimpleError(Bytes::from("ERR EXEC without MULTI"));
+    };
+    let failed = std::mem::take(&mut client.transaction_failed);
+    let watched = std::mem::take(&mut client.watched);
+    let unchanged = store.watched_keys_unchanged(&watched);
+    store.unwatch(&watched);
+    if failed {
+        return RedisType::SimpleError(Bytes::from(
+            "EXECABORT Transaction discarded because of previous errors.",
+        ));
+    }
+    if !unchanged {
+        // a watched key was modified, the transaction is aborted
+        return RedisType::Array(None);
+    }
+
+    let mut responses = Vec::new();
+    for comm in transaction {
+        // a failing command doesn't stop the others, its error is part of the reply
+        let f = match execute_command(comm, store, client_id, false) {
+            Ok(CommandResponse::Immediate(redis_type)) => redis_type,
+            Err(err) => err.to_reply(),
+            // blocking commands don't block here, commands which push were refused when queued
+            Ok(_) => unreachable!("the queued commands reply right away"),
+        };
+        responses.push(f);
+    }
+    RedisType::Array(Some(responses))
+}
+
+pub fn handle_discard(store: &mut Store, client_id: u64) -> RedisType {
+    let client = store.client_mut(client_id);
+    if client.transaction.take().is_none() {
+        return RedisType::SimpleError(Bytes::from("ERR DISCARD without MULTI"));
+    }
+    client.transaction_failed = false;
+    let watched = std::mem::take(&mut client.watched);
+    store.unwatch(&watched);
+    RedisType::SimpleString(Bytes::from("OK"))
+}
+
+/// Watches the keys for the next EXEC of the client
+pub fn handle_watch(
+    arguments: &[RedisType],
+    store: &mut Store,
+    client_id: u64,
+) -> Result<RedisType, CommandError> {
+    if store.client(client_id).transaction.is_some() {
+        return Err(CommandError::InvalidInput(
+            "WATCH inside MULTI is not allowed".into(),
+        ));
+    }
+    if arguments.is_empty() {
+        return Err(CommandError::InvalidInput(
+            "wrong number of arguments for 'watch' command".into(),
+        ));
+    }
+    let keys = (0..arguments.len())
+        .map(|index| argument_as_bytes(arguments, index).cloned())
+        .collect::<Result<Vec<Bytes>, CommandError>>()?;
+    let versions = store.watch(&keys);
+    store.client_mut(client_id).watched.extend(versions);
+    Ok(RedisType::SimpleString(Bytes::from("OK")))
+}
+
+pub fn handle_unwatch(store: &mut Store, client_id: u64) -> RedisType {
+    let watched = std::mem::take(&mut store.client_mut(client_id).watched);
+    store.unwatch(&watched);
+    RedisType::SimpleString(Bytes::from("OK"))
+}
@@ -3,7 +3,10 @@ use std::{collections::HashMap, str::FromStr};
 use bytes::Bytes;
 
 use super::CommandError;
-use crate::{parser::RedisType, store::StreamId};
+use crate::{
+    parser::RedisType,
+    store::{StoreError, StreamId},
+};
 
 pub fn argument_as_bytes(arguments: &[RedisType], index: usize) -> Result<&Bytes, CommandError> {
     let bytes = match arguments.get(index) {
@@ -49,7 +52,231 @@ where
 {
     argument_as_str(arguments, index)?
         .parse::<T>()
-        .map_err(|_| CommandError::InvalidInput("Unable to parse argument to a number".into()))
+        .map_err(|_| CommandError::InvalidInput("value is not an integer or out of range".into()))
+}
+
+/// Timeout of the blocking commands in seconds, 0 blocks forever
+pub fn parse_timeout(arguments: &[RedisType], index: usize) -> Result<f64, CommandError> {
+    let timeout: f64 = argument_as_number(arguments, index)
+        .ok()
+        .filter(|timeout: &f64| !timeout.is_nan())
+        .ok_or_else(|| {
+            CommandError::InvalidInput("timeout is not a float or out of range".into())
+        })?;
+    if timeout < 0.0 {
+        return Err(CommandError::InvalidInput("timeout is negative".into()));
+    }
+    Ok(timeout)
+}
+
+fn syntax_error() -> CommandError {
+    CommandError::InvalidInput("syntax error".into())
+}
+
+/// Walks the options of a command: keywords are matched case-insensitively and may be
+/// followed by a value, a keyword given twice is a syntax error
+pub struct OptionParser<'a> {
+    arguments: &'a [RedisType],
+    position: usize,
+    /// upper cased keywords taken so far
+    seen: Vec<String>,
+}
+
+impl<'a> OptionParser<'a> {
+    pub fn new(arguments: &'a [RedisType]) -> Self {
+        OptionParser {
+            arguments,
+            position: 0,
+            seen: Vec::new(),
+        }
+    }
+
+    /// The arguments which weren't taken yet
+    pub fn rest(&self) -> &'a [RedisType] {
+        &self.arguments[self.position..]
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.position >= self.arguments.len()
+    }
+
+    /// The next argument upper cased, without taking it
+    pub fn peek(&self) -> Option<String> {
+        argument_as_str(self.arguments, self.position)
+            .ok()
+            .map(str::to_ascii_uppercase)
+    }
+
+    /// Takes the next argument if it is the keyword
+    pub fn eat(&mut self, keyword: &str) -> bool {
+        let found = argument_as_str(self.arguments, self.position)
+            .is_ok_and(|next| next.eq_ignore_ascii_case(keyword));
+        if found {
+            self.position += 1;
+            self.seen.push(keyword.to_ascii_uppercase());
+        }
+        found
+    }
+
+    /// Takes the keyword, which has to come next
+    pub fn expect(&mut self, keyword: &str) -> Result<(), CommandError> {
+        match self.eat(keyword) {
+            true => Ok(()),
+            false => Err(syntax_error()),
+        }
+    }
+
+    /// Takes the next argument as an option keyword, upper cased. `None` once all the
+    /// arguments are taken.
+    pub fn next_option(&mut self) -> Result<Option<String>, CommandError> {
+        if self.is_done() {
+            return Ok(None);
+        }
+        let option = argument_as_str(self.arguments, self.position)?.to_ascii_uppercase();
+        if self.seen(&option) {
+            return Err(syntax_error());
+        }
+        self.position += 1;
+        self.seen.push(option.clone());
+        Ok(Some(option))
+    }
+
+    /// Whether the keyword was taken
+    pub fn seen(&self, keyword: &str) -> bool {
+        self.seen
+            .iter()
+            .any(|seen| seen.eq_ignore_ascii_case(keyword))
+    }
+
+    /// Fails with a syntax error when more than one of the keywords were taken
+    pub fn exclusive(&self, keywords: &[&str]) -> Result<(), CommandError> {
+        match keywords.iter().filter(|keyword| self.seen(keyword)).count() {
+            0 | 1 => Ok(()),
+            _ => Err(syntax_error()),
+        }
+    }
+
+    /// Takes the next argument as it is
+    pub fn argument(&mut self) -> Result<&'a RedisType, CommandError> {
+        let argument = self.arguments.get(self.position).ok_or_else(syntax_error)?;
+        self.position += 1;
+        Ok(argument)
+    }
+
+    /// Takes the value of the option just taken
+    pub fn value(&mut self) -> Result<&'a Bytes, CommandError> {
+        redis_type_as_bytes(self.argument()?)
+    }
+
+    pub fn value_as_str(&mut self) -> Result<&'a str, CommandError> {
+        if self.is_done() {
+            return Err(syntax_error());
+        }
+        self.position += 1;
+        argument_as_str(self.arguments, self.position - 1)
+    }
+
+    pub fn value_as_number<T>(&mut self) -> Result<T, CommandError>
+    where
+        T: FromStr,
+    {
+        if self.is_done() {
+            return Err(syntax_error());
+        }
+        self.position += 1;
+        argument_as_number(self.arguments, self.position - 1)
+    }
+}
+
+pub fn wrong_type_error() -> RedisType {
+    RedisType::SimpleError(Bytes::from_static(
+        b"WRONGTYPE Operation against a key holding the wrong kind of value",
+    ))
+}
+
+/// Builds the reply for a store result, store errors which redis reports to the client
+/// (e.g. WRONGTYPE) are turned into error replies instead of failing the command.
+pub fn reply_from_store<T>(
+    result: Result<T, StoreError>,
+    reply: impl FnOnce(T) -> RedisType,
+) -> Result<RedisType, CommandError> {
+    match result {
+        Ok(value) => Ok(reply(value)),
+        Err(StoreError::WrongType) => Ok(wrong_type_error()),
+        Err(error) => Err(CommandError::StoreError(error)),
+    }
+}
+
+/// Glob-style matching as used by KEYS, SCAN and friends: supports `*`, `?`, character
+/// classes like `[a-z]` or `[^abc]` and `\` to escape special characters.
+pub fn glob_match(pattern: &[u8], input: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // position of the last `*` in the pattern and the input position it currently covers
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while s < input.len() {
+        let step = match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, s));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(1),
+            Some(b'[') => match match_class(pattern, p, input[s]) {
+                (true, next) => Some(next - p),
+                (false, _) => None,
+            },
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == input[s]).then_some(2),
+            Some(c) => (*c == input[s]).then_some(1),
+            None => None,
+        };
+
+        match (step, backtrack) {
+            (Some(step), _) => {
+                p += step;
+                s += 1;
+            }
+            (None, Some((star, covered))) => {
+                // let the last star swallow one more character and retry
+                p = star + 1;
+                s = covered + 1;
+                backtrack = Some((star, covered + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[p.min(pattern.len())..].iter().all(|c| *c == b'*')
+}
+
+/// Matches `c` against the character class starting at `start` (the `[`), returns whether it
+/// matched and the pattern position after the class.
+fn match_class(pattern: &[u8], start: usize, c: u8) -> (bool, usize) {
+    let mut p = start + 1;
+    let negate = pattern.get(p) == Some(&b'^');
+    if negate {
+        p += 1;
+    }
+
+    let mut matched = false;
+    while p < pattern.len() && pattern[p] != b']' {
+        if pattern[p] == b'\\' && p + 1 < pattern.len() {
+            p += 1;
+            matched |= pattern[p] == c;
+        } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' && pattern[p + 2] != b']' {
+            let (low, high) = (
+                pattern[p].min(pattern[p + 2]),
+                pattern[p].max(pattern[p + 2]),
+            );
+            matched |= (low..=high).contains(&c);
+            p += 2;
+        } else {
+            matched |= pattern[p] == c;
+        }
+        p += 1;
+    }
+
+    (matched != negate, (p + 1).min(pattern.len()))
 }
 
 pub fn xread_output_to_redis_type(
@@ -75,3 +302,59 @@ pub fn xread_output_to_redis_type(
         RedisType::Array(Some(res)),
     ]))
 }
+
+/// The arguments of a command as the client sends them, for tests
+#[cfg(test)]
+pub fn test_arguments(parts: &[&str]) -> Vec<RedisType> {
+    parts
+        .iter()
+        .map(|part| RedisType::BulkString(Bytes::copy_from_slice(part.as_bytes())))
+        .collect()
+}
+
+/// The command as the client sends it, name and arguments, for tests
+#[cfg(test)]
+pub fn test_command(parts: &[&str]) -> RedisType {
+    RedisType::Array(Some(test_arguments(parts)))
+}
+
+#[test]
+fn test_glob_match() {
+    assert!(glob_match(b"*", b"anything"));
+    assert!(glob_match(b"h?llo", b"hello"));
+    assert!(glob_match(b"h*llo", b"heeeello"));
+    assert!(glob_match(b"h[ae]llo", b"hallo"));
+    assert!(!glob_match(b"h[ae]llo", b"hillo"));
+    assert!(glob_match(b"h[^e]llo", b"hallo"));
+    assert!(!glob_match(b"h[^e]llo", b"hello"));
+    assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+    assert!(glob_match(b"user:*:name", b"user:42:name"));
+    assert!(!glob_match(b"user:*:name", b"user:42:age"));
+    assert!(glob_match(b"h\\*llo", b"h*llo"));
+    assert!(!glob_match(b"h\\*llo", b"hello"));
+}
+
+#[test]
+fn test_option_parser() {
+    let arguments: Vec<RedisType> = ["ex", "10", "NX", "get", "nx"]
+        .into_iter()
+        .map(|argument| RedisType::BulkString(Bytes::from(argument)))
+        .collect();
+    let mut options = OptionParser::new(&arguments);
+    assert_eq!(options.peek().as_deref(), Some("EX"));
+    assert!(!options.eat("PX"));
+    assert_eq!(options.next_option().unwrap().as_deref(), Some("EX"));
+    assert_eq!(options.value_as_number::<u64>().unwrap(), 10);
+    options.expect("nx").unwrap();
+    assert!(options.expect("KEEPTTL").is_err());
+    assert!(options.eat("GET"));
+    assert_eq!(options.rest().len(), 1);
+    assert!(options.exclusive(&["EX", "PX"]).is_ok());
+    assert!(options.exclusive(&["EX", "GET"]).is_err());
+    // NX was given already
+    assert!(options.next_option().is_err());
+    // a missing value
+    let mut options = OptionParser::new(&arguments[..1]);
+    options.next_option().unwrap();
+    assert!(options.value().is_err());
+}
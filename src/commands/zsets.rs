@@ -0,0 +1,762 @@
+use std::ops::Bound::{self, Excluded, Included, Unbounded};
+
+use bytes::Bytes;
+use tokio::sync::oneshot;
+
+use super::{
+    CommandError, CommandResponse,
+    scan::{ScanOptions, scan_reply},
+    utils::{
+        OptionParser, argument_as_bytes, argument_as_number, argument_as_str, extract_key,
+        parse_timeout, redis_type_as_bytes, reply_from_store, wrong_type_error,
+    },
+};
+use crate::{
+    parser::{RedisType, format_double},
+    store::{
+        Aggregate, ScoredMembers, Store, StoreError, ZAddOptions, ZRange, ZRangeBy, ZSetOperation,
+    },
+};
+
+/// ZADD key [NX|XX] [GT|LT] [CH] [INCR] score member [score member ...]
+pub fn handle_zadd(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let mut options = ZAddOptions::default();
+    let mut incr = false;
+    let mut flags = OptionParser::new(&arguments[1..]);
+    while let Some(flag) = flags.peek() {
+        match flag.as_str() {
+            "NX" => options.nx = true,
+            "XX" => options.xx = true,
+            "GT" => options.gt = true,
+            "LT" => options.lt = true,
+            "CH" => options.ch = true,
+            "INCR" => incr = true,
+            _ => break,
+        }
+        flags.eat(&flag);
+    }
+    if options.nx && options.xx {
+        return Err(CommandError::InvalidInput(
+            "XX and NX options at the same time are not compatible".into(),
+        ));
+    }
+    if [options.nx, options.gt, options.lt]
+        .iter()
+        .filter(|set| **set)
+        .count()
+        > 1
+    {
+        return Err(CommandError::InvalidInput(
+            "GT, LT, and/or NX options at the same time are not compatible".into(),
+        ));
+    }
+
+    let scores_and_members = flags.rest();
+    if scores_and_members.is_empty() || !scores_and_members.len().is_multiple_of(2) {
+        return Err(CommandError::InvalidInput("syntax error".into()));
+    }
+    let members = scores_and_members
+        .chunks_exact(2)
+        .map(|pair| {
+            Ok((
+                parse_score(redis_type_as_bytes(&pair[0])?)?,
+                redis_type_as_bytes(&pair[1])?.clone(),
+            ))
+        })
+        .collect::<Result<Vec<(f64, Bytes)>, CommandError>>()?;
+
+    if incr {
+        let [(increment, member)] = members.as_slice() else {
+            return Err(CommandError::InvalidInput(
+                "INCR option supports a single increment-element pair".into(),
+            ));
+        };
+        return score_reply(store.zincrby(key, *increment, member, options));
+    }
+    reply_from_store(store.zadd(key, members, options), |count| {
+        RedisType::Integer(count as i128)
+    })
+}
+
+pub fn handle_zincrby(
+    arguments: &[RedisType],
+    store: &mut Store,
+) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let increment = parse_score(argument_as_bytes(arguments, 1)?)?;
+    let member = argument_as_bytes(arguments, 2)?;
+
+    score_reply(store.zincrby(key, increment, member, ZAddOptions::default()))
+}
+
+/// Reply of the increment forms, a null reply if the update was prevented by the options
+fn score_reply(result: Result<Option<f64>, StoreError>) -> Result<RedisType, CommandError> {
+    if let Err(StoreError::ScoreIsNaN) = result {
+        return Ok(RedisType::SimpleError(Bytes::from(
+            "ERR resulting score is not a number (NaN)",
+        )));
+    }
+    reply_from_store(result, |score| match score {
+        Some(score) => RedisType::Double(score),
+        None => RedisType::NullBulkString,
+    })
+}
+
+pub fn handle_zscore(arguments: &[RedisType], store: &Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let member = argument_as_bytes(arguments, 1)?;
+
+    reply_from_store(store.zscore(key, member), |score| match score {
+        Some(score) => RedisType::Double(score),
+        None => RedisType::NullBulkString,
+    })
+}
+
+pub fn handle_zcard(arguments: &[RedisType], store: &Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+
+    reply_from_store(store.zcard(key), |len| RedisType::Integer(len as i128))
+}
+
+/// ZRANK and ZREVRANK, with `WITHSCORE` the reply also carries the score of the member
+pub fn handle_zrank(
+    arguments: &[RedisType],
+    store: &Store,
+    reverse: bool,
+) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let member = argument_as_bytes(arguments, 1)?;
+    let with_score = match arguments.len() {
+        2 => false,
+        3 if argument_as_str(arguments, 2)?.eq_ignore_ascii_case("WITHSCORE") => true,
+        _ => return Err(CommandError::InvalidInput("syntax error".into())),
+    };
+
+    reply_from_store(store.zrank(key, member, reverse), |rank| match rank {
+        Some((rank, score)) if with_score => RedisType::Array(Some(vec![
+            RedisType::Integer(rank as i128),
+            RedisType::Double(score),
+        ])),
+        Some((rank, _)) => RedisType::Integer(rank as i128),
+        None if with_score => RedisType::Array(None),
+        None => RedisType::NullBulkString,
+    })
+}
+
+pub fn handle_zrem(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    if arguments.len() < 2 {
+        return Err(CommandError::InvalidInput(
+            "wrong number of arguments for 'zrem' command".into(),
+        ));
+    }
+    let members = arguments[1..]
+        .iter()
+        .map(|member| redis_type_as_bytes(member).cloned())
+        .collect::<Result<Vec<Bytes>, CommandError>>()?;
+
+    reply_from_store(store.zrem(key, &members), |removed| {
+        RedisType::Integer(removed as i128)
+    })
+}
+
+/// ZPOPMIN and ZPOPMAX
+pub fn handle_zpop(
+    arguments: &[RedisType],
+    store: &mut Store,
+    highest: bool,
+) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let count = match arguments.len() {
+        1 => 1,
+        2 => argument_as_number(arguments, 1).map_err(|_| {
+            CommandError::InvalidInput("value is out of range, must be positive".into())
+        })?,
+        _ => return Err(CommandError::InvalidInput("syntax error".into())),
+    };
+
+    // only with a count RESP3 clients get the members as pairs
+    let pairs = arguments.len() == 2 && store.current_protocol() >= 3;
+    reply_from_store(store.zpop(key, count, highest), |members| {
+        range_reply(members, true, pairs)
+    })
+}
+
+/// ZMPOP numkeys key [key ...] MIN|MAX [COUNT count]
+pub fn handle_zmpop(arguments: &[RedisType], store: &mut Store) -> Result<RedisType, CommandError> {
+    let (keys, highest, count) = parse_zmpop(arguments)?;
+
+    reply_from_store(store.zmpop(&keys, count, highest), zmpop_reply)
+}
+
+/// BZPOPMIN and BZPOPMAX: key [key ...] timeout
+pub fn handle_bzpop(
+    arguments: &[RedisType],
+    store: &mut Store,
+    highest: bool,
+    can_block: bool,
+) -> Result<CommandResponse, CommandError> {
+    if arguments.len() < 2 {
+        return Err(CommandError::InvalidInput(
+            "wrong number of arguments for 'bzpopmin|bzpopmax' command".into(),
+        ));
+    }
+    let timeout = parse_timeout(arguments, arguments.len() - 1)?;
+    let keys = arguments[..arguments.len() - 1]
+        .iter()
+        .map(|key| redis_type_as_bytes(key).cloned())
+        .collect::<Result<Vec<Bytes>, CommandError>>()?;
+
+    pop_or_wait(store, keys, 1, highest, timeout, can_block, bzpop_reply)
+}
+
+/// BZMPOP timeout numkeys key [key ...] MIN|MAX [COUNT count]
+pub fn handle_bzmpop(
+    arguments: &[RedisType],
+    store: &mut Store,
+    can_block: bool,
+) -> Result<CommandResponse, CommandError> {
+    let timeout = parse_timeout(arguments, 0)?;
+    let (keys, highest, count) = parse_zmpop(&arguments[1..])?;
+
+    pop_or_wait(
+        store,
+        keys,
+        count,
+        highest,
+        timeout,
+        can_block,
+        |key, members| zmpop_reply(Some((key, members))),
+    )
+}
+
+/// Pops right away if one of the keys holds members, otherwise blocks the client until
+/// a write to one of the keys serves it. A client which can't block (in a transaction) gets
+/// a null reply instead.
+fn pop_or_wait(
+    store: &mut Store,
+    keys: Vec<Bytes>,
+    count: usize,
+    highest: bool,
+    timeout: f64,
+    can_block: bool,
+    reply: fn(Bytes, ScoredMembers) -> RedisType,
+) -> Result<CommandResponse, CommandError> {
+    let popped = match store.zmpop(&keys, count, highest) {
+        Err(StoreError::WrongType) => return Ok(CommandResponse::Immediate(wrong_type_error())),
+        result => result.map_err(CommandError::StoreError)?,
+    };
+    if let Some((key, members)) = popped {
+        return Ok(CommandResponse::Immediate(reply(key, members)));
+    }
+    if !can_block {
+        return Ok(CommandResponse::Immediate(RedisType::Array(None)));
+    }
+
+    let (tx, rx) = oneshot::channel();
+    let client_id = store.register_zpop_waiting_client(keys, count, highest, reply, tx);
+    Ok(CommandResponse::WaitForZPOP {
+        timeout,
+        receiver: rx,
+        client_id,
+    })
+}
+
+/// `[key, member, score]` for the single member popped by BZPOPMIN/BZPOPMAX
+fn bzpop_reply(key: Bytes, members: ScoredMembers) -> RedisType {
+    let mut elements = vec![RedisType::BulkString(key)];
+    if let Some((member, score)) = members.into_iter().next() {
+        elements.push(RedisType::BulkString(member));
+        elements.push(RedisType::Double(score));
+    }
+    RedisType::Array(Some(elements))
+}
+
+/// Parses the arguments shared by ZMPOP and BZMPOP, returns the keys, whether to pop the
+/// highest scores and the count
+fn parse_zmpop(arguments: &[RedisType]) -> Result<(Vec<Bytes>, bool, usize), CommandError> {
+    let numkeys: usize = argument_as_number(arguments, 0)?;
+    if numkeys == 0 {
+        return Err(CommandError::InvalidInput(
+            "numkeys should be greater than 0".into(),
+        ));
+    }
+    let keys = (1..=numkeys)
+        .map(|index| argument_as_bytes(arguments, index).cloned())
+        .collect::<Result<Vec<Bytes>, CommandError>>()?;
+
+    let highest = match argument_as_str(arguments, numkeys + 1)?
+        .to_ascii_uppercase()
+        .as_str()
+    {
+        "MIN" => false,
+        "MAX" => true,
+        _ => return Err(CommandError::InvalidInput("syntax error".into())),
+    };
+    let count = match &arguments[numkeys + 2..] {
+        [] => 1,
+        [_, _] if argument_as_str(arguments, numkeys + 2)?.eq_ignore_ascii_case("COUNT") => {
+            match argument_as_number(arguments, numkeys + 3) {
+                Ok(count) if count > 0 => count,
+                _ => {
+                    return Err(CommandError::InvalidInput(
+                        "count should be greater than 0".into(),
+                    ));
+                }
+            }
+        }
+        _ => return Err(CommandError::InvalidInput("syntax error".into())),
+    };
+    Ok((keys, highest, count))
+}
+
+/// The key and its popped members as `[key, [[member, score], ...]]`, null if nothing was popped
+fn zmpop_reply(popped: Option<(Bytes, ScoredMembers)>) -> RedisType {
+    let Some((key, members)) = popped else {
+        return RedisType::Array(None);
+    };
+    let members = members
+        .into_iter()
+        .map(|(member, score)| {
+            RedisType::Array(Some(vec![
+                RedisType::BulkString(member),
+                RedisType::Double(score),
+            ]))
+        })
+        .collect();
+    RedisType::Array(Some(vec![
+        RedisType::BulkString(key),
+        RedisType::Array(Some(members)),
+    ]))
+}
+
+/// ZRANGE key start stop [BYSCORE|BYLEX] [REV] [LIMIT offset count] [WITHSCORES]
+pub fn handle_zrange(arguments: &[RedisType], store: &Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let (range, with_scores) = parse_range(&arguments[1..], true)?;
+
+    let pairs = store.current_protocol() >= 3;
+    reply_from_store(store.zrange(key, &range), |members| {
+        range_reply(members, with_scores, pairs)
+    })
+}
+
+/// The pre-6.2 range commands (ZRANGEBYSCORE, ZREVRANGE, ...), which are ZRANGE with
+/// some of its options implied
+pub fn handle_legacy_zrange(
+    arguments: &[RedisType],
+    store: &Store,
+    implied_options: &[&'static str],
+) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let range_arguments = with_implied_options(&arguments[1..], implied_options);
+    let (range, with_scores) = parse_range(&range_arguments, true)?;
+
+    let pairs = store.current_protocol() >= 3;
+    reply_from_store(store.zrange(key, &range), |members| {
+        range_reply(members, with_scores, pairs)
+    })
+}
+
+/// ZCOUNT and ZLEXCOUNT, `by` is the ZRANGE option selecting the kind of range
+pub fn handle_zcount(
+    arguments: &[RedisType],
+    store: &Store,
+    by: &'static str,
+) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    if arguments.len() != 3 {
+        return Err(CommandError::InvalidInput("syntax error".into()));
+    }
+    let range_arguments = with_implied_options(&arguments[1..], &[by]);
+    let (range, _) = parse_range(&range_arguments, false)?;
+
+    reply_from_store(store.zcount(key, &range), |count| {
+        RedisType::Integer(count as i128)
+    })
+}
+
+fn with_implied_options(arguments: &[RedisType], options: &[&'static str]) -> Vec<RedisType> {
+    arguments
+        .iter()
+        .cloned()
+        .chain(
+            options
+                .iter()
+                .map(|option| RedisType::BulkString(Bytes::from_static(option.as_bytes()))),
+        )
+        .collect()
+}
+
+/// ZRANGESTORE destination source start stop [BYSCORE|BYLEX] [REV] [LIMIT offset count]
+pub fn handle_zrangestore(
+    arguments: &[RedisType],
+    store: &mut Store,
+) -> Result<RedisType, CommandError> {
+    let destination = extract_key(arguments)?;
+    let source = argument_as_bytes(arguments, 1)?;
+    let (range, _) = parse_range(&arguments[2..], false)?;
+
+    reply_from_store(store.zrangestore(destination, source, &range), |len| {
+        RedisType::Integer(len as i128)
+    })
+}
+
+/// Parses `start stop` and the options of ZRANGE, returns the range and whether WITHSCORES
+/// was given (only accepted with `allow_with_scores`)
+fn parse_range(
+    arguments: &[RedisType],
+    allow_with_scores: bool,
+) -> Result<(ZRange, bool), CommandError> {
+    let start = argument_as_bytes(arguments, 0)?;
+    let stop = argument_as_bytes(arguments, 1)?;
+    let (mut by_score, mut by_lex, mut reverse, mut with_scores) = (false, false, false, false);
+    let mut limit = None;
+
+    let mut index = 2;
+    while index < arguments.len() {
+        match argument_as_str(arguments, index)?
+            .to_ascii_uppercase()
+            .as_str()
+        {
+            "BYSCORE" => by_score = true,
+            "BYLEX" => by_lex = true,
+            "REV" => reverse = true,
+            "WITHSCORES" if allow_with_scores => with_scores = true,
+            "LIMIT" => {
+                let offset: i128 = argument_as_number(arguments, index + 1)?;
+                let count: i128 = argument_as_number(arguments, index + 2)?;
+                limit = Some((offset, count));
+                index += 2;
+            }
+            _ => return Err(CommandError::InvalidInput("syntax error".into())),
+        }
+        index += 1;
+    }
+    if by_score && by_lex {
+        return Err(CommandError::InvalidInput("syntax error".into()));
+    }
+    if limit.is_some() && !by_score && !by_lex {
+        return Err(CommandError::InvalidInput(
+            "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"
+                .into(),
+        ));
+    }
+    if with_scores && by_lex {
+        return Err(CommandError::InvalidInput(
+            "syntax error, WITHSCORES not supported in combination with BYLEX".into(),
+        ));
+    }
+
+    // reversed score and lex ranges are given from max to min
+    let (min, max) = if reverse && (by_score || by_lex) {
+        (stop, start)
+    } else {
+        (start, stop)
+    };
+    let by = if by_score {
+        ZRangeBy::Score(parse_score_bound(min)?, parse_score_bound(max)?)
+    } else if by_lex {
+        ZRangeBy::Lex(parse_lex_bound(min)?, parse_lex_bound(max)?)
+    } else {
+        let as_index = |bound: &Bytes| {
+            std::str::from_utf8(bound)
+                .ok()
+                .and_then(|bound| bound.parse::<i128>().ok())
+                .ok_or_else(|| {
+                    CommandError::InvalidInput("value is not an integer or out of range".into())
+                })
+        };
+        ZRangeBy::Index(as_index(min)?, as_index(max)?)
+    };
+
+    let mut range = ZRange::new(by);
+    range.reverse = reverse;
+    if let Some((offset, count)) = limit {
+        range.offset = offset.max(0) as usize;
+        // a negative count returns everything, a negative offset nothing
+        range.count = match (offset, count) {
+            (offset, _) if offset < 0 => Some(0),
+            (_, count) if count < 0 => None,
+            (_, count) => Some(count as usize),
+        };
+    }
+    // `+` as minimum or `-` as maximum lie beyond every member
+    if by_lex && (min.as_ref() == b"+" || max.as_ref() == b"-") {
+        range.count = Some(0);
+    }
+    Ok((range, with_scores))
+}
+
+/// Parses a bound of a score range: a score, `(` in front makes it exclusive
+fn parse_score_bound(bound: &Bytes) -> Result<Bound<f64>, CommandError> {
+    let invalid = || CommandError::InvalidInput("min or max is not a float".into());
+    let (exclusive, score) = match bound.strip_prefix(b"(") {
+        Some(score) => (true, score),
+        None => (false, bound.as_ref()),
+    };
+    let score = parse_score(&Bytes::copy_from_slice(score)).map_err(|_| invalid())?;
+    Ok(if exclusive {
+        Excluded(score)
+    } else {
+        Included(score)
+    })
+}
+
+/// Parses a bound of a lexicographic range: `[member`, `(member` or `-`/`+` for the
+/// lowest/highest possible member
+fn parse_lex_bound(bound: &Bytes) -> Result<Bound<Bytes>, CommandError> {
+    match bound.first() {
+        Some(b'-' | b'+') if bound.len() == 1 => Ok(Unbounded),
+        Some(b'[') => Ok(Included(bound.slice(1..))),
+        Some(b'(') => Ok(Excluded(bound.slice(1..))),
+        _ => Err(CommandError::InvalidInput(
+            "min or max not valid string range item".into(),
+        )),
+    }
+}
+
+/// The members, with `with_scores` each followed by its score. `pairs` puts each member and
+/// its score into an array of their own, as redis does for RESP3 clients.
+fn range_reply(members: Vec<(Bytes, f64)>, with_scores: bool, pairs: bool) -> RedisType {
+    RedisType::Array(Some(
+        members
+            .into_iter()
+            .flat_map(|(member, score)| {
+                let member = RedisType::BulkString(member);
+                match (with_scores, pairs) {
+                    (true, true) => vec![RedisType::Array(Some(vec![
+                        member,
+                        RedisType::Double(score),
+                    ]))],
+                    (true, false) => vec![member, RedisType::Double(score)],
+                    (false, _) => vec![member],
+                }
+            })
+            .collect(),
+    ))
+}
+
+/// ZUNION, ZINTER and ZDIFF
+pub fn handle_zset_algebra(
+    arguments: &[RedisType],
+    store: &Store,
+    operation: ZSetOperation,
+) -> Result<RedisType, CommandError> {
+    let options = AlgebraOptions::parse(arguments, operation, true)?;
+    let pairs = store.current_protocol() >= 3;
+
+    reply_from_store(
+        store.zcombine(
+            &options.keys,
+            operation,
+            &options.weights,
+            options.aggregate,
+        ),
+        |members| range_reply(members, options.with_scores, pairs),
+    )
+}
+
+/// ZUNIONSTORE, ZINTERSTORE and ZDIFFSTORE
+pub fn handle_zset_algebra_store(
+    arguments: &[RedisType],
+    store: &mut Store,
+    operation: ZSetOperation,
+) -> Result<RedisType, CommandError> {
+    let destination = extract_key(arguments)?;
+    let options = AlgebraOptions::parse(&arguments[1..], operation, false)?;
+
+    let members = match store.zcombine(
+        &options.keys,
+        operation,
+        &options.weights,
+        options.aggregate,
+    ) {
+        Ok(members) => members,
+        result => return reply_from_store(result, |_| RedisType::NullBulkString),
+    };
+    let len = store.zstore(destination, members);
+    Ok(RedisType::Integer(len as i128))
+}
+
+/// `numkeys key [key ...] [WEIGHTS weight ...] [AGGREGATE SUM|MIN|MAX] [WITHSCORES]` of the
+/// sorted set algebra commands, the difference takes neither weights nor an aggregate
+struct AlgebraOptions {
+    keys: Vec<Bytes>,
+    weights: Vec<f64>,
+    aggregate: Aggregate,
+    with_scores: bool,
+}
+
+impl AlgebraOptions {
+    fn parse(
+        arguments: &[RedisType],
+        operation: ZSetOperation,
+        allow_with_scores: bool,
+    ) -> Result<Self, CommandError> {
+        let numkeys: usize = argument_as_number(arguments, 0)?;
+        if numkeys == 0 {
+            return Err(CommandError::InvalidInput(
+                "at least 1 input key is needed for this command".into(),
+            ));
+        }
+        let keys = (1..=numkeys)
+            .map(|index| argument_as_bytes(arguments, index).cloned())
+            .collect::<Result<Vec<Bytes>, CommandError>>()?;
+
+        let mut options = AlgebraOptions {
+            keys,
+            weights: vec![],
+            aggregate: Aggregate::Sum,
+            with_scores: false,
+        };
+        let combines_scores = operation != ZSetOperation::Difference;
+        let mut index = numkeys + 1;
+        while index < arguments.len() {
+            match argument_as_str(arguments, index)?
+                .to_ascii_uppercase()
+                .as_str()
+            {
+                "WEIGHTS" if combines_scores => {
+                    options.weights = (index + 1..=index + numkeys)
+                        .map(|index| {
+                            argument_as_bytes(arguments, index)
+                                .ok()
+                                .and_then(|weight| parse_score(weight).ok())
+                                .ok_or_else(|| {
+                                    CommandError::InvalidInput("weight value is not a float".into())
+                                })
+                        })
+                        .collect::<Result<Vec<f64>, CommandError>>()?;
+                    index += numkeys;
+                }
+                "AGGREGATE" if combines_scores => {
+                    options.aggregate = match argument_as_str(arguments, index + 1)?
+                        .to_ascii_uppercase()
+                        .as_str()
+                    {
+                        "SUM" => Aggregate::Sum,
+                        "MIN" => Aggregate::Min,
+                        "MAX" => Aggregate::Max,
+                        _ => return Err(CommandError::InvalidInput("syntax error".into())),
+                    };
+                    index += 1;
+                }
+                "WITHSCORES" if allow_with_scores => options.with_scores = true,
+                _ => return Err(CommandError::InvalidInput("syntax error".into())),
+            }
+            index += 1;
+        }
+        Ok(options)
+    }
+}
+
+/// ZRANDMEMBER key [count [WITHSCORES]]
+pub fn handle_zrandmember(
+    arguments: &[RedisType],
+    store: &Store,
+) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+
+    if arguments.len() == 1 {
+        return reply_from_store(store.zrandmember(key, 1), |members| {
+            match members.into_iter().next() {
+                Some((member, _)) => RedisType::BulkString(member),
+                None => RedisType::NullBulkString,
+            }
+        });
+    }
+    let count: i128 = argument_as_number(arguments, 1)?;
+    let with_scores = match arguments.len() {
+        2 => false,
+        3 if argument_as_str(arguments, 2)?.eq_ignore_ascii_case("WITHSCORES") => true,
+        _ => return Err(CommandError::InvalidInput("syntax error".into())),
+    };
+    let pairs = store.current_protocol() >= 3;
+    reply_from_store(store.zrandmember(key, count), |members| {
+        range_reply(members, with_scores, pairs)
+    })
+}
+
+pub fn handle_zscan(arguments: &[RedisType], store: &Store) -> Result<RedisType, CommandError> {
+    let key = extract_key(arguments)?;
+    let options = ScanOptions::parse(&arguments[1..], false, false)?;
+
+    reply_from_store(
+        store.zscan(key, options.cursor, options.count),
+        |(cursor, members)| {
+            let elements = members
+                .into_iter()
+                .filter(|(member, _)| options.matches(member))
+                .flat_map(|(member, score)| [member, format_score(score)])
+                .collect();
+            scan_reply(cursor, elements)
+        },
+    )
+}
+
+/// Parses a score, accepting `inf`/`+inf`/`-inf` like redis but rejecting NaN
+fn parse_score(score: &Bytes) -> Result<f64, CommandError> {
+    std::str::from_utf8(score)
+        .ok()
+        .and_then(|score| score.parse::<f64>().ok())
+        .filter(|score| !score.is_nan())
+        .ok_or_else(|| CommandError::InvalidInput("value is not a valid float".into()))
+}
+
+/// Formats a score the way redis replies with doubles, see `format_double`
+pub fn format_score(score: f64) -> Bytes {
+    Bytes::from(format_double(score))
+}
+
+#[test]
+fn test_format_score() {
+    assert_eq!(format_score(1.0), Bytes::from("1"));
+    assert_eq!(format_score(-2.5), Bytes::from("-2.5"));
+    assert_eq!(format_score(0.1), Bytes::from("0.1"));
+    assert_eq!(format_score(f64::NEG_INFINITY), Bytes::from("-inf"));
+    assert_eq!(format_score(1e20), Bytes::from("1e+20"));
+    assert_eq!(format_score(1.5e-7), Bytes::from("1.5e-07"));
+}
+
+#[test]
+fn test_resp3_clients_get_scores_as_doubles_in_pairs() {
+    use crate::{
+        commands::{CommandResponse, handle_command, utils::test_command},
+        store::{ProtocolVersion, test_client},
+    };
+
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 1);
+    fn reply(store: &mut Store, command: &[&str]) -> RedisType {
+        match handle_command(test_command(command), store, 1) {
+            Ok(CommandResponse::Immediate(reply)) => reply,
+            _ => panic!("{:?} should reply right away", command),
+        }
+    }
+    reply(&mut store, &["ZADD", "z", "1", "a", "2.5", "b", "3", "c"]);
+    let member = |member: &str| RedisType::BulkString(Bytes::from(member.to_string()));
+    let pair =
+        |name: &str, score| RedisType::Array(Some(vec![member(name), RedisType::Double(score)]));
+
+    // RESP2 clients get a flat array, the doubles are sent as bulk strings
+    assert_eq!(
+        reply(&mut store, &["ZRANGE", "z", "0", "0", "WITHSCORES"]),
+        RedisType::Array(Some(vec![member("a"), RedisType::Double(1.0)]))
+    );
+
+    store.client_mut(1).protocol = ProtocolVersion::new(3);
+    assert_eq!(
+        reply(&mut store, &["ZRANGE", "z", "0", "1", "WITHSCORES"]),
+        RedisType::Array(Some(vec![pair("a", 1.0), pair("b", 2.5)]))
+    );
+    assert_eq!(
+        reply(&mut store, &["ZPOPMIN", "z"]),
+        RedisType::Array(Some(vec![member("a"), RedisType::Double(1.0)]))
+    );
+    assert_eq!(
+        reply(&mut store, &["ZPOPMAX", "z", "1"]),
+        RedisType::Array(Some(vec![pair("c", 3.0)]))
+    );
+}
@@ -1,31 +1,41 @@
-use std::{
-    collections::{HashMap, VecDeque},
-    fmt::Display,
-    time::Duration,
-};
+use std::{collections::VecDeque, fmt::Display, net::SocketAddr};
 
 use bytes::{Bytes, BytesMut};
+use futures_util::{SinkExt, StreamExt};
 use tokio::{
-    io::{self, AsyncReadExt, AsyncWriteExt},
-    net::{TcpListener, TcpStream},
+    io::{self, AsyncReadExt},
+    net::{
+        TcpListener, TcpStream,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+    },
     sync::{
-        mpsc::{self, Sender},
+        mpsc::{self, Sender, UnboundedReceiver},
         oneshot,
     },
-    time::timeout,
+    time::{Instant, sleep_until},
 };
+use tokio_util::codec::{FramedRead, FramedWrite};
+use tracing::{Instrument, Span, debug, error, info, info_span, warn};
 
 use crate::{
-    commands::{CommandResponse, handle_command},
-    parser::{RedisType, RespParseError, parse_resp},
-    store::Store,
+    codec::{CodecError, RespCodec},
+    commands::{CommandResponse, handle_command, is_held_by_pause},
+    metrics::BufferGauge,
+    parser::{ProtocolLimits, RedisType, RespParseError},
+    store::{ClientState, ProtocolVersion, RdbConfig, Store, engine_from_name},
     transactions::create_identifier,
 };
+mod codec;
 mod commands;
+mod metrics;
 mod parser;
 mod store;
 mod transactions;
 
+const INITIAL_BUFFER_CAPACITY: usize = 1024;
+/// Read buffers which grew beyond this (e.g. for one huge command) are shrunk again once drained
+const BUFFER_RECLAIM_THRESHOLD: usize = 64 * 1024;
+
 #[derive(Debug)]
 enum RedisError {
     InvalidResp(RespParseError),
@@ -37,39 +47,201 @@ enum RedisError {
 enum RedisMessage {
     SendMessage {
         message: RedisType,
-        transaction: Option<VecDeque<RedisType>>,
+        /// the client which sent the command
+        client_id: u64,
         reply: oneshot::Sender<CommandResponse>,
+        /// span of the sending client, so errors during dispatch can be attributed to it
+        span: Span,
     },
-    SendTimeout {
-        key: Option<Bytes>,
-        identifier: u64,
-    },
+    /// a client connected, its replies and pushes go to the outbox in its state
+    Register { client: Box<ClientState> },
+    /// the client disconnected, the store forgets its subscriptions and watched keys
+    Disconnect { identifier: u64 },
+    /// the blocked client disconnected
+    Unregister { identifier: u64 },
 }
 
-async fn handle_connection(
-    mut stream: TcpStream,
+/// A command which waits for CLIENT PAUSE to end
+struct HeldCommand {
+    message: RedisType,
+    client_id: u64,
+    reply: oneshot::Sender<CommandResponse>,
+    span: Span,
+}
+
+/// Runs the command in the store task and sends the response back to the connection
+fn run_command(
+    store: &mut Store,
+    message: RedisType,
+    client_id: u64,
+    reply: oneshot::Sender<CommandResponse>,
+    span: Span,
+) {
+    let _entered = span.enter();
+    debug!(?message, "received command");
+    let command = handle_command(message, store, client_id);
+    if let Ok(response) = &command
+        && let Some((client_id, timeout)) = response.blocking_timeout()
+    {
+        store.set_waiting_timeout(client_id, timeout);
+    }
+    store.serve_blocked_clients();
+    match command {
+        Ok(response) => {
+            let _ = reply.send(response);
+        }
+        Err(err) => {
+            warn!(error = %err, "command failed");
+            let _ = reply.send(CommandResponse::Immediate(err.to_reply()));
+        }
+    }
+}
+
+/// Name of the command in a request, used to annotate the command span
+fn command_name(request: &RedisType) -> String {
+    match request {
+        RedisType::Array(Some(elements)) => match elements.first() {
+            Some(RedisType::BulkString(name)) => String::from_utf8_lossy(name).to_ascii_uppercase(),
+            _ => String::new(),
+        },
+        _ => String::new(),
+    }
+}
+
+/// Waits for the store to reply to a blocked client. The socket is watched meanwhile, so a
+/// client which closes its connection stops waiting right away and the store is told to forget
+/// it, `None` is returned then.
+async fn wait_for_reply(
+    reader: &mut FramedRead<OwnedReadHalf, RespCodec>,
     sender: &Sender<RedisMessage>,
-) -> Result<(), RedisError> {
-    let mut buffer = BytesMut::with_capacity(1024);
-    let client_id = create_identifier();
-    let mut transactions: Option<VecDeque<RedisType>> = None;
-    loop {
-        println!("Waiting for data for client: {}", client_id);
-        let read_length = stream
-            .read_buf(&mut buffer)
-            .await
-            .map_err(RedisError::Networking)?;
+    client_id: u64,
+    receiver: oneshot::Receiver<RedisType>,
+) -> Result<Option<RedisType>, RedisError> {
+    tokio::select! {
+        reply = receiver => reply.map(Some).map_err(|_| RedisError::Concurrency),
+        _ = connection_closed(reader) => {
+            debug!("disconnected while blocked, unregistering");
+            sender
+                .send(RedisMessage::Unregister {
+                    identifier: client_id,
+                })
+                .await
+                .map_err(|_| RedisError::Concurrency)?;
+            Ok(None)
+        }
+    }
+}
+
+/// Resolves once the client closed its connection. Whatever it sends until then is added to
+/// the read buffer of the codec for after the wait.
+async fn connection_closed(reader: &mut FramedRead<OwnedReadHalf, RespCodec>) {
+    let mut chunk = BytesMut::with_capacity(INITIAL_BUFFER_CAPACITY);
+    while let Ok(read_length) = reader.get_mut().read_buf(&mut chunk).await {
         if read_length == 0 {
-            println!("Client {} closed connection", client_id);
             break;
         }
-        let result = parse_resp(&mut buffer).map_err(RedisError::InvalidResp)?;
+        reader.read_buffer_mut().extend_from_slice(&chunk);
+        chunk.clear();
+    }
+}
+
+/// BytesMut never gives capacity back on its own, once a big request grew the read buffer past
+/// the threshold, what's left of it is moved into a fresh buffer
+fn reclaim_buffer(buffer: &mut BytesMut) {
+    if buffer.capacity() > BUFFER_RECLAIM_THRESHOLD {
+        let mut reclaimed = BytesMut::with_capacity(INITIAL_BUFFER_CAPACITY.max(buffer.len()));
+        reclaimed.extend_from_slice(buffer);
+        *buffer = reclaimed;
+    }
+}
+
+/// Writes what is put into the outbox of a client to its socket: the replies to its commands
+/// as well as pushes like published messages. Frames which queued up meanwhile are written at
+/// once, the codec encodes them for the RESP version the client negotiated. Ends once every
+/// sender of the outbox is gone.
+async fn write_outbox(
+    mut writer: FramedWrite<OwnedWriteHalf, RespCodec>,
+    mut inbox: UnboundedReceiver<RedisType>,
+) -> Result<(), RedisError> {
+    while let Some(frame) = inbox.recv().await {
+        writer.feed(frame).await?;
+        while let Ok(frame) = inbox.try_recv() {
+            writer.feed(frame).await?;
+        }
+        writer.flush().await?;
+    }
+    Ok(())
+}
+
+/// Reads and runs the commands of the client until it disconnects, while a writer task sends
+/// whatever lands in its outbox. The client registers itself with the store first, which keeps
+/// its state and pushes to its outbox as well, e.g. messages published to its channels.
+async fn handle_connection(
+    stream: TcpStream,
+    client_id: u64,
+    addr: SocketAddr,
+    sender: &Sender<RedisMessage>,
+    limits: ProtocolLimits,
+    protocol_version: u8,
+) -> Result<(), RedisError> {
+    let laddr = stream.local_addr().map_err(RedisError::Networking)?;
+    let (reader, writer) = stream.into_split();
+    let (outbox, inbox) = mpsc::unbounded_channel();
+    // HELLO switches the version in the store, the codec encodes with it
+    let protocol = ProtocolVersion::new(protocol_version);
+    let codec = RespCodec::new(limits, protocol.clone());
+    let mut reader = FramedRead::with_capacity(reader, codec.clone(), INITIAL_BUFFER_CAPACITY);
+    let writer = FramedWrite::new(writer, codec);
+    tokio::spawn(
+        async move {
+            if let Err(e) = write_outbox(writer, inbox).await {
+                error!(error = %e, "writing to the client failed");
+            }
+        }
+        .in_current_span(),
+    );
+    let mut client = ClientState::new(client_id, addr, laddr, outbox.clone());
+    client.protocol = protocol;
+    sender
+        .send(RedisMessage::Register {
+            client: Box::new(client),
+        })
+        .await
+        .map_err(|_| RedisError::Concurrency)?;
+    // the writer is gone once the client can't be written to anymore
+    let send = |frame: RedisType| outbox.send(frame).map_err(|_| RedisError::Concurrency);
+
+    let mut buffer_gauge = BufferGauge::default();
+    loop {
+        let result = match reader.next().await {
+            Some(Ok(result)) => result,
+            Some(Err(CodecError::Protocol(err))) => {
+                // like redis, the client learns why before the connection is closed
+                send(RedisType::SimpleError(Bytes::from(format!(
+                    "ERR Protocol error: {}",
+                    err
+                ))))?;
+                return Err(RedisError::InvalidResp(err));
+            }
+            Some(Err(CodecError::Io(err))) => return Err(RedisError::Networking(err)),
+            None => {
+                debug!("closed connection");
+                break;
+            }
+        };
+        let buffer = reader.read_buffer_mut();
+        buffer_gauge.update(buffer.capacity());
+        reclaim_buffer(buffer);
+        buffer_gauge.update(buffer.capacity());
+        let name = command_name(&result);
+        let command_span = info_span!("command", name = %name);
 
         let (reply_tx, reply_rx) = oneshot::channel();
         let message = RedisMessage::SendMessage {
             message: result,
-            transaction: transactions.clone(),
+            client_id,
             reply: reply_tx,
+            span: command_span,
         };
         sender
             .send(message)
@@ -77,109 +249,135 @@ async fn handle_connection(
             .map_err(|_| RedisError::Concurrency)?;
 
         let command_response = reply_rx.await.map_err(|_| RedisError::Concurrency)?;
+        // the command ran either way, but the client doesn't want to see the reply
+        let (command_response, silent) = match command_response {
+            CommandResponse::Silent(response) => (*response, true),
+            response => (response, false),
+        };
         let response = match command_response {
             CommandResponse::Immediate(redis_type) => redis_type,
-            CommandResponse::ExecTransaction(redis_type) => {
-                if let Some(_transactions) = transactions {
-                    println!("Clearing transactions");
-                    transactions = None;
-                    redis_type
-                } else {
-                    RedisType::SimpleError(Bytes::from("ERR EXEC without MULTI"))
-                }
-            }
-            CommandResponse::StartTransaction => {
-                transactions = Some(VecDeque::new());
-                RedisType::SimpleString(Bytes::from("OK"))
-            }
             CommandResponse::WaitForBLPOP {
-                timeout: timeout_sec,
                 receiver,
-                key,
                 client_id,
-            } => {
-                println!("Received wait command for client: {}", client_id);
-                let result = if timeout_sec == 0.0 {
-                    // timeout=0 means wait forever
-                    println!("Waiting forever for client: {}", client_id);
-                    receiver.await.ok()
-                } else {
-                    println!(
-                        "Waiting with timeout {} for client: {}",
-                        timeout_sec, client_id
-                    );
-                    match timeout(Duration::from_secs_f64(timeout_sec), receiver).await {
-                        Ok(Ok(value)) => Some(value),
-                        Ok(Err(_)) | Err(_) => {
-                            // Timeout or channel closed - send cleanup message
-                            println!(
-                                "Timeout or channel closed, sending cleanup message to client: {}",
-                                client_id
-                            );
-                            let _ = sender
-                                .send(RedisMessage::SendTimeout {
-                                    key: Some(key),
-                                    identifier: client_id,
-                                })
-                                .await;
-                            None
-                        }
-                    }
-                };
-
-                result.unwrap_or(RedisType::Array(None))
+                ..
+            }
+            | CommandResponse::WaitForXREAD {
+                receiver,
+                client_id,
+                ..
             }
-            CommandResponse::WaitForXREAD {
-                timeout: timeout_millis,
+            | CommandResponse::WaitForZPOP {
                 receiver,
                 client_id,
+                ..
             } => {
-                println!("Received wait command for client: {}", client_id);
-                let result = if timeout_millis == 0 {
-                    // timeout=0 means wait forever
-                    println!("Waiting forever for xread client: {}", client_id);
-                    receiver.await.ok()
-                } else {
-                    println!(
-                        "Waiting with timeout {} for xread client: {}",
-                        timeout_millis, client_id
-                    );
-                    match timeout(Duration::from_millis(timeout_millis as u64), receiver).await {
-                        Ok(Ok(value)) => Some(value),
-                        Ok(Err(_)) | Err(_) => {
-                            // Timeout or channel closed - send cleanup message
-                            println!(
-                                "Timeout or channel closed, sending cleanup message to client: {}",
-                                client_id
-                            );
-                            let _ = sender
-                                .send(RedisMessage::SendTimeout {
-                                    key: None,
-                                    identifier: client_id,
-                                })
-                                .await;
-                            None
-                        }
-                    }
-                };
-
-                result.unwrap_or(RedisType::Array(None))
+                // the store replies once the client is served or its timeout passed
+                match wait_for_reply(&mut reader, sender, client_id, receiver).await? {
+                    Some(value) => value,
+                    None => break,
+                }
             }
+            CommandResponse::Pushed => continue,
+            CommandResponse::Quit => {
+                if !silent {
+                    send(RedisType::SimpleString(Bytes::from_static(b"OK")))?;
+                }
+                // the writer sends what is left in the outbox before the connection closes
+                break;
+            }
+            CommandResponse::Silent(_) => unreachable!("replies are only silenced once"),
         };
-
-        let res = response.to_bytes();
-        stream
-            .write_all(&res)
-            .await
-            .map_err(RedisError::Networking)?;
+        if !silent {
+            send(response)?;
+        }
     }
     Ok(())
 }
 
+/// The RDB location given as `--dir` and `--dbfilename` on the command line, the arguments
+/// override the config the environment set
+fn rdb_config_from_args(
+    mut args: impl Iterator<Item = String>,
+    mut config: RdbConfig,
+) -> io::Result<RdbConfig> {
+    while let Some(option) = args.next() {
+        let value = args.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("missing value for option: {}", option),
+            )
+        })?;
+        match option.as_str() {
+            "--dir" => config.dir = value.into(),
+            "--dbfilename" => config.dbfilename = value,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unknown option: {}", option),
+                ));
+            }
+        }
+    }
+    Ok(config)
+}
+
+/// The limit set in the environment variable, the default if it is unset or not a number
+fn limit_from_env(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
 #[tokio::main]
 async fn main() -> io::Result<()> {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+
     let redis_address =
         std::env::var("REDIS_ADDR").unwrap_or_else(|_| "127.0.0.1:6379".to_string());
+    let cluster_enabled = std::env::var("REDIS_CLUSTER_ENABLED").is_ok_and(|value| value == "yes");
+    // new connections speak RESP3 right away, for clients which can't send HELLO
+    let protocol_version = match std::env::var("REDIS_DEFAULT_RESP3") {
+        Ok(value) if value == "yes" => 3,
+        _ => 2,
+    };
+    let engine_name =
+        std::env::var("REDIS_STORAGE_ENGINE").unwrap_or_else(|_| "memory".to_string());
+    let engine = engine_from_name(&engine_name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown storage engine: {}", engine_name),
+        )
+    })?;
+
+    let defaults = RdbConfig::default();
+    let rdb_config = rdb_config_from_args(
+        std::env::args().skip(1),
+        RdbConfig {
+            dir: std::env::var("REDIS_DIR").map_or(defaults.dir, Into::into),
+            dbfilename: std::env::var("REDIS_DBFILENAME").unwrap_or(defaults.dbfilename),
+        },
+    )?;
+
+    let defaults = ProtocolLimits::default();
+    let limits = ProtocolLimits {
+        max_bulk_len: limit_from_env("REDIS_PROTO_MAX_BULK_LEN", defaults.max_bulk_len),
+        max_multibulk_len: limit_from_env(
+            "REDIS_PROTO_MAX_MULTIBULK_LEN",
+            defaults.max_multibulk_len,
+        ),
+        max_depth: limit_from_env("REDIS_PROTO_MAX_DEPTH", defaults.max_depth),
+    };
+
+    // the dataset is loaded before accepting connections, like redis does
+    let mut store = Store::with_engine(engine).with_rdb_config(rdb_config);
+    if cluster_enabled {
+        store = store.with_cluster_enabled();
+    }
+    let loaded = store.load_rdb()?;
+    info!(keys = loaded, "loaded the RDB file");
 
     let tcp_listener = TcpListener::bind(&redis_address).await?;
     let (tx, mut rx) = mpsc::channel::<RedisMessage>(128); // create channel for communication between tasks
@@ -188,64 +386,119 @@ async fn main() -> io::Result<()> {
 
     tokio::spawn(async move {
         // Start receiving messages
-        let mut store = Store::new();
-
-        while let Some(cmd) = rx.recv().await {
+        // commands which came in while the clients are paused, in the order they came
+        let mut held: VecDeque<HeldCommand> = VecDeque::new();
+        loop {
+            let next_deadline = store
+                .next_waiting_deadline()
+                .into_iter()
+                .chain(store.pause().map(|pause| pause.until))
+                .min();
+            let cmd = tokio::select! {
+                cmd = rx.recv() => match cmd {
+                    Some(cmd) => Some(cmd),
+                    None => break,
+                },
+                // timeouts of blocked clients run here, so a client is either served or timed out
+                _ = sleep_until(next_deadline.map(Instant::from_std).unwrap_or_else(Instant::now)),
+                    if next_deadline.is_some() =>
+                {
+                    let now = std::time::Instant::now();
+                    store.expire_waiting_clients(now);
+                    store.lift_expired_pause(now);
+                    None
+                }
+            };
             match cmd {
-                RedisMessage::SendMessage {
+                Some(RedisMessage::SendMessage {
                     message,
+                    client_id,
                     reply,
-                    transaction,
-                } => {
-                    println!("Received command: {:?}", message);
-                    let command = handle_command(message, &mut store, transaction);
-                    match command {
-                        Ok(response) => {
-                            let _ = reply.send(response);
-                        }
-                        Err(err) => {
-                            let _ = reply.send(CommandResponse::Immediate(RedisType::SimpleError(
-                                Bytes::from(format!("ERR {:?}", err)),
-                            )));
-                        }
+                    span,
+                }) => {
+                    if is_held_by_pause(&message, &store, client_id) {
+                        held.push_back(HeldCommand {
+                            message,
+                            client_id,
+                            reply,
+                            span,
+                        });
+                    } else {
+                        run_command(&mut store, message, client_id, reply, span);
                     }
                 }
-                RedisMessage::SendTimeout { key, identifier } => {
-                    println!(
-                        "Cleaning up blocked client {} for key {:?}",
-                        identifier, key
-                    );
-                    if let Some(key) = key {
-                        store.remove_blpop_waiting_client(&key, identifier);
-                    }
+                Some(RedisMessage::Unregister { identifier }) => {
+                    debug!(client = identifier, "unregistering blocked client");
+                    store.remove_waiting_client(identifier);
+                }
+                Some(RedisMessage::Register { client }) => {
+                    store.register_client(*client);
+                }
+                Some(RedisMessage::Disconnect { identifier }) => {
+                    held.retain(|command| command.client_id != identifier);
+                    store.unregister_client(identifier);
                 }
+                None => {}
+            }
+            // the held commands run once the pause ended, unless they are held again by a new one
+            while let Some(command) = held.front()
+                && !is_held_by_pause(&command.message, &store, command.client_id)
+            {
+                let command = held.pop_front().expect("there is a held command");
+                run_command(
+                    &mut store,
+                    command.message,
+                    command.client_id,
+                    command.reply,
+                    command.span,
+                );
             }
         }
     });
 
-    println!("Listening on {} - awaiting connections", redis_address);
+    info!(address = %redis_address, "listening");
 
     loop {
-        let (stream, _addr) = tcp_listener.accept().await?;
-        println!("Accepted connection from client");
+        let (stream, addr) = tcp_listener.accept().await?;
 
         let sender = tx.clone();
-        tokio::spawn(async move {
-            if let Err(e) = handle_connection(stream, &sender).await {
-                eprintln!("Error: {}", e);
+        let client_id = create_identifier();
+        let span = info_span!("client", id = client_id, peer = %addr);
+        tokio::spawn(
+            async move {
+                let result =
+                    handle_connection(stream, client_id, addr, &sender, limits, protocol_version)
+                        .await;
+                // the store holds on to the outbox, the writer task ends once it is dropped
+                let _ = sender
+                    .send(RedisMessage::Disconnect {
+                        identifier: client_id,
+                    })
+                    .await;
+                if let Err(e) = result {
+                    error!(error = %e, "connection failed");
+                }
             }
-        });
+            .instrument(span),
+        );
+    }
+}
+
+impl From<CodecError> for RedisError {
+    fn from(error: CodecError) -> Self {
+        match error {
+            CodecError::Protocol(error) => RedisError::InvalidResp(error),
+            CodecError::Io(error) => RedisError::Networking(error),
+        }
     }
 }
 
 impl Display for RedisError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            RedisError::InvalidResp(resp_parse_error) => match resp_parse_error {
-                RespParseError::InvalidFormat => {
-                    write!(f, "Invalid RESP format")
-                }
-            },
+            RedisError::InvalidResp(resp_parse_error) => {
+                write!(f, "Protocol error: {}", resp_parse_error)
+            }
             RedisError::Networking(error) => {
                 write!(f, "IO error: {:?}", error)
             }
@@ -255,3 +508,144 @@ impl Display for RedisError {
         }
     }
 }
+
+#[test]
+fn test_reclaim_buffer_shrinks_oversized_buffers_and_keeps_unread_bytes() {
+    let mut buffer = BytesMut::with_capacity(4 * BUFFER_RECLAIM_THRESHOLD);
+    buffer.extend_from_slice(b"*1\r\n$4\r\nPI");
+    reclaim_buffer(&mut buffer);
+    assert_eq!(buffer.capacity(), INITIAL_BUFFER_CAPACITY);
+    assert_eq!(&buffer[..], b"*1\r\n$4\r\nPI");
+
+    // a pending request bigger than the initial capacity is kept whole
+    let pending = vec![b'x'; 2 * BUFFER_RECLAIM_THRESHOLD];
+    let mut buffer = BytesMut::with_capacity(4 * BUFFER_RECLAIM_THRESHOLD);
+    buffer.extend_from_slice(&pending);
+    reclaim_buffer(&mut buffer);
+    assert!(buffer.capacity() >= pending.len() && buffer.capacity() < 4 * BUFFER_RECLAIM_THRESHOLD);
+    assert_eq!(&buffer[..], &pending[..]);
+
+    // buffers up to the threshold are left alone
+    let mut buffer = BytesMut::with_capacity(BUFFER_RECLAIM_THRESHOLD);
+    buffer.extend_from_slice(b"PING");
+    reclaim_buffer(&mut buffer);
+    assert_eq!(buffer.capacity(), BUFFER_RECLAIM_THRESHOLD);
+}
+
+#[tokio::test]
+async fn test_write_outbox_sends_replies_and_pushes_in_order() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    let (_reader, writer) = server.into_split();
+
+    let protocol = ProtocolVersion::new(3);
+    let writer = FramedWrite::new(writer, RespCodec::new(ProtocolLimits::default(), protocol));
+    let (outbox, inbox) = mpsc::unbounded_channel();
+    let writer = tokio::spawn(write_outbox(writer, inbox));
+
+    // a push may land in the outbox before the reply of a command which is still running
+    let push = RedisType::Push(vec![
+        RedisType::BulkString("message".into()),
+        RedisType::BulkString("news".into()),
+        RedisType::BulkString("hi".into()),
+    ]);
+    outbox.send(push).unwrap();
+    outbox.send(RedisType::SimpleString("OK".into())).unwrap();
+    drop(outbox);
+    // the writer ends once every sender of the outbox is gone
+    writer.await.unwrap().unwrap();
+
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).await.unwrap();
+    assert_eq!(
+        received,
+        b">3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$2\r\nhi\r\n+OK\r\n"
+    );
+}
+
+/// Connects to a connection handler which runs its commands in a store of its own
+#[cfg(test)]
+async fn test_connection(limits: ProtocolLimits) -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (stream, addr) = listener.accept().await.unwrap();
+
+    let (sender, mut receiver) = mpsc::channel::<RedisMessage>(128);
+    tokio::spawn(async move {
+        let mut store = Store::new();
+        while let Some(message) = receiver.recv().await {
+            match message {
+                RedisMessage::SendMessage {
+                    message,
+                    client_id,
+                    reply,
+                    span,
+                } => run_command(&mut store, message, client_id, reply, span),
+                RedisMessage::Register { client } => store.register_client(*client),
+                _ => {}
+            }
+        }
+    });
+    tokio::spawn(async move {
+        let _ = handle_connection(stream, 1, addr, &sender, limits, 2).await;
+    });
+    client
+}
+
+#[tokio::test]
+async fn test_every_pipelined_command_of_a_read_runs() {
+    use tokio::io::AsyncWriteExt;
+
+    let mut client = test_connection(ProtocolLimits::default()).await;
+    // the last command is only complete with the second write
+    client
+        .write_all(b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n*2\r\n$4\r\nINCR\r\n$1\r\na\r\n*2\r\n$3\r\nGET")
+        .await
+        .unwrap();
+    client.write_all(b"\r\n$1\r\na\r\n").await.unwrap();
+
+    let expected = b"+OK\r\n:2\r\n$1\r\n2\r\n";
+    let mut replies = vec![0; expected.len()];
+    client.read_exact(&mut replies).await.unwrap();
+    assert_eq!(replies, expected);
+}
+
+#[tokio::test]
+async fn test_quit_replies_before_closing() {
+    use tokio::io::AsyncWriteExt;
+
+    let mut client = test_connection(ProtocolLimits::default()).await;
+    // QUIT closes the transaction along with the connection, nothing after it runs
+    client
+        .write_all(b"*1\r\n$5\r\nMULTI\r\n*1\r\n$4\r\nQUIT\r\n*1\r\n$4\r\nPING\r\n")
+        .await
+        .unwrap();
+
+    let mut replies = Vec::new();
+    client.read_to_end(&mut replies).await.unwrap();
+    assert_eq!(replies, b"+OK\r\n+OK\r\n");
+}
+
+#[tokio::test]
+async fn test_protocol_errors_are_replied_before_closing() {
+    use tokio::io::AsyncWriteExt;
+
+    let mut client = test_connection(ProtocolLimits::default()).await;
+    // the command before the broken one still runs
+    client
+        .write_all(b"*1\r\n$4\r\nPING\r\n*1\r\n$x\r\nPING\r\n")
+        .await
+        .unwrap();
+
+    let mut replies = Vec::new();
+    client.read_to_end(&mut replies).await.unwrap();
+    assert_eq!(
+        replies,
+        b"+PONG\r\n-ERR Protocol error: invalid bulk length\r\n"
+    );
+}
@@ -0,0 +1,44 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static CLIENT_BUFFER_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Total capacity of all client read buffers, reported by INFO memory
+pub fn client_buffer_bytes() -> usize {
+    CLIENT_BUFFER_BYTES.load(Ordering::Relaxed)
+}
+
+/// Tracks the buffer capacity of one connection in the global total,
+/// the capacity is released again when the connection is dropped
+#[derive(Default)]
+pub struct BufferGauge {
+    reported: usize,
+}
+
+impl BufferGauge {
+    pub fn update(&mut self, capacity: usize) {
+        if capacity > self.reported {
+            CLIENT_BUFFER_BYTES.fetch_add(capacity - self.reported, Ordering::Relaxed);
+        } else {
+            CLIENT_BUFFER_BYTES.fetch_sub(self.reported - capacity, Ordering::Relaxed);
+        }
+        self.reported = capacity;
+    }
+}
+
+impl Drop for BufferGauge {
+    fn drop(&mut self) {
+        self.update(0);
+    }
+}
+
+#[test]
+fn test_buffer_gauge_follows_the_capacity_and_releases_it_on_drop() {
+    let before = client_buffer_bytes();
+    let mut gauge = BufferGauge::default();
+    gauge.update(128 * 1024);
+    assert_eq!(client_buffer_bytes(), before + 128 * 1024);
+    gauge.update(1024);
+    assert_eq!(client_buffer_bytes(), before + 1024);
+    drop(gauge);
+    assert_eq!(client_buffer_bytes(), before);
+}
@@ -1,4 +1,7 @@
+use std::fmt::Display;
+
 use bytes::{Buf, Bytes, BytesMut};
+use tracing::warn;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum RedisType {
@@ -8,21 +11,148 @@ pub enum RedisType {
     NullBulkString,
     SimpleError(Bytes),
     Array(Option<Vec<RedisType>>),
+    /// RESP3 types, downgraded to their RESP2 counterparts for RESP2 clients
+    Map(Vec<(RedisType, RedisType)>),
+    Set(Vec<RedisType>),
+    Double(f64),
+    Boolean(bool),
+    Null,
+    /// the digits of an integer of any size
+    BigNumber(Bytes),
+    /// text with a three letter format like `txt` or `mkd`
+    VerbatimString {
+        format: [u8; 3],
+        text: Bytes,
+    },
+    /// out of band data like published messages, not a reply to a command
+    Push(Vec<RedisType>),
+    /// metadata about the reply which follows it, e.g. the popularity of a key
+    Attribute {
+        attributes: Vec<(RedisType, RedisType)>,
+        reply: Box<RedisType>,
+    },
 }
 #[derive(Debug, PartialEq)]
 pub enum RespParseError {
     InvalidFormat,
+    /// the frame didn't arrive completely yet, read more and parse again
+    NeedMoreData,
+    /// a bulk string is longer than the limit allows, or has a negative length
+    InvalidBulkLength,
+    /// an array has more elements than the limit allows
+    InvalidMultibulkLength,
+    /// arrays are nested deeper than the limit allows
+    TooDeeplyNested,
+    /// an inline command has a quote which isn't closed
+    UnbalancedQuotes,
+    /// an array element starts with a byte which isn't a RESP type
+    UnexpectedTypeByte(u8),
+    /// a line grew past `MAX_LINE_LEN` without ending
+    TooLargePayload,
+    /// an argument of a command isn't a bulk string, along with the type byte it has instead
+    ExpectedBulkString(u8),
+}
+
+impl Display for RespParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RespParseError::InvalidFormat => write!(f, "invalid RESP format"),
+            RespParseError::NeedMoreData => write!(f, "incomplete RESP frame"),
+            RespParseError::InvalidBulkLength => write!(f, "invalid bulk length"),
+            RespParseError::InvalidMultibulkLength => write!(f, "invalid multibulk length"),
+            RespParseError::TooDeeplyNested => write!(f, "too many nested multibulks"),
+            RespParseError::UnbalancedQuotes => write!(f, "unbalanced quotes in request"),
+            RespParseError::UnexpectedTypeByte(byte) => {
+                write!(f, "unexpected type byte '{}'", byte.escape_ascii())
+            }
+            RespParseError::TooLargePayload => write!(f, "too big request line"),
+            RespParseError::ExpectedBulkString(byte) => {
+                write!(f, "expected '$', got '{}'", byte.escape_ascii())
+            }
+        }
+    }
+}
+
+/// Bounds on the frames clients send, so a client can't make the server allocate unbounded
+/// memory by announcing a huge length
+#[derive(Clone, Copy, Debug)]
+pub struct ProtocolLimits {
+    /// longest bulk string, `proto-max-bulk-len` in redis
+    pub max_bulk_len: usize,
+    /// most elements of an array
+    pub max_multibulk_len: usize,
+    /// how many arrays may be nested into each other, a plain command is one
+    pub max_depth: usize,
+}
+
+impl Default for ProtocolLimits {
+    fn default() -> Self {
+        ProtocolLimits {
+            max_bulk_len: 512 * 1024 * 1024,
+            max_multibulk_len: 1024 * 1024,
+            max_depth: 8,
+        }
+    }
 }
 
 const CRLF: &[u8] = b"\r\n";
 
-pub fn parse_resp(buffer: &mut BytesMut) -> Result<RedisType, RespParseError> {
-    // resp inputs are by definition arrays
-    parse_array(buffer)
+/// Longest line a client may send, inline commands as well as the header lines of RESP types
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// Parses the first frame in the buffer and removes it. Commands may arrive split across
+/// reads, the buffer is left untouched unless the frame is complete.
+pub fn parse_resp(
+    buffer: &mut BytesMut,
+    limits: &ProtocolLimits,
+) -> Result<RedisType, RespParseError> {
+    loop {
+        let mut position = 0;
+        let frame = match buffer.first() {
+            None => return Err(RespParseError::NeedMoreData),
+            // resp inputs are by definition arrays, redis skips null and empty ones
+            Some(b'*') => match parse_array(buffer, &mut position, limits, 1)? {
+                RedisType::Array(Some(arguments)) if !arguments.is_empty() => {
+                    Some(command_from_arguments(arguments)?)
+                }
+                _ => None,
+            },
+            // anything else is an inline command like `PING` typed into netcat
+            Some(_) => parse_inline(buffer, &mut position)?,
+        };
+        buffer.advance(position);
+        // empty lines and arrays are skipped
+        if let Some(frame) = frame {
+            return Ok(frame);
+        }
+    }
+}
+
+/// The command made of the arguments, which have to be bulk strings. Other types fail the
+/// command with the type byte of the first one, like redis does.
+fn command_from_arguments(arguments: Vec<RedisType>) -> Result<RedisType, RespParseError> {
+    if let Some(argument) = arguments
+        .iter()
+        .find(|argument| !matches!(argument, RedisType::BulkString(_)))
+    {
+        let mut encoded = BytesMut::new();
+        argument.encode(&mut encoded, 3);
+        return Err(RespParseError::ExpectedBulkString(encoded[0]));
+    }
+    Ok(RedisType::Array(Some(arguments)))
 }
 
 impl RedisType {
-    pub fn encode(&self, out: &mut BytesMut) {
+    /// Encodes the frame for a client speaking the RESP `protocol` version. RESP2 clients get
+    /// the RESP3 types as their closest RESP2 type: maps and sets as arrays, doubles, big numbers
+    /// and verbatim strings as bulk strings, booleans as integers.
+    pub fn encode(&self, out: &mut BytesMut, protocol: u8) {
+        if protocol < 3
+            && let Some(downgraded) = self.downgrade()
+        {
+            downgraded.encode(out, protocol);
+            return;
+        }
         match self {
             RedisType::SimpleString(s) => {
                 out.extend_from_slice(b"+");
@@ -53,24 +183,131 @@ impl RedisType {
                     out.extend_from_slice(items.len().to_string().as_bytes());
                     out.extend_from_slice(b"\r\n");
                     for item in items {
-                        item.encode(out);
+                        item.encode(out, protocol);
                     }
+                } else if protocol > 2 {
+                    out.extend_from_slice(b"_\r\n");
                 } else {
                     out.extend_from_slice(b"*-1\r\n"); // return a null array https://redis.io/docs/latest/develop/reference/protocol-spec/#null-arrays
                 }
             }
+            RedisType::NullBulkString if protocol > 2 => {
+                out.extend_from_slice(b"_\r\n");
+            }
             RedisType::NullBulkString => {
                 out.extend_from_slice(b"$-1\r\n");
             }
+            RedisType::Map(entries) => {
+                encode_header(out, b'%', entries.len());
+                for (key, value) in entries {
+                    key.encode(out, protocol);
+                    value.encode(out, protocol);
+                }
+            }
+            RedisType::Set(items) | RedisType::Push(items) => {
+                let prefix = if matches!(self, RedisType::Set(_)) {
+                    b'~'
+                } else {
+                    b'>'
+                };
+                encode_header(out, prefix, items.len());
+                for item in items {
+                    item.encode(out, protocol);
+                }
+            }
+            RedisType::Double(value) => {
+                out.extend_from_slice(b",");
+                out.extend_from_slice(format_double(*value).as_bytes());
+                out.extend_from_slice(b"\r\n");
+            }
+            RedisType::Boolean(value) => {
+                out.extend_from_slice(if *value { b"#t\r\n" } else { b"#f\r\n" });
+            }
+            RedisType::Null => {
+                out.extend_from_slice(b"_\r\n");
+            }
+            RedisType::BigNumber(digits) => {
+                out.extend_from_slice(b"(");
+                out.extend_from_slice(digits);
+                out.extend_from_slice(b"\r\n");
+            }
+            RedisType::VerbatimString { format, text } => {
+                // the format and the colon count towards the length
+                encode_header(out, b'=', text.len() + 4);
+                out.extend_from_slice(format);
+                out.extend_from_slice(b":");
+                out.extend_from_slice(text);
+                out.extend_from_slice(b"\r\n");
+            }
+            RedisType::Attribute { attributes, reply } => {
+                encode_header(out, b'|', attributes.len());
+                for (key, value) in attributes {
+                    key.encode(out, protocol);
+                    value.encode(out, protocol);
+                }
+                reply.encode(out, protocol);
+            }
         }
     }
 
+    /// The RESP2 type a RESP3 type is sent as, `None` for the types RESP2 has
+    fn downgrade(&self) -> Option<RedisType> {
+        let downgraded = match self {
+            RedisType::Map(entries) => RedisType::Array(Some(
+                entries
+                    .iter()
+                    .flat_map(|(key, value)| [key.clone(), value.clone()])
+                    .collect(),
+            )),
+            RedisType::Set(items) | RedisType::Push(items) => RedisType::Array(Some(items.clone())),
+            RedisType::Double(value) => RedisType::BulkString(Bytes::from(format_double(*value))),
+            RedisType::Boolean(value) => RedisType::Integer(*value as i128),
+            RedisType::Null => RedisType::NullBulkString,
+            RedisType::BigNumber(digits) => RedisType::BulkString(digits.clone()),
+            RedisType::VerbatimString { text, .. } => RedisType::BulkString(text.clone()),
+            // RESP2 has no way to send them, the client only gets the reply
+            RedisType::Attribute { reply, .. } => *reply.clone(),
+            _ => return None,
+        };
+        Some(downgraded)
+    }
+
     pub fn to_bytes(&self) -> Bytes {
         let mut out = BytesMut::new();
-        self.encode(&mut out);
+        self.encode(&mut out, 2);
         out.freeze()
     }
 }
+/// Writes the type byte and the length line of an aggregate (or a verbatim string)
+fn encode_header(out: &mut BytesMut, prefix: u8, len: usize) {
+    out.extend_from_slice(&[prefix]);
+    out.extend_from_slice(len.to_string().as_bytes());
+    out.extend_from_slice(b"\r\n");
+}
+
+/// Formats a double the way redis replies with them: the shortest representation which parses
+/// back to the same value, in exponent notation (`%g` style) for very large or small values
+pub fn format_double(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    let magnitude = value.abs();
+    if magnitude != 0.0 && !(1e-4..1e17).contains(&magnitude) {
+        let formatted = format!("{:e}", value);
+        if let Some((mantissa, exponent)) = formatted.split_once('e') {
+            let (sign, digits) = match exponent.strip_prefix('-') {
+                Some(digits) => ('-', digits),
+                None => ('+', exponent),
+            };
+            return format!("{}e{}{:0>2}", mantissa, sign, digits);
+        }
+    }
+    value.to_string()
+}
+
 // Happy path, if we encounter a Utf8Error, we assume that the input is invalid
 impl From<std::str::Utf8Error> for RespParseError {
     fn from(_error: std::str::Utf8Error) -> Self {
@@ -92,44 +329,166 @@ impl From<std::io::Error> for RespParseError {
 
 impl From<Bytes> for RedisType {
     fn from(bytes: Bytes) -> Self {
-        let some_type = bytes[0];
-        match some_type {
-            b'$' => parse_bulk_string(&mut BytesMut::from(bytes.as_ref()))
+        match bytes.first() {
+            Some(b'$') => parse_bulk_string(&bytes, &mut 0, &ProtocolLimits::default())
                 .unwrap_or(Self::NullBulkString),
             _ => RedisType::NullBulkString,
         }
     }
 }
 
-fn parse_array(buffer: &mut BytesMut) -> Result<RedisType, RespParseError> {
-    // let array_with_size_prefix = &buffer[1..];
-    let array_len_delimiter_pos = buffer
+/// An inline command is a line of space separated arguments, ended by a newline with or without
+/// a carriage return. `None` for an empty line.
+fn parse_inline(buffer: &[u8], position: &mut usize) -> Result<Option<RedisType>, RespParseError> {
+    let rest = &buffer[*position..];
+    let end = rest
+        .iter()
+        .position(|&b| b == b'\n')
+        .ok_or_else(|| incomplete_line(rest))?;
+    *position += end + 1;
+    let line = rest[..end].strip_suffix(b"\r").unwrap_or(&rest[..end]);
+    let arguments = split_arguments(line).ok_or(RespParseError::UnbalancedQuotes)?;
+    if arguments.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(RedisType::Array(Some(
+        arguments.into_iter().map(RedisType::BulkString).collect(),
+    ))))
+}
+
+/// Splits an inline command into its arguments the way redis does: on whitespace, where double
+/// quoted arguments may contain escapes like `\n` or `\x41` and single quoted ones only `\'`.
+/// `None` if a quote isn't closed, or a closing quote is followed by more than whitespace.
+fn split_arguments(line: &[u8]) -> Option<Vec<Bytes>> {
+    let mut arguments = Vec::new();
+    let mut position = 0;
+    loop {
+        while line.get(position).is_some_and(u8::is_ascii_whitespace) {
+            position += 1;
+        }
+        if position == line.len() {
+            return Some(arguments);
+        }
+        let mut argument = Vec::new();
+        let mut quote = None;
+        loop {
+            let Some(&byte) = line.get(position) else {
+                if quote.is_some() {
+                    return None;
+                }
+                break;
+            };
+            position += 1;
+            match (quote, byte) {
+                (None, b'"' | b'\'') => quote = Some(byte),
+                (None, _) if byte.is_ascii_whitespace() => break,
+                (None, _) => argument.push(byte),
+                (Some(closing), _) if byte == closing => {
+                    if line
+                        .get(position)
+                        .is_some_and(|next| !next.is_ascii_whitespace())
+                    {
+                        return None;
+                    }
+                    break;
+                }
+                (Some(b'"'), b'\\') if position < line.len() => {
+                    let (unescaped, length) = unescape(&line[position..]);
+                    argument.push(unescaped);
+                    position += length;
+                }
+                (Some(b'\''), b'\\') if line.get(position) == Some(&b'\'') => {
+                    argument.push(b'\'');
+                    position += 1;
+                }
+                (Some(_), _) => argument.push(byte),
+            }
+        }
+        arguments.push(Bytes::from(argument));
+    }
+}
+
+/// The byte an escape sequence in double quotes stands for, along with the length of the
+/// sequence after the backslash
+fn unescape(escaped: &[u8]) -> (u8, usize) {
+    if escaped[0] == b'x'
+        && let Some(&[high, low]) = escaped.get(1..3)
+        && let (Some(high), Some(low)) = ((high as char).to_digit(16), (low as char).to_digit(16))
+    {
+        return ((high * 16 + low) as u8, 3);
+    }
+    let unescaped = match escaped[0] {
+        b'n' => b'\n',
+        b'r' => b'\r',
+        b't' => b'\t',
+        b'b' => 0x08,
+        b'a' => 0x07,
+        other => other,
+    };
+    (unescaped, 1)
+}
+
+/// The line starting at `position`, without its CRLF. Moves `position` past the CRLF.
+fn read_line<'a>(buffer: &'a [u8], position: &mut usize) -> Result<&'a [u8], RespParseError> {
+    let rest = &buffer[*position..];
+    let end = rest
         .windows(2)
         .position(|w| w == CRLF)
-        .ok_or(RespParseError::InvalidFormat)?;
+        .ok_or_else(|| incomplete_line(rest))?;
+    *position += end + 2;
+    Ok(&rest[..end])
+}
 
-    let size_as_string = &buffer[1..array_len_delimiter_pos];
-    let array_start_position = array_len_delimiter_pos + 2;
+/// A line without its end yet may still arrive completely, unless it is too long already
+fn incomplete_line(rest: &[u8]) -> RespParseError {
+    if rest.len() > MAX_LINE_LEN {
+        RespParseError::TooLargePayload
+    } else {
+        RespParseError::NeedMoreData
+    }
+}
+
+/// Parses an array, `depth` counts the arrays it is nested in including itself
+fn parse_array(
+    buffer: &[u8],
+    position: &mut usize,
+    limits: &ProtocolLimits,
+    depth: usize,
+) -> Result<RedisType, RespParseError> {
+    if depth > limits.max_depth {
+        return Err(RespParseError::TooDeeplyNested);
+    }
+    let line = read_line(buffer, position)?;
+    let size_as_string = &line[1..];
 
     // Handle null array: *-1\r\n
-    let array_length_signed = str::from_utf8(size_as_string)?.parse::<i64>()?;
+    let array_length_signed = str::from_utf8(size_as_string)
+        .ok()
+        .and_then(|size| size.parse::<i64>().ok())
+        .ok_or(RespParseError::InvalidMultibulkLength)?;
     if array_length_signed < 0 {
-        buffer.advance(array_start_position);
         return Ok(RedisType::Array(None));
     }
+    if array_length_signed as u64 > limits.max_multibulk_len as u64 {
+        return Err(RespParseError::InvalidMultibulkLength);
+    }
     let array_length = array_length_signed as usize;
 
-    buffer.advance(array_start_position);
-
-    let mut elements: Vec<RedisType> = Vec::with_capacity(array_length);
+    // the elements may never arrive, only reserve a little up front
+    let mut elements: Vec<RedisType> = Vec::with_capacity(array_length.min(1024));
 
     while elements.len() < array_length {
-        let element = match buffer[0] {
-            b'+' => parse_simple_string(buffer),
-            b'-' => parse_simple_error(buffer),
-            b'$' => parse_bulk_string(buffer),
-            b'*' => parse_array(buffer),
-            _ => Ok(RedisType::NullBulkString),
+        let element = match buffer.get(*position) {
+            None => Err(RespParseError::NeedMoreData),
+            Some(b'+') => parse_simple_string(buffer, position),
+            Some(b'-') => parse_simple_error(buffer, position),
+            Some(b'$') => parse_bulk_string(buffer, position, limits),
+            Some(b'*') => parse_array(buffer, position, limits, depth + 1),
+            Some(b':') => parse_integer(buffer, position),
+            Some(b'_') => parse_null(buffer, position),
+            Some(b'#') => parse_boolean(buffer, position),
+            Some(b',') => parse_double(buffer, position),
+            Some(byte) => Err(RespParseError::UnexpectedTypeByte(*byte)),
         };
 
         elements.push(element?);
@@ -138,181 +497,246 @@ fn parse_array(buffer: &mut BytesMut) -> Result<RedisType, RespParseError> {
     Ok(RedisType::Array(Some(elements)))
 }
 
-fn parse_bulk_string(buffer: &mut BytesMut) -> Result<RedisType, RespParseError> {
+fn parse_bulk_string(
+    buffer: &[u8],
+    position: &mut usize,
+    limits: &ProtocolLimits,
+) -> Result<RedisType, RespParseError> {
     // determine bulk string length:
-    let str_size_delimiter_pos = buffer
-        .windows(2)
-        .position(|w| w == CRLF)
-        .ok_or(RespParseError::InvalidFormat)?;
-    let size_as_string = &buffer[1..str_size_delimiter_pos];
-
-    let size = str::from_utf8(size_as_string)?.parse::<usize>()?;
-    let string_start_position = str_size_delimiter_pos + 2;
-
-    let delimiter = &buffer[str_size_delimiter_pos..string_start_position];
-    // before the actual data, we have a crlf delimiter
-    if delimiter != CRLF {
-        eprintln!("Invalid format: Expected CRLF delimiter");
-        return Err(RespParseError::InvalidFormat);
+    let line = read_line(buffer, position)?;
+    let size = str::from_utf8(&line[1..])
+        .ok()
+        .and_then(|size| size.parse::<i64>().ok())
+        .ok_or(RespParseError::InvalidBulkLength)?;
+    if size < 0 || size as u64 > limits.max_bulk_len as u64 {
+        return Err(RespParseError::InvalidBulkLength);
     }
-    let string_end = buffer[string_start_position..]
-        .windows(2)
-        .position(|w| w == CRLF)
-        .ok_or(RespParseError::InvalidFormat)?;
-
-    // actual string size is starting after the delimiter and ends before the next crlf
-    if string_end != size {
-        eprintln!(
-            "Size mismatch: Expected size: {}, Actual size: {}",
-            size, string_end
-        );
+    let size = size as usize;
+
+    // the content may contain CRLF itself, only the size tells where it ends
+    let string_start_position = *position;
+    let string_end = string_start_position + size;
+    let trailer = &buffer[string_end.min(buffer.len())..];
+    let trailer = &trailer[..trailer.len().min(2)];
+    if !CRLF.starts_with(trailer) {
+        warn!("Size mismatch: Expected CRLF after {} bytes", size);
         return Err(RespParseError::InvalidFormat);
     }
+    if trailer.len() < 2 {
+        return Err(RespParseError::NeedMoreData);
+    }
 
-    buffer.advance(string_start_position);
-    let content = buffer.split_to(string_end).freeze();
-    buffer.advance(2); // Skip  CRLF
+    let content = Bytes::copy_from_slice(&buffer[string_start_position..string_end]);
+    *position = string_end + 2; // Skip  CRLF
 
     Ok(RedisType::BulkString(content))
 }
 
-fn parse_simple_content(buffer: &mut BytesMut) -> Result<Bytes, RespParseError> {
+fn parse_simple_content(buffer: &[u8], position: &mut usize) -> Result<Bytes, RespParseError> {
     // don't parse the whole buffer, but only until the crlf
-    let end = buffer
-        .windows(2)
-        .position(|word| word == CRLF)
-        .ok_or(RespParseError::InvalidFormat)?;
+    let line = read_line(buffer, position)?;
 
     // a simple string must not contain \r or \n
-    let has_invalid = buffer[1..end].iter().any(|&b| b == b'\r' || b == b'\n');
+    let has_invalid = line[1..].iter().any(|&b| b == b'\r' || b == b'\n');
     if has_invalid {
         return Err(RespParseError::InvalidFormat);
     }
-    buffer.advance(1);
-    let content = buffer.split_to(end - 1).freeze();
-    buffer.advance(2); // Skip the CRLF
 
-    Ok(content)
+    Ok(Bytes::copy_from_slice(&line[1..]))
+}
+
+fn parse_simple_string(buffer: &[u8], position: &mut usize) -> Result<RedisType, RespParseError> {
+    parse_simple_content(buffer, position).map(RedisType::SimpleString)
+}
+
+fn parse_simple_error(buffer: &[u8], position: &mut usize) -> Result<RedisType, RespParseError> {
+    parse_simple_content(buffer, position).map(RedisType::SimpleError)
+}
+
+fn parse_integer(buffer: &[u8], position: &mut usize) -> Result<RedisType, RespParseError> {
+    let line = read_line(buffer, position)?;
+    Ok(RedisType::Integer(str::from_utf8(&line[1..])?.parse()?))
+}
+
+fn parse_null(buffer: &[u8], position: &mut usize) -> Result<RedisType, RespParseError> {
+    match read_line(buffer, position)? {
+        b"_" => Ok(RedisType::Null),
+        _ => Err(RespParseError::InvalidFormat),
+    }
 }
 
-fn parse_simple_string(buffer: &mut BytesMut) -> Result<RedisType, RespParseError> {
-    parse_simple_content(buffer).map(RedisType::SimpleString)
+fn parse_boolean(buffer: &[u8], position: &mut usize) -> Result<RedisType, RespParseError> {
+    match read_line(buffer, position)? {
+        b"#t" => Ok(RedisType::Boolean(true)),
+        b"#f" => Ok(RedisType::Boolean(false)),
+        _ => Err(RespParseError::InvalidFormat),
+    }
 }
 
-fn parse_simple_error(buffer: &mut BytesMut) -> Result<RedisType, RespParseError> {
-    parse_simple_content(buffer).map(RedisType::SimpleError)
+fn parse_double(buffer: &[u8], position: &mut usize) -> Result<RedisType, RespParseError> {
+    let line = read_line(buffer, position)?;
+    // `inf`, `-inf` and `nan` are valid doubles in RESP3 as well
+    let value = str::from_utf8(&line[1..])?
+        .parse()
+        .map_err(|_| RespParseError::InvalidFormat)?;
+    Ok(RedisType::Double(value))
 }
 
 #[test]
 fn test_parse_simple_string() {
-    let mut input = BytesMut::from("+OK\r\n");
+    let input = BytesMut::from("+OK\r\n");
     let expected = RedisType::SimpleString(BytesMut::from("OK").freeze());
-    assert_eq!(parse_simple_string(&mut input), Ok(expected));
+    assert_eq!(parse_simple_string(&input, &mut 0), Ok(expected));
 }
 
 #[test]
 fn test_parse_simple_string_missing_crlf() {
-    let mut input = BytesMut::from("+OK");
-    let expected = RespParseError::InvalidFormat;
-    assert_eq!(parse_simple_string(&mut input), Err(expected));
+    let input = BytesMut::from("+OK");
+    let expected = RespParseError::NeedMoreData;
+    assert_eq!(parse_simple_string(&input, &mut 0), Err(expected));
 }
 #[test]
 fn test_parse_simple_string_invalid_crlf_inside() {
-    let mut input = BytesMut::from("+OK\rBye\r\n");
+    let input = BytesMut::from("+OK\rBye\r\n");
 
     let expected = RespParseError::InvalidFormat;
-    assert_eq!(parse_simple_string(&mut input), Err(expected));
+    assert_eq!(parse_simple_string(&input, &mut 0), Err(expected));
 }
 
 #[test]
 fn test_parse_simple_error() {
-    let mut input = BytesMut::from("-Error message\r\n");
+    let input = BytesMut::from("-Error message\r\n");
     let expected = RedisType::SimpleError(BytesMut::from("Error message").freeze());
-    assert_eq!(parse_simple_error(&mut input), Ok(expected));
+    assert_eq!(parse_simple_error(&input, &mut 0), Ok(expected));
 }
 
 #[test]
 fn test_parse_simple_error_with_error_kind() {
-    let mut input =
+    let input =
         BytesMut::from("-WRONGTYPE Operation against a key holding the wrong kind of error\r\n");
     let expected = RedisType::SimpleError(
         BytesMut::from("WRONGTYPE Operation against a key holding the wrong kind of error")
             .freeze(),
     );
-    assert_eq!(parse_simple_error(&mut input), Ok(expected));
+    assert_eq!(parse_simple_error(&input, &mut 0), Ok(expected));
 }
 
 #[test]
 fn test_parse_bulk_string() {
-    let mut input = BytesMut::from("$5\r\nhello\r\n");
+    let input = BytesMut::from("$5\r\nhello\r\n");
     let expected = RedisType::BulkString(BytesMut::from("hello").freeze());
-    assert_eq!(parse_bulk_string(&mut input), Ok(expected));
+    assert_eq!(
+        parse_bulk_string(&input, &mut 0, &ProtocolLimits::default()),
+        Ok(expected)
+    );
 }
 #[test]
 fn test_parse_bulk_string_with_missing_delimiters() {
     assert_eq!(
-        parse_bulk_string(&mut BytesMut::from("$5\rhello\r\n")),
-        Err(RespParseError::InvalidFormat)
+        parse_bulk_string(
+            &BytesMut::from("$5\rhello\r\n"),
+            &mut 0,
+            &ProtocolLimits::default()
+        ),
+        Err(RespParseError::InvalidBulkLength)
     );
     assert_eq!(
-        parse_bulk_string(&mut BytesMut::from("$5hello\r\n")),
-        Err(RespParseError::InvalidFormat)
+        parse_bulk_string(
+            &BytesMut::from("$5hello\r\n"),
+            &mut 0,
+            &ProtocolLimits::default()
+        ),
+        Err(RespParseError::InvalidBulkLength)
     );
     assert_eq!(
-        parse_bulk_string(&mut BytesMut::from("$5\nhello\r\n")),
-        Err(RespParseError::InvalidFormat)
+        parse_bulk_string(
+            &BytesMut::from("$5\nhello\r\n"),
+            &mut 0,
+            &ProtocolLimits::default()
+        ),
+        Err(RespParseError::InvalidBulkLength)
     );
 
     assert_eq!(
-        parse_bulk_string(&mut BytesMut::from("$5\r\nhello")),
-        Err(RespParseError::InvalidFormat)
+        parse_bulk_string(
+            &BytesMut::from("$5\r\nhello"),
+            &mut 0,
+            &ProtocolLimits::default()
+        ),
+        Err(RespParseError::NeedMoreData)
     );
     assert_eq!(
-        parse_bulk_string(&mut BytesMut::from("$5\r\nhello\r")),
-        Err(RespParseError::InvalidFormat)
+        parse_bulk_string(
+            &BytesMut::from("$5\r\nhello\r"),
+            &mut 0,
+            &ProtocolLimits::default()
+        ),
+        Err(RespParseError::NeedMoreData)
     );
     assert_eq!(
-        parse_bulk_string(&mut BytesMut::from("$5\r\nhello\n")),
+        parse_bulk_string(
+            &BytesMut::from("$5\r\nhello\n"),
+            &mut 0,
+            &ProtocolLimits::default()
+        ),
         Err(RespParseError::InvalidFormat)
     );
 }
 #[test]
 fn test_parse_bulk_string_with_size_mismatch() {
     assert_eq!(
-        parse_bulk_string(&mut BytesMut::from("$1000\r\nhello\r\n")),
-        Err(RespParseError::InvalidFormat)
+        parse_bulk_string(
+            &BytesMut::from("$1000\r\nhello\r\n"),
+            &mut 0,
+            &ProtocolLimits::default()
+        ),
+        Err(RespParseError::NeedMoreData)
     );
 
     assert_eq!(
-        parse_bulk_string(&mut BytesMut::from("$6\r\nhello\r\n")),
+        parse_bulk_string(
+            &BytesMut::from("$6\r\nhello\r\n"),
+            &mut 0,
+            &ProtocolLimits::default()
+        ),
         Err(RespParseError::InvalidFormat)
     );
 
     assert_eq!(
-        parse_bulk_string(&mut BytesMut::from("$4\r\nhello\r\n")),
+        parse_bulk_string(
+            &BytesMut::from("$4\r\nhello\r\n"),
+            &mut 0,
+            &ProtocolLimits::default()
+        ),
         Err(RespParseError::InvalidFormat)
     );
 }
 #[test]
 fn test_parse_bulk_string_with_invalid_size() {
     assert_eq!(
-        parse_bulk_string(&mut BytesMut::from("$-1\r\nhello\r\n")),
-        Err(RespParseError::InvalidFormat)
+        parse_bulk_string(
+            &BytesMut::from("$-1\r\nhello\r\n"),
+            &mut 0,
+            &ProtocolLimits::default()
+        ),
+        Err(RespParseError::InvalidBulkLength)
     );
 }
 #[test]
 fn test_parse_bulk_string_with_empty_string() {
-    let mut input = BytesMut::from("$0\r\n\r\n");
-    let res = parse_bulk_string(&mut input).unwrap().to_bytes();
+    let input = BytesMut::from("$0\r\n\r\n");
+    let res = parse_bulk_string(&input, &mut 0, &ProtocolLimits::default())
+        .unwrap()
+        .to_bytes();
     assert_eq!(res.as_ref(), b"$0\r\n\r\n");
 }
 
 #[test]
 fn test_parse_lrange_array() {
-    let mut input = BytesMut::from("*4\r\n$6\r\nLRANGE\r\n$4\r\npear\r\n$2\r\n-3\r\n$2\r\n-1\r\n");
+    let input = BytesMut::from("*4\r\n$6\r\nLRANGE\r\n$4\r\npear\r\n$2\r\n-3\r\n$2\r\n-1\r\n");
 
     assert_eq!(
-        parse_array(&mut input),
+        parse_array(&input, &mut 0, &ProtocolLimits::default(), 1),
         Ok(RedisType::Array(Some(vec![
             RedisType::BulkString(BytesMut::from("LRANGE").freeze()),
             RedisType::BulkString(BytesMut::from("pear").freeze()),
@@ -324,24 +748,30 @@ fn test_parse_lrange_array() {
 
 #[test]
 fn test_parse_array_empty_array() {
-    let mut input = BytesMut::from("*0\r\n");
-    assert_eq!(parse_array(&mut input), Ok(RedisType::Array(Some(vec![]))));
+    let input = BytesMut::from("*0\r\n");
+    assert_eq!(
+        parse_array(&input, &mut 0, &ProtocolLimits::default(), 1),
+        Ok(RedisType::Array(Some(vec![])))
+    );
 }
 
 #[test]
 fn test_parse_array_null_array() {
-    let mut input = BytesMut::from("*-1\r\n");
-    assert_eq!(parse_array(&mut input), Ok(RedisType::Array(None)));
+    let input = BytesMut::from("*-1\r\n");
+    assert_eq!(
+        parse_array(&input, &mut 0, &ProtocolLimits::default(), 1),
+        Ok(RedisType::Array(None))
+    );
 }
 
 #[test]
 fn test_parse_array_large_string_array() {
-    let mut buffer = BytesMut::from(
+    let buffer = BytesMut::from(
         "*10\r\n$5\r\nhello\r\n$5\r\nhello\r\n$5\r\nhello\r\n$5\r\nhello\r\n$5\r\nhello\r\n$5\r\nhello\r\n$5\r\nhello\r\n$5\r\nhello\r\n$5\r\nhello\r\n$5\r\nhello\r\n",
     );
 
     assert_eq!(
-        parse_array(&mut buffer),
+        parse_array(&buffer, &mut 0, &ProtocolLimits::default(), 1),
         Ok(RedisType::Array(Some(vec![
             RedisType::BulkString(BytesMut::from("hello").freeze()),
             RedisType::BulkString(BytesMut::from("hello").freeze()),
@@ -358,11 +788,11 @@ fn test_parse_array_large_string_array() {
 }
 #[test]
 fn test_parse_array_nested_array() {
-    let mut input =
+    let input =
         BytesMut::from("*3\r\n$3\r\nfoo\r\n*2\r\n$5\r\nhello\r\n$5\r\nworld\r\n$3\r\nbar\r\n");
 
     assert_eq!(
-        parse_array(&mut input),
+        parse_array(&input, &mut 0, &ProtocolLimits::default(), 1),
         Ok(RedisType::Array(Some(vec![
             RedisType::BulkString(BytesMut::from("foo").freeze()),
             RedisType::Array(Some(vec![
@@ -373,3 +803,212 @@ fn test_parse_array_nested_array() {
         ])))
     );
 }
+
+#[test]
+fn test_parse_resp_waits_for_the_whole_frame() {
+    let frame = b"*2\r\n$4\r\nECHO\r\n$4\r\na\r\nb\r\n";
+    let mut buffer = BytesMut::new();
+    for (index, byte) in frame.iter().enumerate() {
+        buffer.extend_from_slice(&[*byte]);
+        if index + 1 < frame.len() {
+            assert_eq!(
+                parse_resp(&mut buffer, &ProtocolLimits::default()),
+                Err(RespParseError::NeedMoreData)
+            );
+            assert_eq!(buffer.len(), index + 1);
+        }
+    }
+    assert_eq!(
+        parse_resp(&mut buffer, &ProtocolLimits::default()),
+        Ok(RedisType::Array(Some(vec![
+            RedisType::BulkString(Bytes::from("ECHO")),
+            RedisType::BulkString(Bytes::from("a\r\nb")),
+        ])))
+    );
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn test_parse_array_enforces_the_limits() {
+    let limits = ProtocolLimits {
+        max_bulk_len: 4,
+        max_multibulk_len: 2,
+        max_depth: 2,
+    };
+    let parse = |input: &str| parse_array(&BytesMut::from(input), &mut 0, &limits, 1);
+    assert!(parse("*2\r\n$4\r\nECHO\r\n*1\r\n$2\r\nhi\r\n").is_ok());
+    assert_eq!(
+        parse("*1\r\n$4294967295\r\n"),
+        Err(RespParseError::InvalidBulkLength)
+    );
+    assert_eq!(
+        parse("*1000000000\r\n"),
+        Err(RespParseError::InvalidMultibulkLength)
+    );
+    assert_eq!(
+        parse("*1\r\n*1\r\n*1\r\n$2\r\nhi\r\n"),
+        Err(RespParseError::TooDeeplyNested)
+    );
+}
+
+#[test]
+fn test_parse_resp3_scalars() {
+    let buffer = BytesMut::from("*5\r\n:-42\r\n_\r\n#t\r\n,1.5\r\n,-inf\r\n");
+    assert_eq!(
+        parse_array(&buffer, &mut 0, &ProtocolLimits::default(), 1),
+        Ok(RedisType::Array(Some(vec![
+            RedisType::Integer(-42),
+            RedisType::Null,
+            RedisType::Boolean(true),
+            RedisType::Double(1.5),
+            RedisType::Double(f64::NEG_INFINITY),
+        ])))
+    );
+    assert_eq!(
+        parse_array(
+            &BytesMut::from("*1\r\n#x\r\n"),
+            &mut 0,
+            &ProtocolLimits::default(),
+            1
+        ),
+        Err(RespParseError::InvalidFormat)
+    );
+    assert_eq!(
+        parse_array(
+            &BytesMut::from("*1\r\n:1.5\r\n"),
+            &mut 0,
+            &ProtocolLimits::default(),
+            1
+        ),
+        Err(RespParseError::InvalidFormat)
+    );
+    assert_eq!(
+        parse_array(
+            &BytesMut::from("*1\r\n!3\r\nerr\r\n"),
+            &mut 0,
+            &ProtocolLimits::default(),
+            1
+        ),
+        Err(RespParseError::UnexpectedTypeByte(b'!'))
+    );
+}
+
+#[test]
+fn test_parse_inline_commands() {
+    let mut buffer = BytesMut::from("PING\r\n\r\nset  key \"a \\\"b\\\" \\x41\\n\" 'it\\'s'\nGET");
+    assert_eq!(
+        parse_resp(&mut buffer, &ProtocolLimits::default()),
+        Ok(RedisType::Array(Some(vec![RedisType::BulkString(
+            Bytes::from("PING")
+        )])))
+    );
+    assert_eq!(
+        parse_resp(&mut buffer, &ProtocolLimits::default()),
+        Ok(RedisType::Array(Some(vec![
+            RedisType::BulkString(Bytes::from("set")),
+            RedisType::BulkString(Bytes::from("key")),
+            RedisType::BulkString(Bytes::from("a \"b\" A\n")),
+            RedisType::BulkString(Bytes::from("it's")),
+        ])))
+    );
+    assert_eq!(
+        parse_resp(&mut buffer, &ProtocolLimits::default()),
+        Err(RespParseError::NeedMoreData)
+    );
+    assert_eq!(buffer, BytesMut::from("GET"));
+
+    assert_eq!(
+        parse_resp(
+            &mut BytesMut::from("get \"key\r\n"),
+            &ProtocolLimits::default()
+        ),
+        Err(RespParseError::UnbalancedQuotes)
+    );
+    assert_eq!(
+        parse_resp(
+            &mut BytesMut::from(vec![b'a'; MAX_LINE_LEN + 1].as_slice()),
+            &ProtocolLimits::default()
+        ),
+        Err(RespParseError::TooLargePayload)
+    );
+    assert_eq!(split_arguments(b"get 'key'x"), None);
+}
+
+#[test]
+fn test_resp3_types_are_downgraded_for_resp2() {
+    let encode = |value: &RedisType, protocol: u8| {
+        let mut out = BytesMut::new();
+        value.encode(&mut out, protocol);
+        out.freeze()
+    };
+    let map = RedisType::Map(vec![(
+        RedisType::BulkString(Bytes::from("score")),
+        RedisType::Double(1.5),
+    )]);
+    assert_eq!(
+        encode(&map, 3),
+        Bytes::from("%1\r\n$5\r\nscore\r\n,1.5\r\n")
+    );
+    assert_eq!(
+        encode(&map, 2),
+        Bytes::from("*2\r\n$5\r\nscore\r\n$3\r\n1.5\r\n")
+    );
+    assert_eq!(encode(&RedisType::Boolean(true), 3), Bytes::from("#t\r\n"));
+    assert_eq!(encode(&RedisType::Boolean(true), 2), Bytes::from(":1\r\n"));
+    assert_eq!(encode(&RedisType::Null, 2), Bytes::from("$-1\r\n"));
+    assert_eq!(
+        encode(&RedisType::Double(f64::NEG_INFINITY), 3),
+        Bytes::from(",-inf\r\n")
+    );
+    let verbatim = RedisType::VerbatimString {
+        format: *b"txt",
+        text: Bytes::from("hi"),
+    };
+    assert_eq!(encode(&verbatim, 3), Bytes::from("=6\r\ntxt:hi\r\n"));
+    assert_eq!(encode(&verbatim, 2), Bytes::from("$2\r\nhi\r\n"));
+    let push = RedisType::Push(vec![RedisType::Set(vec![RedisType::Integer(1)])]);
+    assert_eq!(encode(&push, 3), Bytes::from(">1\r\n~1\r\n:1\r\n"));
+    assert_eq!(encode(&push, 2), Bytes::from("*1\r\n*1\r\n:1\r\n"));
+    let attribute = RedisType::Attribute {
+        attributes: vec![(
+            RedisType::BulkString(Bytes::from("ttl")),
+            RedisType::Integer(5),
+        )],
+        reply: Box::new(RedisType::Double(1.5)),
+    };
+    assert_eq!(
+        encode(&attribute, 3),
+        Bytes::from("|1\r\n$3\r\nttl\r\n:5\r\n,1.5\r\n")
+    );
+    assert_eq!(encode(&attribute, 2), Bytes::from("$3\r\n1.5\r\n"));
+}
+
+#[test]
+fn test_parse_resp_skips_empty_commands_and_wants_bulk_arguments() {
+    let limits = ProtocolLimits::default();
+    let mut buffer = BytesMut::from("*-1\r\n*0\r\n*1\r\n$4\r\nPING\r\n");
+    assert_eq!(
+        parse_resp(&mut buffer, &limits),
+        Ok(RedisType::Array(Some(vec![RedisType::BulkString(
+            Bytes::from("PING")
+        )])))
+    );
+    assert!(buffer.is_empty());
+    assert_eq!(
+        parse_resp(&mut BytesMut::from("*0\r\n"), &limits),
+        Err(RespParseError::NeedMoreData)
+    );
+
+    for (input, byte) in [
+        ("*2\r\n$3\r\nGET\r\n:1\r\n", b':'),
+        ("*2\r\n$3\r\nGET\r\n#t\r\n", b'#'),
+        ("*1\r\n*1\r\n$4\r\nPING\r\n", b'*'),
+    ] {
+        let error = parse_resp(&mut BytesMut::from(input), &limits).unwrap_err();
+        assert_eq!(error, RespParseError::ExpectedBulkString(byte));
+        assert_eq!(
+            error.to_string(),
+            format!("expected '$', got '{}'", byte as char)
+        );
+    }
+}
@@ -2,18 +2,63 @@ use std::num::ParseIntError;
 use std::ops::Bound::{Excluded, Included, Unbounded};
 use std::str::Utf8Error;
 use std::{
-    collections::{BTreeMap, HashMap, VecDeque},
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
     fmt::Display,
-    time::{SystemTime, SystemTimeError, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, SystemTimeError, UNIX_EPOCH},
 };
 
 use bytes::Bytes;
 use tokio::sync::oneshot;
 
-use crate::commands::utils::xread_output_to_redis_type;
+use crate::commands::utils::{wrong_type_error, xread_output_to_redis_type};
 use crate::parser::RedisType;
 use crate::transactions::create_identifier;
 
+mod clients;
+mod engine;
+mod hash_value;
+mod hashes;
+mod histogram;
+mod list_value;
+mod lists;
+mod pubsub;
+mod rdb;
+mod scan;
+mod set_value;
+mod sets;
+mod skiplist;
+mod slots;
+mod stream_group;
+mod stream_value;
+mod streams;
+mod tracking;
+mod watch;
+mod zset_value;
+mod zsets;
+
+#[cfg(test)]
+pub use clients::test_client;
+use clients::{ClientPause, Clients};
+pub use clients::{ClientState, ProtocolVersion, ReplyMode};
+pub use engine::{MemoryEngine, StorageEngine, Value, engine_from_name};
+use hash_value::HashValue;
+pub use hashes::ExpiryCondition;
+use list_value::ListValue;
+pub use lists::ListEnd;
+use pubsub::Subscribers;
+pub use pubsub::SubscriptionKind;
+pub use rdb::RdbConfig;
+use set_value::SetValue;
+pub use slots::CLUSTER_SLOTS;
+pub use stream_value::StreamEntry;
+use stream_value::StreamValue;
+pub use streams::{ClaimOptions, StreamTrim, TrimThreshold};
+pub use tracking::TrackingOptions;
+use tracking::TrackingTable;
+use watch::WatchedKeys;
+use zset_value::ZSetValue;
+pub use zsets::{Aggregate, ScoredMembers, ZAddOptions, ZRange, ZRangeBy, ZSetOperation};
+
 pub struct WithExpiry {
     value: Bytes,
     expires: Option<u128>,
@@ -21,11 +66,28 @@ pub struct WithExpiry {
 #[derive(Debug)]
 pub enum StoreError {
     KeyNotFound,
-    KeyExpired,
     TimeError,
     ValueError,
     StreamIdSmallerThanLast,
     StreamIdNotGreaterThan0,
+    WrongType,
+    ScoreIsNaN,
+    IndexOutOfRange,
+    EntriesAddedSmallerThanLength,
+    BusyGroup,
+    NoGroup,
+}
+
+impl StoreError {
+    /// The error code redis replies with for the error, `ERR` unless there is a specific one
+    pub fn code(&self) -> &'static str {
+        match self {
+            StoreError::WrongType => "WRONGTYPE",
+            StoreError::BusyGroup => "BUSYGROUP",
+            StoreError::NoGroup => "NOGROUP",
+            _ => "ERR",
+        }
+    }
 }
 
 impl From<SystemTimeError> for StoreError {
@@ -45,36 +107,79 @@ impl From<ParseIntError> for StoreError {
     }
 }
 
-enum KeyType {
-    Key,
-    List,
-    Stream,
-}
-
-#[derive(Default)]
 pub struct Store {
-    key_types: HashMap<Bytes, KeyType>,
-    streams: HashMap<Bytes, BTreeMap<StreamId, HashMap<Bytes, Bytes>>>,
-    keys: HashMap<Bytes, WithExpiry>,
-    lists: HashMap<Bytes, Vec<Bytes>>,
-    blpop_waiting_queue: HashMap<Bytes, VecDeque<WaitingLPOPClient>>,
+    engine: Box<dyn StorageEngine>,
+    blpop_waiting_queue: Vec<WaitingLPOPClient>,
     xread_waiting_queue: Vec<WaitingXREADClient>,
+    zpop_waiting_queue: Vec<WaitingZPOPClient>,
+    /// keys which received data while clients are blocked on them, in the order they did
+    ready_keys: VecDeque<Bytes>,
+    /// deadlines of the blocked clients with a timeout, earliest first
+    waiting_deadlines: BTreeSet<(Instant, u64)>,
+    subscribers: Subscribers,
+    pattern_subscribers: Subscribers,
+    shard_subscribers: Subscribers,
+    watched_keys: WatchedKeys,
+    clients: Clients,
+    pause: Option<ClientPause>,
+    tracking: TrackingTable,
+    /// the client whose command is running, if any
+    current_client: Option<u64>,
+    /// clients which issued MONITOR
+    monitors: HashSet<u64>,
+    rdb_config: RdbConfig,
 }
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd)]
 pub struct StreamId {
     pub ms: u128,
     pub seq: u128,
 }
 
-/// Represents a lpop client waiting for data
+/// Represents a client blocked in BLPOP, BRPOP, BLMPOP, BLMOVE or BRPOPLPUSH
 pub struct WaitingLPOPClient {
     pub identifier: u64,
+    pub keys: Vec<Bytes>,
+    /// end of the list the client pops from
+    pub from: ListEnd,
+    pub count: usize,
+    /// list (and its end) the popped element is moved to, for BLMOVE and BRPOPLPUSH
+    pub destination: Option<(Bytes, ListEnd)>,
+    /// builds the reply from the key and the elements popped (or moved) for the client
+    pub reply: fn(Bytes, Vec<Bytes>) -> RedisType,
     pub sender: oneshot::Sender<RedisType>,
 }
-/// Represents a lpop client waiting for data
+/// Where a client blocked in XREAD or XREADGROUP continues reading
+pub enum StreamReadPosition {
+    /// id for each of the keys, only newer entries are delivered. `$` is resolved to the last
+    /// id of the stream when the client blocked.
+    After(Vec<StreamId>),
+    /// the entries the group hasn't delivered yet (`>`), NOACK leaves them out of the pending
+    /// entries
+    Group {
+        group: Bytes,
+        consumer: Bytes,
+        no_ack: bool,
+    },
+}
+
+/// Represents a client blocked in XREAD or XREADGROUP
 pub struct WaitingXREADClient {
     pub identifier: u64,
     pub keys: Vec<Bytes>,
+    pub from: StreamReadPosition,
+    /// delivers at most this many entries
+    pub count: Option<usize>,
+    pub sender: oneshot::Sender<RedisType>,
+}
+
+/// Represents a client blocked in BZPOPMIN, BZPOPMAX or BZMPOP
+pub struct WaitingZPOPClient {
+    pub identifier: u64,
+    pub keys: Vec<Bytes>,
+    pub count: usize,
+    pub highest: bool,
+    /// builds the reply from the key and the members popped for the client
+    pub reply: fn(Bytes, ScoredMembers) -> RedisType,
     pub sender: oneshot::Sender<RedisType>,
 }
 
@@ -90,42 +195,150 @@ impl From<&StreamId> for RedisType {
     }
 }
 
+/// Generates the typed lookups for one kind of value. Each lookup fails with `WrongType`
+/// if the key holds another kind of value, `$entry` creates an empty value for missing keys.
+/// An expired string counts as missing, the mutable lookups drop it. The mutable lookups
+/// don't count as a change of the key, callers touch it once they actually changed the value.
+macro_rules! typed_accessors {
+    ($variant:ident, $value:ty, $get:ident, $get_mut:ident) => {
+        fn $get(&self, key: &Bytes) -> Result<Option<&$value>, StoreError> {
+            match self.value(key) {
+                None => Ok(None),
+                Some(Value::$variant(value)) => Ok(Some(value)),
+                Some(_) => Err(StoreError::WrongType),
+            }
+        }
+
+        #[allow(dead_code)] // not every kind of value is modified in place
+        fn $get_mut(&mut self, key: &Bytes) -> Result<Option<&mut $value>, StoreError> {
+            self.remove_if_expired(key);
+            match self.engine.get_mut(key) {
+                None => Ok(None),
+                Some(Value::$variant(value)) => Ok(Some(value)),
+                Some(_) => Err(StoreError::WrongType),
+            }
+        }
+    };
+    ($variant:ident, $value:ty, $get:ident, $get_mut:ident, $entry:ident) => {
+        typed_accessors!($variant, $value, $get, $get_mut);
+
+        fn $entry(&mut self, key: &Bytes) -> Result<&mut $value, StoreError> {
+            self.remove_if_expired(key);
+            if self.engine.get(key).is_none() {
+                self.insert_value(key.clone(), Value::$variant(Default::default()));
+            }
+            match self.engine.get_mut(key) {
+                Some(Value::$variant(value)) => Ok(value),
+                _ => Err(StoreError::WrongType),
+            }
+        }
+    };
+}
+
 impl Store {
+    /// A store backed by the in-memory engine, the server itself picks the engine from its config
+    #[allow(dead_code)]
     pub fn new() -> Self {
-        Self::default()
+        Self::with_engine(Box::new(MemoryEngine::default()))
+    }
+
+    pub fn with_engine(engine: Box<dyn StorageEngine>) -> Self {
+        Store {
+            engine,
+            blpop_waiting_queue: Vec::new(),
+            xread_waiting_queue: Vec::new(),
+            zpop_waiting_queue: Vec::new(),
+            ready_keys: VecDeque::new(),
+            waiting_deadlines: BTreeSet::new(),
+            subscribers: Subscribers::new(),
+            pattern_subscribers: Subscribers::new(),
+            shard_subscribers: Subscribers::new(),
+            watched_keys: WatchedKeys::new(),
+            clients: Clients::new(),
+            pause: None,
+            tracking: TrackingTable::default(),
+            current_client: None,
+            monitors: HashSet::new(),
+            rdb_config: RdbConfig::default(),
+        }
+    }
+
+    typed_accessors!(String, WithExpiry, string, string_mut);
+    typed_accessors!(List, ListValue, list, list_mut, list_entry);
+    typed_accessors!(Hash, HashValue, hash, hash_mut, hash_entry);
+    typed_accessors!(Set, SetValue, set, set_mut, set_entry);
+    typed_accessors!(Stream, StreamValue, stream, stream_mut, stream_entry);
+    typed_accessors!(SortedSet, ZSetValue, zset, zset_mut, zset_entry);
+
+    fn is_expired(&self, key: &Bytes) -> bool {
+        let now = now_millis().unwrap_or(0);
+        match self.engine.get(key) {
+            Some(Value::String(entry)) => entry.expires.is_some_and(|expiry| expiry < now),
+            _ => false,
+        }
+    }
+
+    /// The value at the key whatever its type, every type check starts here. An expired string
+    /// counts as missing, so any kind of value may take its place.
+    fn value(&self, key: &Bytes) -> Option<&Value> {
+        self.engine.get(key).filter(|_| !self.is_expired(key))
+    }
+
+    fn remove_if_expired(&mut self, key: &Bytes) {
+        if self.is_expired(key) {
+            self.remove_key(key);
+        }
+    }
+
+    /// Stores a value, every key creation goes through here to keep the key indexes up to date
+    fn insert_value(&mut self, key: Bytes, value: Value) {
+        self.touch_key(&key);
+        self.engine.insert(key, value);
+    }
+
+    /// Removes the key if it holds a collection which became empty
+    fn remove_if_empty(&mut self, key: &Bytes) {
+        if self
+            .engine
+            .get(key)
+            .is_some_and(|value| value.is_empty_collection())
+        {
+            self.remove_key(key);
+        }
+    }
+
+    /// Removes the key and its value, whatever type it holds
+    fn remove_key(&mut self, key: &Bytes) {
+        self.touch_key(key);
+        self.engine.remove(key);
     }
 
     pub fn rpush(&mut self, key: Bytes, values: Vec<Bytes>) -> Result<usize, StoreError> {
-        self.key_types.insert(key.clone(), KeyType::List);
-        let list = self.lists.entry(key.clone()).or_default();
-        list.extend(values);
+        let list = self.list_entry(&key)?;
+        for value in values {
+            list.push_back(value);
+        }
 
         let len = list.len();
-        self.notify_first_waiting_client(&key);
+        self.touch_key(&key);
+        self.signal_key_as_ready(&key);
         Ok(len)
     }
 
-    pub fn lpush(&mut self, key: Bytes, mut values: Vec<Bytes>) -> Result<usize, StoreError> {
-        self.key_types.insert(key.clone(), KeyType::List);
-        let list = self.lists.entry(key.clone()).or_default();
-        values.reverse(); // reverse the order of the values
-        list.splice(0..0, values); //  inserts all the values at the beginning of the list
+    pub fn lpush(&mut self, key: Bytes, values: Vec<Bytes>) -> Result<usize, StoreError> {
+        let list = self.list_entry(&key)?;
+        for value in values {
+            list.push_front(value); // the last value ends up first
+        }
 
         let len = list.len();
-        self.notify_first_waiting_client(&key);
+        self.touch_key(&key);
+        self.signal_key_as_ready(&key);
         Ok(len)
     }
 
     pub fn get(&self, key: Bytes) -> Result<Bytes, StoreError> {
-        let result = self.keys.get(&key).ok_or(StoreError::KeyNotFound)?;
-        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
-
-        if let Some(expiry) = result.expires
-            && expiry < now
-        {
-            return Err(StoreError::KeyExpired);
-        }
-
+        let result = self.string(&key)?.ok_or(StoreError::KeyNotFound)?;
         Ok(result.value.clone())
     }
 
@@ -135,7 +348,7 @@ impl Store {
         mut start: i128,
         mut end: i128,
     ) -> Result<Vec<Bytes>, StoreError> {
-        let list = self.lists.get(&key).ok_or(StoreError::KeyNotFound)?;
+        let list = self.list(&key)?.ok_or(StoreError::KeyNotFound)?;
         let list_length = list.len() as i128;
         if start < 0 {
             start += list_length;
@@ -165,8 +378,10 @@ impl Store {
         let start_pos = start as usize;
         let end_pos = end as usize;
 
-        let slice = &list.as_slice()[start_pos..end_pos];
-        Ok(slice.to_vec())
+        Ok(list
+            .range(start_pos, end_pos)
+            .map(Bytes::copy_from_slice)
+            .collect())
     }
 
     pub fn set_with_expiry(
@@ -175,8 +390,6 @@ impl Store {
         value: Bytes,
         expiry: Option<u128>,
     ) -> Result<(), StoreError> {
-        self.key_types.insert(key.clone(), KeyType::Key);
-
         let expires = expiry
             .map(|ex| {
                 SystemTime::now()
@@ -185,157 +398,319 @@ impl Store {
             })
             .transpose()?; // converts a Result<Option<Duration>, Error> to Option<u128>!!
 
+        // SET replaces whatever value the key held before
         let key_value = WithExpiry { value, expires };
-        self.keys.insert(key, key_value);
+        self.insert_value(key, Value::String(key_value));
         Ok(())
     }
 
     pub fn incr(&mut self, key: &Bytes, amount: u128) -> Result<u128, StoreError> {
-        if !self.keys.contains_key(key) {
+        let Some(value_with_expiry) = self.string_mut(key)? else {
             self.set_with_expiry(key.clone(), Bytes::from("1"), None)?;
             return Ok(1);
-        }
-
-        let value_with_expiry = self.keys.get_mut(key).ok_or(StoreError::KeyNotFound)?;
+        };
 
         let existing_val = str::from_utf8(&value_with_expiry.value)?.parse::<u128>()?;
         let new_val = existing_val + amount;
         value_with_expiry.value = Bytes::from(format!("{}", new_val));
+        self.touch_key(key);
         Ok(new_val)
     }
 
     pub fn llen(&self, key: &Bytes) -> Result<usize, StoreError> {
-        let len = self.lists.get(key).map(|l| l.len()).unwrap_or(0);
+        let len = self.list(key)?.map(|l| l.len()).unwrap_or(0);
         Ok(len)
     }
 
+    pub fn object_encoding(&self, key: &Bytes) -> Result<&'static str, StoreError> {
+        self.value(key)
+            .map(|value| value.encoding())
+            .ok_or(StoreError::KeyNotFound)
+    }
+
+    /// Seconds since the key was last accessed
+    pub fn object_idletime(&self, key: &Bytes) -> Result<u64, StoreError> {
+        self.value(key).ok_or(StoreError::KeyNotFound)?;
+        Ok(self
+            .engine
+            .last_access(key)
+            .map_or(0, |accessed| accessed.elapsed().as_secs()))
+    }
+
+    /// Counts the command as access to those of the keys which exist
+    pub fn access_keys(&mut self, keys: &[Bytes]) {
+        let now = Instant::now();
+        for key in keys {
+            self.engine.record_access(key, now);
+        }
+    }
+
     pub fn get_type(&self, key: &Bytes) -> Result<Bytes, StoreError> {
-        self.key_types
-            .get(key)
-            .map(|kt| match kt {
-                KeyType::Key => Bytes::from("string"),
-                KeyType::List => Bytes::from("list"),
-                KeyType::Stream => Bytes::from("stream"),
-            })
+        self.value(key)
+            .map(|value| Bytes::from_static(value.type_name().as_bytes()))
             .ok_or(StoreError::KeyNotFound)
     }
 
-    pub fn lpop(&mut self, key: Bytes, amount: i128) -> Result<Vec<Bytes>, StoreError> {
-        let list = self.lists.entry(key).or_default();
+    /// Pops up to `amount` elements from the head of the list, a count beyond the length
+    /// of the list simply pops all of them
+    pub fn lpop(&mut self, key: Bytes, amount: usize) -> Result<Vec<Bytes>, StoreError> {
+        let Some(list) = self.list_mut(&key)? else {
+            return Err(StoreError::KeyNotFound);
+        };
 
         if !list.is_empty() {
-            let removed = list.drain(..amount as usize).collect();
+            let removed: Vec<Bytes> = (0..amount.min(list.len()))
+                .filter_map(|_| list.pop_front())
+                .collect();
+            if !removed.is_empty() {
+                self.touch_key(&key);
+            }
+            self.remove_if_empty(&key);
             return Ok(removed);
         }
 
         Err(StoreError::KeyNotFound)
     }
-    /// Pops from list if available, returns the values
-    pub fn lpop_for_blpop(&mut self, key: &Bytes) -> Option<Vec<Bytes>> {
-        let list = self.lists.get_mut(key)?;
-        if list.is_empty() {
-            return None;
+
+    /// Pops up to `amount` elements from the tail of the list, the last element comes first
+    pub fn rpop(&mut self, key: Bytes, amount: usize) -> Result<Vec<Bytes>, StoreError> {
+        let Some(list) = self.list_mut(&key)? else {
+            return Err(StoreError::KeyNotFound);
+        };
+
+        if !list.is_empty() {
+            let removed: Vec<Bytes> = (0..amount.min(list.len()))
+                .filter_map(|_| list.pop_back())
+                .collect();
+            if !removed.is_empty() {
+                self.touch_key(&key);
+            }
+            self.remove_if_empty(&key);
+            return Ok(removed);
         }
-        let mut removed: Vec<Bytes> = list.drain(..1).collect();
-        removed.insert(0, key.clone());
-        Some(removed)
+
+        Err(StoreError::KeyNotFound)
     }
 
     pub fn register_blpop_waiting_client(
         &mut self,
-        key: Bytes,
+        keys: Vec<Bytes>,
+        from: ListEnd,
+        count: usize,
+        destination: Option<(Bytes, ListEnd)>,
+        reply: fn(Bytes, Vec<Bytes>) -> RedisType,
         sender: oneshot::Sender<RedisType>,
     ) -> u64 {
         let identifier = create_identifier();
-        let client = WaitingLPOPClient { identifier, sender };
-
-        self.blpop_waiting_queue
-            .entry(key)
-            .or_default()
-            .push_back(client);
-
+        self.blpop_waiting_queue.push(WaitingLPOPClient {
+            identifier,
+            keys,
+            from,
+            count,
+            destination,
+            reply,
+            sender,
+        });
         identifier
     }
 
+    /// Blocks a client until there is an entry to read from one of the keys
     pub fn register_xread_waiting_client(
         &mut self,
         keys: Vec<Bytes>,
+        from: StreamReadPosition,
+        count: Option<usize>,
         sender: oneshot::Sender<RedisType>,
     ) -> u64 {
         let identifier = create_identifier();
         let client = WaitingXREADClient {
             identifier,
             keys,
+            from,
+            count,
             sender,
         };
         self.xread_waiting_queue.push(client);
         identifier
     }
 
-    pub fn remove_blpop_waiting_client(&mut self, key: &Bytes, client_id: u64) {
-        if let Some(queue) = self.blpop_waiting_queue.get_mut(key) {
-            queue.retain(|client| client.identifier != client_id);
-
-            // Clean up empty queues
-            if queue.is_empty() {
-                self.blpop_waiting_queue.remove(key);
-            }
+    /// Forgets a blocked client (BLPOP, XREAD, BZPOPMIN, ...) on all its keys once it stopped
+    /// waiting, returns the sender of its reply if it was still waiting
+    pub fn remove_waiting_client(&mut self, client_id: u64) -> Option<oneshot::Sender<RedisType>> {
+        if let Some(position) = self
+            .blpop_waiting_queue
+            .iter()
+            .position(|client| client.identifier == client_id)
+        {
+            return Some(self.blpop_waiting_queue.remove(position).sender);
+        }
+        if let Some(position) = self
+            .xread_waiting_queue
+            .iter()
+            .position(|client| client.identifier == client_id)
+        {
+            return Some(self.xread_waiting_queue.remove(position).sender);
         }
+        if let Some(position) = self
+            .zpop_waiting_queue
+            .iter()
+            .position(|client| client.identifier == client_id)
+        {
+            return Some(self.zpop_waiting_queue.remove(position).sender);
+        }
+        None
     }
 
-    fn notify_xread_waiting_clients(&mut self, key: &Bytes, stream_id: StreamId) {
-        let mut i = 0;
-        while i < self.xread_waiting_queue.len() {
-            let should_notify = self.xread_waiting_queue[i].keys.contains(key);
-
-            if should_notify {
-                let client = self.xread_waiting_queue.swap_remove(i); // now we own it
+    /// Lets the blocked client wait at most for the timeout
+    pub fn set_waiting_timeout(&mut self, client_id: u64, timeout: Duration) {
+        self.waiting_deadlines
+            .insert((Instant::now() + timeout, client_id));
+    }
 
-                let res = xread_output_to_redis_type(key.clone(), self.xread(key, stream_id, true));
+    /// The earliest deadline of a blocked client
+    pub fn next_waiting_deadline(&self) -> Option<Instant> {
+        self.waiting_deadlines
+            .first()
+            .map(|(deadline, _)| *deadline)
+    }
 
-                if client
-                    .sender
-                    .send(RedisType::Array(Some(vec![res])))
-                    .is_ok()
-                {
-                    println!("Client {} notified", client.identifier);
-                }
-                // don't increment i (swap_remove brings a new element into i)
-            } else {
-                i += 1;
+    /// Replies nil to the blocked clients whose deadline passed and forgets them.
+    /// Deadlines of clients which were served in the meantime are simply dropped.
+    pub fn expire_waiting_clients(&mut self, now: Instant) {
+        while let Some(&(deadline, client_id)) = self.waiting_deadlines.first()
+            && deadline <= now
+        {
+            self.waiting_deadlines.pop_first();
+            if let Some(sender) = self.remove_waiting_client(client_id) {
+                let _ = sender.send(RedisType::Array(None));
             }
         }
     }
 
-    fn notify_first_waiting_client(&mut self, key: &Bytes) {
-        let Some(queue) = self.blpop_waiting_queue.get_mut(key) else {
+    /// Queues the key for `serve_blocked_clients` if a client is blocked on it
+    fn signal_key_as_ready(&mut self, key: &Bytes) {
+        if self.ready_keys.contains(key) {
             return;
-        };
-
-        let Some(list) = self.lists.get_mut(key) else {
-            return;
-        };
+        }
+        let blocked = self
+            .blpop_waiting_queue
+            .iter()
+            .any(|client| client.keys.contains(key))
+            || self
+                .xread_waiting_queue
+                .iter()
+                .any(|client| client.keys.contains(key))
+            || self
+                .zpop_waiting_queue
+                .iter()
+                .any(|client| client.keys.contains(key));
+        if blocked {
+            self.ready_keys.push_back(key.clone());
+        }
+    }
 
-        if list.is_empty() {
-            return;
+    /// Serves the clients blocked on the keys which received data, key by key in the order the
+    /// keys became ready. Runs after every command (after the whole transaction for EXEC), so
+    /// clients see the outcome of a command and never a state in between. Serving a client can
+    /// make further keys ready (BLMOVE pushes to its destination), those are served as well.
+    pub fn serve_blocked_clients(&mut self) {
+        while let Some(key) = self.ready_keys.pop_front() {
+            self.serve_blpop_waiting_clients(&key);
+            self.serve_zpop_waiting_clients(&key);
+            self.serve_xread_waiting_clients(&key);
         }
+    }
 
-        if let Some(waiting_client) = queue.pop_front() {
-            let value = list.remove(0);
-            let response = RedisType::Array(Some(vec![
-                RedisType::BulkString(key.clone()),
-                RedisType::BulkString(value),
-            ]));
+    /// The last id of the stream, 0-0 if there is none
+    pub fn last_stream_id(&self, key: &Bytes) -> Result<StreamId, StoreError> {
+        Ok(self
+            .stream(key)?
+            .map(|stream| stream.last_id())
+            .unwrap_or_default())
+    }
 
-            if waiting_client.sender.send(response).is_ok() {
-                return;
-            }
-            // Send failed (client timed out?)
+    /// Serves every client blocked on `key` with the entries newer than its id. Reading doesn't
+    /// consume entries, so unlike BLPOP one XADD wakes all of them, each with its own entries.
+    /// Clients of the same consumer group share the entries, only the first of them gets them.
+    fn serve_xread_waiting_clients(&mut self, key: &Bytes) {
+        self.xread_waiting_queue
+            .retain(|client| !client.sender.is_closed());
+
+        let mut i = 0;
+        while i < self.xread_waiting_queue.len() {
+            let client = &self.xread_waiting_queue[i];
+            let Some(position) = client.keys.iter().position(|k| k == key) else {
+                i += 1;
+                continue;
+            };
+            let count = client.count;
+            let read = match &client.from {
+                StreamReadPosition::After(last_ids) => {
+                    self.xread(key, last_ids[position], false, count)
+                }
+                StreamReadPosition::Group {
+                    group,
+                    consumer,
+                    no_ack,
+                } => {
+                    let (group, consumer, no_ack) = (group.clone(), consumer.clone(), *no_ack);
+                    self.xreadgroup(key, &group, &consumer, count, no_ack)
+                }
+            };
+            let reply = match read {
+                Ok(entries) if entries.is_empty() => {
+                    i += 1;
+                    continue;
+                }
+                Ok(entries) => {
+                    RedisType::Array(Some(vec![xread_output_to_redis_type(key.clone(), entries)]))
+                }
+                Err(StoreError::WrongType) => wrong_type_error(),
+                // the group (or the whole stream) was removed while the client was blocked
+                Err(_) => RedisType::SimpleError(
+                    "NOGROUP the consumer group this client was blocked on no longer exists".into(),
+                ),
+            };
+
+            // removing keeps the others in the order they blocked
+            let client = self.xread_waiting_queue.remove(i);
+            let _ = client.sender.send(reply);
         }
+    }
 
-        // Clean up empty queue
-        if queue.is_empty() {
-            self.blpop_waiting_queue.remove(key);
+    /// Serves the clients blocked on `key` in the order they blocked, as long as it holds elements
+    fn serve_blpop_waiting_clients(&mut self, key: &Bytes) {
+        // clients which timed out are only cleaned up once their timeout message arrives
+        self.blpop_waiting_queue
+            .retain(|client| !client.sender.is_closed());
+
+        while self
+            .list(key)
+            .is_ok_and(|list| list.is_some_and(|list| !list.is_empty()))
+        {
+            let Some(position) = self
+                .blpop_waiting_queue
+                .iter()
+                .position(|client| client.keys.contains(key))
+            else {
+                return;
+            };
+            let client = self.blpop_waiting_queue.remove(position);
+
+            let popped = match &client.destination {
+                // the element is moved right here, before the client gets its reply;
+                // pushing to the destination wakes up the clients waiting there in turn
+                Some((destination, to)) => self
+                    .lmove(key, destination, client.from, *to)
+                    .map(|moved| moved.into_iter().collect()),
+                None => self.pop_from(key, client.from, client.count),
+            };
+            let response = match popped {
+                Ok(popped) => (client.reply)(key.clone(), popped),
+                Err(_) => wrong_type_error(),
+            };
+            // the client may have timed out meanwhile
+            let _ = client.sender.send(response);
         }
     }
 
@@ -346,25 +721,17 @@ impl Store {
         ms: Option<u128>,
         args: &[RedisType],
     ) -> Result<StreamId, StoreError> {
-        self.key_types.insert(stream_key.clone(), KeyType::Stream);
         let min_stream_id = StreamId { ms: 0, seq: 1 };
         let last_stream_id = self
-            .streams
-            .get(stream_key) // get the btree
-            .and_then(|btree| btree.last_key_value().map(|(id, _)| *id))
-            .unwrap_or(StreamId { ms: 0, seq: 0 });
+            .stream(stream_key)?
+            .map(|stream| stream.last_id())
+            .unwrap_or_default();
 
         let stream_id = match (ms, seq) {
-            (Some(pot_ms), Some(pot_seq)) => {
-                println!(
-                    "ms and seq set: Taking stream with ms: {}, seq: {}",
-                    pot_ms, pot_seq
-                );
-                StreamId {
-                    ms: pot_ms,
-                    seq: pot_seq,
-                }
-            }
+            (Some(pot_ms), Some(pot_seq)) => StreamId {
+                ms: pot_ms,
+                seq: pot_seq,
+            },
             (Some(pot_ms), None) => {
                 if pot_ms == last_stream_id.ms {
                     StreamId {
@@ -400,71 +767,45 @@ impl Store {
             return Err(StoreError::StreamIdNotGreaterThan0);
         }
 
-        match self.streams.entry(stream_key.clone()) {
-            std::collections::hash_map::Entry::Occupied(mut existing_entry) => {
-                let btree = existing_entry.get_mut();
-                match btree.last_key_value() {
-                    Some((last_id, _)) => {
-                        if last_id >= &stream_id {
-                            Err(StoreError::StreamIdSmallerThanLast)
-                        } else {
-                            Ok(())
-                        }
-                    }
-                    None => Ok(()),
-                }?;
-
-                insert_keys_and_values(args, btree.entry(stream_id).or_default());
-            }
-            std::collections::hash_map::Entry::Vacant(vacant_entry) => {
-                let mut btree = BTreeMap::new();
-                let mut map = HashMap::new();
-                insert_keys_and_values(args, &mut map);
-                btree.insert(stream_id, map);
-                vacant_entry.insert(btree);
-            }
+        // deleted entries keep the last id, their ids can't be added again
+        if stream_id <= last_stream_id {
+            return Err(StoreError::StreamIdSmallerThanLast);
         }
-        self.notify_xread_waiting_clients(stream_key, stream_id);
+        let mut entry = StreamEntry::new();
+        insert_keys_and_values(args, &mut entry);
+        self.stream_entry(stream_key)?.insert(stream_id, entry);
+        self.touch_key(stream_key);
+        self.signal_key_as_ready(stream_key);
 
         Ok(stream_id)
     }
 
-    pub fn xrange(
-        &self,
-        stream_key: &Bytes,
-        start_stream_id: Option<StreamId>,
-        end_stream_id: Option<StreamId>,
-    ) -> Vec<(StreamId, HashMap<Bytes, Bytes>)> {
-        let start = start_stream_id.map(Included).unwrap_or(Unbounded);
-        let end = end_stream_id.map(Included).unwrap_or(Unbounded);
-        self.streams
-            .get(stream_key)
-            .iter()
-            .flat_map(|f| f.range((start, end)))
-            .map(|(id, entry)| (*id, entry.clone()))
-            .collect()
-    }
-
     pub(crate) fn xread(
         &self,
         stream_key: &Bytes,
         stream_id: StreamId,
         include_stream_id: bool,
-    ) -> Vec<(StreamId, HashMap<Bytes, Bytes>)> {
+        count: Option<usize>,
+    ) -> Result<Vec<(StreamId, StreamEntry)>, StoreError> {
         let start = if include_stream_id {
             Included(stream_id)
         } else {
             Excluded(stream_id)
         };
-        self.streams
-            .get(stream_key)
+        Ok(self
+            .stream(stream_key)?
             .into_iter()
-            .flat_map(|stream| stream.range((start, Unbounded)))
+            .flat_map(|stream| stream.range(start, Unbounded))
+            .take(count.unwrap_or(usize::MAX))
             .map(|(id, entry)| (*id, entry.clone()))
-            .collect()
+            .collect())
     }
 }
 
+pub fn now_millis() -> Result<u128, StoreError> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis())
+}
+
 fn insert_keys_and_values(arguments: &[RedisType], map: &mut HashMap<Bytes, Bytes>) {
     for chunk in arguments[0..].chunks_exact(2) {
         map.insert(chunk[0].to_bytes(), chunk[1].to_bytes());
@@ -496,17 +837,386 @@ fn test_lpush() {
     );
 }
 
+#[test]
+fn test_pop_count_is_clamped_to_the_list() {
+    let mut store = Store::new();
+    let key = Bytes::from("list");
+    store
+        .rpush(key.clone(), vec!["a".into(), "b".into(), "c".into()])
+        .unwrap();
+
+    assert_eq!(
+        store.rpop(key.clone(), 2).unwrap(),
+        vec![Bytes::from("c"), Bytes::from("b")]
+    );
+    assert_eq!(store.lpop(key.clone(), 10).unwrap(), vec![Bytes::from("a")]);
+    assert!(matches!(
+        store.rpop(key.clone(), 1),
+        Err(StoreError::KeyNotFound)
+    ));
+    assert!(store.get_type(&key).is_err());
+}
+
+#[test]
+fn test_blocked_tail_pop_is_served_from_any_of_its_keys() {
+    let mut store = Store::new();
+    let key = Bytes::from("list");
+    let (tx, mut rx) = oneshot::channel();
+    store.register_blpop_waiting_client(
+        vec!["other".into(), key.clone()],
+        ListEnd::Right,
+        1,
+        None,
+        |key, popped| {
+            RedisType::Array(Some(vec![
+                RedisType::BulkString(key),
+                RedisType::BulkString(popped[0].clone()),
+            ]))
+        },
+        tx,
+    );
+
+    store
+        .rpush(key.clone(), vec!["a".into(), "b".into()])
+        .unwrap();
+    store.serve_blocked_clients();
+
+    assert_eq!(
+        rx.try_recv().unwrap(),
+        RedisType::Array(Some(vec![
+            RedisType::BulkString(key.clone()),
+            RedisType::BulkString("b".into())
+        ]))
+    );
+    assert_eq!(store.lrange(key, 0, -1).unwrap(), vec![Bytes::from("a")]);
+}
+
+#[test]
+fn test_served_client_leaves_all_its_keys() {
+    let mut store = Store::new();
+    let (tx, mut rx) = oneshot::channel();
+    store.register_blpop_waiting_client(
+        vec!["a".into(), "b".into()],
+        ListEnd::Left,
+        1,
+        None,
+        |key, _| RedisType::BulkString(key),
+        tx,
+    );
+
+    store.rpush("b".into(), vec!["1".into()]).unwrap();
+    store.serve_blocked_clients();
+    assert_eq!(rx.try_recv().unwrap(), RedisType::BulkString("b".into()));
+
+    store.rpush("a".into(), vec!["1".into()]).unwrap();
+    assert_eq!(store.llen(&"a".into()).unwrap(), 1);
+}
+
+#[test]
+fn test_one_push_serves_several_blocked_clients() {
+    let mut store = Store::new();
+    let key = Bytes::from("list");
+    let mut receivers = Vec::new();
+    for _ in 0..3 {
+        let (tx, rx) = oneshot::channel();
+        store.register_blpop_waiting_client(
+            vec![key.clone()],
+            ListEnd::Left,
+            1,
+            None,
+            |_, popped| RedisType::BulkString(popped[0].clone()),
+            tx,
+        );
+        receivers.push(rx);
+    }
+
+    store
+        .rpush(key.clone(), vec!["a".into(), "b".into()])
+        .unwrap();
+    store.serve_blocked_clients();
+
+    assert_eq!(
+        receivers[0].try_recv().unwrap(),
+        RedisType::BulkString("a".into())
+    );
+    assert_eq!(
+        receivers[1].try_recv().unwrap(),
+        RedisType::BulkString("b".into())
+    );
+    assert!(receivers[2].try_recv().is_err());
+    assert!(store.get_type(&key).is_err());
+
+    store.rpush(key.clone(), vec!["c".into()]).unwrap();
+    store.serve_blocked_clients();
+    assert_eq!(
+        receivers[2].try_recv().unwrap(),
+        RedisType::BulkString("c".into())
+    );
+}
+
+#[test]
+fn test_blocked_clients_are_served_after_the_command() {
+    let mut store = Store::new();
+    let key = Bytes::from("list");
+    let (tx, mut rx) = oneshot::channel();
+    store.register_blpop_waiting_client(
+        vec![key.clone()],
+        ListEnd::Left,
+        1,
+        None,
+        |_, popped| RedisType::BulkString(popped[0].clone()),
+        tx,
+    );
+
+    // like RPUSH and LPOP inside one transaction, the client never sees the element
+    store.rpush(key.clone(), vec!["a".into()]).unwrap();
+    store.lpop(key.clone(), 1).unwrap();
+    store.serve_blocked_clients();
+    assert!(rx.try_recv().is_err());
+
+    store.rpush(key.clone(), vec!["b".into()]).unwrap();
+    store.serve_blocked_clients();
+    assert_eq!(rx.try_recv().unwrap(), RedisType::BulkString("b".into()));
+}
+
+#[test]
+fn test_expired_client_gets_nil_and_no_element() {
+    let mut store = Store::new();
+    let key = Bytes::from("list");
+    let (tx, mut rx) = oneshot::channel();
+    let client_id = store.register_blpop_waiting_client(
+        vec![key.clone()],
+        ListEnd::Left,
+        1,
+        None,
+        |_, popped| RedisType::BulkString(popped[0].clone()),
+        tx,
+    );
+    store.set_waiting_timeout(client_id, Duration::from_secs(1));
+
+    store.expire_waiting_clients(Instant::now());
+    assert!(rx.try_recv().is_err());
+    store.expire_waiting_clients(Instant::now() + Duration::from_secs(2));
+    assert_eq!(rx.try_recv().unwrap(), RedisType::Array(None));
+    assert_eq!(store.next_waiting_deadline(), None);
+
+    store.rpush(key.clone(), vec!["a".into()]).unwrap();
+    store.serve_blocked_clients();
+    assert_eq!(store.llen(&key).unwrap(), 1);
+}
+
+#[test]
+fn test_blocked_xread_only_gets_entries_newer_than_its_id() {
+    let mut store = Store::new();
+    let key = Bytes::from("stream");
+    let fields = [
+        RedisType::BulkString("field".into()),
+        RedisType::BulkString("value".into()),
+    ];
+    let (tx, mut rx) = oneshot::channel();
+    store.register_xread_waiting_client(
+        vec![key.clone()],
+        StreamReadPosition::After(vec![StreamId { ms: 5, seq: 0 }]),
+        None,
+        tx,
+    );
+
+    store.xadd(&key, Some(1), Some(3), &fields).unwrap();
+    store.serve_blocked_clients();
+    assert!(rx.try_recv().is_err());
+
+    store.xadd(&key, Some(1), Some(6), &fields).unwrap();
+    store.serve_blocked_clients();
+    let expected = xread_output_to_redis_type(
+        key.clone(),
+        store
+            .xread(&key, StreamId { ms: 5, seq: 0 }, false, None)
+            .unwrap(),
+    );
+    assert_eq!(
+        rx.try_recv().unwrap(),
+        RedisType::Array(Some(vec![expected]))
+    );
+}
+
+#[test]
+fn test_one_xadd_wakes_every_blocked_xread() {
+    let mut store = Store::new();
+    let key = Bytes::from("stream");
+    let fields = [
+        RedisType::BulkString("field".into()),
+        RedisType::BulkString("value".into()),
+    ];
+    store.xadd(&key, Some(1), Some(1), &fields).unwrap();
+    let mut receivers = Vec::new();
+    for last_id in [StreamId { ms: 0, seq: 0 }, StreamId { ms: 1, seq: 1 }] {
+        let (tx, rx) = oneshot::channel();
+        let from = StreamReadPosition::After(vec![last_id]);
+        store.register_xread_waiting_client(vec![key.clone()], from, None, tx);
+        receivers.push((last_id, rx));
+    }
+    // a client which went away is dropped instead of served
+    let (tx, rx) = oneshot::channel();
+    let from = StreamReadPosition::After(vec![StreamId::default()]);
+    store.register_xread_waiting_client(vec![key.clone()], from, None, tx);
+    drop(rx);
+
+    store.xadd(&key, Some(1), Some(2), &fields).unwrap();
+    store.serve_blocked_clients();
+    for (last_id, mut rx) in receivers {
+        let expected = xread_output_to_redis_type(
+            key.clone(),
+            store.xread(&key, last_id, false, None).unwrap(),
+        );
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            RedisType::Array(Some(vec![expected]))
+        );
+    }
+    assert!(store.xread_waiting_queue.is_empty());
+}
+
+#[test]
+fn test_blocked_xreadgroup_clients_share_the_entries() {
+    let mut store = Store::new();
+    let key = Bytes::from("stream");
+    let group = Bytes::from("group");
+    let fields = [
+        RedisType::BulkString("field".into()),
+        RedisType::BulkString("value".into()),
+    ];
+    store
+        .xgroup_create(&key, &group, StreamId::default(), None, true)
+        .unwrap();
+    let mut receivers = Vec::new();
+    for consumer in ["alice", "bob"] {
+        let (tx, rx) = oneshot::channel();
+        let from = StreamReadPosition::Group {
+            group: group.clone(),
+            consumer: consumer.into(),
+            no_ack: true,
+        };
+        store.register_xread_waiting_client(vec![key.clone()], from, None, tx);
+        receivers.push(rx);
+    }
+
+    store.xadd(&key, Some(1), Some(1), &fields).unwrap();
+    store.serve_blocked_clients();
+    assert!(matches!(
+        receivers[0].try_recv(),
+        Ok(RedisType::Array(Some(_)))
+    ));
+    assert!(receivers[1].try_recv().is_err());
+    // NOACK leaves nothing pending
+    assert_eq!(store.xpending_summary(&key, &group).unwrap().count, 0);
+
+    store.xadd(&key, Some(2), Some(1), &fields).unwrap();
+    store.serve_blocked_clients();
+    assert!(receivers[1].try_recv().is_ok());
+}
+
+#[test]
+fn test_every_kind_of_value_checks_the_type() {
+    let mut store = Store::new();
+    let string = Bytes::from("string");
+    let list = Bytes::from("list");
+    store
+        .set_with_expiry(string.clone(), "value".into(), None)
+        .unwrap();
+    store.rpush(list.clone(), vec!["a".into()]).unwrap();
+
+    assert!(matches!(
+        store.get(list.clone()),
+        Err(StoreError::WrongType)
+    ));
+    assert!(matches!(
+        store.lpush(string.clone(), vec!["a".into()]),
+        Err(StoreError::WrongType)
+    ));
+    assert!(matches!(
+        store.xread(&list, StreamId::default(), false, None),
+        Err(StoreError::WrongType)
+    ));
+    assert!(matches!(
+        store.last_stream_id(&string),
+        Err(StoreError::WrongType)
+    ));
+
+    // an expired string is gone, any kind of value may take its place
+    store
+        .set_with_expiry(string.clone(), "value".into(), Some(0))
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(2));
+    assert!(matches!(
+        store.get_type(&string),
+        Err(StoreError::KeyNotFound)
+    ));
+    assert_eq!(store.lpush(string.clone(), vec!["a".into()]).unwrap(), 1);
+    assert_eq!(store.get_type(&string).unwrap(), Bytes::from("list"));
+}
+
 impl Display for StoreError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            StoreError::KeyNotFound => write!(f, "Key not found"),
-            StoreError::KeyExpired => write!(f, "Key expired"),
-            StoreError::TimeError => write!(f, "Could not convert time or expiry"),
-            StoreError::StreamIdSmallerThanLast => {
-                write!(f, "Stream ID smaller than last added Id")
+            StoreError::KeyNotFound => write!(f, "no such key"),
+            StoreError::TimeError => write!(f, "invalid expire time"),
+            StoreError::StreamIdSmallerThanLast => write!(
+                f,
+                "The ID specified in XADD is equal or smaller than the target stream top item"
+            ),
+            StoreError::StreamIdNotGreaterThan0 => {
+                write!(f, "The ID specified in XADD must be greater than 0-0")
             }
-            StoreError::StreamIdNotGreaterThan0 => write!(f, "Stream ID must be greater than 0-0"),
-            StoreError::ValueError => write!(f, "Stored value is invalid"),
+            StoreError::ValueError => write!(f, "value is not an integer or out of range"),
+            StoreError::WrongType => {
+                write!(f, "Operation against a key holding the wrong kind of value")
+            }
+            StoreError::ScoreIsNaN => write!(f, "resulting score is not a number (NaN)"),
+            StoreError::IndexOutOfRange => write!(f, "index out of range"),
+            StoreError::EntriesAddedSmallerThanLength => write!(
+                f,
+                "The entries_added specified in XSETID is smaller than the target stream length"
+            ),
+            StoreError::BusyGroup => write!(f, "Consumer Group name already exists"),
+            StoreError::NoGroup => write!(f, "No such key or consumer group"),
         }
     }
 }
+
+#[test]
+fn test_no_touch_clients_leave_the_idle_time_alone() {
+    use crate::commands::{handle_command, utils::test_command};
+
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 1);
+    store
+        .set_with_expiry("key".into(), "value".into(), None)
+        .unwrap();
+    let idle = |store: &Store| store.object_idletime(&"key".into()).unwrap();
+    let backdate = |store: &mut Store| {
+        store
+            .engine
+            .record_access(&"key".into(), Instant::now() - Duration::from_secs(10));
+    };
+    backdate(&mut store);
+    assert_eq!(idle(&store), 10);
+
+    // neither a client with NO-TOUCH nor OBJECT itself count as access
+    for command in [
+        &["CLIENT", "NO-TOUCH", "ON"][..],
+        &["GET", "key"],
+        &["OBJECT", "IDLETIME", "key"],
+    ] {
+        handle_command(test_command(command), &mut store, 1).unwrap();
+    }
+    assert_eq!(idle(&store), 10);
+
+    handle_command(test_command(&["CLIENT", "NO-TOUCH", "OFF"]), &mut store, 1).unwrap();
+    handle_command(test_command(&["GET", "key"]), &mut store, 1).unwrap();
+    assert_eq!(idle(&store), 0);
+
+    assert!(matches!(
+        store.object_idletime(&"missing".into()),
+        Err(StoreError::KeyNotFound)
+    ));
+}
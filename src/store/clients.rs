@@ -0,0 +1,502 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicU8, Ordering},
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::{Store, SubscriptionKind, TrackingOptions};
+use crate::{commands::Subscriptions, parser::RedisType};
+
+/// RESP version of a connection, shared between the store and the writer task of the connection
+/// which encodes the frames accordingly
+#[derive(Clone, Debug)]
+pub struct ProtocolVersion(Arc<AtomicU8>);
+
+impl Default for ProtocolVersion {
+    fn default() -> Self {
+        ProtocolVersion::new(2)
+    }
+}
+
+impl ProtocolVersion {
+    pub fn new(version: u8) -> Self {
+        ProtocolVersion(Arc::new(AtomicU8::new(version)))
+    }
+
+    pub fn get(&self) -> u8 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, version: u8) {
+        self.0.store(version, Ordering::Relaxed);
+    }
+}
+
+/// Whether the client gets replies, set with CLIENT REPLY
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ReplyMode {
+    #[default]
+    On,
+    /// no replies at all, neither to commands nor published messages
+    Off,
+    /// no reply to the next command
+    Skip,
+}
+
+/// Everything the server knows about a connected client
+#[derive(Debug)]
+pub struct ClientState {
+    pub id: u64,
+    pub addr: SocketAddr,
+    /// the address of the server the client connected to
+    pub laddr: SocketAddr,
+    pub connected_at: Instant,
+    /// when the client sent its last command
+    pub last_interaction: Instant,
+    /// set with CLIENT SETNAME
+    pub name: Option<Bytes>,
+    /// the selected database, there is only database 0 for now
+    pub db: usize,
+    /// RESP version the client speaks, 2 unless it negotiated another one with HELLO
+    pub protocol: ProtocolVersion,
+    #[allow(dead_code)] // no AUTH yet
+    pub authenticated: bool,
+    pub subscriptions: Subscriptions,
+    /// commands queued since MULTI, `None` outside of a transaction
+    pub transaction: Option<VecDeque<RedisType>>,
+    /// a command was refused while queuing, EXEC discards the transaction then
+    pub transaction_failed: bool,
+    /// keys watched for the next EXEC, along with their versions when they were watched
+    pub watched: Vec<(Bytes, u64)>,
+    /// CLIENT NO-EVICT, exempts the client from client eviction, of which there is none yet
+    pub no_evict: bool,
+    /// CLIENT NO-TOUCH, the commands of the client don't count as access to the keys, OBJECT
+    /// IDLETIME keeps counting
+    pub no_touch: bool,
+    pub reply_mode: ReplyMode,
+    /// set with CLIENT TRACKING ON, `None` while the client doesn't track keys
+    pub tracking: Option<TrackingOptions>,
+    /// CLIENT CACHING YES or NO, applies to the next command only
+    pub caching: Option<bool>,
+    /// the client issued MONITOR and receives every command the server runs
+    pub monitor: bool,
+    /// lower case name of the last command the client sent, `container|subcommand` for
+    /// commands with subcommands
+    pub last_command: String,
+    /// attributes sent along with the reply to the command being run, RESP3 clients only
+    pub reply_attributes: Vec<(RedisType, RedisType)>,
+    /// replies and pushes for the client, its writer task sends them over the socket
+    pub outbox: UnboundedSender<RedisType>,
+}
+
+/// The connected clients by id
+pub type Clients = HashMap<u64, ClientState>;
+
+/// The argument in double quotes with quotes, backslashes and non printable bytes escaped, the
+/// way MONITOR shows binary arguments
+fn quoted(argument: &[u8]) -> String {
+    let mut quoted = String::from("\"");
+    for &byte in argument {
+        match byte {
+            b'\\' => quoted.push_str("\\\\"),
+            b'"' => quoted.push_str("\\\""),
+            b'\n' => quoted.push_str("\\n"),
+            b'\r' => quoted.push_str("\\r"),
+            b'\t' => quoted.push_str("\\t"),
+            0x07 => quoted.push_str("\\a"),
+            0x08 => quoted.push_str("\\b"),
+            byte if byte.is_ascii_graphic() || byte == b' ' => quoted.push(byte as char),
+            byte => quoted.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Commands of all clients (or only their writes) are held until the pause ends, set with
+/// CLIENT PAUSE
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClientPause {
+    pub until: Instant,
+    pub writes_only: bool,
+}
+
+impl ClientState {
+    pub fn new(
+        id: u64,
+        addr: SocketAddr,
+        laddr: SocketAddr,
+        outbox: UnboundedSender<RedisType>,
+    ) -> Self {
+        let now = Instant::now();
+        ClientState {
+            id,
+            addr,
+            laddr,
+            connected_at: now,
+            last_interaction: now,
+            name: None,
+            db: 0,
+            protocol: ProtocolVersion::default(),
+            authenticated: true,
+            subscriptions: Subscriptions::default(),
+            transaction: None,
+            transaction_failed: false,
+            watched: Vec::new(),
+            no_evict: false,
+            no_touch: false,
+            reply_mode: ReplyMode::On,
+            tracking: None,
+            caching: None,
+            monitor: false,
+            last_command: String::new(),
+            reply_attributes: Vec::new(),
+            outbox,
+        }
+    }
+
+    /// Puts a reply to the command into the outbox ahead of its response, e.g. subscription
+    /// confirmations. Dropped while the client turned replies off or skips this one.
+    pub fn push_reply(&self, frame: RedisType) {
+        if self.reply_mode == ReplyMode::On {
+            self.push(frame);
+        }
+    }
+
+    /// Puts an out-of-band frame into the outbox, e.g. an invalidation. These reach the client
+    /// whatever its reply mode.
+    pub fn push(&self, frame: RedisType) {
+        // the writer task only stops once the client is gone
+        let _ = self.outbox.send(frame);
+    }
+
+    /// Flags as CLIENT LIST shows them: `x` in a transaction, `P` in subscriber mode, `e` for
+    /// NO-EVICT, `T` for NO-TOUCH, `t` while tracking keys, `O` for MONITOR and `N` without any
+    /// flag
+    pub fn flags(&self) -> String {
+        let mut flags = String::new();
+        if self.transaction.is_some() {
+            flags.push('x');
+        }
+        if self.subscriptions.is_subscribed() {
+            flags.push('P');
+        }
+        if self.no_evict {
+            flags.push('e');
+        }
+        if self.no_touch {
+            flags.push('T');
+        }
+        if self.tracking.is_some() {
+            flags.push('t');
+        }
+        if self.monitor {
+            flags.push('O');
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+        flags
+    }
+
+    /// The single line CLIENT INFO and CLIENT LIST describe the client with
+    pub fn info_line(&self, now: Instant) -> String {
+        format!(
+            "id={} addr={} laddr={} name={} age={} idle={} flags={} db={} sub={} psub={} ssub={} multi={} cmd={}",
+            self.id,
+            self.addr,
+            self.laddr,
+            self.name
+                .as_ref()
+                .map(|name| String::from_utf8_lossy(name))
+                .unwrap_or_default(),
+            now.duration_since(self.connected_at).as_secs(),
+            now.duration_since(self.last_interaction).as_secs(),
+            self.flags(),
+            self.db,
+            self.subscriptions.len_of(SubscriptionKind::Channel),
+            self.subscriptions.len_of(SubscriptionKind::Pattern),
+            self.subscriptions.len_of(SubscriptionKind::ShardChannel),
+            self.transaction
+                .as_ref()
+                .map_or(-1, |queue| queue.len() as i64),
+            self.last_command,
+        )
+    }
+}
+
+impl Store {
+    pub fn register_client(&mut self, client: ClientState) {
+        self.clients.insert(client.id, client);
+    }
+
+    /// Forgets the client along with its subscriptions and watched keys
+    pub fn unregister_client(&mut self, id: u64) {
+        if let Some(client) = self.clients.remove(&id) {
+            self.release_client(&client);
+        }
+    }
+
+    /// Returns the client to the state of a fresh connection as RESET does: no transaction,
+    /// watched keys, subscriptions, tracking or MONITOR, database 0, RESP2 and replies on. The
+    /// name is kept.
+    pub fn reset_client(&mut self, id: u64) {
+        let client = self.client_mut(id);
+        let mut fresh =
+            ClientState::new(client.id, client.addr, client.laddr, client.outbox.clone());
+        fresh.connected_at = client.connected_at;
+        fresh.last_interaction = client.last_interaction;
+        fresh.name = client.name.take();
+        fresh.last_command = std::mem::take(&mut client.last_command);
+        // the writer task of the connection holds on to the protocol, it is switched back in place
+        fresh.protocol = client.protocol.clone();
+        fresh.protocol.set(2);
+        let previous = std::mem::replace(client, fresh);
+        self.release_client(&previous);
+    }
+
+    /// Drops what the store keeps for the client besides its state
+    fn release_client(&mut self, client: &ClientState) {
+        for kind in SubscriptionKind::ALL {
+            for name in client.subscriptions.names(kind) {
+                self.unsubscribe(kind, &name, client.id);
+            }
+        }
+        self.unwatch(&client.watched);
+        self.disable_tracking(client.id);
+        self.monitors.remove(&client.id);
+    }
+
+    /// The client receives every command the server runs from now on
+    pub fn start_monitor(&mut self, id: u64) {
+        self.client_mut(id).monitor = true;
+        self.monitors.insert(id);
+    }
+
+    /// Sends the command the client is about to run to the clients which issued MONITOR, e.g.
+    /// `+1339518083.107412 [0 127.0.0.1:60866] "set" "k" "v"`
+    pub fn feed_monitors(&self, client_id: u64, elements: &[RedisType]) {
+        if self.monitors.is_empty() {
+            return;
+        }
+        let Some(client) = self.clients.get(&client_id) else {
+            return;
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut line = format!(
+            "{}.{:06} [{} {}]",
+            now.as_secs(),
+            now.subsec_micros(),
+            client.db,
+            client.addr
+        );
+        for element in elements {
+            if let RedisType::BulkString(argument) | RedisType::SimpleString(argument) = element {
+                line.push(' ');
+                line.push_str(&quoted(argument));
+            }
+        }
+        let frame = RedisType::SimpleString(Bytes::from(line));
+        for id in &self.monitors {
+            if let Some(monitor) = self.clients.get(id) {
+                monitor.push(frame.clone());
+            }
+        }
+    }
+
+    /// Marks the client whose command runs now, modifications are attributed to it
+    pub fn set_current_client(&mut self, id: Option<u64>) {
+        self.current_client = id;
+    }
+
+    /// RESP version of the client whose command runs, for replies which are shaped differently
+    /// for RESP3 clients than just with other types
+    pub fn current_protocol(&self) -> u8 {
+        self.current_client
+            .and_then(|id| self.clients.get(&id))
+            .map_or(2, |client| client.protocol.get())
+    }
+
+    /// Attaches the attribute to the reply to the command being run, for handlers which have
+    /// metadata about their reply
+    pub fn add_reply_attribute(&mut self, name: &'static str, value: RedisType) {
+        if let Some(client) = self.current_client.and_then(|id| self.clients.get_mut(&id)) {
+            client.reply_attributes.push((
+                RedisType::BulkString(Bytes::from_static(name.as_bytes())),
+                value,
+            ));
+        }
+    }
+
+    /// The client which sent the command being run. Connections register themselves before
+    /// sending commands, so the client is known.
+    pub fn client_mut(&mut self, id: u64) -> &mut ClientState {
+        self.clients
+            .get_mut(&id)
+            .expect("commands are only sent by registered clients")
+    }
+
+    pub fn client(&self, id: u64) -> &ClientState {
+        self.clients
+            .get(&id)
+            .expect("commands are only sent by registered clients")
+    }
+
+    /// Pauses the clients until `until`. A pause which is already active is only extended, and
+    /// pausing everything wins over pausing writes.
+    pub fn pause_clients(&mut self, until: Instant, writes_only: bool) {
+        self.pause = Some(match self.pause {
+            Some(pause) => ClientPause {
+                until: pause.until.max(until),
+                writes_only: pause.writes_only && writes_only,
+            },
+            None => ClientPause { until, writes_only },
+        });
+    }
+
+    pub fn unpause_clients(&mut self) {
+        self.pause = None;
+    }
+
+    pub fn pause(&self) -> Option<ClientPause> {
+        self.pause
+    }
+
+    /// Ends the pause once its time is up
+    pub fn lift_expired_pause(&mut self, now: Instant) {
+        if self.pause.is_some_and(|pause| pause.until <= now) {
+            self.pause = None;
+        }
+    }
+
+    /// All connected clients, oldest first
+    pub fn clients(&self) -> Vec<&ClientState> {
+        let mut clients: Vec<&ClientState> = self.clients.values().collect();
+        clients.sort_by_key(|client| client.id);
+        clients
+    }
+}
+
+/// Registers a client connected from 127.0.0.1:4000, returns the receiving end of its outbox
+#[cfg(test)]
+pub fn test_client(store: &mut Store, id: u64) -> tokio::sync::mpsc::UnboundedReceiver<RedisType> {
+    let (outbox, inbox) = tokio::sync::mpsc::unbounded_channel();
+    store.register_client(ClientState::new(
+        id,
+        "127.0.0.1:4000".parse().unwrap(),
+        "127.0.0.1:6379".parse().unwrap(),
+        outbox,
+    ));
+    inbox
+}
+
+#[test]
+fn test_unregistering_a_client_drops_its_subscriptions_and_watched_keys() {
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 7);
+    let news = Bytes::from("news");
+    let outbox = store.client(7).outbox.clone();
+    store
+        .client_mut(7)
+        .subscriptions
+        .subscribe(SubscriptionKind::Channel, std::slice::from_ref(&news));
+    store.subscribe(SubscriptionKind::Channel, news.clone(), 7, outbox);
+    store.client_mut(7).watched = store.watch(std::slice::from_ref(&news));
+
+    store.unregister_client(7);
+    assert!(store.clients.is_empty());
+    assert_eq!(store.publish(&news, &Bytes::from("hello")), 0);
+    assert!(store.watched_keys.is_empty());
+}
+
+#[test]
+fn test_pausing_again_extends_the_pause() {
+    use std::time::Duration;
+
+    let mut store = Store::new();
+    let now = Instant::now();
+    store.pause_clients(now + Duration::from_secs(10), true);
+    store.pause_clients(now + Duration::from_secs(5), false);
+    assert_eq!(
+        store.pause(),
+        Some(ClientPause {
+            until: now + Duration::from_secs(10),
+            writes_only: false,
+        })
+    );
+    store.lift_expired_pause(now + Duration::from_secs(9));
+    assert!(store.pause().is_some());
+    store.lift_expired_pause(now + Duration::from_secs(10));
+    assert!(store.pause().is_none());
+}
+
+#[test]
+fn test_quoted_escapes_binary_arguments() {
+    assert_eq!(quoted(b"plain"), "\"plain\"");
+    assert_eq!(quoted(b"a \"b\"\n"), "\"a \\\"b\\\"\\n\"");
+    assert_eq!(quoted(&[0x00, b'\\', 0xff]), "\"\\x00\\\\\\xff\"");
+}
+
+#[test]
+fn test_reset_returns_the_client_to_a_fresh_state() {
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 7);
+    let news = Bytes::from("news");
+    let outbox = store.client(7).outbox.clone();
+    let client = store.client_mut(7);
+    client
+        .subscriptions
+        .subscribe(SubscriptionKind::Channel, std::slice::from_ref(&news));
+    client.name = Some(Bytes::from("worker"));
+    client.transaction = Some(VecDeque::new());
+    client.reply_mode = ReplyMode::Skip;
+    store.subscribe(SubscriptionKind::Channel, news.clone(), 7, outbox);
+    store.start_monitor(7);
+
+    store.reset_client(7);
+    let client = store.client(7);
+    assert!(!client.subscriptions.is_subscribed());
+    assert!(client.transaction.is_none());
+    assert!(!client.monitor);
+    assert_eq!(client.reply_mode, ReplyMode::On);
+    assert_eq!(client.name, Some(Bytes::from("worker")));
+    assert_eq!(store.publish(&news, &Bytes::from("hello")), 0);
+    assert!(store.monitors.is_empty());
+}
+
+#[test]
+fn test_reset_switches_the_codec_back_to_resp2() {
+    use bytes::BytesMut;
+    use tokio_util::codec::Encoder;
+
+    use crate::{
+        codec::RespCodec,
+        commands::{handle_command, utils::test_command},
+        parser::ProtocolLimits,
+    };
+
+    let mut store = Store::new();
+    let _inbox = test_client(&mut store, 1);
+    let mut codec = RespCodec::new(ProtocolLimits::default(), store.client(1).protocol.clone());
+    let encoded = |codec: &mut RespCodec| {
+        let mut out = BytesMut::new();
+        codec.encode(RedisType::Double(1.0), &mut out).unwrap();
+        out
+    };
+
+    handle_command(test_command(&["HELLO", "3"]), &mut store, 1).unwrap();
+    assert_eq!(encoded(&mut codec), BytesMut::from(",1\r\n"));
+    handle_command(test_command(&["RESET"]), &mut store, 1).unwrap();
+    assert_eq!(encoded(&mut codec), BytesMut::from("$1\r\n1\r\n"));
+    handle_command(test_command(&["HELLO", "3"]), &mut store, 1).unwrap();
+    assert_eq!(encoded(&mut codec), BytesMut::from(",1\r\n"));
+}
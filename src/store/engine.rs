@@ -0,0 +1,213 @@
+use std::{collections::HashMap, time::Instant};
+
+use bytes::Bytes;
+
+use super::{
+    HashValue, ListValue, SetValue, StreamValue, WithExpiry, ZSetValue, scan::ScanIndex,
+    slots::SlotIndex,
+};
+
+/// A value stored at a key
+pub enum Value {
+    String(WithExpiry),
+    List(ListValue),
+    Hash(HashValue),
+    Set(SetValue),
+    SortedSet(ZSetValue),
+    Stream(StreamValue),
+}
+
+impl Value {
+    /// The type name as reported by TYPE
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::SortedSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// The encoding name as reported by OBJECT ENCODING
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Value::String(string) if is_integer(&string.value) => "int",
+            // redis embeds short strings into the object header
+            Value::String(string) if string.value.len() <= 44 => "embstr",
+            Value::String(_) => "raw",
+            Value::List(list) => list.encoding(),
+            Value::Hash(_) => "hashtable",
+            Value::Set(set) => set.encoding(),
+            Value::SortedSet(_) => "skiplist",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// Whether the value is a collection without elements. Such keys are removed,
+    /// like in redis, except for streams which may exist without entries.
+    pub fn is_empty_collection(&self) -> bool {
+        match self {
+            Value::List(list) => list.is_empty(),
+            Value::Hash(hash) => hash.is_empty(),
+            Value::Set(set) => set.is_empty(),
+            Value::SortedSet(zset) => zset.is_empty(),
+            Value::String(_) | Value::Stream(_) => false,
+        }
+    }
+}
+
+fn is_integer(value: &Bytes) -> bool {
+    std::str::from_utf8(value).is_ok_and(|value| value.parse::<i64>().is_ok() && value.len() <= 20)
+}
+
+/// Where the keyspace lives. The `Store` implements all command semantics on top of these
+/// primitives, so a backend only has to provide key/value access along with the metadata it
+/// keeps per key: the cursor order for SCAN, the keys per hash slot and the access times.
+pub trait StorageEngine: Send {
+    fn get(&self, key: &Bytes) -> Option<&Value>;
+    fn get_mut(&mut self, key: &Bytes) -> Option<&mut Value>;
+    /// Stores the value, returns the value previously stored at the key. Counts as access to
+    /// the key.
+    fn insert(&mut self, key: Bytes, value: Value) -> Option<Value>;
+    fn remove(&mut self, key: &Bytes) -> Option<Value>;
+    fn keys(&self) -> Box<dyn Iterator<Item = &Bytes> + '_>;
+    /// The next `count` keys from the cursor on, along with the cursor of the page after
+    fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<&Bytes>);
+    /// Starts keeping the keys per hash slot, as needed in cluster mode
+    fn enable_slot_index(&mut self);
+    fn has_slot_index(&self) -> bool;
+    fn count_keys_in_slot(&self, slot: u16) -> usize;
+    fn keys_in_slot(&self, slot: u16, count: usize) -> Vec<Bytes>;
+    /// When the key was last accessed, `None` if it does not exist
+    fn last_access(&self, key: &Bytes) -> Option<Instant>;
+    /// Records an access to the key, if it exists
+    fn record_access(&mut self, key: &Bytes, at: Instant);
+}
+
+/// Keeps the whole keyspace in a hash map, the default engine
+#[derive(Default)]
+pub struct MemoryEngine {
+    entries: HashMap<Bytes, Value>,
+    scan_index: ScanIndex,
+    slot_index: Option<SlotIndex>,
+    last_access: HashMap<Bytes, Instant>,
+}
+
+impl StorageEngine for MemoryEngine {
+    fn get(&self, key: &Bytes) -> Option<&Value> {
+        self.entries.get(key)
+    }
+
+    fn get_mut(&mut self, key: &Bytes) -> Option<&mut Value> {
+        self.entries.get_mut(key)
+    }
+
+    fn insert(&mut self, key: Bytes, value: Value) -> Option<Value> {
+        self.last_access.insert(key.clone(), Instant::now());
+        let replaced = self.entries.insert(key.clone(), value);
+        if replaced.is_none() {
+            self.scan_index.insert(&key);
+            if let Some(index) = self.slot_index.as_mut() {
+                index.insert(&key);
+            }
+        }
+        replaced
+    }
+
+    fn remove(&mut self, key: &Bytes) -> Option<Value> {
+        let removed = self.entries.remove(key)?;
+        self.last_access.remove(key);
+        self.scan_index.remove(key);
+        if let Some(index) = self.slot_index.as_mut() {
+            index.remove(key);
+        }
+        Some(removed)
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &Bytes> + '_> {
+        Box::new(self.entries.keys())
+    }
+
+    fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<&Bytes>) {
+        self.scan_index.page(cursor, count)
+    }
+
+    fn enable_slot_index(&mut self) {
+        let mut index = SlotIndex::default();
+        for key in self.entries.keys() {
+            index.insert(key);
+        }
+        self.slot_index = Some(index);
+    }
+
+    fn has_slot_index(&self) -> bool {
+        self.slot_index.is_some()
+    }
+
+    fn count_keys_in_slot(&self, slot: u16) -> usize {
+        self.slot_index
+            .as_ref()
+            .map_or(0, |index| index.count_keys(slot))
+    }
+
+    fn keys_in_slot(&self, slot: u16, count: usize) -> Vec<Bytes> {
+        self.slot_index
+            .as_ref()
+            .map(|index| index.keys(slot, count))
+            .unwrap_or_default()
+    }
+
+    fn last_access(&self, key: &Bytes) -> Option<Instant> {
+        self.last_access.get(key).copied()
+    }
+
+    fn record_access(&mut self, key: &Bytes, at: Instant) {
+        if let Some(accessed) = self.last_access.get_mut(key) {
+            *accessed = at;
+        }
+    }
+}
+
+/// Creates the engine configured by name, `None` if there is no engine with that name
+pub fn engine_from_name(name: &str) -> Option<Box<dyn StorageEngine>> {
+    match name {
+        "memory" => Some(Box::new(MemoryEngine::default())),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_engine_from_name() {
+    assert!(engine_from_name("memory").is_some());
+    assert!(engine_from_name("rocksdb").is_none());
+}
+
+#[test]
+fn test_memory_engine_keeps_the_key_metadata_with_the_keys() {
+    use super::slots::key_hash_slot;
+
+    let mut engine = MemoryEngine::default();
+    let string = |value: &str| {
+        Value::String(WithExpiry {
+            value: Bytes::from(value.to_owned()),
+            expires: None,
+        })
+    };
+    let key = Bytes::from("{tag}a");
+    engine.insert(key.clone(), string("1"));
+    engine.enable_slot_index();
+    engine.insert(Bytes::from("{tag}b"), string("2"));
+
+    assert_eq!(engine.scan(0, 10).1.len(), 2);
+    assert_eq!(engine.count_keys_in_slot(key_hash_slot(&key)), 2);
+    assert!(engine.last_access(&key).is_some());
+
+    engine.remove(&key);
+    assert_eq!(engine.scan(0, 10).1, vec![&Bytes::from("{tag}b")]);
+    assert_eq!(engine.count_keys_in_slot(key_hash_slot(&key)), 1);
+    assert!(engine.last_access(&key).is_none());
+    engine.record_access(&key, Instant::now());
+    assert!(engine.last_access(&key).is_none());
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+use super::{WithExpiry, scan::ScanIndex};
+
+/// Fields of a hash, each with its value and optional deadline. The scan index keeps the
+/// fields in cursor order for HSCAN, every change of the fields goes through here to keep it
+/// up to date.
+#[derive(Default)]
+pub struct HashValue {
+    fields: HashMap<Bytes, WithExpiry>,
+    scan_index: ScanIndex,
+}
+
+impl HashValue {
+    /// Sets the field, returns the entry it replaced
+    pub fn insert(&mut self, field: Bytes, entry: WithExpiry) -> Option<WithExpiry> {
+        let replaced = self.fields.insert(field.clone(), entry);
+        if replaced.is_none() {
+            self.scan_index.insert(&field);
+        }
+        replaced
+    }
+
+    pub fn remove(&mut self, field: &Bytes) -> Option<WithExpiry> {
+        let removed = self.fields.remove(field)?;
+        self.scan_index.remove(field);
+        Some(removed)
+    }
+
+    /// Keeps only the fields the predicate holds for
+    pub fn retain(&mut self, mut keep: impl FnMut(&Bytes, &WithExpiry) -> bool) {
+        let scan_index = &mut self.scan_index;
+        self.fields.retain(|field, entry| {
+            let kept = keep(field, entry);
+            if !kept {
+                scan_index.remove(field);
+            }
+            kept
+        });
+    }
+
+    pub fn get(&self, field: &Bytes) -> Option<&WithExpiry> {
+        self.fields.get(field)
+    }
+
+    /// The entry of the field, to change its value or deadline
+    pub fn get_mut(&mut self, field: &Bytes) -> Option<&mut WithExpiry> {
+        self.fields.get_mut(field)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, &WithExpiry)> {
+        self.fields.iter()
+    }
+
+    /// One page of HSCAN, along with the cursor of the next page
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(&Bytes, &WithExpiry)>) {
+        let (next_cursor, page) = self.scan_index.page(cursor, count);
+        let fields = page
+            .into_iter()
+            .map(|field| (field, &self.fields[field]))
+            .collect();
+        (next_cursor, fields)
+    }
+}
+
+impl FromIterator<(Bytes, WithExpiry)> for HashValue {
+    fn from_iter<T: IntoIterator<Item = (Bytes, WithExpiry)>>(iter: T) -> Self {
+        let mut hash = HashValue::default();
+        for (field, entry) in iter {
+            hash.insert(field, entry);
+        }
+        hash
+    }
+}
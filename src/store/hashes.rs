@@ -0,0 +1,312 @@
+use bytes::Bytes;
+
+use super::{Store, StoreError, WithExpiry, now_millis};
+
+/// Condition under which HEXPIRE/HPEXPIRE update the deadline of a field
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExpiryCondition {
+    Always,
+    /// Only if the field has no deadline yet
+    Nx,
+    /// Only if the field already has a deadline
+    Xx,
+    /// Only if the new deadline is later than the current one
+    Gt,
+    /// Only if the new deadline is earlier than the current one
+    Lt,
+}
+
+// Per-field replies of the field expiration commands, as documented by redis
+const FIELD_MISSING: i128 = -2;
+const FIELD_WITHOUT_DEADLINE: i128 = -1;
+const DEADLINE_NOT_SET: i128 = 0;
+const DEADLINE_SET: i128 = 1;
+const FIELD_DELETED: i128 = 2;
+
+impl Store {
+    /// Sets the given fields, returns the number of fields which were newly added.
+    /// Overwriting a field clears its deadline.
+    pub fn hset(&mut self, key: &Bytes, pairs: Vec<(Bytes, Bytes)>) -> Result<usize, StoreError> {
+        self.reap_expired_fields(key)?;
+        let hash = self.hash_entry(key)?;
+        let added = pairs
+            .into_iter()
+            .filter(|(field, value)| {
+                let value = WithExpiry {
+                    value: value.clone(),
+                    expires: None,
+                };
+                hash.insert(field.clone(), value).is_none()
+            })
+            .count();
+        self.touch_key(key);
+        Ok(added)
+    }
+
+    pub fn hget(&mut self, key: &Bytes, field: &Bytes) -> Result<Option<Bytes>, StoreError> {
+        self.reap_expired_fields(key)?;
+        Ok(self
+            .hash(key)?
+            .and_then(|hash| hash.get(field))
+            .map(|field| field.value.clone()))
+    }
+
+    pub fn hscan(
+        &mut self,
+        key: &Bytes,
+        cursor: u64,
+        count: usize,
+    ) -> Result<(u64, Vec<(Bytes, Bytes)>), StoreError> {
+        self.reap_expired_fields(key)?;
+        let Some(hash) = self.hash(key)? else {
+            return Ok((0, vec![]));
+        };
+
+        let (next_cursor, page) = hash.scan(cursor, count);
+        let fields = page
+            .into_iter()
+            .map(|(field, value)| (field.clone(), value.value.clone()))
+            .collect();
+        Ok((next_cursor, fields))
+    }
+
+    /// Sets the absolute deadline (unix millis) of the given fields, returns one reply code per field.
+    /// A deadline in the past deletes the field right away.
+    pub fn hexpire(
+        &mut self,
+        key: &Bytes,
+        deadline: u128,
+        condition: ExpiryCondition,
+        fields: &[Bytes],
+    ) -> Result<Vec<i128>, StoreError> {
+        self.reap_expired_fields(key)?;
+        let now = now_millis()?;
+        let Some(hash) = self.hash_mut(key)? else {
+            return Ok(vec![FIELD_MISSING; fields.len()]);
+        };
+
+        let replies = fields
+            .iter()
+            .map(|field| {
+                let Some(entry) = hash.get_mut(field) else {
+                    return FIELD_MISSING;
+                };
+                let allowed = match (condition, entry.expires) {
+                    (ExpiryCondition::Always, _) => true,
+                    (ExpiryCondition::Nx, current) => current.is_none(),
+                    (ExpiryCondition::Xx, current) => current.is_some(),
+                    // a field without deadline lives forever, nothing is greater than that
+                    (ExpiryCondition::Gt, current) => current.is_some_and(|c| deadline > c),
+                    (ExpiryCondition::Lt, current) => current.is_none_or(|c| deadline < c),
+                };
+                if !allowed {
+                    DEADLINE_NOT_SET
+                } else if deadline <= now {
+                    hash.remove(field);
+                    FIELD_DELETED
+                } else {
+                    entry.expires = Some(deadline);
+                    DEADLINE_SET
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if replies
+            .iter()
+            .any(|reply| matches!(*reply, DEADLINE_SET | FIELD_DELETED))
+        {
+            self.touch_key(key);
+        }
+        self.remove_if_empty(key);
+        Ok(replies)
+    }
+
+    /// Returns the remaining time to live of the given fields in milliseconds,
+    /// or -1 for fields without deadline and -2 for missing fields
+    pub fn httl(&mut self, key: &Bytes, fields: &[Bytes]) -> Result<Vec<i128>, StoreError> {
+        self.reap_expired_fields(key)?;
+        let now = now_millis()?;
+        let hash = self.hash(key)?;
+
+        Ok(fields
+            .iter()
+            .map(
+                |field| match hash.and_then(|hash| hash.get(field)).map(|e| e.expires) {
+                    None => FIELD_MISSING,
+                    Some(None) => FIELD_WITHOUT_DEADLINE,
+                    Some(Some(deadline)) => (deadline - now) as i128,
+                },
+            )
+            .collect())
+    }
+
+    /// Removes the deadline of the given fields, returns one reply code per field
+    pub fn hpersist(&mut self, key: &Bytes, fields: &[Bytes]) -> Result<Vec<i128>, StoreError> {
+        self.reap_expired_fields(key)?;
+        let Some(hash) = self.hash_mut(key)? else {
+            return Ok(vec![FIELD_MISSING; fields.len()]);
+        };
+
+        let replies: Vec<i128> = fields
+            .iter()
+            .map(|field| match hash.get_mut(field) {
+                None => FIELD_MISSING,
+                Some(entry) if entry.expires.is_none() => FIELD_WITHOUT_DEADLINE,
+                Some(entry) => {
+                    entry.expires = None;
+                    DEADLINE_SET
+                }
+            })
+            .collect();
+        if replies.contains(&DEADLINE_SET) {
+            self.touch_key(key);
+        }
+        Ok(replies)
+    }
+
+    /// Lazily drops the fields of a hash whose deadline has passed. Reads go through here as
+    /// well, the key only counts as changed if a field was actually dropped.
+    fn reap_expired_fields(&mut self, key: &Bytes) -> Result<(), StoreError> {
+        let now = now_millis()?;
+        let is_live = |field: &WithExpiry| field.expires.is_none_or(|deadline| deadline > now);
+        let has_expired = self
+            .hash(key)?
+            .is_some_and(|hash| !hash.iter().all(|(_, field)| is_live(field)));
+        if !has_expired {
+            return Ok(());
+        }
+        if let Some(hash) = self.hash_mut(key)? {
+            hash.retain(|_, field| is_live(field));
+        }
+        self.touch_key(key);
+        self.remove_if_empty(key);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_hset_counts_new_fields_only() {
+    let mut store = Store::new();
+    let key = Bytes::from("hash");
+    let added = store
+        .hset(
+            &key,
+            vec![("a".into(), "1".into()), ("b".into(), "2".into())],
+        )
+        .unwrap();
+    assert_eq!(added, 2);
+
+    let added = store
+        .hset(
+            &key,
+            vec![("a".into(), "3".into()), ("c".into(), "4".into())],
+        )
+        .unwrap();
+    assert_eq!(added, 1);
+    assert_eq!(
+        store.hget(&key, &"a".into()).unwrap(),
+        Some(Bytes::from("3"))
+    );
+}
+
+#[test]
+fn test_hset_on_wrong_type() {
+    let mut store = Store::new();
+    let key = Bytes::from("string");
+    store
+        .set_with_expiry(key.clone(), "value".into(), None)
+        .unwrap();
+    assert!(matches!(
+        store.hset(&key, vec![("a".into(), "1".into())]),
+        Err(StoreError::WrongType)
+    ));
+}
+
+#[test]
+fn test_hexpire_conditions() {
+    let mut store = Store::new();
+    let key = Bytes::from("hash");
+    let fields = vec![Bytes::from("a"), Bytes::from("missing")];
+    store.hset(&key, vec![("a".into(), "1".into())]).unwrap();
+    let deadline = now_millis().unwrap() + 10_000;
+
+    assert_eq!(
+        store
+            .hexpire(&key, deadline, ExpiryCondition::Xx, &fields)
+            .unwrap(),
+        vec![DEADLINE_NOT_SET, FIELD_MISSING]
+    );
+    assert_eq!(
+        store
+            .hexpire(&key, deadline, ExpiryCondition::Nx, &fields)
+            .unwrap(),
+        vec![DEADLINE_SET, FIELD_MISSING]
+    );
+    assert_eq!(
+        store
+            .hexpire(&key, deadline - 1, ExpiryCondition::Gt, &fields[..1])
+            .unwrap(),
+        vec![DEADLINE_NOT_SET]
+    );
+    assert!(store.httl(&key, &fields[..1]).unwrap()[0] > 9_000);
+
+    assert_eq!(
+        store.hpersist(&key, &fields[..1]).unwrap(),
+        vec![DEADLINE_SET]
+    );
+    assert_eq!(
+        store.httl(&key, &fields[..1]).unwrap(),
+        vec![FIELD_WITHOUT_DEADLINE]
+    );
+}
+
+#[test]
+fn test_hexpire_in_the_past_deletes_field_and_key() {
+    let mut store = Store::new();
+    let key = Bytes::from("hash");
+    store.hset(&key, vec![("a".into(), "1".into())]).unwrap();
+
+    assert_eq!(
+        store
+            .hexpire(&key, 0, ExpiryCondition::Always, &["a".into()])
+            .unwrap(),
+        vec![FIELD_DELETED]
+    );
+    assert!(store.get_type(&key).is_err());
+}
+
+#[test]
+fn test_reads_only_change_the_hash_once_a_field_expired() {
+    let mut store = Store::new();
+    let key = Bytes::from("hash");
+    let (a, b) = (Bytes::from("a"), Bytes::from("b"));
+    store
+        .hset(&key, vec![(a.clone(), "1".into()), (b.clone(), "2".into())])
+        .unwrap();
+    let deadline = now_millis().unwrap() + 10_000;
+    store
+        .hexpire(
+            &key,
+            deadline,
+            ExpiryCondition::Always,
+            std::slice::from_ref(&b),
+        )
+        .unwrap();
+
+    let watched = store.watch(std::slice::from_ref(&key));
+    assert_eq!(store.hget(&key, &a).unwrap(), Some(Bytes::from("1")));
+    store.hscan(&key, 0, 10).unwrap();
+    store.httl(&key, std::slice::from_ref(&b)).unwrap();
+    assert!(store.watched_keys_unchanged(&watched));
+
+    // the deadline of the field passed
+    store
+        .hash_mut(&key)
+        .unwrap()
+        .unwrap()
+        .get_mut(&b)
+        .unwrap()
+        .expires = Some(1);
+    assert_eq!(store.hget(&key, &b).unwrap(), None);
+    assert!(!store.watched_keys_unchanged(&watched));
+}
@@ -0,0 +1,138 @@
+use bytes::Bytes;
+
+use super::{Store, StoreError, Value, now_millis};
+
+/// Size statistics over the elements of a collection key
+#[derive(Debug, Default, PartialEq)]
+pub struct ElementHistogram {
+    pub encoding: &'static str,
+    pub elements: usize,
+    pub min_size: usize,
+    pub max_size: usize,
+    pub total_size: usize,
+}
+
+impl ElementHistogram {
+    fn new(encoding: &'static str) -> Self {
+        ElementHistogram {
+            encoding,
+            ..Default::default()
+        }
+    }
+
+    /// Accounts one more element, the statistics are updated in place so the
+    /// elements never have to be collected
+    fn record(&mut self, size: usize) {
+        if self.elements == 0 {
+            self.min_size = size;
+            self.max_size = size;
+        } else {
+            self.min_size = self.min_size.min(size);
+            self.max_size = self.max_size.max(size);
+        }
+        self.elements += 1;
+        self.total_size += size;
+    }
+
+    pub fn avg_size(&self) -> f64 {
+        if self.elements == 0 {
+            return 0.0;
+        }
+        self.total_size as f64 / self.elements as f64
+    }
+}
+
+impl Store {
+    /// Walks the elements of a list, hash, set, sorted set or stream key and accumulates their sizes.
+    /// Hash elements are sized as field + value, stream entries as the sum of all their fields and values.
+    pub fn element_histogram(&self, key: &Bytes) -> Result<ElementHistogram, StoreError> {
+        let value = self.value(key).ok_or(StoreError::KeyNotFound)?;
+
+        let mut histogram = ElementHistogram::new(value.encoding());
+        match value {
+            Value::List(list) => {
+                for element in list.iter() {
+                    histogram.record(element.len());
+                }
+            }
+            Value::Hash(hash) => {
+                // fields past their deadline are gone, even if they weren't removed yet
+                let now = now_millis()?;
+                let live = hash
+                    .iter()
+                    .filter(|(_, value)| value.expires.is_none_or(|deadline| deadline > now));
+                for (field, value) in live {
+                    histogram.record(field.len() + value.value.len());
+                }
+                if histogram.elements == 0 {
+                    return Err(StoreError::KeyNotFound);
+                }
+            }
+            Value::Set(set) => {
+                for member in set.iter() {
+                    histogram.record(member.len());
+                }
+            }
+            Value::SortedSet(zset) => {
+                for (member, _) in zset.iter() {
+                    histogram.record(member.len());
+                }
+            }
+            Value::Stream(stream) => {
+                for entry in stream.entries() {
+                    histogram.record(entry.iter().map(|(f, v)| f.len() + v.len()).sum());
+                }
+            }
+            Value::String(_) => return Err(StoreError::WrongType),
+        }
+        Ok(histogram)
+    }
+}
+
+#[test]
+fn test_element_histogram_for_list() {
+    let mut store = Store::new();
+    let key = Bytes::from("list");
+    store
+        .rpush(key.clone(), vec!["a".into(), "abcd".into(), "ab".into()])
+        .unwrap();
+
+    let histogram = store.element_histogram(&key).unwrap();
+    assert_eq!(histogram.elements, 3);
+    assert_eq!(histogram.min_size, 1);
+    assert_eq!(histogram.max_size, 4);
+    assert_eq!(histogram.total_size, 7);
+}
+
+#[test]
+fn test_element_histogram_skips_expired_hash_fields() {
+    let mut store = Store::new();
+    let key = Bytes::from("hash");
+    store
+        .hset(
+            &key,
+            vec![("a".into(), "1".into()), ("bc".into(), "22".into())],
+        )
+        .unwrap();
+    let expired = |store: &mut Store, field: &str| {
+        store
+            .hash_mut(&key)
+            .unwrap()
+            .unwrap()
+            .get_mut(&Bytes::copy_from_slice(field.as_bytes()))
+            .unwrap()
+            .expires = Some(0);
+    };
+
+    expired(&mut store, "bc");
+    let histogram = store.element_histogram(&key).unwrap();
+    assert_eq!(histogram.elements, 1);
+    assert_eq!(histogram.max_size, 2);
+
+    // a hash whose fields all expired counts as missing
+    expired(&mut store, "a");
+    assert!(matches!(
+        store.element_histogram(&key),
+        Err(StoreError::KeyNotFound)
+    ));
+}
@@ -0,0 +1,384 @@
+use std::collections::VecDeque;
+
+use bytes::Bytes;
+
+/// Chunks grow up to this many bytes before a new one is started, mirrors the default
+/// `list-max-listpack-size` of -2 (8 kb per node)
+const MAX_CHUNK_BYTES: usize = 8 * 1024;
+/// Bytes of the length stored in front of and behind every element
+const LENGTH_BYTES: usize = size_of::<u32>();
+
+/// Elements of a list.
+///
+/// Instead of one allocation per element, elements are packed into chunks of up to
+/// `MAX_CHUNK_BYTES`, like the listpacks of a redis quicklist. Each element is stored as its
+/// length, its bytes and its length again, so a chunk can be walked from both ends. Pushing and
+/// popping only touches the first or last chunk; positional access skips whole chunks.
+#[derive(Default)]
+pub struct ListValue {
+    chunks: VecDeque<Chunk>,
+    len: usize,
+}
+
+#[derive(Default)]
+struct Chunk {
+    data: Vec<u8>,
+    len: usize,
+}
+
+fn encoded_size(element: &[u8]) -> usize {
+    element.len() + 2 * LENGTH_BYTES
+}
+
+fn read_length(data: &[u8], offset: usize) -> usize {
+    let bytes = data[offset..offset + LENGTH_BYTES]
+        .try_into()
+        .expect("length has a fixed size");
+    u32::from_le_bytes(bytes) as usize
+}
+
+impl Chunk {
+    fn encode(element: &[u8]) -> Vec<u8> {
+        let length = (element.len() as u32).to_le_bytes();
+        let mut encoded = Vec::with_capacity(encoded_size(element));
+        encoded.extend_from_slice(&length);
+        encoded.extend_from_slice(element);
+        encoded.extend_from_slice(&length);
+        encoded
+    }
+
+    fn fits(&self, element: &[u8]) -> bool {
+        self.data.len() + encoded_size(element) <= MAX_CHUNK_BYTES
+    }
+
+    /// Byte offset of the element at the index, or of the end of the chunk
+    fn offset(&self, index: usize) -> usize {
+        let mut offset = 0;
+        for _ in 0..index {
+            offset += read_length(&self.data, offset) + 2 * LENGTH_BYTES;
+        }
+        offset
+    }
+
+    fn element_at(&self, offset: usize) -> &[u8] {
+        let length = read_length(&self.data, offset);
+        &self.data[offset + LENGTH_BYTES..offset + LENGTH_BYTES + length]
+    }
+
+    fn insert(&mut self, index: usize, element: &[u8]) {
+        let offset = self.offset(index);
+        self.data.splice(offset..offset, Chunk::encode(element));
+        self.len += 1;
+    }
+
+    fn remove(&mut self, index: usize) -> Bytes {
+        let offset = self.offset(index);
+        let element = Bytes::copy_from_slice(self.element_at(offset));
+        self.data.drain(offset..offset + encoded_size(&element));
+        self.len -= 1;
+        element
+    }
+
+    fn pop_back(&mut self) -> Option<Bytes> {
+        let length = read_length(&self.data, self.data.len().checked_sub(LENGTH_BYTES)?);
+        let offset = self.data.len() - length - 2 * LENGTH_BYTES;
+        let element = Bytes::copy_from_slice(self.element_at(offset));
+        self.data.truncate(offset);
+        self.len -= 1;
+        Some(element)
+    }
+
+    fn iter(&self) -> ChunkIter<'_> {
+        ChunkIter {
+            data: &self.data,
+            front: 0,
+            back: self.data.len(),
+        }
+    }
+
+    /// Splits off the second half of a chunk which grew beyond its size, e.g. by an insertion
+    fn split_off(&mut self) -> Option<Chunk> {
+        if self.data.len() <= MAX_CHUNK_BYTES || self.len < 2 {
+            return None;
+        }
+        let kept = self.len / 2;
+        let data = self.data.split_off(self.offset(kept));
+        let split = Chunk {
+            data,
+            len: self.len - kept,
+        };
+        self.len = kept;
+        Some(split)
+    }
+}
+
+struct ChunkIter<'a> {
+    data: &'a [u8],
+    front: usize,
+    back: usize,
+}
+
+impl<'a> Iterator for ChunkIter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front >= self.back {
+            return None;
+        }
+        let length = read_length(self.data, self.front);
+        let element = &self.data[self.front + LENGTH_BYTES..self.front + LENGTH_BYTES + length];
+        self.front += length + 2 * LENGTH_BYTES;
+        Some(element)
+    }
+}
+
+impl DoubleEndedIterator for ChunkIter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front >= self.back {
+            return None;
+        }
+        let length = read_length(self.data, self.back - LENGTH_BYTES);
+        let element = &self.data[self.back - LENGTH_BYTES - length..self.back - LENGTH_BYTES];
+        self.back -= length + 2 * LENGTH_BYTES;
+        Some(element)
+    }
+}
+
+impl ListValue {
+    /// Name of the encoding as reported by OBJECT ENCODING
+    pub fn encoding(&self) -> &'static str {
+        if self.chunks.len() <= 1 {
+            "listpack"
+        } else {
+            "quicklist"
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push_back(&mut self, element: Bytes) {
+        match self.chunks.back_mut() {
+            Some(chunk) if chunk.fits(&element) => {
+                chunk.data.extend_from_slice(&Chunk::encode(&element));
+                chunk.len += 1;
+            }
+            _ => self.chunks.push_back(Chunk {
+                data: Chunk::encode(&element),
+                len: 1,
+            }),
+        }
+        self.len += 1;
+    }
+
+    pub fn push_front(&mut self, element: Bytes) {
+        match self.chunks.front_mut() {
+            Some(chunk) if chunk.fits(&element) => chunk.insert(0, &element),
+            _ => self.chunks.push_front(Chunk {
+                data: Chunk::encode(&element),
+                len: 1,
+            }),
+        }
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<Bytes> {
+        let chunk = self.chunks.front_mut()?;
+        let element = chunk.remove(0);
+        if chunk.len == 0 {
+            self.chunks.pop_front();
+        }
+        self.len -= 1;
+        Some(element)
+    }
+
+    pub fn pop_back(&mut self) -> Option<Bytes> {
+        let chunk = self.chunks.back_mut()?;
+        let element = chunk.pop_back()?;
+        if chunk.len == 0 {
+            self.chunks.pop_back();
+        }
+        self.len -= 1;
+        Some(element)
+    }
+
+    /// The chunk holding the element at the index and the index within that chunk
+    fn locate(&self, mut index: usize) -> Option<(usize, usize)> {
+        for (position, chunk) in self.chunks.iter().enumerate() {
+            if index < chunk.len {
+                return Some((position, index));
+            }
+            index -= chunk.len;
+        }
+        None
+    }
+
+    pub fn get(&self, index: usize) -> Option<Bytes> {
+        let (chunk, index) = self.locate(index)?;
+        let chunk = &self.chunks[chunk];
+        Some(Bytes::copy_from_slice(
+            chunk.element_at(chunk.offset(index)),
+        ))
+    }
+
+    /// Replaces the element at the index, which has to be within the list
+    pub fn set(&mut self, index: usize, element: Bytes) {
+        let (position, index) = self.locate(index).expect("index is within the list");
+        let chunk = &mut self.chunks[position];
+        chunk.remove(index);
+        chunk.insert(index, &element);
+        self.split_if_full(position);
+    }
+
+    /// Inserts the element so it ends up at the index, which may be the length of the list
+    pub fn insert(&mut self, index: usize, element: Bytes) {
+        if index == self.len {
+            return self.push_back(element);
+        }
+        let (position, index) = self.locate(index).expect("index is within the list");
+        self.chunks[position].insert(index, &element);
+        self.len += 1;
+        self.split_if_full(position);
+    }
+
+    fn split_if_full(&mut self, position: usize) {
+        if let Some(split) = self.chunks[position].split_off() {
+            self.chunks.insert(position + 1, split);
+        }
+    }
+
+    /// Keeps the elements for which `keep` holds, in order
+    pub fn retain(&mut self, mut keep: impl FnMut(&[u8]) -> bool) {
+        let mut retained = ListValue::default();
+        for element in self.iter().filter(|element| keep(element)) {
+            retained.push_back(Bytes::copy_from_slice(element));
+        }
+        *self = retained;
+    }
+
+    /// Keeps the elements with a position in `start..end`
+    pub fn trim(&mut self, start: usize, end: usize) {
+        let end = end.min(self.len);
+        if start >= end {
+            *self = ListValue::default();
+            return;
+        }
+        // drop whole chunks from both ends first, then single elements
+        let mut dropped_back = self.len - end;
+        while let Some(chunk) = self.chunks.back()
+            && chunk.len <= dropped_back
+        {
+            dropped_back -= chunk.len;
+            self.len -= chunk.len;
+            self.chunks.pop_back();
+        }
+        for _ in 0..dropped_back {
+            self.pop_back();
+        }
+        let mut dropped_front = start;
+        while let Some(chunk) = self.chunks.front()
+            && chunk.len <= dropped_front
+        {
+            dropped_front -= chunk.len;
+            self.len -= chunk.len;
+            self.chunks.pop_front();
+        }
+        if let Some(chunk) = self.chunks.front_mut()
+            && dropped_front > 0
+        {
+            let offset = chunk.offset(dropped_front);
+            chunk.data.drain(..offset);
+            chunk.len -= dropped_front;
+            self.len -= dropped_front;
+        }
+    }
+
+    /// Iterates over the elements from head to tail
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &[u8]> {
+        self.chunks.iter().flat_map(|chunk| chunk.iter())
+    }
+
+    /// Iterates over the elements with a position in `start..end`, whole chunks before the
+    /// start are skipped without walking them
+    pub fn range(&self, start: usize, end: usize) -> impl Iterator<Item = &[u8]> {
+        let end = end.min(self.len);
+        let (first_chunk, skipped) = self.locate(start).unwrap_or((self.chunks.len(), 0));
+        self.chunks
+            .range(first_chunk..)
+            .flat_map(|chunk| chunk.iter())
+            .skip(skipped)
+            .take(end.saturating_sub(start))
+    }
+}
+
+#[test]
+fn test_list_value_matches_a_deque() {
+    let mut rng = rand::rng();
+    let mut list = ListValue::default();
+    let mut expected: VecDeque<Bytes> = VecDeque::new();
+    let element = |i: usize| Bytes::from("x".repeat(i % 700));
+
+    use rand::Rng;
+    for i in 0..5_000 {
+        match rng.random_range(0..6) {
+            0 => {
+                list.push_front(element(i));
+                expected.push_front(element(i));
+            }
+            1 | 2 => {
+                list.push_back(element(i));
+                expected.push_back(element(i));
+            }
+            3 => assert_eq!(list.pop_front(), expected.pop_front()),
+            4 => assert_eq!(list.pop_back(), expected.pop_back()),
+            _ => {
+                let index = rng.random_range(0..=expected.len());
+                list.insert(index, element(i));
+                expected.insert(index, element(i));
+            }
+        }
+    }
+    assert!(list.chunks.len() > 1);
+    assert_eq!(list.encoding(), "quicklist");
+    assert_eq!(list.len(), expected.len());
+    assert!(list.iter().eq(expected.iter().map(|e| e.as_ref())));
+    assert!(
+        list.iter()
+            .rev()
+            .eq(expected.iter().rev().map(|e| e.as_ref()))
+    );
+    assert!(
+        list.range(100, 200)
+            .eq(expected.range(100..200).map(|e| e.as_ref()))
+    );
+    assert_eq!(list.get(42), expected.get(42).cloned());
+
+    list.set(7, "replaced".into());
+    expected[7] = "replaced".into();
+    list.retain(|element| element.len() % 3 != 0);
+    expected.retain(|element| element.len() % 3 != 0);
+    assert!(list.iter().eq(expected.iter().map(|e| e.as_ref())));
+
+    list.trim(10, expected.len() - 10);
+    let expected: Vec<Bytes> = expected.range(10..expected.len() - 10).cloned().collect();
+    assert_eq!(list.len(), expected.len());
+    assert!(list.iter().eq(expected.iter().map(|e| e.as_ref())));
+}
+
+#[test]
+fn test_small_lists_are_a_single_listpack() {
+    let mut list = ListValue::default();
+    for element in ["a", "b", "c"] {
+        list.push_back(element.into());
+    }
+    assert_eq!(list.encoding(), "listpack");
+    assert_eq!(list.pop_front(), Some(Bytes::from("a")));
+    assert_eq!(list.pop_back(), Some(Bytes::from("c")));
+    assert_eq!(list.get(0), Some(Bytes::from("b")));
+    assert_eq!(list.get(1), None);
+}
@@ -0,0 +1,527 @@
+use bytes::Bytes;
+
+use super::{ListValue, Store, StoreError};
+
+/// End of a list which LMOVE and friends pop from or push to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+impl ListEnd {
+    pub(super) fn pop(self, list: &mut ListValue) -> Option<Bytes> {
+        match self {
+            ListEnd::Left => list.pop_front(),
+            ListEnd::Right => list.pop_back(),
+        }
+    }
+
+    pub(super) fn push(self, list: &mut ListValue, value: Bytes) {
+        match self {
+            ListEnd::Left => list.push_front(value),
+            ListEnd::Right => list.push_back(value),
+        }
+    }
+}
+
+// Replies of LINSERT besides the new length of the list
+const PIVOT_MISSING: i128 = -1;
+const LIST_MISSING: i128 = 0;
+
+/// Position of a (possibly negative, counting from the tail) index within a list of `len` elements
+fn list_position(index: i128, len: usize) -> Option<usize> {
+    let position = if index < 0 {
+        index + len as i128
+    } else {
+        index
+    };
+    (0..len as i128)
+        .contains(&position)
+        .then_some(position as usize)
+}
+
+impl Store {
+    /// The element at the index, negative indexes count from the tail
+    pub fn lindex(&self, key: &Bytes, index: i128) -> Result<Option<Bytes>, StoreError> {
+        Ok(self.list(key)?.and_then(|list| {
+            list_position(index, list.len()).and_then(|position| list.get(position))
+        }))
+    }
+
+    /// Replaces the element at the index, the key has to exist and the index has to be within the list
+    pub fn lset(&mut self, key: &Bytes, index: i128, value: Bytes) -> Result<(), StoreError> {
+        let list = self.list_mut(key)?.ok_or(StoreError::KeyNotFound)?;
+        let position = list_position(index, list.len()).ok_or(StoreError::IndexOutOfRange)?;
+        list.set(position, value);
+        self.touch_key(key);
+        Ok(())
+    }
+
+    /// Inserts the value before (or after) the first occurrence of the pivot, returns the new
+    /// length of the list, -1 if the pivot is not part of the list and 0 if the key is missing
+    pub fn linsert(
+        &mut self,
+        key: &Bytes,
+        before: bool,
+        pivot: &Bytes,
+        value: Bytes,
+    ) -> Result<i128, StoreError> {
+        let Some(list) = self.list_mut(key)? else {
+            return Ok(LIST_MISSING);
+        };
+        let Some(position) = list.iter().position(|element| element == pivot.as_ref()) else {
+            return Ok(PIVOT_MISSING);
+        };
+        list.insert(if before { position } else { position + 1 }, value);
+        let len = list.len() as i128;
+        self.touch_key(key);
+        Ok(len)
+    }
+
+    /// Removes up to `count` occurrences of the value, starting at the head (or at the tail for a
+    /// negative count), 0 removes all of them. Returns the number of removed elements.
+    pub fn lrem(&mut self, key: &Bytes, count: i128, value: &Bytes) -> Result<usize, StoreError> {
+        let Some(list) = self.list_mut(key)? else {
+            return Ok(0);
+        };
+        let limit = if count == 0 {
+            usize::MAX
+        } else {
+            count.unsigned_abs().try_into().unwrap_or(usize::MAX)
+        };
+
+        let mut matches: Vec<usize> = list
+            .iter()
+            .enumerate()
+            .filter(|(_, element)| *element == value.as_ref())
+            .map(|(position, _)| position)
+            .collect();
+        if count < 0 {
+            matches.drain(..matches.len().saturating_sub(limit));
+        } else {
+            matches.truncate(limit);
+        }
+
+        let mut position = 0;
+        let mut next_match = matches.iter().peekable();
+        list.retain(|_| {
+            let removed = next_match.next_if_eq(&&position).is_some();
+            position += 1;
+            !removed
+        });
+        if !matches.is_empty() {
+            self.touch_key(key);
+            self.remove_if_empty(key);
+        }
+        Ok(matches.len())
+    }
+
+    /// Keeps only the elements between start and stop (both inclusive, negative indexes count
+    /// from the tail), the key is deleted if nothing remains
+    pub fn ltrim(&mut self, key: &Bytes, start: i128, stop: i128) -> Result<(), StoreError> {
+        let Some(list) = self.list_mut(key)? else {
+            return Ok(());
+        };
+        let len = list.len() as i128;
+        let start = if start < 0 { start + len } else { start }.max(0);
+        let stop = if stop < 0 { stop + len } else { stop }.min(len - 1);
+        if start > stop {
+            list.trim(0, 0);
+        } else {
+            list.trim(start as usize, stop as usize + 1);
+        }
+        if list.len() as i128 != len {
+            self.touch_key(key);
+        }
+        self.remove_if_empty(key);
+        Ok(())
+    }
+
+    /// Pops an element from one end of the source and pushes it to one end of the destination,
+    /// in one go. Returns the moved element, `None` if the source is empty.
+    pub fn lmove(
+        &mut self,
+        source: &Bytes,
+        destination: &Bytes,
+        from: ListEnd,
+        to: ListEnd,
+    ) -> Result<Option<Bytes>, StoreError> {
+        // check the destination first, a wrong type must not cost the source its element
+        self.list(destination)?;
+        let Some(value) = self.list_mut(source)?.and_then(|list| from.pop(list)) else {
+            return Ok(None);
+        };
+        self.touch_key(source);
+        self.remove_if_empty(source);
+
+        to.push(self.list_entry(destination)?, value.clone());
+        self.touch_key(destination);
+        self.signal_key_as_ready(destination);
+        Ok(Some(value))
+    }
+
+    /// Positions of the elements equal to the value. Matching starts at the `rank`-th match,
+    /// counted from the tail for a negative rank, and stops after `count` matches (0 for all).
+    /// Only the first `maxlen` elements (from the respective end) are compared, 0 compares all.
+    pub fn lpos(
+        &self,
+        key: &Bytes,
+        value: &Bytes,
+        rank: i128,
+        count: usize,
+        maxlen: usize,
+    ) -> Result<Vec<usize>, StoreError> {
+        let Some(list) = self.list(key)? else {
+            return Ok(vec![]);
+        };
+        let last = list.len().saturating_sub(1);
+        let elements: Box<dyn Iterator<Item = (usize, &[u8])>> = if rank < 0 {
+            Box::new(list.iter().rev().enumerate().map(|(i, e)| (last - i, e)))
+        } else {
+            Box::new(list.iter().enumerate())
+        };
+        let skipped = rank.unsigned_abs().saturating_sub(1);
+
+        Ok(elements
+            .take(if maxlen == 0 { usize::MAX } else { maxlen })
+            .filter(|(_, element)| *element == value.as_ref())
+            .map(|(position, _)| position)
+            .skip(skipped.try_into().unwrap_or(usize::MAX))
+            .take(if count == 0 { usize::MAX } else { count })
+            .collect())
+    }
+
+    /// Pops up to `count` elements from one end of the list
+    pub(super) fn pop_from(
+        &mut self,
+        key: &Bytes,
+        from: ListEnd,
+        count: usize,
+    ) -> Result<Vec<Bytes>, StoreError> {
+        match from {
+            ListEnd::Left => self.lpop(key.clone(), count),
+            ListEnd::Right => self.rpop(key.clone(), count),
+        }
+    }
+
+    /// Pops up to `count` elements from the first of the keys which holds a non-empty list,
+    /// returns that key together with the popped elements
+    pub fn lmpop(
+        &mut self,
+        keys: &[Bytes],
+        from: ListEnd,
+        count: usize,
+    ) -> Result<Option<(Bytes, Vec<Bytes>)>, StoreError> {
+        for key in keys {
+            if self.list(key)?.is_some_and(|list| !list.is_empty()) {
+                let popped = self.pop_from(key, from, count)?;
+                return Ok(Some((key.clone(), popped)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[test]
+fn test_lindex_and_lset_with_negative_indexes() {
+    let mut store = Store::new();
+    let key = Bytes::from("list");
+    store
+        .rpush(key.clone(), vec!["a".into(), "b".into(), "c".into()])
+        .unwrap();
+
+    assert_eq!(store.lindex(&key, -1).unwrap(), Some(Bytes::from("c")));
+    assert_eq!(store.lindex(&key, 3).unwrap(), None);
+    assert_eq!(store.lindex(&key, -4).unwrap(), None);
+
+    store.lset(&key, -3, "x".into()).unwrap();
+    assert_eq!(store.lindex(&key, 0).unwrap(), Some(Bytes::from("x")));
+    assert!(matches!(
+        store.lset(&key, 3, "y".into()),
+        Err(StoreError::IndexOutOfRange)
+    ));
+    assert!(matches!(
+        store.lset(&"missing".into(), 0, "y".into()),
+        Err(StoreError::KeyNotFound)
+    ));
+}
+
+#[test]
+fn test_linsert_around_the_first_pivot() {
+    let mut store = Store::new();
+    let key = Bytes::from("list");
+    store
+        .rpush(key.clone(), vec!["a".into(), "b".into(), "a".into()])
+        .unwrap();
+
+    assert_eq!(
+        store.linsert(&key, true, &"a".into(), "x".into()).unwrap(),
+        4
+    );
+    assert_eq!(
+        store.linsert(&key, false, &"b".into(), "y".into()).unwrap(),
+        5
+    );
+    assert_eq!(
+        store.lrange(key.clone(), 0, -1).unwrap(),
+        vec![
+            Bytes::from("x"),
+            Bytes::from("a"),
+            Bytes::from("b"),
+            Bytes::from("y"),
+            Bytes::from("a")
+        ]
+    );
+    assert_eq!(
+        store
+            .linsert(&key, true, &"missing".into(), "z".into())
+            .unwrap(),
+        PIVOT_MISSING
+    );
+    assert_eq!(
+        store
+            .linsert(&"missing".into(), true, &"a".into(), "z".into())
+            .unwrap(),
+        LIST_MISSING
+    );
+}
+
+#[test]
+fn test_lrem_from_head_tail_and_everywhere() {
+    let mut store = Store::new();
+    let key = Bytes::from("list");
+    let elements = || {
+        ["a", "b", "a", "c", "a"]
+            .into_iter()
+            .map(Bytes::from)
+            .collect::<Vec<_>>()
+    };
+    store.rpush(key.clone(), elements()).unwrap();
+
+    assert_eq!(store.lrem(&key, -2, &"a".into()).unwrap(), 2);
+    assert_eq!(
+        store.lrange(key.clone(), 0, -1).unwrap(),
+        vec![Bytes::from("a"), Bytes::from("b"), Bytes::from("c")]
+    );
+    assert_eq!(store.lrem(&key, 1, &"b".into()).unwrap(), 1);
+    assert_eq!(store.lrem(&key, 1, &"missing".into()).unwrap(), 0);
+
+    store.rpush(key.clone(), elements()).unwrap();
+    assert_eq!(store.lrem(&key, 0, &"a".into()).unwrap(), 4);
+    assert_eq!(
+        store.lrange(key.clone(), 0, -1).unwrap(),
+        vec![Bytes::from("c"), Bytes::from("b"), Bytes::from("c")]
+    );
+    assert_eq!(store.lrem(&key, 0, &"c".into()).unwrap(), 2);
+    assert_eq!(store.lrem(&key, 0, &"b".into()).unwrap(), 1);
+    assert!(store.get_type(&key).is_err());
+}
+
+#[test]
+fn test_ltrim_keeps_the_range() {
+    let mut store = Store::new();
+    let key = Bytes::from("list");
+    store
+        .rpush(
+            key.clone(),
+            vec!["a".into(), "b".into(), "c".into(), "d".into()],
+        )
+        .unwrap();
+
+    store.ltrim(&key, 1, -2).unwrap();
+    assert_eq!(
+        store.lrange(key.clone(), 0, -1).unwrap(),
+        vec![Bytes::from("b"), Bytes::from("c")]
+    );
+    store.ltrim(&key, -100, 100).unwrap();
+    assert_eq!(store.llen(&key).unwrap(), 2);
+
+    store.ltrim(&key, 1, 0).unwrap();
+    assert!(store.get_type(&key).is_err());
+}
+
+#[test]
+fn test_lpos_with_rank_count_and_maxlen() {
+    let mut store = Store::new();
+    let key = Bytes::from("list");
+    let elements = ["a", "b", "c", "1", "2", "3", "c", "c"];
+    store
+        .rpush(key.clone(), elements.into_iter().map(Bytes::from).collect())
+        .unwrap();
+    let c = Bytes::from("c");
+
+    assert_eq!(store.lpos(&key, &c, 1, 1, 0).unwrap(), vec![2]);
+    assert_eq!(store.lpos(&key, &c, 2, 1, 0).unwrap(), vec![6]);
+    assert_eq!(store.lpos(&key, &c, -1, 1, 0).unwrap(), vec![7]);
+    assert_eq!(store.lpos(&key, &c, 1, 0, 0).unwrap(), vec![2, 6, 7]);
+    assert_eq!(store.lpos(&key, &c, -2, 0, 0).unwrap(), vec![6, 2]);
+    assert_eq!(store.lpos(&key, &c, 1, 0, 3).unwrap(), vec![2]);
+    assert_eq!(store.lpos(&key, &c, 4, 1, 0).unwrap(), Vec::<usize>::new());
+    assert_eq!(
+        store.lpos(&"missing".into(), &c, 1, 1, 0).unwrap(),
+        Vec::<usize>::new()
+    );
+}
+
+#[test]
+fn test_lmpop_pops_from_the_first_non_empty_list() {
+    let mut store = Store::new();
+    let keys = vec![Bytes::from("missing"), Bytes::from("a"), Bytes::from("b")];
+    store.rpush("b".into(), vec!["1".into()]).unwrap();
+    store
+        .rpush("a".into(), vec!["1".into(), "2".into(), "3".into()])
+        .unwrap();
+
+    assert_eq!(
+        store.lmpop(&keys, ListEnd::Right, 2).unwrap(),
+        Some((Bytes::from("a"), vec![Bytes::from("3"), Bytes::from("2")]))
+    );
+    assert_eq!(
+        store.lmpop(&keys, ListEnd::Left, 5).unwrap(),
+        Some((Bytes::from("a"), vec![Bytes::from("1")]))
+    );
+    assert_eq!(
+        store.lmpop(&keys, ListEnd::Left, 5).unwrap(),
+        Some((Bytes::from("b"), vec![Bytes::from("1")]))
+    );
+    assert_eq!(store.lmpop(&keys, ListEnd::Left, 5).unwrap(), None);
+}
+
+#[test]
+fn test_lmove_between_and_within_lists() {
+    let mut store = Store::new();
+    let source = Bytes::from("source");
+    let destination = Bytes::from("destination");
+    store
+        .rpush(source.clone(), vec!["a".into(), "b".into()])
+        .unwrap();
+
+    assert_eq!(
+        store
+            .lmove(&source, &destination, ListEnd::Right, ListEnd::Left)
+            .unwrap(),
+        Some(Bytes::from("b"))
+    );
+    assert_eq!(
+        store
+            .lmove(&source, &source, ListEnd::Left, ListEnd::Right)
+            .unwrap(),
+        Some(Bytes::from("a"))
+    );
+    assert_eq!(
+        store
+            .lmove(&source, &destination, ListEnd::Left, ListEnd::Right)
+            .unwrap(),
+        Some(Bytes::from("a"))
+    );
+    assert!(store.get_type(&source).is_err());
+    assert_eq!(
+        store.lrange(destination.clone(), 0, -1).unwrap(),
+        vec![Bytes::from("b"), Bytes::from("a")]
+    );
+    assert_eq!(
+        store
+            .lmove(&source, &destination, ListEnd::Left, ListEnd::Right)
+            .unwrap(),
+        None
+    );
+}
+
+#[test]
+fn test_lmove_to_wrong_type_keeps_the_source() {
+    let mut store = Store::new();
+    let source = Bytes::from("source");
+    let destination = Bytes::from("string");
+    store.rpush(source.clone(), vec!["a".into()]).unwrap();
+    store
+        .set_with_expiry(destination.clone(), "value".into(), None)
+        .unwrap();
+
+    assert!(matches!(
+        store.lmove(&source, &destination, ListEnd::Left, ListEnd::Left),
+        Err(StoreError::WrongType)
+    ));
+    assert_eq!(store.llen(&source).unwrap(), 1);
+}
+
+#[test]
+fn test_blocked_move_pushes_before_replying() {
+    use crate::parser::RedisType;
+    use tokio::sync::oneshot;
+
+    let mut store = Store::new();
+    let source = Bytes::from("source");
+    let destination = Bytes::from("destination");
+    let (move_tx, mut move_rx) = oneshot::channel();
+    let (pop_tx, mut pop_rx) = oneshot::channel();
+    store.register_blpop_waiting_client(
+        vec![source.clone()],
+        ListEnd::Right,
+        1,
+        Some((destination.clone(), ListEnd::Left)),
+        |_, moved| RedisType::BulkString(moved[0].clone()),
+        move_tx,
+    );
+    store.register_blpop_waiting_client(
+        vec![destination.clone()],
+        ListEnd::Left,
+        1,
+        None,
+        |key, popped| {
+            RedisType::Array(Some(vec![
+                RedisType::BulkString(key),
+                RedisType::BulkString(popped[0].clone()),
+            ]))
+        },
+        pop_tx,
+    );
+
+    store
+        .rpush(source.clone(), vec!["a".into(), "b".into()])
+        .unwrap();
+    store.serve_blocked_clients();
+
+    assert_eq!(
+        move_rx.try_recv().unwrap(),
+        RedisType::BulkString("b".into())
+    );
+    // the moved element woke up the client waiting on the destination
+    assert_eq!(
+        pop_rx.try_recv().unwrap(),
+        RedisType::Array(Some(vec![
+            RedisType::BulkString(destination.clone()),
+            RedisType::BulkString("b".into())
+        ]))
+    );
+    assert_eq!(store.lrange(source, 0, -1).unwrap(), vec![Bytes::from("a")]);
+    assert!(store.get_type(&destination).is_err());
+}
+
+/// Timings of pushing to and popping from the head of growing lists, the time per element
+/// should stay flat as the lists grow. Run with
+/// `cargo test --release -- --ignored --nocapture bench_list`
+#[test]
+#[ignore]
+fn bench_list_head_operations() {
+    use std::time::Instant;
+
+    for elements in [10_000, 100_000] {
+        let mut store = Store::new();
+        let key = Bytes::from("list");
+
+        let started = Instant::now();
+        for i in 0..elements {
+            store
+                .lpush(key.clone(), vec![Bytes::from(format!("element:{}", i))])
+                .unwrap();
+        }
+        println!("LPUSH x{}: {:?}", elements, started.elapsed());
+
+        let started = Instant::now();
+        for _ in 0..elements {
+            store.lpop(key.clone(), 1).unwrap();
+        }
+        println!("LPOP x{}: {:?}", elements, started.elapsed());
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::Store;
+use crate::commands::utils::glob_match;
+use crate::parser::RedisType;
+
+/// Outboxes of the clients subscribed to each channel (or pattern), by client id
+pub type Subscribers = HashMap<Bytes, HashMap<u64, UnboundedSender<RedisType>>>;
+
+/// What a client subscribes to
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SubscriptionKind {
+    Channel,
+    /// glob-style pattern of channel names
+    Pattern,
+    /// channel of the sharded pub/sub, only reached by SPUBLISH
+    ShardChannel,
+}
+
+impl SubscriptionKind {
+    pub const ALL: [SubscriptionKind; 3] = [
+        SubscriptionKind::Channel,
+        SubscriptionKind::Pattern,
+        SubscriptionKind::ShardChannel,
+    ];
+
+    /// Kind of the confirmation the client receives when subscribing
+    pub fn subscribe_reply(&self) -> &'static str {
+        match self {
+            SubscriptionKind::Channel => "subscribe",
+            SubscriptionKind::Pattern => "psubscribe",
+            SubscriptionKind::ShardChannel => "ssubscribe",
+        }
+    }
+
+    /// Kind of the confirmation the client receives when unsubscribing
+    pub fn unsubscribe_reply(&self) -> &'static str {
+        match self {
+            SubscriptionKind::Channel => "unsubscribe",
+            SubscriptionKind::Pattern => "punsubscribe",
+            SubscriptionKind::ShardChannel => "sunsubscribe",
+        }
+    }
+}
+
+/// Sends the message to each of the clients, returns how many received it. Clients which went
+/// away without unsubscribing are dropped along the way.
+fn deliver(clients: &mut HashMap<u64, UnboundedSender<RedisType>>, message: &RedisType) -> usize {
+    // published messages are out-of-band, they bypass the reply mode of the client
+    clients.retain(|_, outbox| outbox.send(message.clone()).is_ok());
+    clients.len()
+}
+
+impl Store {
+    fn subscribers(&self, kind: SubscriptionKind) -> &Subscribers {
+        match kind {
+            SubscriptionKind::Channel => &self.subscribers,
+            SubscriptionKind::Pattern => &self.pattern_subscribers,
+            SubscriptionKind::ShardChannel => &self.shard_subscribers,
+        }
+    }
+
+    fn subscribers_mut(&mut self, kind: SubscriptionKind) -> &mut Subscribers {
+        match kind {
+            SubscriptionKind::Channel => &mut self.subscribers,
+            SubscriptionKind::Pattern => &mut self.pattern_subscribers,
+            SubscriptionKind::ShardChannel => &mut self.shard_subscribers,
+        }
+    }
+
+    /// Registers the outbox of the client for messages published to the channel, or to the
+    /// channels matching the pattern
+    pub fn subscribe(
+        &mut self,
+        kind: SubscriptionKind,
+        name: Bytes,
+        client_id: u64,
+        outbox: UnboundedSender<RedisType>,
+    ) {
+        self.subscribers_mut(kind)
+            .entry(name)
+            .or_default()
+            .insert(client_id, outbox);
+    }
+
+    /// Stops delivering messages published to the channel (or pattern) to the client
+    pub fn unsubscribe(&mut self, kind: SubscriptionKind, name: &Bytes, client_id: u64) {
+        let subscribers = self.subscribers_mut(kind);
+        if let Some(clients) = subscribers.get_mut(name) {
+            clients.remove(&client_id);
+            if clients.is_empty() {
+                subscribers.remove(name);
+            }
+        }
+    }
+
+    /// Delivers the message to the subscribers of the channel and of each pattern matching it,
+    /// returns how many received it. A client subscribed more than once receives it more than
+    /// once.
+    pub fn publish(&mut self, channel: &Bytes, message: &Bytes) -> usize {
+        let mut receivers = 0;
+        if let Some(clients) = self.subscribers.get_mut(channel) {
+            receivers += deliver(
+                clients,
+                &RedisType::Push(vec![
+                    RedisType::BulkString(Bytes::from_static(b"message")),
+                    RedisType::BulkString(channel.clone()),
+                    RedisType::BulkString(message.clone()),
+                ]),
+            );
+        }
+        for (pattern, clients) in self.pattern_subscribers.iter_mut() {
+            if glob_match(pattern, channel) {
+                receivers += deliver(
+                    clients,
+                    &RedisType::Push(vec![
+                        RedisType::BulkString(Bytes::from_static(b"pmessage")),
+                        RedisType::BulkString(pattern.clone()),
+                        RedisType::BulkString(channel.clone()),
+                        RedisType::BulkString(message.clone()),
+                    ]),
+                );
+            }
+        }
+        self.subscribers.retain(|_, clients| !clients.is_empty());
+        self.pattern_subscribers
+            .retain(|_, clients| !clients.is_empty());
+        receivers
+    }
+
+    /// Delivers the message to the subscribers of the shard channel, returns how many received
+    /// it. Patterns never match shard channels.
+    pub fn spublish(&mut self, channel: &Bytes, message: &Bytes) -> usize {
+        let Some(clients) = self.shard_subscribers.get_mut(channel) else {
+            return 0;
+        };
+        let receivers = deliver(
+            clients,
+            &RedisType::Push(vec![
+                RedisType::BulkString(Bytes::from_static(b"smessage")),
+                RedisType::BulkString(channel.clone()),
+                RedisType::BulkString(message.clone()),
+            ]),
+        );
+        if receivers == 0 {
+            self.shard_subscribers.remove(channel);
+        }
+        receivers
+    }
+
+    /// Channels (or shard channels) with at least one subscriber, optionally only those matching
+    /// the pattern
+    pub fn active_channels(&self, kind: SubscriptionKind, pattern: Option<&Bytes>) -> Vec<Bytes> {
+        self.subscribers(kind)
+            .keys()
+            .filter(|channel| pattern.is_none_or(|pattern| glob_match(pattern, channel)))
+            .cloned()
+            .collect()
+    }
+
+    /// Number of clients subscribed to the channel (or shard channel), pattern subscriptions not
+    /// counted
+    pub fn channel_subscribers(&self, kind: SubscriptionKind, channel: &Bytes) -> usize {
+        self.subscribers(kind).get(channel).map_or(0, HashMap::len)
+    }
+
+    /// Number of distinct patterns clients are subscribed to
+    pub fn pattern_count(&self) -> usize {
+        self.pattern_subscribers.len()
+    }
+}
+
+#[test]
+fn test_publish_reaches_subscribers_of_the_channel() {
+    use tokio::sync::mpsc;
+
+    let mut store = Store::new();
+    let news = Bytes::from("news");
+    let (alice, mut alice_inbox) = mpsc::unbounded_channel();
+    let (bob, bob_inbox) = mpsc::unbounded_channel();
+    store.subscribe(SubscriptionKind::Channel, news.clone(), 1, alice.clone());
+    store.subscribe(SubscriptionKind::Channel, news.clone(), 2, bob);
+    store.subscribe(SubscriptionKind::Channel, Bytes::from("sports"), 1, alice);
+
+    assert_eq!(store.publish(&news, &Bytes::from("hello")), 2);
+    assert_eq!(
+        alice_inbox.try_recv().unwrap(),
+        RedisType::Push(vec![
+            RedisType::BulkString(Bytes::from("message")),
+            RedisType::BulkString(news.clone()),
+            RedisType::BulkString(Bytes::from("hello")),
+        ])
+    );
+    assert!(alice_inbox.try_recv().is_err());
+
+    // bob disconnected, alice unsubscribed
+    drop(bob_inbox);
+    store.unsubscribe(SubscriptionKind::Channel, &news, 1);
+    assert_eq!(store.publish(&news, &Bytes::from("again")), 0);
+    assert!(!store.subscribers.contains_key(&news));
+    assert_eq!(
+        store.publish(&Bytes::from("sports"), &Bytes::from("goal")),
+        1
+    );
+}
+
+#[test]
+fn test_publish_reaches_matching_patterns() {
+    use tokio::sync::mpsc;
+
+    let mut store = Store::new();
+    let (alice, mut alice_inbox) = mpsc::unbounded_channel();
+    store.subscribe(
+        SubscriptionKind::Pattern,
+        Bytes::from("news.*"),
+        1,
+        alice.clone(),
+    );
+    store.subscribe(
+        SubscriptionKind::Channel,
+        Bytes::from("news.tech"),
+        1,
+        alice,
+    );
+
+    // once for the channel, once for the pattern
+    assert_eq!(
+        store.publish(&Bytes::from("news.tech"), &Bytes::from("hello")),
+        2
+    );
+    let RedisType::Push(message) = alice_inbox.try_recv().unwrap() else {
+        panic!("expected the message of the channel");
+    };
+    assert_eq!(message[0], RedisType::BulkString(Bytes::from("message")));
+    assert_eq!(
+        alice_inbox.try_recv().unwrap(),
+        RedisType::Push(vec![
+            RedisType::BulkString(Bytes::from("pmessage")),
+            RedisType::BulkString(Bytes::from("news.*")),
+            RedisType::BulkString(Bytes::from("news.tech")),
+            RedisType::BulkString(Bytes::from("hello")),
+        ])
+    );
+    assert_eq!(
+        store.publish(&Bytes::from("sports"), &Bytes::from("goal")),
+        0
+    );
+
+    store.unsubscribe(SubscriptionKind::Pattern, &Bytes::from("news.*"), 1);
+    assert!(store.pattern_subscribers.is_empty());
+}
+
+#[test]
+fn test_pubsub_introspection() {
+    use tokio::sync::mpsc;
+
+    let mut store = Store::new();
+    let (alice, _alice_inbox) = mpsc::unbounded_channel();
+    let (bob, _bob_inbox) = mpsc::unbounded_channel();
+    store.subscribe(
+        SubscriptionKind::Channel,
+        Bytes::from("news"),
+        1,
+        alice.clone(),
+    );
+    store.subscribe(
+        SubscriptionKind::Channel,
+        Bytes::from("news"),
+        2,
+        bob.clone(),
+    );
+    store.subscribe(
+        SubscriptionKind::Channel,
+        Bytes::from("sports"),
+        2,
+        bob.clone(),
+    );
+    store.subscribe(SubscriptionKind::Pattern, Bytes::from("n*"), 1, alice);
+    store.subscribe(SubscriptionKind::Pattern, Bytes::from("n*"), 2, bob);
+
+    let mut channels = store.active_channels(SubscriptionKind::Channel, None);
+    channels.sort();
+    assert_eq!(channels, vec![Bytes::from("news"), Bytes::from("sports")]);
+    assert_eq!(
+        store.active_channels(SubscriptionKind::Channel, Some(&Bytes::from("s*"))),
+        vec![Bytes::from("sports")]
+    );
+    assert_eq!(
+        store.channel_subscribers(SubscriptionKind::Channel, &Bytes::from("news")),
+        2
+    );
+    assert_eq!(
+        store.channel_subscribers(SubscriptionKind::Channel, &Bytes::from("weather")),
+        0
+    );
+    assert_eq!(store.pattern_count(), 1);
+}
+
+#[test]
+fn test_shard_channels_are_separate_from_channels() {
+    use tokio::sync::mpsc;
+
+    let mut store = Store::new();
+    let orders = Bytes::from("orders");
+    let (alice, mut alice_inbox) = mpsc::unbounded_channel();
+    store.subscribe(
+        SubscriptionKind::ShardChannel,
+        orders.clone(),
+        1,
+        alice.clone(),
+    );
+    store.subscribe(SubscriptionKind::Pattern, Bytes::from("*"), 1, alice);
+
+    assert_eq!(store.spublish(&orders, &Bytes::from("new")), 1);
+    assert_eq!(
+        alice_inbox.try_recv().unwrap(),
+        RedisType::Push(vec![
+            RedisType::BulkString(Bytes::from("smessage")),
+            RedisType::BulkString(orders.clone()),
+            RedisType::BulkString(Bytes::from("new")),
+        ])
+    );
+    assert!(alice_inbox.try_recv().is_err());
+    // PUBLISH only reaches the pattern
+    assert_eq!(store.publish(&orders, &Bytes::from("new")), 1);
+    assert_eq!(
+        store.active_channels(SubscriptionKind::ShardChannel, None),
+        vec![orders.clone()]
+    );
+    assert!(
+        store
+            .active_channels(SubscriptionKind::Channel, None)
+            .is_empty()
+    );
+    assert_eq!(
+        store.channel_subscribers(SubscriptionKind::ShardChannel, &orders),
+        1
+    );
+}
@@ -0,0 +1,1164 @@
+use std::{collections::BTreeSet, fs, io, ops::Bound::Unbounded, path::PathBuf};
+
+use bytes::Bytes;
+
+use super::{
+    HashValue, ListValue, Store, StreamEntry, StreamId, StreamValue, Value, WithExpiry, ZSetValue,
+    now_millis,
+    stream_group::{Consumer, PendingEntry},
+    stream_value::NODE_MAX_ENTRIES,
+};
+
+/// The RDB version written, the one of redis 7.2
+const RDB_VERSION: &[u8] = b"REDIS0011";
+
+const OPCODE_FUNCTION: u8 = 0xF5;
+const OPCODE_SLOT_INFO: u8 = 0xF6;
+const OPCODE_IDLE: u8 = 0xF8;
+const OPCODE_FREQ: u8 = 0xF9;
+const OPCODE_AUX: u8 = 0xFA;
+const OPCODE_RESIZEDB: u8 = 0xFB;
+const OPCODE_EXPIRETIME_MS: u8 = 0xFC;
+const OPCODE_EXPIRETIME: u8 = 0xFD;
+const OPCODE_SELECTDB: u8 = 0xFE;
+const OPCODE_EOF: u8 = 0xFF;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_STREAM_LISTPACKS: u8 = 15;
+const TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const TYPE_SET_LISTPACK: u8 = 20;
+const TYPE_STREAM_LISTPACKS_3: u8 = 21;
+
+/// Flags of the entries of a stream listpack
+const ITEM_FLAG_NONE: i64 = 0;
+const ITEM_FLAG_DELETED: i64 = 1;
+const ITEM_FLAG_SAMEFIELDS: i64 = 2;
+
+/// Special encodings of strings, flagged by the first two bits of the length
+const ENCODING_INT8: usize = 0;
+const ENCODING_INT16: usize = 1;
+const ENCODING_INT32: usize = 2;
+const ENCODING_LZF: usize = 3;
+
+/// Node of a quicklist holding a single large element rather than a listpack
+const QUICKLIST_NODE_PLAIN: usize = 1;
+
+/// Where the dataset is loaded from at startup and SAVE writes it to, `dir` and `dbfilename` in the redis config
+#[derive(Clone, Debug, PartialEq)]
+pub struct RdbConfig {
+    pub dir: PathBuf,
+    pub dbfilename: String,
+}
+
+impl Default for RdbConfig {
+    fn default() -> Self {
+        RdbConfig {
+            dir: PathBuf::from("."),
+            dbfilename: "dump.rdb".to_string(),
+        }
+    }
+}
+
+impl RdbConfig {
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(&self.dbfilename)
+    }
+}
+
+impl Store {
+    pub fn with_rdb_config(mut self, config: RdbConfig) -> Self {
+        self.rdb_config = config;
+        self
+    }
+
+    pub fn rdb_config(&self) -> &RdbConfig {
+        &self.rdb_config
+    }
+
+    /// Writes the dataset to the configured RDB file. Like redis it writes a temporary file
+    /// first and renames it, so a failed save leaves the previous dump intact.
+    pub fn save(&self) -> io::Result<()> {
+        let path = self.rdb_config.path();
+        let temporary = self
+            .rdb_config
+            .dir
+            .join(format!("temp-{}.rdb", std::process::id()));
+        let result =
+            fs::write(&temporary, self.dump()).and_then(|()| fs::rename(&temporary, &path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+        result
+    }
+
+    /// Loads the configured RDB file into the store, returns the number of keys loaded. A
+    /// missing file is an empty dataset.
+    pub fn load_rdb(&mut self) -> io::Result<usize> {
+        match fs::read(self.rdb_config.path()) {
+            Ok(data) => self.load(&data),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Inserts the keys of the RDB dump. Only database 0 is loaded, the store has no other, and
+    /// keys which expired in the meantime are left out. Only strings keep their expiry, the
+    /// store doesn't expire other kinds of value.
+    fn load(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut reader = RdbReader { data, position: 0 };
+        let header = reader.take(RDB_VERSION.len())?;
+        let version = header
+            .strip_prefix(b"REDIS")
+            .and_then(|version| std::str::from_utf8(version).ok())
+            .and_then(|version| version.parse::<u32>().ok())
+            .ok_or_else(|| corrupt("wrong signature"))?;
+        if version > 12 {
+            return Err(corrupt(format!(
+                "can't handle RDB format version {}",
+                version
+            )));
+        }
+
+        let now = now_millis().unwrap_or(0);
+        let mut database = 0;
+        let mut expires = None;
+        let mut loaded = 0;
+        loop {
+            match reader.byte()? {
+                OPCODE_EOF => break,
+                OPCODE_AUX => {
+                    reader.string()?;
+                    reader.string()?;
+                }
+                OPCODE_SELECTDB => database = reader.length()?,
+                OPCODE_RESIZEDB => {
+                    reader.length()?;
+                    reader.length()?;
+                }
+                OPCODE_EXPIRETIME_MS => expires = Some(reader.u64_le()? as u128),
+                OPCODE_EXPIRETIME => {
+                    let seconds = u32::from_le_bytes(reader.array()?);
+                    expires = Some(seconds as u128 * 1000);
+                }
+                OPCODE_FUNCTION => {
+                    reader.string()?;
+                }
+                OPCODE_SLOT_INFO => {
+                    for _ in 0..3 {
+                        reader.length()?;
+                    }
+                }
+                OPCODE_IDLE => {
+                    reader.length()?;
+                }
+                OPCODE_FREQ => {
+                    reader.byte()?;
+                }
+                value_type => {
+                    let key = reader.string()?;
+                    let value = reader.value(value_type, expires.take())?;
+                    let expired = matches!(&value, Value::String(string)
+                        if string.expires.is_some_and(|expiry| expiry < now));
+                    if database == 0 && !expired {
+                        self.insert_value(key, value);
+                        loaded += 1;
+                    }
+                }
+            }
+        }
+
+        // files before version 5 end without a checksum, with rdbchecksum off redis writes zero
+        let end = reader.position;
+        if version >= 5
+            && let Ok(checksum) = reader.u64_le()
+            && checksum != 0
+            && checksum != crc64(&data[..end])
+        {
+            return Err(corrupt("wrong checksum"));
+        }
+        Ok(loaded)
+    }
+
+    /// The dataset in the RDB format, all keys in database 0. Hash fields lose their expiry,
+    /// expired fields are left out.
+    fn dump(&self) -> Vec<u8> {
+        let now = now_millis().unwrap_or(0);
+        let mut out = Vec::from(RDB_VERSION);
+        let ctime = (now / 1000).to_string();
+        for (name, value) in [
+            ("redis-ver", "7.2.0"),
+            ("redis-bits", "64"),
+            ("ctime", ctime.as_str()),
+        ] {
+            out.push(OPCODE_AUX);
+            write_string(&mut out, name.as_bytes());
+            write_string(&mut out, value.as_bytes());
+        }
+
+        let entries: Vec<(&Bytes, &Value)> = self
+            .engine
+            .keys()
+            .filter_map(|key| Some((key, self.value(key)?)))
+            .collect();
+        let expiring = entries
+            .iter()
+            .filter(|(_, value)| matches!(value, Value::String(string) if string.expires.is_some()))
+            .count();
+        out.push(OPCODE_SELECTDB);
+        write_length(&mut out, 0);
+        out.push(OPCODE_RESIZEDB);
+        write_length(&mut out, entries.len());
+        write_length(&mut out, expiring);
+
+        for (key, value) in entries {
+            if let Value::String(string) = value
+                && let Some(expires) = string.expires
+            {
+                out.push(OPCODE_EXPIRETIME_MS);
+                out.extend_from_slice(&(expires as u64).to_le_bytes());
+            }
+            match value {
+                Value::String(string) => {
+                    out.push(TYPE_STRING);
+                    write_string(&mut out, key);
+                    write_string(&mut out, &string.value);
+                }
+                Value::List(list) => {
+                    out.push(TYPE_LIST);
+                    write_string(&mut out, key);
+                    write_length(&mut out, list.len());
+                    for element in list.iter() {
+                        write_string(&mut out, element);
+                    }
+                }
+                Value::Set(set) => {
+                    out.push(TYPE_SET);
+                    write_string(&mut out, key);
+                    write_length(&mut out, set.len());
+                    for member in set.iter() {
+                        write_string(&mut out, &member);
+                    }
+                }
+                Value::SortedSet(zset) => {
+                    out.push(TYPE_ZSET_2);
+                    write_string(&mut out, key);
+                    write_length(&mut out, zset.len());
+                    for (member, score) in zset.iter() {
+                        write_string(&mut out, member);
+                        out.extend_from_slice(&score.to_le_bytes());
+                    }
+                }
+                Value::Hash(hash) => {
+                    let fields: Vec<_> = hash
+                        .iter()
+                        .filter(|(_, field)| field.expires.is_none_or(|deadline| deadline > now))
+                        .collect();
+                    out.push(TYPE_HASH);
+                    write_string(&mut out, key);
+                    write_length(&mut out, fields.len());
+                    for (field, entry) in fields {
+                        write_string(&mut out, field);
+                        write_string(&mut out, &entry.value);
+                    }
+                }
+                Value::Stream(stream) => {
+                    out.push(TYPE_STREAM_LISTPACKS_3);
+                    write_string(&mut out, key);
+                    write_stream(&mut out, stream);
+                }
+            }
+        }
+
+        out.push(OPCODE_EOF);
+        let checksum = crc64(&out);
+        out.extend_from_slice(&checksum.to_le_bytes());
+        out
+    }
+}
+
+/// Lengths take one, two, five or nine bytes, the first two bits tell which
+fn write_length(out: &mut Vec<u8>, length: usize) {
+    if length < 1 << 6 {
+        out.push(length as u8);
+    } else if length < 1 << 14 {
+        out.extend_from_slice(&(0x4000 | length as u16).to_be_bytes());
+    } else if let Ok(length) = u32::try_from(length) {
+        out.push(0x80);
+        out.extend_from_slice(&length.to_be_bytes());
+    } else {
+        out.push(0x81);
+        out.extend_from_slice(&(length as u64).to_be_bytes());
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, string: &[u8]) {
+    write_length(out, string.len());
+    out.extend_from_slice(string);
+}
+
+/// CRC-64 with the Jones polynomial, reflected, the checksum redis ends the file with
+const CRC64_TABLE: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x95AC_9329_AC4B_C9B5
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+};
+
+fn crc64(data: &[u8]) -> u64 {
+    data.iter().fold(0, |crc, &byte| {
+        CRC64_TABLE[((crc ^ byte as u64) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+fn corrupt(message: impl Into<String>) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("bad RDB file: {}", message.into()),
+    )
+}
+
+/// Cursor over an RDB dump, every read fails with `InvalidData` if the dump ends early
+struct RdbReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> RdbReader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position.saturating_add(len))
+            .ok_or_else(|| corrupt("unexpected end of file"))?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64_le(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    /// A length, or the special encoding of a string with the `encoded` flag set
+    fn encoded_length(&mut self) -> io::Result<(usize, bool)> {
+        let first = self.byte()?;
+        let length = match first >> 6 {
+            0 => (first & 0x3F) as usize,
+            1 => u16::from_be_bytes([first & 0x3F, self.byte()?]) as usize,
+            3 => return Ok(((first & 0x3F) as usize, true)),
+            _ if first == 0x80 => u32::from_be_bytes(self.array()?) as usize,
+            _ if first == 0x81 => u64::from_be_bytes(self.array()?) as usize,
+            _ => return Err(corrupt(format!("unknown length encoding {:#x}", first))),
+        };
+        Ok((length, false))
+    }
+
+    fn length(&mut self) -> io::Result<usize> {
+        match self.encoded_length()? {
+            (length, false) => Ok(length),
+            (_, true) => Err(corrupt("expected a length, found an encoded string")),
+        }
+    }
+
+    /// A string, which redis may have written as an integer or compressed with LZF
+    fn string(&mut self) -> io::Result<Bytes> {
+        let (length, encoded) = self.encoded_length()?;
+        if !encoded {
+            return Ok(Bytes::copy_from_slice(self.take(length)?));
+        }
+        let integer = match length {
+            ENCODING_INT8 => self.byte()? as i8 as i64,
+            ENCODING_INT16 => i16::from_le_bytes(self.array()?) as i64,
+            ENCODING_INT32 => i32::from_le_bytes(self.array()?) as i64,
+            ENCODING_LZF => {
+                let compressed_len = self.length()?;
+                let len = self.length()?;
+                return lzf_decompress(self.take(compressed_len)?, len).map(Bytes::from);
+            }
+            _ => return Err(corrupt(format!("unknown string encoding {}", length))),
+        };
+        Ok(Bytes::from(integer.to_string()))
+    }
+
+    /// The score of a sorted set in the old format, a number in ascii with its length first
+    fn ascii_score(&mut self) -> io::Result<f64> {
+        match self.byte()? {
+            253 => Ok(f64::NAN),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            len => parse_score(self.take(len as usize)?),
+        }
+    }
+
+    fn value(&mut self, value_type: u8, expires: Option<u128>) -> io::Result<Value> {
+        let value = match value_type {
+            TYPE_STRING => Value::String(WithExpiry {
+                value: self.string()?,
+                expires,
+            }),
+            TYPE_LIST => {
+                let mut list = ListValue::default();
+                for _ in 0..self.length()? {
+                    list.push_back(self.string()?);
+                }
+                Value::List(list)
+            }
+            TYPE_SET => Value::Set(
+                (0..self.length()?)
+                    .map(|_| self.string())
+                    .collect::<io::Result<_>>()?,
+            ),
+            TYPE_ZSET | TYPE_ZSET_2 => {
+                let mut zset = ZSetValue::default();
+                for _ in 0..self.length()? {
+                    let member = self.string()?;
+                    let score = if value_type == TYPE_ZSET {
+                        self.ascii_score()?
+                    } else {
+                        f64::from_le_bytes(self.array()?)
+                    };
+                    zset.insert(member, score);
+                }
+                Value::SortedSet(zset)
+            }
+            TYPE_HASH => {
+                let mut hash = HashValue::default();
+                for _ in 0..self.length()? {
+                    let field = self.string()?;
+                    let value = self.string()?;
+                    hash.insert(
+                        field,
+                        WithExpiry {
+                            value,
+                            expires: None,
+                        },
+                    );
+                }
+                Value::Hash(hash)
+            }
+            TYPE_SET_INTSET => Value::Set(intset_members(&self.string()?)?.into_iter().collect()),
+            TYPE_SET_LISTPACK => {
+                Value::Set(listpack_entries(&self.string()?)?.into_iter().collect())
+            }
+            TYPE_HASH_LISTPACK => {
+                let entries = listpack_entries(&self.string()?)?;
+                Value::Hash(
+                    entries
+                        .chunks_exact(2)
+                        .map(|pair| {
+                            let value = WithExpiry {
+                                value: pair[1].clone(),
+                                expires: None,
+                            };
+                            (pair[0].clone(), value)
+                        })
+                        .collect(),
+                )
+            }
+            TYPE_ZSET_LISTPACK => {
+                let mut zset = ZSetValue::default();
+                for pair in listpack_entries(&self.string()?)?.chunks_exact(2) {
+                    zset.insert(pair[0].clone(), parse_score(&pair[1])?);
+                }
+                Value::SortedSet(zset)
+            }
+            TYPE_LIST_QUICKLIST_2 => {
+                let mut list = ListValue::default();
+                for _ in 0..self.length()? {
+                    let container = self.length()?;
+                    let node = self.string()?;
+                    if container == QUICKLIST_NODE_PLAIN {
+                        list.push_back(node);
+                    } else {
+                        for element in listpack_entries(&node)? {
+                            list.push_back(element);
+                        }
+                    }
+                }
+                Value::List(list)
+            }
+            TYPE_STREAM_LISTPACKS | TYPE_STREAM_LISTPACKS_2 | TYPE_STREAM_LISTPACKS_3 => {
+                Value::Stream(self.stream(value_type)?)
+            }
+            _ => return Err(corrupt(format!("unsupported value type {}", value_type))),
+        };
+        Ok(value)
+    }
+
+    fn stream_id(&mut self) -> io::Result<StreamId> {
+        Ok(StreamId {
+            ms: self.length()? as u128,
+            seq: self.length()? as u128,
+        })
+    }
+
+    /// A stream in any of the listpack formats, the older ones lack part of the bookkeeping
+    fn stream(&mut self, value_type: u8) -> io::Result<StreamValue> {
+        let mut stream = StreamValue::default();
+        for _ in 0..self.length()? {
+            let master_id = stream_id_from_key(&self.string()?)?;
+            let listpack = listpack_entries(&self.string()?)?;
+            let mut items = listpack.into_iter();
+            let count = next_integer(&mut items)?;
+            let deleted = next_integer(&mut items)?;
+            let master_fields = (0..next_integer(&mut items)?)
+                .map(|_| next_item(&mut items))
+                .collect::<io::Result<Vec<_>>>()?;
+            next_integer(&mut items)?; // the end of the master entry
+            for _ in 0..count.saturating_add(deleted) {
+                let flags = next_integer(&mut items)?;
+                let ms = master_id.ms as i128 + next_integer(&mut items)? as i128;
+                let seq = master_id.seq as i128 + next_integer(&mut items)? as i128;
+                let entry: StreamEntry = if flags & ITEM_FLAG_SAMEFIELDS != 0 {
+                    master_fields
+                        .iter()
+                        .map(|field| Ok((field.clone(), next_item(&mut items)?)))
+                        .collect::<io::Result<_>>()?
+                } else {
+                    (0..next_integer(&mut items)?)
+                        .map(|_| Ok((next_item(&mut items)?, next_item(&mut items)?)))
+                        .collect::<io::Result<_>>()?
+                };
+                next_integer(&mut items)?; // the number of items of the entry
+                let id = StreamId {
+                    ms: u128::try_from(ms).map_err(|_| corrupt("negative stream id"))?,
+                    seq: u128::try_from(seq).map_err(|_| corrupt("negative stream id"))?,
+                };
+                if id <= stream.last_id() {
+                    return Err(corrupt("stream entries out of order"));
+                }
+                if flags & ITEM_FLAG_DELETED == 0 {
+                    stream.insert(id, entry);
+                }
+            }
+        }
+
+        let length = self.length()?;
+        let last_id = self.stream_id()?;
+        let (max_deleted_id, entries_added) = if value_type == TYPE_STREAM_LISTPACKS {
+            (StreamId::default(), length as u64)
+        } else {
+            self.stream_id()?; // the first id, known from the entries
+            (self.stream_id()?, self.length()? as u64)
+        };
+        stream.set_last_id(last_id, Some(entries_added), Some(max_deleted_id));
+
+        for _ in 0..self.length()? {
+            let name = self.string()?;
+            let last_delivered_id = self.stream_id()?;
+            let entries_read = if value_type == TYPE_STREAM_LISTPACKS {
+                None
+            } else {
+                Some(self.length()? as u64).filter(|&read| read != u64::MAX)
+            };
+            if !stream.create_group(&name, last_delivered_id, entries_read) {
+                return Err(corrupt("duplicated consumer group"));
+            }
+            let group = stream.group_mut(&name).expect("the group was just created");
+            group.entries_read = entries_read;
+            for _ in 0..self.length()? {
+                let id = stream_id_from_key(self.take(16)?)?;
+                let pending = PendingEntry {
+                    consumer: Bytes::new(),
+                    delivery_time: self.u64_le()? as u128,
+                    delivery_count: self.length()? as u64,
+                };
+                group.pending.insert(id, pending);
+            }
+            for _ in 0..self.length()? {
+                let consumer = self.string()?;
+                let seen_time = self.u64_le()? as u128;
+                // before the active time was written, it was the seen time
+                let active_time = if value_type == TYPE_STREAM_LISTPACKS_3 {
+                    Some(self.u64_le()?)
+                        .filter(|&time| time != u64::MAX)
+                        .map(u128::from)
+                } else {
+                    Some(seen_time)
+                };
+                let mut pending = BTreeSet::new();
+                for _ in 0..self.length()? {
+                    let id = stream_id_from_key(self.take(16)?)?;
+                    let entry = group
+                        .pending
+                        .get_mut(&id)
+                        .ok_or_else(|| corrupt("pending entry missing from the group"))?;
+                    entry.consumer = consumer.clone();
+                    pending.insert(id);
+                }
+                let consumer_state = Consumer {
+                    seen_time,
+                    active_time,
+                    pending,
+                };
+                group.consumers.insert(consumer, consumer_state);
+            }
+        }
+        Ok(stream)
+    }
+}
+
+fn parse_score(score: &[u8]) -> io::Result<f64> {
+    std::str::from_utf8(score)
+        .ok()
+        .and_then(|score| score.parse().ok())
+        .ok_or_else(|| corrupt("invalid score"))
+}
+
+/// Decompresses an LZF block: runs of literal bytes and back references into the output
+fn lzf_decompress(input: &[u8], len: usize) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(len);
+    let mut bytes = input.iter().map(|&byte| byte as usize);
+    let mut next = || bytes.next().ok_or_else(|| corrupt("truncated LZF data"));
+    while out.len() < len {
+        let control = next()?;
+        if control < 32 {
+            for _ in 0..=control {
+                out.push(next()? as u8);
+            }
+            continue;
+        }
+        let mut run = control >> 5;
+        if run == 7 {
+            run += next()?;
+        }
+        let distance = ((control & 0x1F) << 8) + next()? + 1;
+        let start = out
+            .len()
+            .checked_sub(distance)
+            .ok_or_else(|| corrupt("LZF reference before the start"))?;
+        for offset in 0..run + 2 {
+            out.push(out[start + offset]);
+        }
+    }
+    if out.len() != len {
+        return Err(corrupt("LZF data of the wrong length"));
+    }
+    Ok(out)
+}
+
+/// Members of an intset: the width of the integers, their count and the integers
+fn intset_members(blob: &[u8]) -> io::Result<Vec<Bytes>> {
+    let mut reader = RdbReader {
+        data: blob,
+        position: 0,
+    };
+    let width = u32::from_le_bytes(reader.array()?) as usize;
+    let count = u32::from_le_bytes(reader.array()?);
+    (0..count)
+        .map(|_| {
+            let integer = match width {
+                2 => i16::from_le_bytes(reader.array()?) as i64,
+                4 => i32::from_le_bytes(reader.array()?) as i64,
+                8 => i64::from_le_bytes(reader.array()?),
+                _ => return Err(corrupt(format!("intset of width {}", width))),
+            };
+            Ok(Bytes::from(integer.to_string()))
+        })
+        .collect()
+}
+
+/// Entries of a listpack. Each entry is a string or an integer, the encoding in its first
+/// byte, followed by its size for iterating backwards which is skipped here.
+fn listpack_entries(blob: &[u8]) -> io::Result<Vec<Bytes>> {
+    let mut reader = RdbReader {
+        data: blob,
+        position: 6, // total bytes and number of entries
+    };
+    let mut entries = Vec::new();
+    loop {
+        let start = reader.position;
+        let first = reader.byte()?;
+        let entry = match first {
+            0xFF => return Ok(entries),
+            _ if first & 0x80 == 0 => Bytes::from((first as i64).to_string()),
+            _ if first & 0xC0 == 0x80 => {
+                Bytes::copy_from_slice(reader.take((first & 0x3F) as usize)?)
+            }
+            _ if first & 0xE0 == 0xC0 => {
+                let integer = ((first as i64 & 0x1F) << 8) | reader.byte()? as i64;
+                // 13 bit two's complement
+                Bytes::from(((integer << 51) >> 51).to_string())
+            }
+            _ if first & 0xF0 == 0xE0 => {
+                let len = ((first as usize & 0x0F) << 8) | reader.byte()? as usize;
+                Bytes::copy_from_slice(reader.take(len)?)
+            }
+            0xF0 => {
+                let len = u32::from_le_bytes(reader.array()?) as usize;
+                Bytes::copy_from_slice(reader.take(len)?)
+            }
+            0xF1 => Bytes::from(i16::from_le_bytes(reader.array()?).to_string()),
+            0xF2 => {
+                let [low, middle, high] = reader.array()?;
+                Bytes::from((i32::from_le_bytes([0, low, middle, high]) >> 8).to_string())
+            }
+            0xF3 => Bytes::from(i32::from_le_bytes(reader.array()?).to_string()),
+            0xF4 => Bytes::from(i64::from_le_bytes(reader.array()?).to_string()),
+            _ => return Err(corrupt(format!("unknown listpack encoding {:#x}", first))),
+        };
+        reader.take(backlen_size(reader.position - start))?;
+        entries.push(entry);
+    }
+}
+
+/// A stream the way redis 7 writes it: the entries in listpacks of up to a node's worth of
+/// entries, keyed by the id of their first entry, then the bookkeeping of the stream and its
+/// consumer groups
+fn write_stream(out: &mut Vec<u8>, stream: &StreamValue) {
+    let entries: Vec<_> = stream.range(Unbounded, Unbounded).collect();
+    write_length(out, entries.len().div_ceil(NODE_MAX_ENTRIES));
+    for node in entries.chunks(NODE_MAX_ENTRIES) {
+        let (master_id, master_entry) = node[0];
+        // the fields of the first entry are stored once, entries with the same fields omit them
+        let master_fields: Vec<&Bytes> = master_entry.keys().collect();
+        let mut listpack = Listpack::default();
+        listpack.push_integer(node.len() as i64);
+        listpack.push_integer(0); // deleted entries
+        listpack.push_integer(master_fields.len() as i64);
+        for field in &master_fields {
+            listpack.push_string(field);
+        }
+        listpack.push_integer(0); // the end of the master entry
+        for (id, entry) in node {
+            let same_fields = entry.len() == master_fields.len()
+                && master_fields.iter().all(|field| entry.contains_key(*field));
+            listpack.push_integer(if same_fields {
+                ITEM_FLAG_SAMEFIELDS
+            } else {
+                ITEM_FLAG_NONE
+            });
+            listpack.push_integer((id.ms - master_id.ms) as i64);
+            listpack.push_integer(id.seq as i64 - master_id.seq as i64);
+            if same_fields {
+                for field in &master_fields {
+                    listpack.push_string(&entry[*field]);
+                }
+                listpack.push_integer(master_fields.len() as i64 + 3);
+            } else {
+                listpack.push_integer(entry.len() as i64);
+                for (field, value) in entry.iter() {
+                    listpack.push_string(field);
+                    listpack.push_string(value);
+                }
+                listpack.push_integer(entry.len() as i64 * 2 + 4);
+            }
+        }
+        write_string(out, &stream_id_key(*master_id));
+        write_string(out, &listpack.finish());
+    }
+
+    write_length(out, stream.len());
+    write_stream_id(out, stream.last_id());
+    let first_id = entries.first().map_or(StreamId::default(), |(id, _)| **id);
+    write_stream_id(out, first_id);
+    write_stream_id(out, stream.max_deleted_id());
+    write_length(out, stream.entries_added() as usize);
+    write_length(out, stream.groups().len());
+    for (name, group) in stream.groups() {
+        write_string(out, name);
+        write_stream_id(out, group.last_delivered_id);
+        // redis writes -1 if the entries read are unknown
+        write_length(
+            out,
+            group.entries_read.map_or(usize::MAX, |read| read as usize),
+        );
+        write_length(out, group.pending.len());
+        for (id, pending) in &group.pending {
+            out.extend_from_slice(&stream_id_key(*id));
+            out.extend_from_slice(&(pending.delivery_time as u64).to_le_bytes());
+            write_length(out, pending.delivery_count as usize);
+        }
+        write_length(out, group.consumers.len());
+        for (name, consumer) in &group.consumers {
+            write_string(out, name);
+            out.extend_from_slice(&(consumer.seen_time as u64).to_le_bytes());
+            let active_time = consumer.active_time.map_or(u64::MAX, |time| time as u64);
+            out.extend_from_slice(&active_time.to_le_bytes());
+            write_length(out, consumer.pending.len());
+            for id in &consumer.pending {
+                out.extend_from_slice(&stream_id_key(*id));
+            }
+        }
+    }
+}
+
+fn write_stream_id(out: &mut Vec<u8>, id: StreamId) {
+    write_length(out, id.ms as usize);
+    write_length(out, id.seq as usize);
+}
+
+/// The id as the 16 bytes redis keys stream nodes and pending entries with, big endian so
+/// they sort like the ids
+fn stream_id_key(id: StreamId) -> [u8; 16] {
+    let mut key = [0; 16];
+    key[..8].copy_from_slice(&(id.ms as u64).to_be_bytes());
+    key[8..].copy_from_slice(&(id.seq as u64).to_be_bytes());
+    key
+}
+
+fn stream_id_from_key(key: &[u8]) -> io::Result<StreamId> {
+    let key: [u8; 16] = key
+        .try_into()
+        .map_err(|_| corrupt("stream id of the wrong size"))?;
+    Ok(StreamId {
+        ms: u64::from_be_bytes(key[..8].try_into().expect("8 bytes")) as u128,
+        seq: u64::from_be_bytes(key[8..].try_into().expect("8 bytes")) as u128,
+    })
+}
+
+/// Builds a listpack, the compact list of strings and integers redis keeps stream entries in
+#[derive(Default)]
+struct Listpack {
+    entries: Vec<u8>,
+    len: usize,
+}
+
+impl Listpack {
+    fn push_string(&mut self, string: &[u8]) {
+        let mut entry = Vec::with_capacity(string.len() + 5);
+        match string.len() {
+            len @ 0..64 => entry.push(0x80 | len as u8),
+            len @ 64..4096 => entry.extend_from_slice(&[0xE0 | (len >> 8) as u8, len as u8]),
+            len => {
+                entry.push(0xF0);
+                entry.extend_from_slice(&(len as u32).to_le_bytes());
+            }
+        }
+        entry.extend_from_slice(string);
+        self.push(entry);
+    }
+
+    /// Integers take as few bytes as their value allows
+    fn push_integer(&mut self, integer: i64) {
+        let entry = match integer {
+            0..=127 => vec![integer as u8],
+            -4096..=4095 => {
+                let bits = integer as u16 & 0x1FFF;
+                vec![0xC0 | (bits >> 8) as u8, bits as u8]
+            }
+            -32768..=32767 => [&[0xF1][..], &(integer as i16).to_le_bytes()].concat(),
+            -8388608..=8388607 => [&[0xF2][..], &(integer as i32).to_le_bytes()[..3]].concat(),
+            _ if i32::try_from(integer).is_ok() => {
+                [&[0xF3][..], &(integer as i32).to_le_bytes()].concat()
+            }
+            _ => [&[0xF4][..], &integer.to_le_bytes()].concat(),
+        };
+        self.push(entry);
+    }
+
+    /// Appends the entry followed by its size, 7 bits per byte with the most significant first
+    fn push(&mut self, mut entry: Vec<u8>) {
+        let len = entry.len();
+        let backlen = backlen_size(len);
+        for byte in 0..backlen {
+            let bits = (len >> (7 * (backlen - 1 - byte))) as u8 & 0x7F;
+            entry.push(if byte == 0 { bits } else { bits | 0x80 });
+        }
+        self.entries.extend_from_slice(&entry);
+        self.len += 1;
+    }
+
+    /// The total size and the number of entries (65535 if unknown) come first
+    fn finish(self) -> Vec<u8> {
+        let total = 6 + self.entries.len() + 1;
+        let mut listpack = Vec::with_capacity(total);
+        listpack.extend_from_slice(&(total as u32).to_le_bytes());
+        listpack.extend_from_slice(&(self.len.min(u16::MAX as usize) as u16).to_le_bytes());
+        listpack.extend_from_slice(&self.entries);
+        listpack.push(0xFF);
+        listpack
+    }
+}
+
+/// How many bytes the size of a listpack entry takes after the entry
+fn backlen_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..16383 => 2,
+        16383..2097151 => 3,
+        2097151..268435455 => 4,
+        _ => 5,
+    }
+}
+
+fn next_item(items: &mut impl Iterator<Item = Bytes>) -> io::Result<Bytes> {
+    items
+        .next()
+        .ok_or_else(|| corrupt("stream listpack ends early"))
+}
+
+fn next_integer(items: &mut impl Iterator<Item = Bytes>) -> io::Result<i64> {
+    let item = next_item(items)?;
+    std::str::from_utf8(&item)
+        .ok()
+        .and_then(|item| item.parse().ok())
+        .ok_or_else(|| corrupt("expected an integer in the stream listpack"))
+}
+
+#[test]
+fn test_crc64_matches_redis() {
+    // the check value of redis' crc64 test
+    assert_eq!(crc64(b"123456789"), 0xe9c6d914c4b8d9ca);
+}
+
+#[test]
+fn test_dump_writes_keys_and_values() {
+    let mut store = Store::new();
+    store
+        .set_with_expiry(Bytes::from("greeting"), Bytes::from("hello"), None)
+        .unwrap();
+    store
+        .rpush(
+            Bytes::from("list"),
+            vec![Bytes::from("a"), Bytes::from("b")],
+        )
+        .unwrap();
+
+    let dump = store.dump();
+    assert!(dump.starts_with(b"REDIS0011"));
+    let body = &dump[..dump.len() - 8];
+    assert_eq!(body.last(), Some(&OPCODE_EOF));
+    assert_eq!(dump[dump.len() - 8..], crc64(body).to_le_bytes());
+
+    let mut string = vec![TYPE_STRING];
+    write_string(&mut string, b"greeting");
+    write_string(&mut string, b"hello");
+    assert!(body.windows(string.len()).any(|window| window == string));
+    let list = b"\x01\x04list\x02\x01a\x01b";
+    assert!(body.windows(list.len()).any(|window| window == list));
+    assert!(
+        body.windows(3)
+            .any(|window| window == [OPCODE_RESIZEDB, 2, 0])
+    );
+}
+
+#[test]
+fn test_write_length() {
+    let encoded = |length| {
+        let mut out = Vec::new();
+        write_length(&mut out, length);
+        out
+    };
+    assert_eq!(encoded(10), vec![10]);
+    assert_eq!(encoded(700), vec![0x42, 0xBC]);
+    assert_eq!(encoded(17000), vec![0x80, 0, 0, 0x42, 0x68]);
+}
+
+#[test]
+fn test_load_reads_back_a_dump() {
+    use super::ZAddOptions;
+
+    let mut store = Store::new();
+    store
+        .set_with_expiry(Bytes::from("session"), Bytes::from("abc"), Some(60_000))
+        .unwrap();
+    store
+        .rpush(
+            Bytes::from("list"),
+            vec![Bytes::from("a"), Bytes::from("b")],
+        )
+        .unwrap();
+    store
+        .hset(
+            &Bytes::from("hash"),
+            vec![(Bytes::from("f"), Bytes::from("v"))],
+        )
+        .unwrap();
+    store
+        .sadd(&Bytes::from("set"), vec![Bytes::from("member")])
+        .unwrap();
+    store
+        .zadd(
+            &Bytes::from("zset"),
+            vec![(1.5, Bytes::from("m"))],
+            ZAddOptions::default(),
+        )
+        .unwrap();
+
+    let mut loaded = Store::new();
+    assert_eq!(loaded.load(&store.dump()).unwrap(), 5);
+    assert_eq!(loaded.get(Bytes::from("session")).unwrap(), "abc");
+    assert!(
+        loaded
+            .string(&Bytes::from("session"))
+            .unwrap()
+            .unwrap()
+            .expires
+            .is_some()
+    );
+    assert_eq!(
+        loaded.lrange(Bytes::from("list"), 0, -1).unwrap(),
+        vec![Bytes::from("a"), Bytes::from("b")]
+    );
+    assert_eq!(
+        loaded
+            .hget(&Bytes::from("hash"), &Bytes::from("f"))
+            .unwrap(),
+        Some(Bytes::from("v"))
+    );
+    assert_eq!(
+        loaded.smembers(&Bytes::from("set")).unwrap(),
+        vec![Bytes::from("member")]
+    );
+    assert_eq!(
+        loaded
+            .zscore(&Bytes::from("zset"), &Bytes::from("m"))
+            .unwrap(),
+        Some(1.5)
+    );
+
+    let mut corrupted = store.dump();
+    corrupted[12] ^= 1;
+    assert!(Store::new().load(&corrupted).is_err());
+}
+
+#[test]
+fn test_load_reads_the_encodings_redis_writes() {
+    use super::StoreError;
+
+    let mut dump = b"REDIS0011".to_vec();
+    // redis-bits as an 8 bit integer
+    dump.extend_from_slice(b"\xFA\x0Aredis-bits\xC0\x40");
+    dump.extend_from_slice(b"\xFE\x00\xFB\x04\x01");
+    // expired a long time ago
+    dump.extend_from_slice(b"\xFC\xE8\x03\x00\x00\x00\x00\x00\x00\x00\x03old\x01v");
+    // an integer as a 16 bit integer, ten times `a` compressed with LZF
+    dump.extend_from_slice(b"\x00\x06number\xC1\x39\x30");
+    dump.extend_from_slice(b"\x00\x04long\xC3\x05\x0A\x00a\xE0\x00\x00");
+    // a sorted set as a listpack with `m` scored 5, a set as an intset of 1 and -3
+    dump.extend_from_slice(b"\x11\x04zset\x0C\x0C\x00\x00\x00\x02\x00\x81m\x02\x05\x01\xFF");
+    dump.extend_from_slice(b"\x0B\x03set\x0C\x02\x00\x00\x00\x02\x00\x00\x00\x01\x00\xFD\xFF");
+    // without a checksum
+    dump.extend_from_slice(b"\xFF\x00\x00\x00\x00\x00\x00\x00\x00");
+
+    let mut store = Store::new();
+    assert_eq!(store.load(&dump).unwrap(), 4);
+    assert!(matches!(
+        store.get(Bytes::from("old")),
+        Err(StoreError::KeyNotFound)
+    ));
+    assert_eq!(store.get(Bytes::from("number")).unwrap(), "12345");
+    assert_eq!(store.get(Bytes::from("long")).unwrap(), "aaaaaaaaaa");
+    assert_eq!(
+        store
+            .zscore(&Bytes::from("zset"), &Bytes::from("m"))
+            .unwrap(),
+        Some(5.0)
+    );
+    let mut members = store.smembers(&Bytes::from("set")).unwrap();
+    members.sort();
+    assert_eq!(members, vec![Bytes::from("-3"), Bytes::from("1")]);
+}
+
+#[test]
+fn test_load_reads_back_streams() {
+    use crate::parser::RedisType;
+
+    let mut store = Store::new();
+    let key = Bytes::from("stream");
+    let group = Bytes::from("group");
+    // more entries than fit a node, some with fields other than the first entry's
+    for seq in 1..=150 {
+        let mut fields = vec![
+            RedisType::BulkString("field".into()),
+            RedisType::BulkString(Bytes::from(seq.to_string())),
+        ];
+        if seq % 3 == 0 {
+            fields.push(RedisType::BulkString("other".into()));
+            fields.push(RedisType::BulkString(Bytes::from("x".repeat(5000))));
+        }
+        store.xadd(&key, Some(seq), Some(1), &fields).unwrap();
+    }
+    store.xdel(&key, &[StreamId { ms: 1, seq: 150 }]).unwrap();
+    store
+        .xgroup_create(&key, &group, StreamId::default(), None, false)
+        .unwrap();
+    store
+        .xreadgroup(&key, &group, &Bytes::from("alice"), Some(2), false)
+        .unwrap();
+
+    let mut loaded = Store::new();
+    assert_eq!(loaded.load(&store.dump()).unwrap(), 1);
+    assert_eq!(
+        loaded.xinfo_stream(&key).unwrap(),
+        store.xinfo_stream(&key).unwrap()
+    );
+    assert_eq!(
+        loaded.xrange(&key, Unbounded, Unbounded, None).unwrap(),
+        store.xrange(&key, Unbounded, Unbounded, None).unwrap()
+    );
+    assert_eq!(
+        loaded.xinfo_groups(&key).unwrap(),
+        store.xinfo_groups(&key).unwrap()
+    );
+    assert_eq!(
+        loaded.xinfo_consumers(&key, &group).unwrap(),
+        store.xinfo_consumers(&key, &group).unwrap()
+    );
+    assert_eq!(
+        loaded.xpending_summary(&key, &group).unwrap(),
+        store.xpending_summary(&key, &group).unwrap()
+    );
+}
+
+#[test]
+fn test_listpack_round_trips_integers_and_strings() {
+    let integers = [
+        0,
+        127,
+        128,
+        -1,
+        4095,
+        -4096,
+        32767,
+        -32768,
+        8388607,
+        i32::MIN as i64,
+        i64::MAX,
+    ];
+    let strings = [String::new(), "a".repeat(70), "b".repeat(5000)];
+    let mut listpack = Listpack::default();
+    for integer in integers {
+        listpack.push_integer(integer);
+    }
+    for string in &strings {
+        listpack.push_string(string.as_bytes());
+    }
+
+    let entries = listpack_entries(&listpack.finish()).unwrap();
+    let expected: Vec<Bytes> = integers
+        .iter()
+        .map(|integer| Bytes::from(integer.to_string()))
+        .chain(strings.iter().map(|string| Bytes::from(string.clone())))
+        .collect();
+    assert_eq!(entries, expected);
+}
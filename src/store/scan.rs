@@ -0,0 +1,233 @@
+use std::{
+    collections::BTreeSet,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use bytes::Bytes;
+
+use super::Store;
+
+/// Position of an element in the cursor order. The default hasher is seeded with fixed keys,
+/// so the same element always lands on the same position between calls.
+fn cursor_position(element: &Bytes) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    element.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Keys or the members of a collection in cursor order, so a scan resumes at the cursor instead
+/// of going over all of them on every call.
+///
+/// Elements are visited in the order of their hash, the cursor is the position of the first
+/// element of the next page (or 0 once the iteration is complete). Elements which are present
+/// during the whole iteration are therefore returned at least once, even if other elements are
+/// added or removed between the calls.
+#[derive(Clone, Debug, Default)]
+pub struct ScanIndex {
+    elements: BTreeSet<(u64, Bytes)>,
+}
+
+impl ScanIndex {
+    pub(super) fn insert(&mut self, element: &Bytes) {
+        self.elements
+            .insert((cursor_position(element), element.clone()));
+    }
+
+    pub(super) fn remove(&mut self, element: &Bytes) {
+        self.elements
+            .remove(&(cursor_position(element), element.clone()));
+    }
+
+    /// The next `count` elements from the cursor on, along with the cursor of the page after
+    pub(super) fn page(&self, cursor: u64, count: usize) -> (u64, Vec<&Bytes>) {
+        let mut page = Vec::new();
+        let mut last_position = None;
+        for (position, element) in self.elements.range((cursor, Bytes::new())..) {
+            // never split elements with the same position across pages, we could not resume between them
+            if page.len() >= count.max(1) && last_position != Some(*position) {
+                return (*position, page);
+            }
+            last_position = Some(*position);
+            page.push(element);
+        }
+        (0, page)
+    }
+}
+
+impl Store {
+    /// Iterates over the keyspace, expired keys are skipped. Visits about `count` keys per
+    /// call, whatever the size of the keyspace.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<Bytes>) {
+        let (next_cursor, page) = self.engine.scan(cursor, count);
+        let keys = page
+            .into_iter()
+            .filter(|key| !self.is_expired(key))
+            .cloned()
+            .collect();
+        (next_cursor, keys)
+    }
+}
+
+#[test]
+fn test_scan_index_visits_every_element_once() {
+    let elements: Vec<Bytes> = (0..100)
+        .map(|i| Bytes::from(format!("key:{}", i)))
+        .collect();
+    let mut index = ScanIndex::default();
+    for element in &elements {
+        index.insert(element);
+    }
+
+    let mut seen = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let (next_cursor, page) = index.page(cursor, 7);
+        assert!(page.len() <= 7);
+        seen.extend(page.into_iter().cloned());
+        if next_cursor == 0 {
+            break;
+        }
+        cursor = next_cursor;
+    }
+
+    seen.sort();
+    let mut expected = elements.clone();
+    expected.sort();
+    assert_eq!(seen, expected);
+}
+
+#[test]
+fn test_scan_resumes_at_the_cursor_while_keys_change() {
+    let mut store = Store::new();
+    let stable: Vec<Bytes> = (0..200)
+        .map(|i| Bytes::from(format!("stable:{}", i)))
+        .collect();
+    for key in &stable {
+        store
+            .set_with_expiry(key.clone(), "v".into(), None)
+            .unwrap();
+    }
+    store
+        .set_with_expiry("removed".into(), "v".into(), None)
+        .unwrap();
+    store.remove_key(&Bytes::from("removed"));
+
+    let mut seen = Vec::new();
+    let mut cursor = 0;
+    for round in 0.. {
+        let (next_cursor, page) = store.scan(cursor, 10);
+        assert!(page.len() <= 10);
+        seen.extend(page);
+        // keys come and go between the calls
+        let churn = |round| Bytes::from(format!("churn:{}", round));
+        store
+            .set_with_expiry(churn(round), "v".into(), None)
+            .unwrap();
+        store.remove_key(&churn(round.max(1) - 1));
+        if next_cursor == 0 {
+            break;
+        }
+        cursor = next_cursor;
+    }
+
+    assert!(!seen.contains(&Bytes::from("removed")));
+    seen.retain(|key| key.starts_with(b"stable:"));
+    seen.sort();
+    let mut expected = stable.clone();
+    expected.sort();
+    assert_eq!(seen, expected);
+}
+
+#[test]
+fn test_collection_scans_resume_at_the_cursor() {
+    use super::ZAddOptions;
+
+    /// Every element a scan returns, the store may change between the pages
+    fn scan_all(
+        store: &mut Store,
+        mut page: impl FnMut(&mut Store, u64) -> (u64, Vec<Bytes>),
+        mut change: impl FnMut(&mut Store, usize),
+    ) -> Vec<Bytes> {
+        let mut seen = Vec::new();
+        let mut cursor = 0;
+        for round in 0.. {
+            let (next_cursor, elements) = page(store, cursor);
+            assert!(elements.len() <= 10);
+            seen.extend(elements);
+            change(store, round);
+            if next_cursor == 0 {
+                break;
+            }
+            cursor = next_cursor;
+        }
+        seen.retain(|element| element.starts_with(b"member:"));
+        seen.sort();
+        seen.dedup();
+        seen
+    }
+
+    let mut store = Store::new();
+    let (set, zset, hash) = (Bytes::from("set"), Bytes::from("zset"), Bytes::from("hash"));
+    let mut members: Vec<Bytes> = (0..300)
+        .map(|i| Bytes::from(format!("member:{}", i)))
+        .collect();
+    store.sadd(&set, members.clone()).unwrap();
+    let scored = members.iter().map(|member| (1.0, member.clone())).collect();
+    store.zadd(&zset, scored, ZAddOptions::default()).unwrap();
+    let pairs = members.iter().map(|m| (m.clone(), m.clone())).collect();
+    store.hset(&hash, pairs).unwrap();
+    members.sort();
+
+    // members come and go between the calls
+    let churn = |round: usize| Bytes::from(format!("churn:{}", round));
+    let seen = scan_all(
+        &mut store,
+        |store, cursor| store.sscan(&set, cursor, 10).unwrap(),
+        |store, round| {
+            store.sadd(&set, vec![churn(round)]).unwrap();
+            store.srem(&set, &[churn(round.max(1) - 1)]).unwrap();
+        },
+    );
+    assert_eq!(seen, members);
+    let seen = scan_all(
+        &mut store,
+        |store, cursor| {
+            let (next_cursor, page) = store.zscan(&zset, cursor, 10).unwrap();
+            (
+                next_cursor,
+                page.into_iter().map(|(member, _)| member).collect(),
+            )
+        },
+        |store, round| {
+            store.zrem(&zset, &[churn(round)]).unwrap();
+        },
+    );
+    assert_eq!(seen, members);
+    let seen = scan_all(
+        &mut store,
+        |store, cursor| {
+            let (next_cursor, page) = store.hscan(&hash, cursor, 10).unwrap();
+            (
+                next_cursor,
+                page.into_iter().map(|(field, _)| field).collect(),
+            )
+        },
+        |store, round| {
+            store
+                .hset(&hash, vec![(churn(round), churn(round))])
+                .unwrap();
+        },
+    );
+    assert_eq!(seen, members);
+
+    // like a listpack in redis, an intset is small enough for one reply
+    let integers = Bytes::from("integers");
+    store
+        .sadd(
+            &integers,
+            (0..100).map(|i| Bytes::from(i.to_string())).collect(),
+        )
+        .unwrap();
+    let (cursor, page) = store.sscan(&integers, 0, 10).unwrap();
+    assert_eq!((cursor, page.len()), (0, 100));
+}
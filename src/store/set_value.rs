@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use rand::Rng;
+
+use super::scan::ScanIndex;
+
+/// Sets with more members than this are never kept as intset, mirrors `set-max-intset-entries`
+const MAX_INTSET_ENTRIES: usize = 512;
+
+/// Members of a set.
+///
+/// Sets whose members are all integers start out as a sorted vector of integers ("intset"),
+/// which needs a fraction of the memory and is searched by bisection. Adding a member which is
+/// not an integer, or growing beyond `MAX_INTSET_ENTRIES`, converts the set to a hash table for
+/// good. Both encodings support picking a random member in O(1).
+pub struct SetValue {
+    encoding: Encoding,
+}
+
+enum Encoding {
+    IntSet(Vec<i64>),
+    HashTable(HashTable),
+}
+
+/// Members live in a dense vector, a map points from each member to its position and removals
+/// swap the last member into the freed slot. The scan index keeps them in cursor order for SSCAN.
+#[derive(Default)]
+struct HashTable {
+    members: Vec<Bytes>,
+    positions: HashMap<Bytes, usize>,
+    scan_index: ScanIndex,
+}
+
+impl Default for SetValue {
+    fn default() -> Self {
+        SetValue {
+            encoding: Encoding::IntSet(Vec::new()),
+        }
+    }
+}
+
+/// The integer a member represents, only canonical representations count ("01" or "+1" are
+/// not integers), so converting back yields the exact same member
+fn as_integer(member: &Bytes) -> Option<i64> {
+    let integer: i64 = std::str::from_utf8(member).ok()?.parse().ok()?;
+    (integer.to_string().as_bytes() == member.as_ref()).then_some(integer)
+}
+
+fn integer_member(integer: i64) -> Bytes {
+    Bytes::from(integer.to_string())
+}
+
+impl SetValue {
+    /// Name of the encoding as reported by OBJECT ENCODING
+    pub fn encoding(&self) -> &'static str {
+        match self.encoding {
+            Encoding::IntSet(_) => "intset",
+            Encoding::HashTable(_) => "hashtable",
+        }
+    }
+
+    /// Returns false if the member was already part of the set
+    pub fn insert(&mut self, member: Bytes) -> bool {
+        if let Encoding::IntSet(integers) = &mut self.encoding {
+            match as_integer(&member) {
+                Some(integer) => match integers.binary_search(&integer) {
+                    Ok(_) => return false,
+                    Err(position) if integers.len() < MAX_INTSET_ENTRIES => {
+                        integers.insert(position, integer);
+                        return true;
+                    }
+                    Err(_) => self.convert_to_hash_table(),
+                },
+                None => self.convert_to_hash_table(),
+            }
+        }
+        match &mut self.encoding {
+            Encoding::HashTable(table) => table.insert(member),
+            Encoding::IntSet(_) => unreachable!("intset was converted above"),
+        }
+    }
+
+    /// Returns false if the member was not part of the set
+    pub fn remove(&mut self, member: &Bytes) -> bool {
+        match &mut self.encoding {
+            Encoding::IntSet(integers) => {
+                match as_integer(member).map(|integer| integers.binary_search(&integer)) {
+                    Some(Ok(position)) => {
+                        integers.remove(position);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            Encoding::HashTable(table) => table.remove(member),
+        }
+    }
+
+    pub fn contains(&self, member: &Bytes) -> bool {
+        match &self.encoding {
+            Encoding::IntSet(integers) => {
+                as_integer(member).is_some_and(|integer| integers.binary_search(&integer).is_ok())
+            }
+            Encoding::HashTable(table) => table.positions.contains_key(member),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.encoding {
+            Encoding::IntSet(integers) => integers.len(),
+            Encoding::HashTable(table) => table.members.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over the members, intset members are formatted on the fly
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Bytes> + '_> {
+        match &self.encoding {
+            Encoding::IntSet(integers) => Box::new(integers.iter().map(|i| integer_member(*i))),
+            Encoding::HashTable(table) => Box::new(table.members.iter().cloned()),
+        }
+    }
+
+    /// One page of SSCAN, along with the cursor of the next page. Like redis does for its
+    /// compact encodings, an intset is small enough to be returned in one go.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<Bytes>) {
+        match &self.encoding {
+            Encoding::IntSet(_) => (0, self.iter().collect()),
+            Encoding::HashTable(table) => {
+                let (next_cursor, page) = table.scan_index.page(cursor, count);
+                (next_cursor, page.into_iter().cloned().collect())
+            }
+        }
+    }
+
+    pub fn get(&self, position: usize) -> Option<Bytes> {
+        match &self.encoding {
+            Encoding::IntSet(integers) => integers.get(position).map(|i| integer_member(*i)),
+            Encoding::HashTable(table) => table.members.get(position).cloned(),
+        }
+    }
+
+    pub fn random_member(&self, rng: &mut impl Rng) -> Option<Bytes> {
+        if self.is_empty() {
+            return None;
+        }
+        self.get(rng.random_range(0..self.len()))
+    }
+
+    /// Removes and returns a random member
+    pub fn pop_random(&mut self, rng: &mut impl Rng) -> Option<Bytes> {
+        if self.is_empty() {
+            return None;
+        }
+        let position = rng.random_range(0..self.len());
+        match &mut self.encoding {
+            Encoding::IntSet(integers) => Some(integer_member(integers.remove(position))),
+            Encoding::HashTable(table) => {
+                let member = table.remove_at(position);
+                table.positions.remove(&member);
+                table.scan_index.remove(&member);
+                Some(member)
+            }
+        }
+    }
+
+    fn convert_to_hash_table(&mut self) {
+        if let Encoding::IntSet(integers) = &self.encoding {
+            let mut table = HashTable::default();
+            for integer in integers {
+                table.insert(integer_member(*integer));
+            }
+            self.encoding = Encoding::HashTable(table);
+        }
+    }
+}
+
+impl HashTable {
+    fn insert(&mut self, member: Bytes) -> bool {
+        if self.positions.contains_key(&member) {
+            return false;
+        }
+        self.positions.insert(member.clone(), self.members.len());
+        self.scan_index.insert(&member);
+        self.members.push(member);
+        true
+    }
+
+    fn remove(&mut self, member: &Bytes) -> bool {
+        let Some(position) = self.positions.remove(member) else {
+            return false;
+        };
+        self.scan_index.remove(member);
+        self.remove_at(position);
+        true
+    }
+
+    /// Swap-removes the member at `position` and fixes up the position of the moved member
+    fn remove_at(&mut self, position: usize) -> Bytes {
+        let removed = self.members.swap_remove(position);
+        if let Some(moved) = self.members.get(position) {
+            self.positions.insert(moved.clone(), position);
+        }
+        removed
+    }
+}
+
+impl FromIterator<Bytes> for SetValue {
+    fn from_iter<T: IntoIterator<Item = Bytes>>(iter: T) -> Self {
+        let mut set = SetValue::default();
+        for member in iter {
+            set.insert(member);
+        }
+        set
+    }
+}
+
+#[test]
+fn test_remove_keeps_positions_consistent() {
+    let mut set: SetValue = ["a", "b", "c", "d"].into_iter().map(Bytes::from).collect();
+    assert!(set.remove(&"b".into()));
+    assert!(!set.remove(&"b".into()));
+    assert_eq!(set.len(), 3);
+
+    // every remaining member must still be removable through its position
+    for member in ["a", "c", "d"] {
+        assert!(set.contains(&member.into()));
+        assert!(set.remove(&member.into()));
+    }
+    assert!(set.is_empty());
+}
+
+#[test]
+fn test_pop_random_drains_the_set() {
+    let mut set: SetValue = ["a", "b", "c"].into_iter().map(Bytes::from).collect();
+    let mut rng = rand::rng();
+    let mut popped: Vec<Bytes> = std::iter::from_fn(|| set.pop_random(&mut rng)).collect();
+    popped.sort();
+    assert_eq!(
+        popped,
+        vec![Bytes::from("a"), Bytes::from("b"), Bytes::from("c")]
+    );
+    assert!(set.is_empty());
+}
+
+#[test]
+fn test_intset_upgrades_on_non_integer_member() {
+    let mut set: SetValue = ["3", "-1", "2"].into_iter().map(Bytes::from).collect();
+    assert_eq!(set.encoding(), "intset");
+    assert_eq!(
+        set.iter().collect::<Vec<_>>(),
+        vec![Bytes::from("-1"), Bytes::from("2"), Bytes::from("3")]
+    );
+    assert!(!set.insert("2".into()));
+    // not canonical, so it is a different member than 2
+    assert!(!set.contains(&"02".into()));
+
+    assert!(set.insert("02".into()));
+    assert_eq!(set.encoding(), "hashtable");
+    assert_eq!(set.len(), 4);
+    assert!(set.contains(&"2".into()) && set.contains(&"02".into()));
+}
+
+#[test]
+fn test_intset_upgrades_when_growing_too_large() {
+    let mut set: SetValue = (0..MAX_INTSET_ENTRIES)
+        .map(|i| Bytes::from(i.to_string()))
+        .collect();
+    assert_eq!(set.encoding(), "intset");
+
+    assert!(set.insert(Bytes::from(MAX_INTSET_ENTRIES.to_string())));
+    assert_eq!(set.encoding(), "hashtable");
+    assert_eq!(set.len(), MAX_INTSET_ENTRIES + 1);
+    assert!(set.contains(&"0".into()));
+}
@@ -0,0 +1,339 @@
+use std::collections::HashSet;
+
+use bytes::Bytes;
+
+use rand::seq::index;
+
+use super::{SetValue, Store, StoreError, Value};
+
+impl Store {
+    /// Adds the members to the set, returns the number of members which were not yet part of it
+    pub fn sadd(&mut self, key: &Bytes, members: Vec<Bytes>) -> Result<usize, StoreError> {
+        let set = self.set_entry(key)?;
+        let added = members
+            .into_iter()
+            .filter(|member| set.insert(member.clone()))
+            .count();
+        if added > 0 {
+            self.touch_key(key);
+        }
+        Ok(added)
+    }
+
+    /// Removes the members from the set, returns the number of members which were removed.
+    /// The key is deleted once the set is empty.
+    pub fn srem(&mut self, key: &Bytes, members: &[Bytes]) -> Result<usize, StoreError> {
+        let Some(set) = self.set_mut(key)? else {
+            return Ok(0);
+        };
+
+        let removed = members.iter().filter(|member| set.remove(member)).count();
+        if removed > 0 {
+            self.touch_key(key);
+            self.remove_if_empty(key);
+        }
+        Ok(removed)
+    }
+
+    pub fn smembers(&self, key: &Bytes) -> Result<Vec<Bytes>, StoreError> {
+        Ok(self
+            .set(key)?
+            .map(|set| set.iter().collect())
+            .unwrap_or_default())
+    }
+
+    pub fn scard(&self, key: &Bytes) -> Result<usize, StoreError> {
+        Ok(self.set(key)?.map(|set| set.len()).unwrap_or(0))
+    }
+
+    pub fn sscan(
+        &self,
+        key: &Bytes,
+        cursor: u64,
+        count: usize,
+    ) -> Result<(u64, Vec<Bytes>), StoreError> {
+        let Some(set) = self.set(key)? else {
+            return Ok((0, vec![]));
+        };
+
+        Ok(set.scan(cursor, count))
+    }
+
+    /// Returns for every given member whether it is part of the set
+    pub fn smismember(&self, key: &Bytes, members: &[Bytes]) -> Result<Vec<bool>, StoreError> {
+        let set = self.set(key)?;
+        Ok(members
+            .iter()
+            .map(|member| set.is_some_and(|set| set.contains(member)))
+            .collect())
+    }
+
+    pub fn sinter(&self, keys: &[Bytes]) -> Result<HashSet<Bytes>, StoreError> {
+        // a missing key is an empty set, so the intersection is empty as well
+        let Some(sets) = self
+            .sets_for_keys(keys)?
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(HashSet::new());
+        };
+        let Some((first, others)) = sets.split_first() else {
+            return Ok(HashSet::new());
+        };
+        Ok(first
+            .iter()
+            .filter(|member| others.iter().all(|set| set.contains(member)))
+            .collect())
+    }
+
+    /// Cardinality of the intersection, counting stops once `limit` is reached (0 means no limit)
+    pub fn sintercard(&self, keys: &[Bytes], limit: usize) -> Result<usize, StoreError> {
+        let Some(mut sets) = self
+            .sets_for_keys(keys)?
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(0);
+        };
+        // walking the smallest set keeps the number of lookups low
+        sets.sort_by_key(|set| set.len());
+        let Some((smallest, others)) = sets.split_first() else {
+            return Ok(0);
+        };
+
+        let mut cardinality = 0;
+        for member in smallest.iter() {
+            if others.iter().all(|set| set.contains(&member)) {
+                cardinality += 1;
+                if cardinality == limit {
+                    break;
+                }
+            }
+        }
+        Ok(cardinality)
+    }
+
+    pub fn sunion(&self, keys: &[Bytes]) -> Result<HashSet<Bytes>, StoreError> {
+        let sets = self.sets_for_keys(keys)?;
+        Ok(sets
+            .into_iter()
+            .flatten()
+            .flat_map(|set| set.iter())
+            .collect())
+    }
+
+    /// Members of the first set which are not part of any of the other sets
+    pub fn sdiff(&self, keys: &[Bytes]) -> Result<HashSet<Bytes>, StoreError> {
+        let sets = self.sets_for_keys(keys)?;
+        let Some((Some(first), others)) = sets.split_first() else {
+            return Ok(HashSet::new());
+        };
+        Ok(first
+            .iter()
+            .filter(|member| !others.iter().flatten().any(|set| set.contains(member)))
+            .collect())
+    }
+
+    /// Replaces whatever the destination holds with the given members, returns the new cardinality.
+    /// An empty result deletes the destination.
+    pub fn sstore(&mut self, destination: &Bytes, members: HashSet<Bytes>) -> usize {
+        self.remove_key(destination);
+        let len = members.len();
+        if len > 0 {
+            self.insert_value(
+                destination.clone(),
+                Value::Set(members.into_iter().collect()),
+            );
+        }
+        len
+    }
+
+    /// Looks up the sets stored at the given keys, `None` for keys which don't exist
+    fn sets_for_keys(&self, keys: &[Bytes]) -> Result<Vec<Option<&SetValue>>, StoreError> {
+        keys.iter().map(|key| self.set(key)).collect()
+    }
+
+    /// Moves the member from source to destination, returns false if it is not part of source
+    pub fn smove(
+        &mut self,
+        source: &Bytes,
+        destination: &Bytes,
+        member: &Bytes,
+    ) -> Result<bool, StoreError> {
+        // fail before touching the source if the destination is not a set
+        self.set(destination)?;
+
+        let removed = self.set_mut(source)?.is_some_and(|set| set.remove(member));
+        if !removed {
+            return Ok(false);
+        }
+        self.touch_key(source);
+        self.remove_if_empty(source);
+        self.sadd(destination, vec![member.clone()])?;
+        Ok(true)
+    }
+
+    /// Removes and returns up to `count` random members
+    pub fn spop(&mut self, key: &Bytes, count: usize) -> Result<Vec<Bytes>, StoreError> {
+        let Some(set) = self.set_mut(key)? else {
+            return Ok(vec![]);
+        };
+
+        let mut rng = rand::rng();
+        let popped: Vec<Bytes> = std::iter::from_fn(|| set.pop_random(&mut rng))
+            .take(count)
+            .collect();
+        if !popped.is_empty() {
+            self.touch_key(key);
+            self.remove_if_empty(key);
+        }
+        Ok(popped)
+    }
+
+    /// Returns random members without removing them. A positive count returns up to `count`
+    /// distinct members, a negative count returns exactly `-count` members which may repeat.
+    pub fn srandmember(&self, key: &Bytes, count: i128) -> Result<Vec<Bytes>, StoreError> {
+        let Some(set) = self.set(key)? else {
+            return Ok(vec![]);
+        };
+
+        let mut rng = rand::rng();
+        let members = if count < 0 {
+            (0..count.unsigned_abs())
+                .filter_map(|_| set.random_member(&mut rng))
+                .collect()
+        } else if count as usize >= set.len() {
+            set.iter().collect()
+        } else {
+            index::sample(&mut rng, set.len(), count as usize)
+                .into_iter()
+                .filter_map(|position| set.get(position))
+                .collect()
+        };
+        Ok(members)
+    }
+}
+
+#[test]
+fn test_srem_deletes_empty_set() {
+    let mut store = Store::new();
+    let key = Bytes::from("set");
+    assert_eq!(
+        store
+            .sadd(&key, vec!["a".into(), "b".into(), "a".into()])
+            .unwrap(),
+        2
+    );
+    assert_eq!(store.scard(&key).unwrap(), 2);
+
+    assert_eq!(store.srem(&key, &["a".into(), "c".into()]).unwrap(), 1);
+    assert_eq!(
+        store.smismember(&key, &["a".into(), "b".into()]).unwrap(),
+        vec![false, true]
+    );
+
+    assert_eq!(store.srem(&key, &["b".into()]).unwrap(), 1);
+    assert!(matches!(store.get_type(&key), Err(StoreError::KeyNotFound)));
+}
+
+#[test]
+fn test_set_algebra() {
+    let mut store = Store::new();
+    let (a, b, missing) = (Bytes::from("a"), Bytes::from("b"), Bytes::from("missing"));
+    store
+        .sadd(&a, vec!["1".into(), "2".into(), "3".into()])
+        .unwrap();
+    store.sadd(&b, vec!["2".into(), "4".into()]).unwrap();
+
+    let sorted = |set: HashSet<Bytes>| {
+        let mut members: Vec<Bytes> = set.into_iter().collect();
+        members.sort();
+        members
+    };
+    assert_eq!(
+        sorted(store.sinter(&[a.clone(), b.clone()]).unwrap()),
+        vec![Bytes::from("2")]
+    );
+    assert_eq!(
+        sorted(store.sunion(&[a.clone(), b.clone()]).unwrap()),
+        vec![
+            Bytes::from("1"),
+            Bytes::from("2"),
+            Bytes::from("3"),
+            Bytes::from("4")
+        ]
+    );
+    assert_eq!(
+        sorted(store.sdiff(&[a.clone(), b.clone()]).unwrap()),
+        vec![Bytes::from("1"), Bytes::from("3")]
+    );
+    assert!(store.sinter(&[a.clone(), missing]).unwrap().is_empty());
+
+    assert_eq!(store.sintercard(&[a.clone(), b.clone()], 0).unwrap(), 1);
+    assert_eq!(store.sintercard(&[a.clone(), a.clone()], 2).unwrap(), 2);
+
+    let empty = store.sinter(&[a.clone(), Bytes::from("nothing")]).unwrap();
+    assert_eq!(store.sstore(&b, empty), 0);
+    assert!(store.get_type(&b).is_err());
+}
+
+#[test]
+fn test_spop_and_srandmember() {
+    let mut store = Store::new();
+    let key = Bytes::from("set");
+    store
+        .sadd(&key, vec!["a".into(), "b".into(), "c".into()])
+        .unwrap();
+
+    let mut distinct = store.srandmember(&key, 2).unwrap();
+    distinct.sort();
+    distinct.dedup();
+    assert_eq!(distinct.len(), 2);
+    assert_eq!(store.srandmember(&key, 10).unwrap().len(), 3);
+    assert_eq!(store.srandmember(&key, -10).unwrap().len(), 10);
+
+    assert_eq!(store.spop(&key, 2).unwrap().len(), 2);
+    assert_eq!(store.scard(&key).unwrap(), 1);
+    assert_eq!(store.spop(&key, 5).unwrap().len(), 1);
+    assert!(store.get_type(&key).is_err());
+}
+
+#[test]
+fn test_smove() {
+    let mut store = Store::new();
+    let (source, destination) = (Bytes::from("source"), Bytes::from("destination"));
+    store.sadd(&source, vec!["a".into()]).unwrap();
+
+    assert!(!store.smove(&source, &destination, &"b".into()).unwrap());
+    assert!(store.smove(&source, &destination, &"a".into()).unwrap());
+    assert!(store.get_type(&source).is_err());
+    assert_eq!(
+        store.smembers(&destination).unwrap(),
+        vec![Bytes::from("a")]
+    );
+}
+
+#[test]
+fn test_sscan_visits_every_member() {
+    let mut store = Store::new();
+    let key = Bytes::from("set");
+    let members: Vec<Bytes> = (0..50).map(|i| Bytes::from(format!("m{}", i))).collect();
+    store.sadd(&key, members.clone()).unwrap();
+
+    let mut seen = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let (next_cursor, page) = store.sscan(&key, cursor, 10).unwrap();
+        seen.extend(page);
+        if next_cursor == 0 {
+            break;
+        }
+        cursor = next_cursor;
+    }
+
+    seen.sort();
+    let mut expected = members;
+    expected.sort();
+    assert_eq!(seen, expected);
+    assert_eq!(store.sscan(&"missing".into(), 0, 10).unwrap(), (0, vec![]));
+}
@@ -0,0 +1,354 @@
+use rand::Rng;
+
+/// Enough levels for 2^64 elements at the 1/4 promotion probability below
+const MAX_LEVEL: usize = 32;
+/// Index of the head node, which holds no key and starts every level
+const HEAD: usize = 0;
+
+struct Link {
+    next: Option<usize>,
+    /// Number of level 0 steps this link skips, which is what makes ranks O(log n)
+    span: usize,
+}
+
+struct Node<K> {
+    /// `None` for the head and for freed nodes
+    key: Option<K>,
+    levels: Vec<Link>,
+    backward: Option<usize>,
+}
+
+/// An ordered set with rank support, modelled after the skip list redis uses for sorted sets.
+///
+/// Besides O(log n) insertion, removal and lookup of the first key not before a bound, every
+/// link knows how many elements it skips. That gives O(log n) ranks and positional access,
+/// so ZRANK and index based ZRANGE don't have to walk the set. Nodes live in a vector and
+/// point to each other by index, freed slots are reused.
+pub struct SkipList<K> {
+    nodes: Vec<Node<K>>,
+    free: Vec<usize>,
+    tail: Option<usize>,
+    /// Number of levels currently in use, at least 1
+    level: usize,
+    len: usize,
+}
+
+impl<K: Ord> Default for SkipList<K> {
+    fn default() -> Self {
+        SkipList {
+            nodes: vec![Node {
+                key: None,
+                levels: (0..MAX_LEVEL)
+                    .map(|_| Link {
+                        next: None,
+                        span: 0,
+                    })
+                    .collect(),
+                backward: None,
+            }],
+            free: Vec::new(),
+            tail: None,
+            level: 1,
+            len: 0,
+        }
+    }
+}
+
+/// Level of a new node: every further level with probability 1/4, like redis
+fn random_level() -> usize {
+    let mut rng = rand::rng();
+    let mut level = 1;
+    while level < MAX_LEVEL && rng.random_ratio(1, 4) {
+        level += 1;
+    }
+    level
+}
+
+impl<K: Ord> SkipList<K> {
+    fn key(&self, node: usize) -> &K {
+        self.nodes[node]
+            .key
+            .as_ref()
+            .expect("linked nodes always hold a key")
+    }
+
+    fn next(&self, node: usize, level: usize) -> Option<usize> {
+        self.nodes[node].levels[level].next
+    }
+
+    /// Finds the last node before `key` on every level, together with the rank of that node
+    fn predecessors(&self, key: &K) -> ([usize; MAX_LEVEL], [usize; MAX_LEVEL]) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut node = HEAD;
+        for level in (0..self.level).rev() {
+            rank[level] = if level + 1 == self.level {
+                0
+            } else {
+                rank[level + 1]
+            };
+            while let Some(next) = self.next(node, level)
+                && self.key(next) < key
+            {
+                rank[level] += self.nodes[node].levels[level].span;
+                node = next;
+            }
+            update[level] = node;
+        }
+        (update, rank)
+    }
+
+    /// Inserts a key which is not part of the list yet
+    pub fn insert(&mut self, key: K) {
+        let (mut update, mut rank) = self.predecessors(&key);
+
+        let level = random_level();
+        if level > self.level {
+            for new_level in self.level..level {
+                rank[new_level] = 0;
+                update[new_level] = HEAD;
+                self.nodes[HEAD].levels[new_level].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let node = self.allocate(key, level);
+        for level in 0..level {
+            let previous = update[level];
+            let skipped = rank[0] - rank[level];
+            self.nodes[node].levels[level].next = self.nodes[previous].levels[level].next;
+            self.nodes[node].levels[level].span = self.nodes[previous].levels[level].span - skipped;
+            self.nodes[previous].levels[level].next = Some(node);
+            self.nodes[previous].levels[level].span = skipped + 1;
+        }
+        // the new node sits below the links of the higher levels, they skip one more element
+        for (level, previous) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[*previous].levels[level].span += 1;
+        }
+
+        self.nodes[node].backward = (update[0] != HEAD).then_some(update[0]);
+        match self.next(node, 0) {
+            Some(next) => self.nodes[next].backward = Some(node),
+            None => self.tail = Some(node),
+        }
+        self.len += 1;
+    }
+
+    /// Removes the key, returns it if it was part of the list
+    pub fn remove(&mut self, key: &K) -> Option<K> {
+        let (update, _) = self.predecessors(key);
+        let node = self
+            .next(update[0], 0)
+            .filter(|node| self.key(*node) == key)?;
+
+        for (level, &previous) in update.iter().enumerate().take(self.level) {
+            if self.next(previous, level) == Some(node) {
+                self.nodes[previous].levels[level].span += self.nodes[node].levels[level].span;
+                self.nodes[previous].levels[level].span -= 1;
+                self.nodes[previous].levels[level].next = self.next(node, level);
+            } else {
+                self.nodes[previous].levels[level].span -= 1;
+            }
+        }
+        match self.next(node, 0) {
+            Some(next) => self.nodes[next].backward = self.nodes[node].backward,
+            None => self.tail = self.nodes[node].backward,
+        }
+        while self.level > 1 && self.next(HEAD, self.level - 1).is_none() {
+            self.level -= 1;
+        }
+        self.len -= 1;
+
+        self.free.push(node);
+        self.nodes[node].levels.clear();
+        self.nodes[node].key.take()
+    }
+
+    fn allocate(&mut self, key: K, level: usize) -> usize {
+        let links = (0..level).map(|_| Link {
+            next: None,
+            span: 0,
+        });
+        match self.free.pop() {
+            Some(node) => {
+                self.nodes[node].key = Some(key);
+                self.nodes[node].levels.extend(links);
+                self.nodes[node].backward = None;
+                node
+            }
+            None => {
+                self.nodes.push(Node {
+                    key: Some(key),
+                    levels: links.collect(),
+                    backward: None,
+                });
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    /// Number of keys for which `before` holds. `before` has to hold for a prefix of the list,
+    /// e.g. "is less than some bound", the search then only visits O(log n) nodes.
+    pub fn count_before(&self, before: impl Fn(&K) -> bool) -> usize {
+        let mut rank = 0;
+        let mut node = HEAD;
+        for level in (0..self.level).rev() {
+            while let Some(next) = self.next(node, level)
+                && before(self.key(next))
+            {
+                rank += self.nodes[node].levels[level].span;
+                node = next;
+            }
+        }
+        rank
+    }
+
+    /// Zero based position of the key
+    pub fn rank(&self, key: &K) -> Option<usize> {
+        let rank = self.count_before(|other| other < key);
+        self.node_at(rank)
+            .filter(|node| self.key(*node) == key)
+            .map(|_| rank)
+    }
+
+    /// The node at the zero based position
+    fn node_at(&self, rank: usize) -> Option<usize> {
+        if rank >= self.len {
+            return None;
+        }
+        // ranks of the links are 1 based, the head sits at 0
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut node = HEAD;
+        for level in (0..self.level).rev() {
+            while let Some(next) = self.next(node, level)
+                && traversed + self.nodes[node].levels[level].span <= target
+            {
+                traversed += self.nodes[node].levels[level].span;
+                node = next;
+            }
+            if traversed == target {
+                return Some(node);
+            }
+        }
+        None
+    }
+
+    pub fn get(&self, rank: usize) -> Option<&K> {
+        self.node_at(rank).map(|node| self.key(node))
+    }
+
+    pub fn first(&self) -> Option<&K> {
+        self.next(HEAD, 0).map(|node| self.key(node))
+    }
+
+    pub fn last(&self) -> Option<&K> {
+        self.tail.map(|node| self.key(node))
+    }
+
+    /// Iterates over the keys with a position in `start..end`
+    pub fn range(&self, start: usize, end: usize) -> Iter<'_, K> {
+        let end = end.min(self.len);
+        if start >= end {
+            return Iter {
+                list: self,
+                front: None,
+                back: None,
+                remaining: 0,
+            };
+        }
+        Iter {
+            list: self,
+            front: self.node_at(start),
+            back: self.node_at(end - 1),
+            remaining: end - start,
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K> {
+        Iter {
+            list: self,
+            front: self.next(HEAD, 0),
+            back: self.tail,
+            remaining: self.len,
+        }
+    }
+}
+
+pub struct Iter<'a, K> {
+    list: &'a SkipList<K>,
+    front: Option<usize>,
+    back: Option<usize>,
+    remaining: usize,
+}
+
+impl<'a, K: Ord> Iterator for Iter<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.front?;
+        self.front = self.list.next(node, 0);
+        self.remaining -= 1;
+        Some(self.list.key(node))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K: Ord> DoubleEndedIterator for Iter<'_, K> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.back?;
+        self.back = self.list.nodes[node].backward;
+        self.remaining -= 1;
+        Some(self.list.key(node))
+    }
+}
+
+impl<K: Ord> ExactSizeIterator for Iter<'_, K> {}
+
+#[test]
+fn test_skiplist_matches_a_sorted_vector() {
+    let mut rng = rand::rng();
+    let mut list = SkipList::default();
+    let mut expected: Vec<u32> = Vec::new();
+    for _ in 0..2_000 {
+        let key = rng.random_range(0..500);
+        match expected.binary_search(&key) {
+            Ok(position) => {
+                assert_eq!(list.remove(&key), Some(key));
+                expected.remove(position);
+            }
+            Err(position) => {
+                list.insert(key);
+                expected.insert(position, key);
+            }
+        }
+    }
+
+    assert_eq!(list.iter().len(), expected.len());
+    assert_eq!(list.iter().copied().collect::<Vec<_>>(), expected);
+    assert_eq!(list.iter().rev().count(), expected.len());
+    for (rank, key) in expected.iter().enumerate() {
+        assert_eq!(list.rank(key), Some(rank));
+        assert_eq!(list.get(rank), Some(key));
+    }
+    assert_eq!(list.rank(&1_000), None);
+    assert_eq!(
+        list.count_before(|key| *key < 250),
+        expected.partition_point(|key| *key < 250)
+    );
+    assert_eq!(
+        list.range(10, 20).rev().copied().collect::<Vec<_>>(),
+        expected[10..20].iter().rev().copied().collect::<Vec<_>>()
+    );
+    assert_eq!(list.first(), expected.first());
+    assert_eq!(list.last(), expected.last());
+}
@@ -0,0 +1,126 @@
+use std::collections::{HashMap, HashSet};
+
+use bytes::Bytes;
+
+use super::Store;
+
+pub const CLUSTER_SLOTS: u16 = 16384;
+
+/// Keys per hash slot, only maintained when cluster mode is enabled
+#[derive(Default)]
+pub struct SlotIndex {
+    slots: HashMap<u16, HashSet<Bytes>>,
+}
+
+/// CRC16 (XMODEM variant) as used by redis cluster to map keys to slots
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Hash slot of a key. If the key contains a non-empty hash tag (`{...}`),
+/// only the tag is hashed so related keys end up in the same slot.
+pub fn key_hash_slot(key: &[u8]) -> u16 {
+    let hashed = key
+        .iter()
+        .position(|c| *c == b'{')
+        .and_then(|open| {
+            let close = key[open + 1..].iter().position(|c| *c == b'}')?;
+            (close > 0).then(|| &key[open + 1..open + 1 + close])
+        })
+        .unwrap_or(key);
+    crc16(hashed) % CLUSTER_SLOTS
+}
+
+impl SlotIndex {
+    pub(super) fn insert(&mut self, key: &Bytes) {
+        self.slots
+            .entry(key_hash_slot(key))
+            .or_default()
+            .insert(key.clone());
+    }
+
+    pub(super) fn remove(&mut self, key: &Bytes) {
+        let slot = key_hash_slot(key);
+        if let Some(keys) = self.slots.get_mut(&slot) {
+            keys.remove(key);
+            if keys.is_empty() {
+                self.slots.remove(&slot);
+            }
+        }
+    }
+
+    pub(super) fn count_keys(&self, slot: u16) -> usize {
+        self.slots.get(&slot).map_or(0, |keys| keys.len())
+    }
+
+    pub(super) fn keys(&self, slot: u16, count: usize) -> Vec<Bytes> {
+        self.slots
+            .get(&slot)
+            .map(|keys| keys.iter().take(count).cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+impl Store {
+    /// Creates a store which maintains the per-slot key index needed in cluster mode
+    pub fn with_cluster_enabled(mut self) -> Self {
+        self.engine.enable_slot_index();
+        self
+    }
+
+    pub fn cluster_enabled(&self) -> bool {
+        self.engine.has_slot_index()
+    }
+
+    pub fn count_keys_in_slot(&self, slot: u16) -> usize {
+        self.engine.count_keys_in_slot(slot)
+    }
+
+    pub fn get_keys_in_slot(&self, slot: u16, count: usize) -> Vec<Bytes> {
+        self.engine.keys_in_slot(slot, count)
+    }
+}
+
+#[test]
+fn test_key_hash_slot() {
+    // reference values from the redis cluster specification / CLUSTER KEYSLOT
+    assert_eq!(key_hash_slot(b"123456789"), 12739);
+    assert_eq!(key_hash_slot(b"foo"), 12182);
+    assert_eq!(
+        key_hash_slot(b"{user1000}.following"),
+        key_hash_slot(b"user1000")
+    );
+    assert_eq!(key_hash_slot(b"foo{}{bar}"), key_hash_slot(b"foo{}{bar}"));
+    assert_ne!(key_hash_slot(b"foo{}{bar}"), key_hash_slot(b"bar"));
+}
+
+#[test]
+fn test_slot_index_follows_key_lifecycle() {
+    let mut store = Store::new().with_cluster_enabled();
+    let key = Bytes::from("{tag}set");
+    let slot = key_hash_slot(&key);
+
+    store.sadd(&key, vec!["a".into()]).unwrap();
+    store
+        .set_with_expiry(Bytes::from("{tag}string"), "v".into(), None)
+        .unwrap();
+    assert_eq!(store.count_keys_in_slot(slot), 2);
+
+    store.srem(&key, &["a".into()]).unwrap();
+    assert_eq!(store.count_keys_in_slot(slot), 1);
+    assert_eq!(
+        store.get_keys_in_slot(slot, 10),
+        vec![Bytes::from("{tag}string")]
+    );
+}
@@ -0,0 +1,174 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound::{Excluded, Unbounded};
+
+use bytes::Bytes;
+
+use super::StreamId;
+
+/// A consumer group of a stream: how far the group has read and which entries were delivered
+/// to its consumers without being acknowledged yet.
+pub struct ConsumerGroup {
+    pub last_delivered_id: StreamId,
+    /// number of entries the group has read, `None` if it can't be told (e.g. after SETID to an
+    /// arbitrary id)
+    pub entries_read: Option<u64>,
+    /// consumers by name, ordered like XINFO CONSUMERS lists them
+    pub consumers: BTreeMap<Bytes, Consumer>,
+    /// the pending entries list (PEL) of the whole group
+    pub pending: BTreeMap<StreamId, PendingEntry>,
+}
+
+pub struct Consumer {
+    /// last time the consumer was seen, in milliseconds since the epoch
+    pub seen_time: u128,
+    /// last time the consumer read or claimed an entry, `None` if it never did
+    pub active_time: Option<u128>,
+    /// ids of the pending entries owned by the consumer, each also in the PEL of the group
+    pub pending: BTreeSet<StreamId>,
+}
+
+/// An entry which was delivered but not acknowledged yet
+pub struct PendingEntry {
+    pub consumer: Bytes,
+    /// last time the entry was delivered, in milliseconds since the epoch
+    pub delivery_time: u128,
+    pub delivery_count: u64,
+}
+
+impl Consumer {
+    fn new(now: u128) -> Self {
+        Consumer {
+            seen_time: now,
+            active_time: None,
+            pending: BTreeSet::new(),
+        }
+    }
+}
+
+impl ConsumerGroup {
+    pub fn new(last_delivered_id: StreamId, entries_read: Option<u64>) -> Self {
+        ConsumerGroup {
+            last_delivered_id,
+            entries_read,
+            consumers: BTreeMap::new(),
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the consumer unless it exists already, returns whether it was added
+    pub fn create_consumer(&mut self, name: &Bytes, now: u128) -> bool {
+        if self.consumers.contains_key(name) {
+            return false;
+        }
+        self.consumers.insert(name.clone(), Consumer::new(now));
+        true
+    }
+
+    /// The consumer, which is created if it doesn't exist yet, seen at `now`
+    pub fn seen_consumer(&mut self, name: &Bytes, now: u128) -> &mut Consumer {
+        let consumer = self
+            .consumers
+            .entry(name.clone())
+            .or_insert_with(|| Consumer::new(now));
+        consumer.seen_time = now;
+        consumer
+    }
+
+    /// Adds an entry delivered for the first time (`>`) to the pending entries of the consumer.
+    /// An entry which is pending already (the group was moved back by SETID) changes owner.
+    pub fn add_pending(&mut self, consumer: &Bytes, id: StreamId, now: u128) {
+        let pending = self.claim(consumer, id, now);
+        pending.delivery_time = now;
+        pending.delivery_count = 1;
+    }
+
+    /// Makes the consumer the owner of the pending entry, an entry which isn't pending yet is
+    /// added as delivered once at `now`
+    pub fn claim(&mut self, consumer: &Bytes, id: StreamId, now: u128) -> &mut PendingEntry {
+        let pending = self.pending.entry(id).or_insert_with(|| PendingEntry {
+            consumer: consumer.clone(),
+            delivery_time: now,
+            delivery_count: 1,
+        });
+        let previous = std::mem::replace(&mut pending.consumer, consumer.clone());
+        if let Some(owner) = self.consumers.get_mut(&previous) {
+            owner.pending.remove(&id);
+        }
+        let consumer = self.seen_consumer(consumer, now);
+        consumer.pending.insert(id);
+        consumer.active_time = Some(now);
+        self.pending.get_mut(&id).expect("the entry was just added")
+    }
+
+    /// Drops the entry from the pending entries of the group and its owner
+    pub fn remove_pending(&mut self, id: &StreamId) {
+        if let Some(pending) = self.pending.remove(id)
+            && let Some(owner) = self.consumers.get_mut(&pending.consumer)
+        {
+            owner.pending.remove(id);
+        }
+    }
+
+    /// Ids of the pending entries of the consumer after `after`, each of them counts as
+    /// delivered once more
+    pub fn redeliver_pending(
+        &mut self,
+        consumer: &Bytes,
+        after: StreamId,
+        count: usize,
+        now: u128,
+    ) -> Vec<StreamId> {
+        let consumer = self.seen_consumer(consumer, now);
+        let ids: Vec<StreamId> = consumer
+            .pending
+            .range((Excluded(after), Unbounded))
+            .take(count)
+            .copied()
+            .collect();
+        if !ids.is_empty() {
+            consumer.active_time = Some(now);
+        }
+        for id in &ids {
+            if let Some(pending) = self.pending.get_mut(id) {
+                pending.delivery_time = now;
+                pending.delivery_count += 1;
+            }
+        }
+        ids
+    }
+
+    /// Removes the consumer and its pending entries, returns how many entries it had pending
+    pub fn delete_consumer(&mut self, name: &Bytes) -> usize {
+        let Some(consumer) = self.consumers.remove(name) else {
+            return 0;
+        };
+        for id in &consumer.pending {
+            self.pending.remove(id);
+        }
+        consumer.pending.len()
+    }
+}
+
+#[test]
+fn test_deleting_a_consumer_drops_its_pending_entries() {
+    let mut group = ConsumerGroup::new(StreamId::default(), Some(0));
+    let alice = Bytes::from("alice");
+    assert!(group.create_consumer(&alice, 1));
+    assert!(!group.create_consumer(&alice, 2));
+    assert_eq!(group.consumers[&alice].seen_time, 1);
+
+    let id = StreamId { ms: 1, seq: 1 };
+    group.pending.insert(
+        id,
+        PendingEntry {
+            consumer: alice.clone(),
+            delivery_time: 1,
+            delivery_count: 1,
+        },
+    );
+    group.consumers.get_mut(&alice).unwrap().pending.insert(id);
+
+    assert_eq!(group.delete_consumer(&alice), 1);
+    assert!(group.pending.is_empty());
+    assert_eq!(group.delete_consumer(&alice), 0);
+}
@@ -0,0 +1,292 @@
+use std::collections::{BTreeMap, HashMap, btree_map};
+use std::ops::Bound::{self, Excluded, Included, Unbounded};
+
+use bytes::Bytes;
+
+use super::{
+    ClaimOptions, StoreError, StreamId, StreamTrim, TrimThreshold, stream_group::ConsumerGroup,
+};
+
+/// Entries per node of a redis stream (`stream-node-max-entries`). Entries live in a map here,
+/// approximate trimming still removes entries in steps of whole nodes like redis does.
+pub const NODE_MAX_ENTRIES: usize = 100;
+/// Nodes an approximate trim removes at most unless it names a LIMIT
+const DEFAULT_TRIM_NODES: usize = 100;
+
+/// Fields and values of one stream entry
+pub type StreamEntry = HashMap<Bytes, Bytes>;
+
+/// What happened to a pending entry a consumer tried to claim
+pub enum ClaimOutcome {
+    Claimed(StreamEntry),
+    /// the entry is gone from the stream, so it was dropped from the pending entries
+    Deleted,
+    /// the entry isn't pending or not idle long enough
+    NotClaimed,
+}
+
+/// Whether no id lies within the bounds, `BTreeMap::range` panics for such bounds
+pub fn bounds_cross(start: Bound<StreamId>, end: Bound<StreamId>) -> bool {
+    match (start, end) {
+        (Excluded(start), Excluded(end)) => start >= end,
+        (Included(start) | Excluded(start), Included(end) | Excluded(end)) => start > end,
+        _ => false,
+    }
+}
+
+/// Entries of a stream, ordered by their id.
+///
+/// The stream remembers the largest id it ever handed out, deleting entries (even the last
+/// one) keeps it, so ids of deleted entries are never reused.
+#[derive(Default)]
+pub struct StreamValue {
+    entries: BTreeMap<StreamId, StreamEntry>,
+    last_id: StreamId,
+    /// number of entries added over the lifetime of the stream, deleted ones included
+    entries_added: u64,
+    /// largest id of an entry which was deleted or trimmed
+    max_deleted_id: StreamId,
+    /// consumer groups by name
+    groups: BTreeMap<Bytes, ConsumerGroup>,
+}
+
+impl StreamValue {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The largest id which was ever added, 0-0 for a stream which never had entries
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// Adds the entry, the id has to be greater than the last id
+    pub fn insert(&mut self, id: StreamId, entry: StreamEntry) {
+        debug_assert!(id > self.last_id, "stream ids only grow");
+        self.entries.insert(id, entry);
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    pub fn remove(&mut self, id: &StreamId) -> bool {
+        let removed = self.entries.remove(id).is_some();
+        if removed {
+            self.max_deleted_id = self.max_deleted_id.max(*id);
+        }
+        removed
+    }
+
+    /// The id of the newest entry, which may be smaller than the last id
+    pub fn last_entry_id(&self) -> Option<StreamId> {
+        self.entries.last_key_value().map(|(id, _)| *id)
+    }
+
+    pub fn entries_added(&self) -> u64 {
+        self.entries_added
+    }
+
+    pub fn max_deleted_id(&self) -> StreamId {
+        self.max_deleted_id
+    }
+
+    /// Overrides the bookkeeping of the stream (XSETID), the caller checks it is consistent
+    /// with the entries
+    pub fn set_last_id(
+        &mut self,
+        last_id: StreamId,
+        entries_added: Option<u64>,
+        max_deleted_id: Option<StreamId>,
+    ) {
+        self.last_id = last_id;
+        if let Some(entries_added) = entries_added {
+            self.entries_added = entries_added;
+        }
+        if let Some(max_deleted_id) = max_deleted_id {
+            self.max_deleted_id = max_deleted_id;
+        }
+    }
+
+    /// Removes the oldest entries beyond the threshold, returns how many were removed
+    pub fn trim(&mut self, trim: StreamTrim) -> usize {
+        let mut removed = match trim.threshold {
+            TrimThreshold::MaxLen(max_len) => self.len().saturating_sub(max_len),
+            TrimThreshold::MinId(min_id) => self.entries.range(..min_id).count(),
+        };
+        if trim.approximate {
+            let limit = match trim.limit {
+                None => DEFAULT_TRIM_NODES * NODE_MAX_ENTRIES,
+                Some(0) => usize::MAX,
+                Some(limit) => limit,
+            };
+            removed = removed.min(limit);
+            removed -= removed % NODE_MAX_ENTRIES;
+        }
+        for _ in 0..removed {
+            if let Some((id, _)) = self.entries.pop_first() {
+                self.max_deleted_id = self.max_deleted_id.max(id);
+            }
+        }
+        removed
+    }
+
+    /// Entries with ids within the bounds, none if the bounds cross
+    pub fn range(
+        &self,
+        start: Bound<StreamId>,
+        end: Bound<StreamId>,
+    ) -> btree_map::Range<'_, StreamId, StreamEntry> {
+        if bounds_cross(start, end) {
+            // nothing is stored below 0-1
+            return self.entries.range(..StreamId::default());
+        }
+        self.entries.range((start, end))
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &StreamEntry> {
+        self.entries.values()
+    }
+
+    pub fn groups(&self) -> &BTreeMap<Bytes, ConsumerGroup> {
+        &self.groups
+    }
+
+    pub fn group_mut(&mut self, name: &Bytes) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    /// Adds a group which delivers the entries after `id`, returns false if the group exists
+    pub fn create_group(&mut self, name: &Bytes, id: StreamId, entries_read: Option<u64>) -> bool {
+        if self.groups.contains_key(name) {
+            return false;
+        }
+        let entries_read = entries_read.or_else(|| self.entries_read_until(id));
+        self.groups
+            .insert(name.clone(), ConsumerGroup::new(id, entries_read));
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &Bytes) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Delivers the entries the group hasn't delivered yet to the consumer (`>`), at most
+    /// `count` of them, and adds them to its pending entries unless `no_ack`
+    pub fn read_group(
+        &mut self,
+        name: &Bytes,
+        consumer: &Bytes,
+        count: usize,
+        no_ack: bool,
+        now: u128,
+    ) -> Result<Vec<(StreamId, StreamEntry)>, StoreError> {
+        let group = self.groups.get(name).ok_or(StoreError::NoGroup)?;
+        let entries: Vec<(StreamId, StreamEntry)> = self
+            .entries
+            .range((Excluded(group.last_delivered_id), Unbounded))
+            .take(count)
+            .map(|(id, entry)| (*id, entry.clone()))
+            .collect();
+        // the count of read entries only stays exact if nothing after the group was deleted
+        let entries_read = match (group.entries_read, entries.last()) {
+            (_, None) => group.entries_read,
+            (Some(read), Some(_)) if self.max_deleted_id <= group.last_delivered_id => {
+                Some(read + entries.len() as u64)
+            }
+            (_, Some((last, _))) => self.entries_read_until(*last),
+        };
+
+        let group = self.groups.get_mut(name).ok_or(StoreError::NoGroup)?;
+        let seen = group.seen_consumer(consumer, now);
+        if !entries.is_empty() {
+            seen.active_time = Some(now);
+        }
+        if !no_ack {
+            for (id, _) in &entries {
+                group.add_pending(consumer, *id, now);
+            }
+        }
+        if let Some((last, _)) = entries.last() {
+            group.last_delivered_id = *last;
+            group.entries_read = entries_read;
+        }
+        Ok(entries)
+    }
+
+    /// Delivers the pending entries of the consumer after `after` once more, entries which were
+    /// deleted meanwhile come without fields
+    pub fn read_group_pending(
+        &mut self,
+        name: &Bytes,
+        consumer: &Bytes,
+        after: StreamId,
+        count: usize,
+        now: u128,
+    ) -> Result<Vec<(StreamId, Option<StreamEntry>)>, StoreError> {
+        let group = self.groups.get_mut(name).ok_or(StoreError::NoGroup)?;
+        let ids = group.redeliver_pending(consumer, after, count, now);
+        Ok(ids
+            .into_iter()
+            .map(|id| (id, self.entries.get(&id).cloned()))
+            .collect())
+    }
+
+    /// Claims the pending entry for the consumer if it is idle for at least `min_idle`
+    /// milliseconds. `force` claims an entry of the stream even if it isn't pending.
+    pub fn claim(
+        &mut self,
+        name: &Bytes,
+        consumer: &Bytes,
+        id: StreamId,
+        options: &ClaimOptions,
+        now: u128,
+    ) -> Result<ClaimOutcome, StoreError> {
+        let group = self.groups.get_mut(name).ok_or(StoreError::NoGroup)?;
+        let entry = self.entries.get(&id);
+        match group.pending.get(&id) {
+            Some(pending) if now.saturating_sub(pending.delivery_time) < options.min_idle => {
+                return Ok(ClaimOutcome::NotClaimed);
+            }
+            Some(_) => {}
+            None if options.force && entry.is_some() => {}
+            None => return Ok(ClaimOutcome::NotClaimed),
+        }
+        let Some(entry) = entry else {
+            group.remove_pending(&id);
+            return Ok(ClaimOutcome::Deleted);
+        };
+
+        let pending = group.claim(consumer, id, now);
+        pending.delivery_time = options.delivery_time.unwrap_or(now).min(now);
+        match options.retry_count {
+            Some(retry_count) => pending.delivery_count = retry_count,
+            None if !options.just_id => pending.delivery_count += 1,
+            None => {}
+        }
+        Ok(ClaimOutcome::Claimed(entry.clone()))
+    }
+
+    /// Number of entries added up to and including `id`, if it can be told. Once entries were
+    /// deleted (or the count was set by XSETID) only reading up to the last id is certain.
+    pub fn entries_read_until(&self, id: StreamId) -> Option<u64> {
+        if id >= self.last_id {
+            return Some(self.entries_added);
+        }
+        let intact =
+            self.max_deleted_id == StreamId::default() && self.entries_added == self.len() as u64;
+        intact.then(|| self.entries.range(..=id).count() as u64)
+    }
+
+    /// Number of entries the group has yet to read, `None` if entries after its last delivered
+    /// id were deleted, which makes the count of the group meaningless
+    pub fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if group.last_delivered_id >= self.last_id {
+            return Some(0);
+        }
+        if self.max_deleted_id > group.last_delivered_id {
+            return None;
+        }
+        group
+            .entries_read
+            .map(|read| self.entries_added.saturating_sub(read))
+    }
+}
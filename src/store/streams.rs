@@ -0,0 +1,1124 @@
+use std::ops::Bound::{self, Unbounded};
+
+use bytes::Bytes;
+
+use super::{
+    Store, StoreError, StreamEntry, StreamId, now_millis,
+    stream_group::ConsumerGroup,
+    stream_value::{ClaimOutcome, bounds_cross},
+};
+
+/// Which entries XTRIM (or XADD) removes from the head of a stream
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrimThreshold {
+    /// Keep at most this many entries
+    MaxLen(usize),
+    /// Remove the entries with a smaller id
+    MinId(StreamId),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StreamTrim {
+    pub threshold: TrimThreshold,
+    /// `~`: only whole nodes are removed, which may leave some entries beyond the threshold
+    pub approximate: bool,
+    /// Removes at most this many entries, only with `~`. 0 removes any number, `None` the
+    /// default of 100 nodes.
+    pub limit: Option<usize>,
+}
+
+/// How XCLAIM and XAUTOCLAIM pick and update the claimed entries
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ClaimOptions {
+    /// only entries idle for at least this many milliseconds are claimed
+    pub min_idle: u128,
+    /// last delivery of the claimed entries in milliseconds since the epoch, now by default
+    pub delivery_time: Option<u128>,
+    /// sets the delivery count instead of incrementing it
+    pub retry_count: Option<u64>,
+    /// claims entries which aren't pending as long as they are in the stream
+    pub force: bool,
+    /// leaves the delivery count as it is (unless `retry_count` is given)
+    pub just_id: bool,
+    /// moves the last delivered id of the group forward to this id
+    pub last_id: Option<StreamId>,
+}
+
+/// Outcome of XAUTOCLAIM
+#[derive(Debug, PartialEq)]
+pub struct AutoClaimed {
+    /// where the next call continues, 0-0 once the pending entries were scanned to the end
+    pub next: StreamId,
+    pub claimed: Vec<(StreamId, StreamEntry)>,
+    /// pending entries which were deleted from the stream, they are dropped while scanning
+    pub deleted: Vec<StreamId>,
+}
+
+/// Metadata of a stream as reported by XINFO STREAM
+#[derive(Debug, PartialEq)]
+pub struct StreamInfo {
+    pub length: usize,
+    pub last_generated_id: StreamId,
+    pub max_deleted_id: StreamId,
+    pub entries_added: u64,
+    pub first_entry: Option<(StreamId, StreamEntry)>,
+    pub last_entry: Option<(StreamId, StreamEntry)>,
+    pub groups: usize,
+}
+
+/// A consumer group as reported by XINFO GROUPS
+#[derive(Debug, PartialEq)]
+pub struct GroupInfo {
+    pub name: Bytes,
+    pub consumers: usize,
+    pub pending: usize,
+    pub last_delivered_id: StreamId,
+    pub entries_read: Option<u64>,
+    pub lag: Option<u64>,
+}
+
+/// Pending entries of a group as reported by the summary form of XPENDING
+#[derive(Debug, PartialEq)]
+pub struct PendingSummary {
+    pub count: usize,
+    pub first: Option<StreamId>,
+    pub last: Option<StreamId>,
+    /// consumers with pending entries and how many they have, ordered by name
+    pub consumers: Vec<(Bytes, usize)>,
+}
+
+/// A pending entry as reported by the extended form of XPENDING
+#[derive(Debug, PartialEq)]
+pub struct PendingInfo {
+    pub id: StreamId,
+    pub consumer: Bytes,
+    /// milliseconds since the entry was delivered last
+    pub idle: u128,
+    pub delivery_count: u64,
+}
+
+/// A consumer as reported by XINFO CONSUMERS, times in milliseconds since the epoch
+#[derive(Debug, PartialEq)]
+pub struct ConsumerInfo {
+    pub name: Bytes,
+    pub pending: usize,
+    pub seen_time: u128,
+    pub active_time: Option<u128>,
+}
+
+impl Store {
+    /// Deletes the entries with the given ids, returns the number of entries which existed.
+    /// The stream keeps its last id, so XADD never hands out a deleted id again.
+    pub fn xdel(&mut self, key: &Bytes, ids: &[StreamId]) -> Result<usize, StoreError> {
+        let Some(stream) = self.stream_mut(key)? else {
+            return Ok(0);
+        };
+        let deleted = ids.iter().filter(|id| stream.remove(id)).count();
+        if deleted > 0 {
+            self.touch_key(key);
+        }
+        Ok(deleted)
+    }
+
+    /// Sets the last id of the stream and optionally its count of added entries and largest
+    /// deleted id. The last id can't go below the newest entry, the count not below the length.
+    pub fn xsetid(
+        &mut self,
+        key: &Bytes,
+        last_id: StreamId,
+        entries_added: Option<u64>,
+        max_deleted_id: Option<StreamId>,
+    ) -> Result<(), StoreError> {
+        let stream = self.stream_mut(key)?.ok_or(StoreError::KeyNotFound)?;
+        if entries_added.is_some_and(|entries_added| entries_added < stream.len() as u64) {
+            return Err(StoreError::EntriesAddedSmallerThanLength);
+        }
+        if stream
+            .last_entry_id()
+            .is_some_and(|newest| last_id < newest)
+        {
+            return Err(StoreError::StreamIdSmallerThanLast);
+        }
+        stream.set_last_id(last_id, entries_added, max_deleted_id);
+        self.touch_key(key);
+        Ok(())
+    }
+
+    /// Entries with ids within the bounds, oldest first, at most `count` of them
+    pub fn xrange(
+        &self,
+        key: &Bytes,
+        start: Bound<StreamId>,
+        end: Bound<StreamId>,
+        count: Option<usize>,
+    ) -> Result<Vec<(StreamId, StreamEntry)>, StoreError> {
+        let Some(stream) = self.stream(key)? else {
+            return Ok(Vec::new());
+        };
+        Ok(stream
+            .range(start, end)
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(id, entry)| (*id, entry.clone()))
+            .collect())
+    }
+
+    /// Entries with ids within the bounds, newest first, at most `count` of them
+    pub fn xrevrange(
+        &self,
+        key: &Bytes,
+        start: Bound<StreamId>,
+        end: Bound<StreamId>,
+        count: Option<usize>,
+    ) -> Result<Vec<(StreamId, StreamEntry)>, StoreError> {
+        let Some(stream) = self.stream(key)? else {
+            return Ok(Vec::new());
+        };
+        Ok(stream
+            .range(start, end)
+            .rev()
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(id, entry)| (*id, entry.clone()))
+            .collect())
+    }
+
+    /// Length, ids and first and last entry of the stream, fails with `KeyNotFound` for a
+    /// missing key
+    pub fn xinfo_stream(&self, key: &Bytes) -> Result<StreamInfo, StoreError> {
+        let stream = self.stream(key)?.ok_or(StoreError::KeyNotFound)?;
+        let entry = |(id, entry): (&StreamId, &StreamEntry)| (*id, entry.clone());
+        Ok(StreamInfo {
+            length: stream.len(),
+            last_generated_id: stream.last_id(),
+            max_deleted_id: stream.max_deleted_id(),
+            entries_added: stream.entries_added(),
+            first_entry: stream.range(Unbounded, Unbounded).next().map(entry),
+            last_entry: stream.range(Unbounded, Unbounded).next_back().map(entry),
+            groups: stream.groups().len(),
+        })
+    }
+
+    /// The consumer groups of the stream ordered by name
+    pub fn xinfo_groups(&self, key: &Bytes) -> Result<Vec<GroupInfo>, StoreError> {
+        let stream = self.stream(key)?.ok_or(StoreError::KeyNotFound)?;
+        Ok(stream
+            .groups()
+            .iter()
+            .map(|(name, group)| GroupInfo {
+                name: name.clone(),
+                consumers: group.consumers.len(),
+                pending: group.pending.len(),
+                last_delivered_id: group.last_delivered_id,
+                entries_read: group.entries_read,
+                lag: stream.lag(group),
+            })
+            .collect())
+    }
+
+    /// The consumers of the group ordered by name
+    pub fn xinfo_consumers(
+        &self,
+        key: &Bytes,
+        group: &Bytes,
+    ) -> Result<Vec<ConsumerInfo>, StoreError> {
+        let stream = self.stream(key)?.ok_or(StoreError::KeyNotFound)?;
+        let group = stream.groups().get(group).ok_or(StoreError::NoGroup)?;
+        Ok(group
+            .consumers
+            .iter()
+            .map(|(name, consumer)| ConsumerInfo {
+                name: name.clone(),
+                pending: consumer.pending.len(),
+                seen_time: consumer.seen_time,
+                active_time: consumer.active_time,
+            })
+            .collect())
+    }
+
+    /// Creates a consumer group which delivers the entries after `id`. Without `make_stream`
+    /// (MKSTREAM) the stream has to exist.
+    pub fn xgroup_create(
+        &mut self,
+        key: &Bytes,
+        group: &Bytes,
+        id: StreamId,
+        entries_read: Option<u64>,
+        make_stream: bool,
+    ) -> Result<(), StoreError> {
+        let stream = match self.stream_mut(key)? {
+            Some(stream) => stream,
+            None if make_stream => self.stream_entry(key)?,
+            None => return Err(StoreError::KeyNotFound),
+        };
+        if !stream.create_group(group, id, entries_read) {
+            return Err(StoreError::BusyGroup);
+        }
+        self.touch_key(key);
+        Ok(())
+    }
+
+    /// Removes the consumer group, returns whether it existed
+    pub fn xgroup_destroy(&mut self, key: &Bytes, group: &Bytes) -> Result<bool, StoreError> {
+        let stream = self.stream_mut(key)?.ok_or(StoreError::KeyNotFound)?;
+        let destroyed = stream.destroy_group(group);
+        if destroyed {
+            self.touch_key(key);
+        }
+        Ok(destroyed)
+    }
+
+    /// Moves the last delivered id of the group, its pending entries stay untouched
+    pub fn xgroup_setid(
+        &mut self,
+        key: &Bytes,
+        group: &Bytes,
+        id: StreamId,
+        entries_read: Option<u64>,
+    ) -> Result<(), StoreError> {
+        let stream = self.stream_mut(key)?.ok_or(StoreError::KeyNotFound)?;
+        let entries_read = entries_read.or_else(|| stream.entries_read_until(id));
+        let group = stream.group_mut(group).ok_or(StoreError::NoGroup)?;
+        group.last_delivered_id = id;
+        group.entries_read = entries_read;
+        self.touch_key(key);
+        Ok(())
+    }
+
+    /// Adds a consumer to the group, returns whether it didn't exist yet
+    pub fn xgroup_createconsumer(
+        &mut self,
+        key: &Bytes,
+        group: &Bytes,
+        consumer: &Bytes,
+    ) -> Result<bool, StoreError> {
+        let now = now_millis()?;
+        let created = self
+            .consumer_group_mut(key, group)?
+            .create_consumer(consumer, now);
+        if created {
+            self.touch_key(key);
+        }
+        Ok(created)
+    }
+
+    /// Removes a consumer from the group, returns how many entries it had pending. Those
+    /// entries are dropped from the group as well.
+    pub fn xgroup_delconsumer(
+        &mut self,
+        key: &Bytes,
+        group: &Bytes,
+        consumer: &Bytes,
+    ) -> Result<usize, StoreError> {
+        let pending = self
+            .consumer_group_mut(key, group)?
+            .delete_consumer(consumer);
+        self.touch_key(key);
+        Ok(pending)
+    }
+
+    pub fn has_consumer_group(&self, key: &Bytes, group: &Bytes) -> Result<bool, StoreError> {
+        Ok(self
+            .stream(key)?
+            .is_some_and(|stream| stream.groups().contains_key(group)))
+    }
+
+    /// Delivers the entries the group hasn't delivered yet to the consumer (XREADGROUP with
+    /// `>`), they stay pending for the consumer until they are acknowledged unless `no_ack`
+    pub fn xreadgroup(
+        &mut self,
+        key: &Bytes,
+        group: &Bytes,
+        consumer: &Bytes,
+        count: Option<usize>,
+        no_ack: bool,
+    ) -> Result<Vec<(StreamId, StreamEntry)>, StoreError> {
+        let now = now_millis()?;
+        let stream = self.stream_mut(key)?.ok_or(StoreError::KeyNotFound)?;
+        let entries =
+            stream.read_group(group, consumer, count.unwrap_or(usize::MAX), no_ack, now)?;
+        if !entries.is_empty() {
+            self.touch_key(key);
+        }
+        Ok(entries)
+    }
+
+    /// The pending entries of the consumer with an id after `after` (XREADGROUP with an id),
+    /// `None` for entries which were deleted since they were delivered
+    pub fn xreadgroup_pending(
+        &mut self,
+        key: &Bytes,
+        group: &Bytes,
+        consumer: &Bytes,
+        after: StreamId,
+        count: Option<usize>,
+    ) -> Result<Vec<(StreamId, Option<StreamEntry>)>, StoreError> {
+        let now = now_millis()?;
+        let stream = self.stream_mut(key)?.ok_or(StoreError::KeyNotFound)?;
+        stream.read_group_pending(group, consumer, after, count.unwrap_or(usize::MAX), now)
+    }
+
+    /// Number and range of the pending entries of the group and who they are pending for
+    pub fn xpending_summary(
+        &self,
+        key: &Bytes,
+        group: &Bytes,
+    ) -> Result<PendingSummary, StoreError> {
+        let group = self.consumer_group(key, group)?;
+        Ok(PendingSummary {
+            count: group.pending.len(),
+            first: group.pending.first_key_value().map(|(id, _)| *id),
+            last: group.pending.last_key_value().map(|(id, _)| *id),
+            consumers: group
+                .consumers
+                .iter()
+                .filter(|(_, consumer)| !consumer.pending.is_empty())
+                .map(|(name, consumer)| (name.clone(), consumer.pending.len()))
+                .collect(),
+        })
+    }
+
+    /// The pending entries of the group within the bounds, oldest first, at most `count` of
+    /// them. Optionally only those of one consumer or which are idle for at least `min_idle`
+    /// milliseconds.
+    pub fn xpending(
+        &self,
+        key: &Bytes,
+        group: &Bytes,
+        (start, end): (Bound<StreamId>, Bound<StreamId>),
+        count: usize,
+        consumer: Option<&Bytes>,
+        min_idle: Option<u128>,
+    ) -> Result<Vec<PendingInfo>, StoreError> {
+        let now = now_millis()?;
+        let group = self.consumer_group(key, group)?;
+        if bounds_cross(start, end) {
+            return Ok(Vec::new());
+        }
+        Ok(group
+            .pending
+            .range((start, end))
+            .filter(|(_, pending)| consumer.is_none_or(|consumer| pending.consumer == consumer))
+            .map(|(id, pending)| PendingInfo {
+                id: *id,
+                consumer: pending.consumer.clone(),
+                idle: now.saturating_sub(pending.delivery_time),
+                delivery_count: pending.delivery_count,
+            })
+            .filter(|pending| min_idle.is_none_or(|min_idle| pending.idle >= min_idle))
+            .take(count)
+            .collect())
+    }
+
+    /// Makes the consumer the owner of the pending entries with the given ids, returns the
+    /// claimed entries. Entries which were deleted from the stream meanwhile are dropped from
+    /// the pending entries instead.
+    pub fn xclaim(
+        &mut self,
+        key: &Bytes,
+        group: &Bytes,
+        consumer: &Bytes,
+        ids: &[StreamId],
+        options: &ClaimOptions,
+    ) -> Result<Vec<(StreamId, StreamEntry)>, StoreError> {
+        let now = now_millis()?;
+        let stream = self.stream_mut(key)?.ok_or(StoreError::KeyNotFound)?;
+        stream
+            .group_mut(group)
+            .ok_or(StoreError::NoGroup)?
+            .seen_consumer(consumer, now);
+
+        let mut claimed = Vec::new();
+        for id in ids {
+            if let ClaimOutcome::Claimed(entry) =
+                stream.claim(group, consumer, *id, options, now)?
+            {
+                claimed.push((*id, entry));
+            }
+        }
+        if let Some(last_id) = options.last_id
+            && let Some(group) = stream.group_mut(group)
+        {
+            group.last_delivered_id = group.last_delivered_id.max(last_id);
+        }
+        if !claimed.is_empty() {
+            self.touch_key(key);
+        }
+        Ok(claimed)
+    }
+
+    /// Claims up to `count` pending entries idle for long enough, scanning the pending entries
+    /// of the group from `start`. Like redis it looks at no more than ten times `count` entries,
+    /// so one call stays cheap however many entries are pending.
+    pub fn xautoclaim(
+        &mut self,
+        key: &Bytes,
+        group: &Bytes,
+        consumer: &Bytes,
+        start: Bound<StreamId>,
+        count: usize,
+        options: &ClaimOptions,
+    ) -> Result<AutoClaimed, StoreError> {
+        let now = now_millis()?;
+        let stream = self.stream_mut(key)?.ok_or(StoreError::KeyNotFound)?;
+        let consumer_group = stream.group_mut(group).ok_or(StoreError::NoGroup)?;
+        consumer_group.seen_consumer(consumer, now);
+        let attempts = count.saturating_mul(10);
+        // one more than scanned to tell where the next call continues
+        let candidates: Vec<StreamId> = consumer_group
+            .pending
+            .range((start, Unbounded))
+            .map(|(id, _)| *id)
+            .take(attempts.saturating_add(1))
+            .collect();
+
+        let mut claimed = Vec::new();
+        let mut deleted = Vec::new();
+        let mut scanned = 0;
+        for id in candidates.iter().take(attempts) {
+            if claimed.len() == count {
+                break;
+            }
+            scanned += 1;
+            match stream.claim(group, consumer, *id, options, now)? {
+                ClaimOutcome::Claimed(entry) => claimed.push((*id, entry)),
+                ClaimOutcome::Deleted => deleted.push(*id),
+                ClaimOutcome::NotClaimed => {}
+            }
+        }
+        if !(claimed.is_empty() && deleted.is_empty()) {
+            self.touch_key(key);
+        }
+        Ok(AutoClaimed {
+            next: candidates.get(scanned).copied().unwrap_or_default(),
+            claimed,
+            deleted,
+        })
+    }
+
+    fn consumer_group(&self, key: &Bytes, group: &Bytes) -> Result<&ConsumerGroup, StoreError> {
+        self.stream(key)?
+            .ok_or(StoreError::KeyNotFound)?
+            .groups()
+            .get(group)
+            .ok_or(StoreError::NoGroup)
+    }
+
+    fn consumer_group_mut(
+        &mut self,
+        key: &Bytes,
+        group: &Bytes,
+    ) -> Result<&mut ConsumerGroup, StoreError> {
+        self.stream_mut(key)?
+            .ok_or(StoreError::KeyNotFound)?
+            .group_mut(group)
+            .ok_or(StoreError::NoGroup)
+    }
+
+    /// Trims the stream, returns the number of removed entries
+    pub fn xtrim(&mut self, key: &Bytes, trim: StreamTrim) -> Result<usize, StoreError> {
+        let removed = self
+            .stream_mut(key)?
+            .map(|stream| stream.trim(trim))
+            .unwrap_or(0);
+        if removed > 0 {
+            self.touch_key(key);
+        }
+        Ok(removed)
+    }
+}
+
+#[test]
+fn test_xdel_keeps_the_last_id() {
+    use crate::parser::RedisType;
+
+    let mut store = Store::new();
+    let key = Bytes::from("stream");
+    let fields = [
+        RedisType::BulkString("field".into()),
+        RedisType::BulkString("value".into()),
+    ];
+    store.xadd(&key, Some(1), Some(1), &fields).unwrap();
+    store.xadd(&key, Some(2), Some(1), &fields).unwrap();
+
+    let last = StreamId { ms: 1, seq: 2 };
+    assert_eq!(
+        store
+            .xdel(&key, &[last, last, StreamId { ms: 5, seq: 0 }])
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        store
+            .xrange(&key, Unbounded, Unbounded, None)
+            .unwrap()
+            .len(),
+        1
+    );
+
+    assert!(matches!(
+        store.xadd(&key, Some(2), Some(1), &fields),
+        Err(StoreError::StreamIdSmallerThanLast)
+    ));
+    assert_eq!(
+        store.xadd(&key, None, Some(1), &fields).unwrap(),
+        StreamId { ms: 1, seq: 3 }
+    );
+}
+
+#[test]
+fn test_xtrim_exact_and_approximate() {
+    use crate::parser::RedisType;
+
+    let mut store = Store::new();
+    let key = Bytes::from("stream");
+    let fields = [
+        RedisType::BulkString("field".into()),
+        RedisType::BulkString("value".into()),
+    ];
+    for seq in 1..=350 {
+        store.xadd(&key, Some(seq), Some(0), &fields).unwrap();
+    }
+    let trim = |threshold, approximate, limit| StreamTrim {
+        threshold,
+        approximate,
+        limit,
+    };
+
+    // whole nodes of 100 entries only
+    assert_eq!(
+        store
+            .xtrim(&key, trim(TrimThreshold::MaxLen(120), true, None))
+            .unwrap(),
+        200
+    );
+    assert_eq!(
+        store
+            .xtrim(&key, trim(TrimThreshold::MaxLen(120), true, None))
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        store
+            .xtrim(&key, trim(TrimThreshold::MaxLen(120), false, None))
+            .unwrap(),
+        30
+    );
+
+    let min_id = TrimThreshold::MinId(StreamId { ms: 0, seq: 300 });
+    assert_eq!(store.xtrim(&key, trim(min_id, true, Some(10))).unwrap(), 0);
+    assert_eq!(store.xtrim(&key, trim(min_id, false, None)).unwrap(), 69);
+    assert_eq!(
+        store
+            .xrange(&key, Unbounded, Unbounded, None)
+            .unwrap()
+            .first()
+            .map(|(id, _)| *id),
+        Some(StreamId { ms: 0, seq: 300 })
+    );
+}
+
+#[test]
+fn test_xsetid_moves_the_last_id() {
+    use crate::parser::RedisType;
+
+    let mut store = Store::new();
+    let key = Bytes::from("stream");
+    let fields = [
+        RedisType::BulkString("field".into()),
+        RedisType::BulkString("value".into()),
+    ];
+    store.xadd(&key, Some(1), Some(5), &fields).unwrap();
+    store.xadd(&key, Some(2), Some(5), &fields).unwrap();
+    store.xdel(&key, &[StreamId { ms: 5, seq: 2 }]).unwrap();
+
+    assert!(matches!(
+        store.xsetid(&key, StreamId { ms: 5, seq: 0 }, None, None),
+        Err(StoreError::StreamIdSmallerThanLast)
+    ));
+    assert!(matches!(
+        store.xsetid(&key, StreamId { ms: 9, seq: 0 }, Some(0), None),
+        Err(StoreError::EntriesAddedSmallerThanLength)
+    ));
+    assert!(matches!(
+        store.xsetid(&"missing".into(), StreamId { ms: 9, seq: 0 }, None, None),
+        Err(StoreError::KeyNotFound)
+    ));
+
+    // below the deleted entry but not below the newest one
+    store
+        .xsetid(&key, StreamId { ms: 5, seq: 1 }, Some(7), None)
+        .unwrap();
+    let stream = store.stream(&key).unwrap().unwrap();
+    assert_eq!(stream.entries_added(), 7);
+    assert_eq!(stream.max_deleted_id(), StreamId { ms: 5, seq: 2 });
+    assert_eq!(
+        store.xadd(&key, None, Some(5), &fields).unwrap(),
+        StreamId { ms: 5, seq: 2 }
+    );
+}
+
+#[test]
+fn test_xrange_bounds_and_count() {
+    use crate::parser::RedisType;
+    use std::ops::Bound::{Excluded, Included};
+
+    let mut store = Store::new();
+    let key = Bytes::from("stream");
+    let fields = [
+        RedisType::BulkString("field".into()),
+        RedisType::BulkString("value".into()),
+    ];
+    for seq in 1..=5 {
+        store.xadd(&key, Some(seq), Some(1), &fields).unwrap();
+    }
+    let id = |seq| StreamId { ms: 1, seq };
+    let ids = |entries: Vec<(StreamId, StreamEntry)>| {
+        entries.into_iter().map(|(id, _)| id).collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        ids(store
+            .xrange(&key, Excluded(id(1)), Unbounded, Some(2))
+            .unwrap()),
+        vec![id(2), id(3)]
+    );
+    assert_eq!(
+        ids(store
+            .xrevrange(&key, Unbounded, Unbounded, Some(2))
+            .unwrap()),
+        vec![id(5), id(4)]
+    );
+    assert_eq!(
+        ids(store
+            .xrevrange(&key, Included(id(2)), Excluded(id(4)), None)
+            .unwrap()),
+        vec![id(3), id(2)]
+    );
+    // crossed bounds are empty instead of panicking
+    assert!(
+        store
+            .xrange(&key, Included(id(3)), Included(id(2)), None)
+            .unwrap()
+            .is_empty()
+    );
+    assert!(
+        store
+            .xrange(&key, Excluded(id(3)), Excluded(id(3)), None)
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_xread_count_limits_the_entries() {
+    use crate::parser::RedisType;
+
+    let mut store = Store::new();
+    let key = Bytes::from("stream");
+    let fields = [
+        RedisType::BulkString("field".into()),
+        RedisType::BulkString("value".into()),
+    ];
+    for seq in 1..=5 {
+        store.xadd(&key, Some(seq), Some(1), &fields).unwrap();
+    }
+
+    let first = StreamId { ms: 1, seq: 1 };
+    let read = store.xread(&key, first, false, Some(2)).unwrap();
+    assert_eq!(
+        read.iter().map(|(id, _)| id.seq).collect::<Vec<_>>(),
+        vec![2, 3]
+    );
+    assert_eq!(store.xread(&key, first, false, None).unwrap().len(), 4);
+}
+
+#[test]
+fn test_xinfo_stream_reports_the_bookkeeping() {
+    use crate::parser::RedisType;
+
+    let mut store = Store::new();
+    let key = Bytes::from("stream");
+    assert!(matches!(
+        store.xinfo_stream(&key),
+        Err(StoreError::KeyNotFound)
+    ));
+    let fields = [
+        RedisType::BulkString("field".into()),
+        RedisType::BulkString("value".into()),
+    ];
+    for seq in 1..=3 {
+        store.xadd(&key, Some(seq), Some(1), &fields).unwrap();
+    }
+    store.xdel(&key, &[StreamId { ms: 1, seq: 3 }]).unwrap();
+
+    let info = store.xinfo_stream(&key).unwrap();
+    assert_eq!(info.length, 2);
+    assert_eq!(info.entries_added, 3);
+    assert_eq!(info.last_generated_id, StreamId { ms: 1, seq: 3 });
+    assert_eq!(info.max_deleted_id, StreamId { ms: 1, seq: 3 });
+    assert_eq!(
+        info.first_entry.map(|(id, _)| id),
+        Some(StreamId { ms: 1, seq: 1 })
+    );
+    assert_eq!(
+        info.last_entry.map(|(id, _)| id),
+        Some(StreamId { ms: 1, seq: 2 })
+    );
+}
+
+#[test]
+fn test_xgroup_create_and_setid() {
+    use crate::parser::RedisType;
+
+    let mut store = Store::new();
+    let key = Bytes::from("stream");
+    let group = Bytes::from("group");
+    assert!(matches!(
+        store.xgroup_create(&key, &group, StreamId::default(), None, false),
+        Err(StoreError::KeyNotFound)
+    ));
+    store
+        .xgroup_create(&key, &group, StreamId::default(), None, true)
+        .unwrap();
+    assert!(matches!(
+        store.xgroup_create(&key, &group, StreamId::default(), None, true),
+        Err(StoreError::BusyGroup)
+    ));
+
+    let fields = [
+        RedisType::BulkString("field".into()),
+        RedisType::BulkString("value".into()),
+    ];
+    for seq in 1..=3 {
+        store.xadd(&key, Some(seq), Some(1), &fields).unwrap();
+    }
+    let info = |store: &Store| store.xinfo_groups(&key).unwrap().remove(0);
+    assert_eq!(info(&store).entries_read, Some(0));
+    assert_eq!(info(&store).lag, Some(3));
+
+    store
+        .xgroup_setid(&key, &group, StreamId { ms: 1, seq: 1 }, None)
+        .unwrap();
+    assert_eq!(info(&store).entries_read, Some(1));
+    assert_eq!(info(&store).lag, Some(2));
+
+    // a deleted entry the group has yet to read makes the lag unknown
+    store.xdel(&key, &[StreamId { ms: 1, seq: 2 }]).unwrap();
+    assert_eq!(info(&store).lag, None);
+    store
+        .xgroup_setid(&key, &group, StreamId { ms: 1, seq: 3 }, None)
+        .unwrap();
+    assert_eq!(info(&store).lag, Some(0));
+
+    assert!(matches!(
+        store.xgroup_setid(&key, &"missing".into(), StreamId::default(), None),
+        Err(StoreError::NoGroup)
+    ));
+    assert!(store.xgroup_destroy(&key, &group).unwrap());
+    assert!(!store.xgroup_destroy(&key, &group).unwrap());
+}
+
+#[test]
+fn test_xreadgroup_delivers_new_entries_once_and_replays_pending() {
+    use crate::parser::RedisType;
+
+    let mut store = Store::new();
+    let key = Bytes::from("stream");
+    let group = Bytes::from("group");
+    let (alice, bob) = (Bytes::from("alice"), Bytes::from("bob"));
+    let fields = [
+        RedisType::BulkString("field".into()),
+        RedisType::BulkString("value".into()),
+    ];
+    for seq in 1..=3 {
+        store.xadd(&key, Some(seq), Some(1), &fields).unwrap();
+    }
+    store
+        .xgroup_create(&key, &group, StreamId::default(), None, false)
+        .unwrap();
+    let ids = |entries: Vec<(StreamId, StreamEntry)>| {
+        entries
+            .into_iter()
+            .map(|(id, _)| id.seq)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        ids(store
+            .xreadgroup(&key, &group, &alice, Some(2), false)
+            .unwrap()),
+        vec![1, 2]
+    );
+    assert_eq!(
+        ids(store.xreadgroup(&key, &group, &bob, None, false).unwrap()),
+        vec![3]
+    );
+    assert!(
+        store
+            .xreadgroup(&key, &group, &bob, None, false)
+            .unwrap()
+            .is_empty()
+    );
+
+    // alice only sees her own pending entries, deleted ones without fields
+    store.xdel(&key, &[StreamId { ms: 1, seq: 1 }]).unwrap();
+    let pending = store
+        .xreadgroup_pending(&key, &group, &alice, StreamId::default(), None)
+        .unwrap();
+    assert_eq!(
+        pending
+            .iter()
+            .map(|(id, entry)| (id.seq, entry.is_some()))
+            .collect::<Vec<_>>(),
+        vec![(1, false), (2, true)]
+    );
+
+    let groups = store.xinfo_groups(&key).unwrap();
+    assert_eq!(groups[0].pending, 3);
+    assert_eq!(groups[0].entries_read, Some(3));
+    assert_eq!(groups[0].lag, Some(0));
+    assert!(matches!(
+        store.xreadgroup(&key, &"missing".into(), &alice, None, false),
+        Err(StoreError::NoGroup)
+    ));
+}
+
+#[test]
+fn test_xpending_summary_and_filters() {
+    use crate::parser::RedisType;
+    use std::ops::Bound::Included;
+
+    let mut store = Store::new();
+    let key = Bytes::from("stream");
+    let group = Bytes::from("group");
+    let (alice, bob) = (Bytes::from("alice"), Bytes::from("bob"));
+    let fields = [
+        RedisType::BulkString("field".into()),
+        RedisType::BulkString("value".into()),
+    ];
+    for seq in 1..=4 {
+        store.xadd(&key, Some(seq), Some(1), &fields).unwrap();
+    }
+    store
+        .xgroup_create(&key, &group, StreamId::default(), None, false)
+        .unwrap();
+    store
+        .xreadgroup(&key, &group, &alice, Some(3), false)
+        .unwrap();
+    store.xreadgroup(&key, &group, &bob, None, false).unwrap();
+    store
+        .xreadgroup_pending(&key, &group, &alice, StreamId::default(), Some(1))
+        .unwrap();
+
+    let summary = store.xpending_summary(&key, &group).unwrap();
+    assert_eq!(summary.count, 4);
+    assert_eq!(summary.first, Some(StreamId { ms: 1, seq: 1 }));
+    assert_eq!(summary.last, Some(StreamId { ms: 1, seq: 4 }));
+    assert_eq!(
+        summary.consumers,
+        vec![(alice.clone(), 3), (bob.clone(), 1)]
+    );
+
+    let all = (Unbounded, Unbounded);
+    let pending = store
+        .xpending(&key, &group, all, 10, Some(&alice), None)
+        .unwrap();
+    assert_eq!(
+        pending
+            .iter()
+            .map(|pending| (pending.id.seq, pending.delivery_count))
+            .collect::<Vec<_>>(),
+        vec![(1, 2), (2, 1), (3, 1)]
+    );
+    let range = (
+        Included(StreamId { ms: 1, seq: 2 }),
+        Included(StreamId { ms: 1, seq: 4 }),
+    );
+    assert_eq!(
+        store
+            .xpending(&key, &group, range, 2, None, None)
+            .unwrap()
+            .len(),
+        2
+    );
+    assert!(
+        store
+            .xpending(&key, &group, all, 10, None, Some(60_000))
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_xclaim_moves_idle_entries() {
+    use crate::parser::RedisType;
+
+    let mut store = Store::new();
+    let key = Bytes::from("stream");
+    let group = Bytes::from("group");
+    let (alice, bob) = (Bytes::from("alice"), Bytes::from("bob"));
+    let fields = [
+        RedisType::BulkString("field".into()),
+        RedisType::BulkString("value".into()),
+    ];
+    for seq in 1..=3 {
+        store.xadd(&key, Some(seq), Some(1), &fields).unwrap();
+    }
+    store
+        .xgroup_create(&key, &group, StreamId::default(), None, false)
+        .unwrap();
+    store
+        .xreadgroup(&key, &group, &alice, Some(2), false)
+        .unwrap();
+    let id = |seq| StreamId { ms: 1, seq };
+
+    // delivered just now, so not idle for a minute yet
+    let options = ClaimOptions {
+        min_idle: 60_000,
+        ..Default::default()
+    };
+    assert!(
+        store
+            .xclaim(&key, &group, &bob, &[id(1)], &options)
+            .unwrap()
+            .is_empty()
+    );
+
+    store.xdel(&key, &[id(2)]).unwrap();
+    let claimed = store
+        .xclaim(
+            &key,
+            &group,
+            &bob,
+            &[id(1), id(2), id(3)],
+            &Default::default(),
+        )
+        .unwrap();
+    assert_eq!(
+        claimed.iter().map(|(id, _)| id.seq).collect::<Vec<_>>(),
+        vec![1]
+    );
+    let pending = store
+        .xpending(&key, &group, (Unbounded, Unbounded), 10, None, None)
+        .unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].consumer, bob);
+    assert_eq!(pending[0].delivery_count, 2);
+
+    // FORCE claims an entry which was never delivered
+    let options = ClaimOptions {
+        force: true,
+        retry_count: Some(5),
+        last_id: Some(id(3)),
+        ..Default::default()
+    };
+    store
+        .xclaim(&key, &group, &alice, &[id(3)], &options)
+        .unwrap();
+    let summary = store.xpending_summary(&key, &group).unwrap();
+    assert_eq!(summary.consumers, vec![(alice, 1), (bob, 1)]);
+    assert_eq!(
+        store.xinfo_groups(&key).unwrap()[0].last_delivered_id,
+        id(3)
+    );
+}
+
+#[test]
+fn test_xautoclaim_continues_with_a_cursor() {
+    use crate::parser::RedisType;
+    use std::ops::Bound::Included;
+
+    let mut store = Store::new();
+    let key = Bytes::from("stream");
+    let group = Bytes::from("group");
+    let (alice, bob) = (Bytes::from("alice"), Bytes::from("bob"));
+    let fields = [
+        RedisType::BulkString("field".into()),
+        RedisType::BulkString("value".into()),
+    ];
+    for seq in 1..=5 {
+        store.xadd(&key, Some(seq), Some(1), &fields).unwrap();
+    }
+    store
+        .xgroup_create(&key, &group, StreamId::default(), None, false)
+        .unwrap();
+    store.xreadgroup(&key, &group, &alice, None, false).unwrap();
+    store.xdel(&key, &[StreamId { ms: 1, seq: 2 }]).unwrap();
+    let options = ClaimOptions::default();
+    let seqs = |claimed: &[(StreamId, StreamEntry)]| {
+        claimed.iter().map(|(id, _)| id.seq).collect::<Vec<_>>()
+    };
+
+    let first = store
+        .xautoclaim(&key, &group, &bob, Unbounded, 2, &options)
+        .unwrap();
+    assert_eq!(seqs(&first.claimed), vec![1, 3]);
+    assert_eq!(first.deleted, vec![StreamId { ms: 1, seq: 2 }]);
+    assert_eq!(first.next, StreamId { ms: 1, seq: 4 });
+
+    let second = store
+        .xautoclaim(&key, &group, &bob, Included(first.next), 2, &options)
+        .unwrap();
+    assert_eq!(seqs(&second.claimed), vec![4, 5]);
+    assert_eq!(second.next, StreamId::default());
+    assert_eq!(
+        store.xpending_summary(&key, &group).unwrap().consumers,
+        vec![(bob, 4)]
+    );
+}
+
+/// Timings of XADD and the range lookups on a 1M entry stream. Run with
+/// `cargo test --release -- --ignored --nocapture bench_stream`
+#[test]
+#[ignore]
+fn bench_stream_one_million_entries() {
+    use crate::parser::RedisType;
+    use rand::Rng;
+    use std::ops::Bound::Included;
+    use std::time::Instant;
+
+    const ENTRIES: u128 = 1_000_000;
+    const LOOKUPS: usize = 1_000;
+    let mut store = Store::new();
+    let key = Bytes::from("stream");
+    let fields = [
+        RedisType::BulkString("field".into()),
+        RedisType::BulkString("value".into()),
+    ];
+    let id = |ms| StreamId { ms, seq: 0 };
+    let mut rng = rand::rng();
+
+    let started = Instant::now();
+    for ms in 1..=ENTRIES {
+        store.xadd(&key, Some(0), Some(ms), &fields).unwrap();
+    }
+    println!("XADD x{}: {:?}", ENTRIES, started.elapsed());
+
+    let started = Instant::now();
+    for _ in 0..LOOKUPS {
+        let start = rng.random_range(1..ENTRIES - 10);
+        let range = store
+            .xrange(&key, Included(id(start)), Unbounded, Some(10))
+            .unwrap();
+        assert_eq!(range.len(), 10);
+    }
+    println!("XRANGE (10 entries) x{}: {:?}", LOOKUPS, started.elapsed());
+
+    let started = Instant::now();
+    for _ in 0..LOOKUPS {
+        let after = rng.random_range(1..ENTRIES - 10);
+        assert_eq!(
+            store.xread(&key, id(after), false, Some(10)).unwrap().len(),
+            10
+        );
+    }
+    println!("XREAD (10 entries) x{}: {:?}", LOOKUPS, started.elapsed());
+
+    let started = Instant::now();
+    let trim = StreamTrim {
+        threshold: TrimThreshold::MinId(id(ENTRIES / 2)),
+        approximate: false,
+        limit: None,
+    };
+    assert_eq!(store.xtrim(&key, trim).unwrap(), ENTRIES as usize / 2 - 1);
+    println!("XTRIM MINID (half the stream): {:?}", started.elapsed());
+}
@@ -0,0 +1,195 @@
+use std::collections::{HashMap, HashSet};
+
+use bytes::Bytes;
+
+use super::{Store, SubscriptionKind};
+use crate::parser::RedisType;
+
+/// Channel RESP2 clients subscribe to in order to receive invalidations for another client
+pub const INVALIDATE_CHANNEL: &[u8] = b"__redis__:invalidate";
+
+/// How a client tracks the keys it caches, set with CLIENT TRACKING ON
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrackingOptions {
+    /// the client which receives the invalidations instead, RESP2 clients need one
+    pub redirect: Option<u64>,
+    /// every modified key matching one of the prefixes is invalidated, not only those read
+    pub bcast: bool,
+    /// BCAST prefixes, none means every key
+    pub prefixes: Vec<Bytes>,
+    /// only keys read right after CLIENT CACHING YES are tracked
+    pub optin: bool,
+    /// keys read right after CLIENT CACHING NO are not tracked
+    pub optout: bool,
+    /// no invalidations for keys the client modified itself
+    pub noloop: bool,
+}
+
+/// The clients to invalidate on modification, by key and by BCAST prefix
+#[derive(Default)]
+pub struct TrackingTable {
+    keys: HashMap<Bytes, HashSet<u64>>,
+    prefixes: HashMap<Bytes, HashSet<u64>>,
+}
+
+impl Store {
+    /// Enables tracking for the client, BCAST clients are registered for their prefixes right
+    /// away
+    pub fn enable_tracking(&mut self, client_id: u64, options: TrackingOptions) {
+        self.disable_tracking(client_id);
+        if options.bcast {
+            let prefixes = if options.prefixes.is_empty() {
+                vec![Bytes::new()]
+            } else {
+                options.prefixes.clone()
+            };
+            for prefix in prefixes {
+                self.tracking
+                    .prefixes
+                    .entry(prefix)
+                    .or_default()
+                    .insert(client_id);
+            }
+        }
+        self.client_mut(client_id).tracking = Some(options);
+    }
+
+    /// Forgets the keys and prefixes the client tracked
+    pub fn disable_tracking(&mut self, client_id: u64) {
+        if let Some(client) = self.clients.get_mut(&client_id) {
+            client.tracking = None;
+            client.caching = None;
+        }
+        for clients in self
+            .tracking
+            .keys
+            .values_mut()
+            .chain(self.tracking.prefixes.values_mut())
+        {
+            clients.remove(&client_id);
+        }
+        self.tracking.keys.retain(|_, clients| !clients.is_empty());
+        self.tracking
+            .prefixes
+            .retain(|_, clients| !clients.is_empty());
+    }
+
+    /// Remembers that the client read the keys, the next modification of each invalidates it.
+    /// Honors OPTIN and OPTOUT, BCAST clients track by prefix instead.
+    pub fn track_keys(&mut self, client_id: u64, keys: Vec<Bytes>) {
+        let Some(client) = self.clients.get(&client_id) else {
+            return;
+        };
+        let Some(options) = &client.tracking else {
+            return;
+        };
+        let tracked = if options.bcast {
+            false
+        } else if options.optin {
+            client.caching == Some(true)
+        } else if options.optout {
+            client.caching != Some(false)
+        } else {
+            true
+        };
+        if !tracked {
+            return;
+        }
+        for key in keys {
+            self.tracking.keys.entry(key).or_default().insert(client_id);
+        }
+    }
+
+    /// Sends the invalidation of the modified key to the clients which track it. Clients which
+    /// read the key have to read it again to be told about the next modification.
+    pub(super) fn invalidate_key(&mut self, key: &Bytes) {
+        if self.tracking.keys.is_empty() && self.tracking.prefixes.is_empty() {
+            return;
+        }
+        let mut clients = self.tracking.keys.remove(key).unwrap_or_default();
+        for (prefix, prefix_clients) in &self.tracking.prefixes {
+            if key.starts_with(prefix) {
+                clients.extend(prefix_clients);
+            }
+        }
+        for client_id in clients {
+            self.send_invalidation(client_id, key);
+        }
+    }
+
+    fn send_invalidation(&self, client_id: u64, key: &Bytes) {
+        let Some(client) = self.clients.get(&client_id) else {
+            return;
+        };
+        let Some(options) = &client.tracking else {
+            return;
+        };
+        if options.noloop && self.current_client == Some(client_id) {
+            return;
+        }
+        let Some(target) = self.clients.get(&options.redirect.unwrap_or(client_id)) else {
+            return;
+        };
+        let keys = RedisType::Array(Some(vec![RedisType::BulkString(key.clone())]));
+        if target.protocol.get() > 2 {
+            target.push(RedisType::Push(vec![
+                RedisType::BulkString(Bytes::from_static(b"invalidate")),
+                keys,
+            ]));
+        } else if target
+            .subscriptions
+            .names(SubscriptionKind::Channel)
+            .iter()
+            .any(|channel| channel.as_ref() == INVALIDATE_CHANNEL)
+        {
+            // RESP2 has no pushes, the invalidation looks like a published message
+            target.push(RedisType::Push(vec![
+                RedisType::BulkString(Bytes::from_static(b"message")),
+                RedisType::BulkString(Bytes::from_static(INVALIDATE_CHANNEL)),
+                keys,
+            ]));
+        }
+    }
+}
+
+#[test]
+fn test_modified_keys_are_invalidated_once() {
+    use super::test_client;
+
+    let mut store = Store::new();
+    let mut inbox = test_client(&mut store, 1);
+    store.client_mut(1).subscriptions.subscribe(
+        SubscriptionKind::Channel,
+        &[Bytes::from_static(INVALIDATE_CHANNEL)],
+    );
+    store.enable_tracking(1, TrackingOptions::default());
+
+    let key = Bytes::from("k");
+    store.track_keys(1, vec![key.clone()]);
+    store.rpush(key.clone(), vec![Bytes::from("a")]).unwrap();
+    assert_eq!(
+        inbox.try_recv().unwrap(),
+        RedisType::Push(vec![
+            RedisType::BulkString(Bytes::from_static(b"message")),
+            RedisType::BulkString(Bytes::from_static(INVALIDATE_CHANNEL)),
+            RedisType::Array(Some(vec![RedisType::BulkString(key.clone())])),
+        ])
+    );
+    store.rpush(key.clone(), vec![Bytes::from("b")]).unwrap();
+    assert!(inbox.try_recv().is_err());
+
+    store.enable_tracking(
+        1,
+        TrackingOptions {
+            bcast: true,
+            prefixes: vec![Bytes::from("user:")],
+            ..Default::default()
+        },
+    );
+    store.rpush(key, vec![Bytes::from("c")]).unwrap();
+    assert!(inbox.try_recv().is_err());
+    store
+        .rpush(Bytes::from("user:1"), vec![Bytes::from("a")])
+        .unwrap();
+    assert!(inbox.try_recv().is_ok());
+}
@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+use super::Store;
+
+/// A key watched by at least one client
+pub struct WatchedKey {
+    /// bumped on every modification of the key
+    version: u64,
+    /// number of clients watching the key
+    watchers: usize,
+}
+
+/// Versions of the watched keys, only keys at least one client watches are tracked
+pub type WatchedKeys = HashMap<Bytes, WatchedKey>;
+
+impl Store {
+    /// Starts watching the keys, returns the version of each so a later EXEC can tell whether
+    /// they were modified meanwhile
+    pub fn watch(&mut self, keys: &[Bytes]) -> Vec<(Bytes, u64)> {
+        keys.iter()
+            .map(|key| {
+                let watched = self.watched_keys.entry(key.clone()).or_insert(WatchedKey {
+                    version: 0,
+                    watchers: 0,
+                });
+                watched.watchers += 1;
+                (key.clone(), watched.version)
+            })
+            .collect()
+    }
+
+    /// Stops watching the keys a client watched
+    pub fn unwatch(&mut self, watched: &[(Bytes, u64)]) {
+        for (key, _) in watched {
+            if let Some(entry) = self.watched_keys.get_mut(key) {
+                entry.watchers -= 1;
+                if entry.watchers == 0 {
+                    self.watched_keys.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Whether none of the keys was modified since the client started watching them
+    pub fn watched_keys_unchanged(&self, watched: &[(Bytes, u64)]) -> bool {
+        watched.iter().all(|(key, version)| {
+            self.watched_keys
+                .get(key)
+                .is_some_and(|entry| entry.version == *version)
+        })
+    }
+
+    /// Records a modification of the key for the clients watching it, and invalidates it for
+    /// the clients tracking it
+    pub(super) fn touch_key(&mut self, key: &Bytes) {
+        if let Some(entry) = self.watched_keys.get_mut(key) {
+            entry.version += 1;
+        }
+        self.invalidate_key(key);
+    }
+}
+
+#[test]
+fn test_modifying_a_watched_key_changes_its_version() {
+    let mut store = Store::new();
+    let key = Bytes::from("k");
+    let other = Bytes::from("other");
+
+    let watched = store.watch(std::slice::from_ref(&key));
+    store.rpush(other.clone(), vec![Bytes::from("a")]).unwrap();
+    assert!(store.watched_keys_unchanged(&watched));
+
+    let again = store.watch(std::slice::from_ref(&key));
+    store.rpush(key.clone(), vec![Bytes::from("a")]).unwrap();
+    assert!(!store.watched_keys_unchanged(&watched));
+
+    store.unwatch(&watched);
+    assert!(store.watched_keys.contains_key(&key));
+    store.unwatch(&again);
+    assert!(store.watched_keys.is_empty());
+}
@@ -0,0 +1,281 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    ops::Bound::{self, Excluded, Included, Unbounded},
+};
+
+use bytes::Bytes;
+
+use super::{scan::ScanIndex, skiplist::SkipList};
+
+/// Score of a sorted set member. Scores are never NaN, which gives them a total order;
+/// -0.0 is stored as 0.0 so both sort (and compare) as the same score.
+#[derive(Clone, Copy, Debug)]
+struct Score(f64);
+
+impl Score {
+    fn new(score: f64) -> Self {
+        Score(score + 0.0)
+    }
+}
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Members of a sorted set. The map answers score lookups in O(1), the skip list keeps the
+/// members sorted by score and then lexicographically, like redis does, and answers ranks
+/// and positional lookups in O(log n). The scan index keeps the members in cursor order for
+/// ZSCAN.
+#[derive(Default)]
+pub struct ZSetValue {
+    scores: HashMap<Bytes, Score>,
+    ordered: SkipList<(Score, Bytes)>,
+    scan_index: ScanIndex,
+}
+
+impl ZSetValue {
+    /// Sets the score of the member, returns false if the member was already part of the set
+    pub fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        let score = Score::new(score);
+        match self.scores.insert(member.clone(), score) {
+            Some(previous) => {
+                if previous != score {
+                    self.ordered.remove(&(previous, member.clone()));
+                    self.ordered.insert((score, member));
+                }
+                false
+            }
+            None => {
+                self.scan_index.insert(&member);
+                self.ordered.insert((score, member));
+                true
+            }
+        }
+    }
+
+    /// Returns false if the member was not part of the set
+    pub fn remove(&mut self, member: &Bytes) -> bool {
+        let Some(score) = self.scores.remove(member) else {
+            return false;
+        };
+        self.ordered.remove(&(score, member.clone()));
+        self.scan_index.remove(member);
+        true
+    }
+
+    pub fn score(&self, member: &Bytes) -> Option<f64> {
+        self.scores.get(member).map(|score| score.0)
+    }
+
+    /// Zero based position of the member in score order
+    pub fn rank(&self, member: &Bytes) -> Option<usize> {
+        let score = self.scores.get(member)?;
+        self.ordered.rank(&(*score, member.clone()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// Removes and returns the member with the lowest score, or the highest with `highest`
+    pub fn pop(&mut self, highest: bool) -> Option<(Bytes, f64)> {
+        let entry = if highest {
+            self.ordered.last()?
+        } else {
+            self.ordered.first()?
+        };
+        let (score, member) = self.ordered.remove(&entry.clone())?;
+        self.scores.remove(&member);
+        self.scan_index.remove(&member);
+        Some((member, score.0))
+    }
+
+    /// The members at the given positions (in score order), in the order the positions are
+    /// given. Positions may repeat, positions past the end are skipped.
+    pub fn members_at(&self, positions: &[usize]) -> Vec<(&Bytes, f64)> {
+        positions
+            .iter()
+            .filter_map(|position| self.ordered.get(*position))
+            .map(|(score, member)| (member, score.0))
+            .collect()
+    }
+
+    /// One page of ZSCAN with the scores, along with the cursor of the next page
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(&Bytes, f64)>) {
+        let (next_cursor, page) = self.scan_index.page(cursor, count);
+        let members = page
+            .into_iter()
+            .map(|member| (member, self.scores[member].0))
+            .collect();
+        (next_cursor, members)
+    }
+
+    /// Iterates over the members in score order
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&Bytes, f64)> {
+        self.ordered.iter().map(|(score, member)| (member, score.0))
+    }
+
+    /// Members with a position in `start..end`, in score order
+    pub fn range_by_rank(&self, start: usize, end: usize) -> MemberRange<'_> {
+        Box::new(
+            self.ordered
+                .range(start, end)
+                .map(|(score, member)| (member, score.0)),
+        )
+    }
+
+    /// Members whose score lies within the bounds, in score order
+    pub fn range_by_score(&self, min: Bound<f64>, max: Bound<f64>) -> MemberRange<'_> {
+        let start = match min {
+            Included(min) => self.ordered.count_before(|(score, _)| score.0 < min),
+            Excluded(min) => self.ordered.count_before(|(score, _)| score.0 <= min),
+            Unbounded => 0,
+        };
+        let end = match max {
+            Included(max) => self.ordered.count_before(|(score, _)| score.0 <= max),
+            Excluded(max) => self.ordered.count_before(|(score, _)| score.0 < max),
+            Unbounded => self.len(),
+        };
+        self.range_by_rank(start, end)
+    }
+
+    /// Members within the lexicographic bounds, in order. Like in redis this is only
+    /// meaningful if all members have the same score.
+    pub fn range_by_lex(&self, min: Bound<Bytes>, max: Bound<Bytes>) -> MemberRange<'_> {
+        let start = match &min {
+            Included(min) => self.ordered.count_before(|(_, member)| member < min),
+            Excluded(min) => self.ordered.count_before(|(_, member)| member <= min),
+            Unbounded => 0,
+        };
+        let end = match &max {
+            Included(max) => self.ordered.count_before(|(_, member)| member <= max),
+            Excluded(max) => self.ordered.count_before(|(_, member)| member < max),
+            Unbounded => self.len(),
+        };
+        self.range_by_rank(start, end)
+    }
+}
+
+/// Members of a sorted set within some range, with their scores
+pub type MemberRange<'a> = Box<dyn DoubleEndedIterator<Item = (&'a Bytes, f64)> + 'a>;
+
+#[test]
+fn test_members_are_ordered_by_score_then_member() {
+    let mut zset = ZSetValue::default();
+    assert!(zset.insert("b".into(), 1.0));
+    assert!(zset.insert("a".into(), 1.0));
+    assert!(zset.insert("c".into(), -0.5));
+    assert!(!zset.insert("c".into(), 2.0));
+
+    let order: Vec<(&Bytes, f64)> = zset.iter().collect();
+    assert_eq!(
+        order,
+        vec![
+            (&Bytes::from("a"), 1.0),
+            (&Bytes::from("b"), 1.0),
+            (&Bytes::from("c"), 2.0)
+        ]
+    );
+    assert_eq!(zset.rank(&"b".into()), Some(1));
+    assert_eq!(zset.rank(&"missing".into()), None);
+
+    assert!(zset.remove(&"a".into()));
+    assert_eq!(zset.rank(&"b".into()), Some(0));
+    assert_eq!(zset.len(), 2);
+}
+
+#[test]
+fn test_negative_zero_is_zero() {
+    let mut zset = ZSetValue::default();
+    zset.insert("a".into(), -0.0);
+    assert!(zset.score(&"a".into()).unwrap().is_sign_positive());
+}
+
+#[test]
+fn test_range_by_score_bounds() {
+    let zset: ZSetValue = {
+        let mut zset = ZSetValue::default();
+        for (member, score) in [("a", 1.0), ("b", 2.0), ("c", 2.0), ("d", 3.0)] {
+            zset.insert(member.into(), score);
+        }
+        zset
+    };
+    let members = |range: MemberRange| range.map(|(m, _)| m.clone()).collect::<Vec<_>>();
+
+    assert_eq!(
+        members(zset.range_by_score(Included(2.0), Included(2.0))),
+        vec![Bytes::from("b"), Bytes::from("c")]
+    );
+    assert_eq!(
+        members(zset.range_by_score(Excluded(1.0), Excluded(3.0))),
+        vec![Bytes::from("b"), Bytes::from("c")]
+    );
+    assert_eq!(
+        members(zset.range_by_score(Included(f64::NEG_INFINITY), Included(f64::INFINITY))).len(),
+        4
+    );
+    assert!(members(zset.range_by_score(Included(3.0), Included(1.0))).is_empty());
+    assert!(members(zset.range_by_score(Excluded(2.0), Excluded(2.0))).is_empty());
+}
+
+#[test]
+fn test_range_by_lex_bounds() {
+    let mut zset = ZSetValue::default();
+    for member in ["a", "b", "c", "d"] {
+        zset.insert(member.into(), 0.0);
+    }
+    let members = |range: MemberRange| range.map(|(m, _)| m.clone()).collect::<Vec<_>>();
+
+    assert_eq!(
+        members(zset.range_by_lex(Excluded("a".into()), Included("c".into()))),
+        vec![Bytes::from("b"), Bytes::from("c")]
+    );
+    assert_eq!(
+        members(zset.range_by_lex(Unbounded, Excluded("b".into()))),
+        vec![Bytes::from("a")]
+    );
+    assert!(members(zset.range_by_lex(Included("c".into()), Included("b".into()))).is_empty());
+}
+
+#[test]
+fn test_members_at_keeps_the_given_order() {
+    let mut zset = ZSetValue::default();
+    for (member, score) in [("a", 1.0), ("b", 2.0), ("c", 3.0)] {
+        zset.insert(member.into(), score);
+    }
+    let members: Vec<&Bytes> = zset
+        .members_at(&[2, 0, 2, 1])
+        .into_iter()
+        .map(|(member, _)| member)
+        .collect();
+    assert_eq!(
+        members,
+        vec![
+            &Bytes::from("c"),
+            &Bytes::from("a"),
+            &Bytes::from("c"),
+            &Bytes::from("b")
+        ]
+    );
+}
@@ -0,0 +1,961 @@
+use std::{collections::HashMap, ops::Bound};
+
+use bytes::Bytes;
+
+use rand::{Rng, seq::index};
+use tokio::sync::oneshot;
+
+use super::{Store, StoreError, Value, WaitingZPOPClient, ZSetValue};
+use crate::{parser::RedisType, transactions::create_identifier};
+
+/// Flags of ZADD deciding which members are added or updated
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ZAddOptions {
+    /// Only add new members, never update existing ones
+    pub nx: bool,
+    /// Only update existing members, never add new ones
+    pub xx: bool,
+    /// Only update if the new score is greater than the current one
+    pub gt: bool,
+    /// Only update if the new score is less than the current one
+    pub lt: bool,
+    /// Count members whose score changed in addition to the added ones
+    pub ch: bool,
+}
+
+impl ZAddOptions {
+    /// Whether a member with the `current` score (`None` if it is not part of the set)
+    /// may be set to `score`
+    fn allows(&self, current: Option<f64>, score: f64) -> bool {
+        match current {
+            None => !self.xx,
+            Some(_) if self.nx => false,
+            Some(current) if self.gt => score > current,
+            Some(current) if self.lt => score < current,
+            Some(_) => true,
+        }
+    }
+}
+
+/// Members of a sorted set together with their scores, in the order they were selected
+pub type ScoredMembers = Vec<(Bytes, f64)>;
+
+/// How ZUNION and ZINTER combine the scores of a member found in several inputs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            // inf + -inf, redis settles on 0 for it
+            Aggregate::Sum => zero_if_nan(a + b),
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ZSetOperation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+fn zero_if_nan(score: f64) -> f64 {
+    if score.is_nan() { 0.0 } else { score }
+}
+
+/// Which members of a sorted set a range selects
+#[derive(Clone, Debug)]
+pub enum ZRangeBy {
+    /// Positions, negative ones count from the end
+    Index(i128, i128),
+    Score(Bound<f64>, Bound<f64>),
+    Lex(Bound<Bytes>, Bound<Bytes>),
+}
+
+/// A range over a sorted set as understood by ZRANGE and friends
+#[derive(Clone, Debug)]
+pub struct ZRange {
+    pub by: ZRangeBy,
+    /// Walk from the highest score down, index ranges then count from the highest score as well
+    pub reverse: bool,
+    /// Members of the range skipped before the first returned one
+    pub offset: usize,
+    /// Maximum number of returned members, `None` returns all
+    pub count: Option<usize>,
+}
+
+impl ZRange {
+    pub fn new(by: ZRangeBy) -> Self {
+        ZRange {
+            by,
+            reverse: false,
+            offset: 0,
+            count: None,
+        }
+    }
+}
+
+/// Converts a possibly negative index range into positions within `len` members,
+/// `None` if it selects nothing
+fn index_range(start: i128, stop: i128, len: usize) -> Option<(usize, usize)> {
+    let len = len as i128;
+    let start = if start < 0 { start + len } else { start }.max(0);
+    let stop = if stop < 0 { stop + len } else { stop }.min(len - 1);
+    (start <= stop).then_some((start as usize, stop as usize))
+}
+
+/// The members of the range together with their scores
+fn members_in_range<'a>(
+    zset: &'a ZSetValue,
+    range: &ZRange,
+) -> Box<dyn Iterator<Item = (&'a Bytes, f64)> + 'a> {
+    let members = match &range.by {
+        ZRangeBy::Index(start, stop) => {
+            let Some((start, stop)) = index_range(*start, *stop, zset.len()) else {
+                return Box::new(std::iter::empty());
+            };
+            // reversed positions count from the highest score, map them to positions from the lowest
+            if range.reverse {
+                zset.range_by_rank(zset.len() - 1 - stop, zset.len() - start)
+            } else {
+                zset.range_by_rank(start, stop + 1)
+            }
+        }
+        ZRangeBy::Score(min, max) => zset.range_by_score(*min, *max),
+        ZRangeBy::Lex(min, max) => zset.range_by_lex(min.clone(), max.clone()),
+    };
+    let members: Box<dyn Iterator<Item = (&Bytes, f64)>> = if range.reverse {
+        Box::new(members.rev())
+    } else {
+        members
+    };
+    Box::new(
+        members
+            .skip(range.offset)
+            .take(range.count.unwrap_or(usize::MAX)),
+    )
+}
+
+impl Store {
+    /// Adds the members with their scores or updates the score of existing members, returns
+    /// the number of members which were added (and changed, with `ch`)
+    pub fn zadd(
+        &mut self,
+        key: &Bytes,
+        members: Vec<(f64, Bytes)>,
+        options: ZAddOptions,
+    ) -> Result<usize, StoreError> {
+        // XX never creates the key
+        if options.xx && self.zset(key)?.is_none() {
+            return Ok(0);
+        }
+
+        let zset = self.zset_entry(key)?;
+        let mut count = 0;
+        let mut changed = false;
+        for (score, member) in members {
+            let current = zset.score(&member);
+            if !options.allows(current, score) {
+                continue;
+            }
+            let added = zset.insert(member, score);
+            changed |= added || current != Some(score);
+            if added || (options.ch && current != Some(score)) {
+                count += 1;
+            }
+        }
+        if changed {
+            self.touch_key(key);
+        }
+        self.signal_key_as_ready(key);
+        Ok(count)
+    }
+
+    /// Increments the score of the member (a missing member starts at 0), returns the new score
+    /// or `None` if the options prevented the update
+    pub fn zincrby(
+        &mut self,
+        key: &Bytes,
+        increment: f64,
+        member: &Bytes,
+        options: ZAddOptions,
+    ) -> Result<Option<f64>, StoreError> {
+        let current = self.zset(key)?.and_then(|zset| zset.score(member));
+        let score = current.unwrap_or(0.0) + increment;
+        if score.is_nan() {
+            return Err(StoreError::ScoreIsNaN);
+        }
+        if !options.allows(current, score) {
+            return Ok(None);
+        }
+
+        self.zset_entry(key)?.insert(member.clone(), score);
+        self.touch_key(key);
+        self.signal_key_as_ready(key);
+        Ok(Some(score))
+    }
+
+    pub fn zscore(&self, key: &Bytes, member: &Bytes) -> Result<Option<f64>, StoreError> {
+        Ok(self.zset(key)?.and_then(|zset| zset.score(member)))
+    }
+
+    pub fn zcard(&self, key: &Bytes) -> Result<usize, StoreError> {
+        Ok(self.zset(key)?.map(|zset| zset.len()).unwrap_or(0))
+    }
+
+    /// Rank and score of the member, the rank counts from the lowest score unless `reverse` is set
+    pub fn zrank(
+        &self,
+        key: &Bytes,
+        member: &Bytes,
+        reverse: bool,
+    ) -> Result<Option<(usize, f64)>, StoreError> {
+        let Some(zset) = self.zset(key)? else {
+            return Ok(None);
+        };
+        Ok(zset
+            .rank(member)
+            .zip(zset.score(member))
+            .map(|(rank, score)| {
+                if reverse {
+                    (zset.len() - 1 - rank, score)
+                } else {
+                    (rank, score)
+                }
+            }))
+    }
+
+    pub fn zrange(&self, key: &Bytes, range: &ZRange) -> Result<Vec<(Bytes, f64)>, StoreError> {
+        Ok(self
+            .zset(key)?
+            .map(|zset| {
+                members_in_range(zset, range)
+                    .map(|(member, score)| (member.clone(), score))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Number of members in the range, without collecting them
+    pub fn zcount(&self, key: &Bytes, range: &ZRange) -> Result<usize, StoreError> {
+        Ok(self
+            .zset(key)?
+            .map(|zset| members_in_range(zset, range).count())
+            .unwrap_or(0))
+    }
+
+    /// Stores the members of the range of `source` at `destination`, replacing whatever it
+    /// holds. Returns the number of stored members, an empty range deletes the destination.
+    pub fn zrangestore(
+        &mut self,
+        destination: &Bytes,
+        source: &Bytes,
+        range: &ZRange,
+    ) -> Result<usize, StoreError> {
+        let members = self.zrange(source, range)?;
+        Ok(self.zstore(destination, members))
+    }
+
+    /// Removes and returns up to `count` members with the lowest scores (highest with `highest`)
+    pub fn zpop(
+        &mut self,
+        key: &Bytes,
+        count: usize,
+        highest: bool,
+    ) -> Result<Vec<(Bytes, f64)>, StoreError> {
+        let Some(zset) = self.zset_mut(key)? else {
+            return Ok(vec![]);
+        };
+
+        let popped: Vec<(Bytes, f64)> = std::iter::from_fn(|| zset.pop(highest))
+            .take(count)
+            .collect();
+        if !popped.is_empty() {
+            self.touch_key(key);
+            self.remove_if_empty(key);
+        }
+        Ok(popped)
+    }
+
+    /// Pops from the first of the keys holding a non-empty sorted set, returns that key
+    /// together with the popped members
+    pub fn zmpop(
+        &mut self,
+        keys: &[Bytes],
+        count: usize,
+        highest: bool,
+    ) -> Result<Option<(Bytes, ScoredMembers)>, StoreError> {
+        for key in keys {
+            if self.zset(key)?.is_some() {
+                return Ok(Some((key.clone(), self.zpop(key, count, highest)?)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns random members without removing them. A positive count returns up to `count`
+    /// distinct members, a negative count returns exactly `-count` members which may repeat.
+    pub fn zrandmember(&self, key: &Bytes, count: i128) -> Result<ScoredMembers, StoreError> {
+        let Some(zset) = self.zset(key)? else {
+            return Ok(vec![]);
+        };
+
+        let mut rng = rand::rng();
+        let len = zset.len();
+        let positions: Vec<usize> = if count < 0 {
+            (0..count.unsigned_abs())
+                .map(|_| rng.random_range(0..len))
+                .collect()
+        } else if count as usize >= len {
+            (0..len).collect()
+        } else {
+            index::sample(&mut rng, len, count as usize).into_vec()
+        };
+        Ok(zset
+            .members_at(&positions)
+            .into_iter()
+            .map(|(member, score)| (member.clone(), score))
+            .collect())
+    }
+
+    pub fn zscan(
+        &self,
+        key: &Bytes,
+        cursor: u64,
+        count: usize,
+    ) -> Result<(u64, ScoredMembers), StoreError> {
+        let Some(zset) = self.zset(key)? else {
+            return Ok((0, vec![]));
+        };
+
+        let (next_cursor, page) = zset.scan(cursor, count);
+        Ok((
+            next_cursor,
+            page.into_iter()
+                .map(|(member, score)| (member.clone(), score))
+                .collect(),
+        ))
+    }
+
+    /// Combines the sorted sets (or plain sets, whose members score 1) at the keys. The scores of
+    /// every input are multiplied by its weight (1 if missing) first, the difference keeps the
+    /// scores of the first input. Returns the members in score order.
+    pub fn zcombine(
+        &self,
+        keys: &[Bytes],
+        operation: ZSetOperation,
+        weights: &[f64],
+        aggregate: Aggregate,
+    ) -> Result<ScoredMembers, StoreError> {
+        let inputs = keys
+            .iter()
+            .map(|key| self.scored_input(key))
+            .collect::<Result<Vec<_>, StoreError>>()?;
+        // 0 * inf is NaN, redis treats it as 0
+        let weighted = |input: usize, score: f64| {
+            zero_if_nan(score * weights.get(input).copied().unwrap_or(1.0))
+        };
+
+        let mut combined: HashMap<Bytes, f64> = HashMap::new();
+        match operation {
+            ZSetOperation::Union => {
+                for (input, members) in inputs.iter().enumerate() {
+                    for (member, score) in members.iter().flatten() {
+                        let score = weighted(input, *score);
+                        combined
+                            .entry(member.clone())
+                            .and_modify(|combined| *combined = aggregate.apply(*combined, score))
+                            .or_insert(score);
+                    }
+                }
+            }
+            ZSetOperation::Intersection => {
+                // a missing key is an empty set, so the intersection is empty as well
+                let Some(inputs) = inputs.into_iter().collect::<Option<Vec<_>>>() else {
+                    return Ok(vec![]);
+                };
+                let Some((first, others)) = inputs.split_first() else {
+                    return Ok(vec![]);
+                };
+                'members: for (member, score) in first {
+                    let mut score = weighted(0, *score);
+                    for (input, other) in others.iter().enumerate() {
+                        let Some(other_score) = other.get(member) else {
+                            continue 'members;
+                        };
+                        score = aggregate.apply(score, weighted(input + 1, *other_score));
+                    }
+                    combined.insert(member.clone(), score);
+                }
+            }
+            ZSetOperation::Difference => {
+                let Some((Some(first), others)) = inputs.split_first() else {
+                    return Ok(vec![]);
+                };
+                combined = first
+                    .iter()
+                    .filter(|(member, _)| {
+                        !others
+                            .iter()
+                            .flatten()
+                            .any(|other| other.contains_key(*member))
+                    })
+                    .map(|(member, score)| (member.clone(), *score))
+                    .collect();
+            }
+        }
+
+        let mut zset = ZSetValue::default();
+        for (member, score) in combined {
+            zset.insert(member, score);
+        }
+        Ok(zset
+            .iter()
+            .map(|(member, score)| (member.clone(), score))
+            .collect())
+    }
+
+    /// The members and scores of the sorted set or set at the key, `None` if it doesn't exist
+    fn scored_input(&self, key: &Bytes) -> Result<Option<HashMap<Bytes, f64>>, StoreError> {
+        match self.value(key) {
+            None => Ok(None),
+            Some(Value::SortedSet(zset)) => Ok(Some(
+                zset.iter()
+                    .map(|(member, score)| (member.clone(), score))
+                    .collect(),
+            )),
+            Some(Value::Set(set)) => Ok(Some(set.iter().map(|member| (member, 1.0)).collect())),
+            Some(_) => Err(StoreError::WrongType),
+        }
+    }
+
+    /// Replaces whatever the destination holds with a sorted set of the given members, returns
+    /// the new cardinality. An empty result deletes the destination.
+    pub fn zstore(&mut self, destination: &Bytes, members: ScoredMembers) -> usize {
+        self.remove_key(destination);
+        let len = members.len();
+        if len > 0 {
+            let mut zset = ZSetValue::default();
+            for (member, score) in members {
+                zset.insert(member, score);
+            }
+            self.insert_value(destination.clone(), Value::SortedSet(zset));
+            self.signal_key_as_ready(destination);
+        }
+        len
+    }
+
+    /// Blocks a client until one of the keys holds a sorted set to pop from, returns its identifier
+    pub fn register_zpop_waiting_client(
+        &mut self,
+        keys: Vec<Bytes>,
+        count: usize,
+        highest: bool,
+        reply: fn(Bytes, ScoredMembers) -> RedisType,
+        sender: oneshot::Sender<RedisType>,
+    ) -> u64 {
+        let identifier = create_identifier();
+        self.zpop_waiting_queue.push(WaitingZPOPClient {
+            identifier,
+            keys,
+            count,
+            highest,
+            reply,
+            sender,
+        });
+        identifier
+    }
+
+    /// Serves the clients blocked on `key` in the order they blocked, as long as it holds members
+    pub(super) fn serve_zpop_waiting_clients(&mut self, key: &Bytes) {
+        // clients which timed out are only cleaned up once their timeout message arrives
+        self.zpop_waiting_queue
+            .retain(|client| !client.sender.is_closed());
+
+        while let Some(position) = self
+            .zpop_waiting_queue
+            .iter()
+            .position(|client| client.keys.contains(key))
+        {
+            let client = &self.zpop_waiting_queue[position];
+            let popped = match self.zpop(key, client.count, client.highest) {
+                Ok(popped) if !popped.is_empty() => popped,
+                _ => return,
+            };
+            let client = self.zpop_waiting_queue.remove(position);
+            let _ = client.sender.send((client.reply)(key.clone(), popped));
+        }
+    }
+
+    /// Removes the members, returns the number of members which were removed.
+    /// The key is deleted once the sorted set is empty.
+    pub fn zrem(&mut self, key: &Bytes, members: &[Bytes]) -> Result<usize, StoreError> {
+        let Some(zset) = self.zset_mut(key)? else {
+            return Ok(0);
+        };
+
+        let removed = members.iter().filter(|member| zset.remove(member)).count();
+        if removed > 0 {
+            self.touch_key(key);
+            self.remove_if_empty(key);
+        }
+        Ok(removed)
+    }
+}
+
+#[test]
+fn test_zadd_updates_scores_and_ranks() {
+    let mut store = Store::new();
+    let key = Bytes::from("zset");
+    assert_eq!(
+        store
+            .zadd(
+                &key,
+                vec![(1.0, "a".into()), (2.0, "b".into())],
+                ZAddOptions::default()
+            )
+            .unwrap(),
+        2
+    );
+    assert_eq!(
+        store
+            .zadd(&key, vec![(3.0, "a".into())], ZAddOptions::default())
+            .unwrap(),
+        0
+    );
+
+    assert_eq!(store.zscore(&key, &"a".into()).unwrap(), Some(3.0));
+    assert_eq!(store.zcard(&key).unwrap(), 2);
+    assert_eq!(
+        store.zrank(&key, &"a".into(), false).unwrap(),
+        Some((1, 3.0))
+    );
+    assert_eq!(
+        store.zrank(&key, &"a".into(), true).unwrap(),
+        Some((0, 3.0))
+    );
+    assert_eq!(store.zrank(&key, &"c".into(), false).unwrap(), None);
+}
+
+#[test]
+fn test_zrem_deletes_empty_zset() {
+    let mut store = Store::new();
+    let key = Bytes::from("zset");
+    store
+        .zadd(&key, vec![(1.0, "a".into())], ZAddOptions::default())
+        .unwrap();
+
+    assert_eq!(store.zrem(&key, &["a".into(), "b".into()]).unwrap(), 1);
+    assert!(matches!(store.get_type(&key), Err(StoreError::KeyNotFound)));
+
+    store
+        .set_with_expiry(key.clone(), "value".into(), None)
+        .unwrap();
+    assert!(matches!(
+        store.zadd(&key, vec![(1.0, "a".into())], ZAddOptions::default()),
+        Err(StoreError::WrongType)
+    ));
+}
+
+#[test]
+fn test_zadd_conditions() {
+    let mut store = Store::new();
+    let key = Bytes::from("zset");
+    let xx = ZAddOptions {
+        xx: true,
+        ..Default::default()
+    };
+    assert_eq!(store.zadd(&key, vec![(1.0, "a".into())], xx).unwrap(), 0);
+    assert!(store.get_type(&key).is_err());
+
+    store
+        .zadd(&key, vec![(5.0, "a".into())], ZAddOptions::default())
+        .unwrap();
+    let gt_ch = ZAddOptions {
+        gt: true,
+        ch: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        store
+            .zadd(&key, vec![(3.0, "a".into()), (1.0, "b".into())], gt_ch)
+            .unwrap(),
+        1
+    );
+    assert_eq!(store.zscore(&key, &"a".into()).unwrap(), Some(5.0));
+    assert_eq!(store.zadd(&key, vec![(7.0, "a".into())], gt_ch).unwrap(), 1);
+
+    let nx = ZAddOptions {
+        nx: true,
+        ..Default::default()
+    };
+    assert_eq!(store.zincrby(&key, 1.0, &"a".into(), nx).unwrap(), None);
+    assert_eq!(
+        store
+            .zincrby(&key, 1.5, &"a".into(), ZAddOptions::default())
+            .unwrap(),
+        Some(8.5)
+    );
+}
+
+#[test]
+fn test_zincrby_to_nan_fails() {
+    let mut store = Store::new();
+    let key = Bytes::from("zset");
+    store
+        .zadd(
+            &key,
+            vec![(f64::INFINITY, "a".into())],
+            ZAddOptions::default(),
+        )
+        .unwrap();
+    assert!(matches!(
+        store.zincrby(&key, f64::NEG_INFINITY, &"a".into(), ZAddOptions::default()),
+        Err(StoreError::ScoreIsNaN)
+    ));
+    assert_eq!(
+        store.zscore(&key, &"a".into()).unwrap(),
+        Some(f64::INFINITY)
+    );
+}
+
+#[test]
+fn test_zrange_variants() {
+    use std::ops::Bound::{Excluded, Included};
+
+    let mut store = Store::new();
+    let key = Bytes::from("zset");
+    store
+        .zadd(
+            &key,
+            vec![
+                (1.0, "a".into()),
+                (2.0, "b".into()),
+                (3.0, "c".into()),
+                (4.0, "d".into()),
+            ],
+            ZAddOptions::default(),
+        )
+        .unwrap();
+    let members = |range: ZRange| -> Vec<Bytes> {
+        store
+            .zrange(&key, &range)
+            .unwrap()
+            .into_iter()
+            .map(|(member, _)| member)
+            .collect()
+    };
+
+    assert_eq!(
+        members(ZRange::new(ZRangeBy::Index(1, -2))),
+        vec![Bytes::from("b"), Bytes::from("c")]
+    );
+    assert_eq!(
+        members(ZRange {
+            reverse: true,
+            ..ZRange::new(ZRangeBy::Index(0, 0))
+        }),
+        vec![Bytes::from("d")]
+    );
+    assert_eq!(
+        members(ZRange {
+            offset: 1,
+            count: Some(1),
+            ..ZRange::new(ZRangeBy::Score(Excluded(1.0), Included(4.0)))
+        }),
+        vec![Bytes::from("c")]
+    );
+    assert_eq!(
+        members(ZRange {
+            reverse: true,
+            ..ZRange::new(ZRangeBy::Score(Included(2.0), Included(3.0)))
+        }),
+        vec![Bytes::from("c"), Bytes::from("b")]
+    );
+    assert!(members(ZRange::new(ZRangeBy::Index(5, 10))).is_empty());
+    assert_eq!(
+        store
+            .zcount(
+                &key,
+                &ZRange::new(ZRangeBy::Score(Included(2.0), Excluded(4.0)))
+            )
+            .unwrap(),
+        2
+    );
+}
+
+#[test]
+fn test_zrangestore_replaces_destination() {
+    let mut store = Store::new();
+    let (source, destination) = (Bytes::from("source"), Bytes::from("destination"));
+    store
+        .zadd(
+            &source,
+            vec![(1.0, "a".into()), (2.0, "b".into())],
+            ZAddOptions::default(),
+        )
+        .unwrap();
+    store
+        .set_with_expiry(destination.clone(), "value".into(), None)
+        .unwrap();
+
+    let range = ZRange::new(ZRangeBy::Index(-1, -1));
+    assert_eq!(store.zrangestore(&destination, &source, &range).unwrap(), 1);
+    assert_eq!(store.zscore(&destination, &"b".into()).unwrap(), Some(2.0));
+
+    let empty = ZRange::new(ZRangeBy::Index(5, 10));
+    assert_eq!(store.zrangestore(&destination, &source, &empty).unwrap(), 0);
+    assert!(store.get_type(&destination).is_err());
+}
+
+#[test]
+fn test_zpop_and_zmpop() {
+    let mut store = Store::new();
+    let key = Bytes::from("zset");
+    store
+        .zadd(
+            &key,
+            vec![(1.0, "a".into()), (2.0, "b".into()), (3.0, "c".into())],
+            ZAddOptions::default(),
+        )
+        .unwrap();
+
+    assert_eq!(
+        store.zpop(&key, 1, false).unwrap(),
+        vec![(Bytes::from("a"), 1.0)]
+    );
+    assert_eq!(
+        store.zpop(&key, 1, true).unwrap(),
+        vec![(Bytes::from("c"), 3.0)]
+    );
+
+    let keys = [Bytes::from("missing"), key.clone()];
+    assert_eq!(
+        store.zmpop(&keys, 10, false).unwrap(),
+        Some((key.clone(), vec![(Bytes::from("b"), 2.0)]))
+    );
+    assert!(store.get_type(&key).is_err());
+    assert_eq!(store.zmpop(&keys, 10, false).unwrap(), None);
+}
+
+#[test]
+fn test_zadd_serves_blocked_clients_in_order() {
+    let mut store = Store::new();
+    let key = Bytes::from("zset");
+    let reply = |key: Bytes, members: ScoredMembers| {
+        RedisType::Array(Some(
+            std::iter::once(RedisType::BulkString(key))
+                .chain(
+                    members
+                        .into_iter()
+                        .map(|(member, _)| RedisType::BulkString(member)),
+                )
+                .collect(),
+        ))
+    };
+    let (first_tx, mut first_rx) = oneshot::channel();
+    let (second_tx, mut second_rx) = oneshot::channel();
+    store.register_zpop_waiting_client(
+        vec!["other".into(), key.clone()],
+        1,
+        false,
+        reply,
+        first_tx,
+    );
+    store.register_zpop_waiting_client(vec![key.clone()], 1, true, reply, second_tx);
+
+    store
+        .zadd(
+            &key,
+            vec![(1.0, "a".into()), (2.0, "b".into()), (3.0, "c".into())],
+            ZAddOptions::default(),
+        )
+        .unwrap();
+    store.serve_blocked_clients();
+
+    let member = |reply: RedisType| match reply {
+        RedisType::Array(Some(elements)) => elements[1].clone(),
+        other => panic!("unexpected reply {:?}", other),
+    };
+    assert_eq!(
+        member(first_rx.try_recv().unwrap()),
+        RedisType::BulkString("a".into())
+    );
+    assert_eq!(
+        member(second_rx.try_recv().unwrap()),
+        RedisType::BulkString("c".into())
+    );
+    assert_eq!(store.zcard(&key).unwrap(), 1);
+}
+
+#[test]
+fn test_zcombine_with_weights_and_aggregate() {
+    let mut store = Store::new();
+    let (a, b, plain) = (Bytes::from("a"), Bytes::from("b"), Bytes::from("plain"));
+    store
+        .zadd(
+            &a,
+            vec![(1.0, "x".into()), (2.0, "y".into())],
+            ZAddOptions::default(),
+        )
+        .unwrap();
+    store
+        .zadd(
+            &b,
+            vec![(10.0, "y".into()), (20.0, "z".into())],
+            ZAddOptions::default(),
+        )
+        .unwrap();
+    store.sadd(&plain, vec!["y".into()]).unwrap();
+
+    assert_eq!(
+        store
+            .zcombine(
+                &[a.clone(), b.clone()],
+                ZSetOperation::Union,
+                &[2.0, 1.0],
+                Aggregate::Sum
+            )
+            .unwrap(),
+        vec![
+            (Bytes::from("x"), 2.0),
+            (Bytes::from("y"), 14.0),
+            (Bytes::from("z"), 20.0)
+        ]
+    );
+    assert_eq!(
+        store
+            .zcombine(
+                &[a.clone(), b.clone(), plain.clone()],
+                ZSetOperation::Intersection,
+                &[],
+                Aggregate::Min
+            )
+            .unwrap(),
+        vec![(Bytes::from("y"), 1.0)]
+    );
+    assert_eq!(
+        store
+            .zcombine(
+                &[a.clone(), plain.clone()],
+                ZSetOperation::Difference,
+                &[],
+                Aggregate::Sum
+            )
+            .unwrap(),
+        vec![(Bytes::from("x"), 1.0)]
+    );
+    assert!(
+        store
+            .zcombine(
+                &[a.clone(), "missing".into()],
+                ZSetOperation::Intersection,
+                &[],
+                Aggregate::Sum
+            )
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_zrandmember_counts() {
+    let mut store = Store::new();
+    let key = Bytes::from("zset");
+    store
+        .zadd(
+            &key,
+            vec![(1.0, "a".into()), (2.0, "b".into()), (3.0, "c".into())],
+            ZAddOptions::default(),
+        )
+        .unwrap();
+
+    let mut distinct = store.zrandmember(&key, 2).unwrap();
+    distinct.sort_by(|a, b| a.0.cmp(&b.0));
+    distinct.dedup();
+    assert_eq!(distinct.len(), 2);
+    assert_eq!(store.zrandmember(&key, 10).unwrap().len(), 3);
+    assert_eq!(store.zrandmember(&key, -10).unwrap().len(), 10);
+    for (member, score) in store.zrandmember(&key, -5).unwrap() {
+        assert_eq!(store.zscore(&key, &member).unwrap(), Some(score));
+    }
+    assert!(store.zrandmember(&"missing".into(), 3).unwrap().is_empty());
+}
+
+/// Timings of the hot sorted set operations on a 1M member set. Run with
+/// `cargo test --release -- --ignored --nocapture bench_zset`
+#[test]
+#[ignore]
+fn bench_zset_one_million_members() {
+    use std::time::Instant;
+
+    const MEMBERS: usize = 1_000_000;
+    const LOOKUPS: usize = 1_000;
+    let mut store = Store::new();
+    let key = Bytes::from("zset");
+    let member = |i: usize| Bytes::from(format!("member:{}", i));
+    let mut rng = rand::rng();
+
+    let started = Instant::now();
+    for i in 0..MEMBERS {
+        store
+            .zadd(
+                &key,
+                vec![(rng.random_range(0.0..1e6), member(i))],
+                ZAddOptions::default(),
+            )
+            .unwrap();
+    }
+    println!("ZADD x{}: {:?}", MEMBERS, started.elapsed());
+
+    let started = Instant::now();
+    for _ in 0..LOOKUPS {
+        let i = rng.random_range(0..MEMBERS);
+        assert!(store.zrank(&key, &member(i), false).unwrap().is_some());
+    }
+    println!("ZRANK x{}: {:?}", LOOKUPS, started.elapsed());
+
+    let started = Instant::now();
+    for _ in 0..LOOKUPS {
+        let start = rng.random_range(0..MEMBERS as i128 - 10);
+        let range = ZRange::new(ZRangeBy::Index(start, start + 9));
+        assert_eq!(store.zrange(&key, &range).unwrap().len(), 10);
+    }
+    println!(
+        "ZRANGE by index (10 members) x{}: {:?}",
+        LOOKUPS,
+        started.elapsed()
+    );
+
+    let started = Instant::now();
+    for _ in 0..LOOKUPS {
+        let min = rng.random_range(0.0..1e6 - 100.0);
+        let range = ZRange {
+            count: Some(10),
+            ..ZRange::new(ZRangeBy::Score(Bound::Included(min), Bound::Unbounded))
+        };
+        store.zrange(&key, &range).unwrap();
+    }
+    println!(
+        "ZRANGE BYSCORE LIMIT 0 10 x{}: {:?}",
+        LOOKUPS,
+        started.elapsed()
+    );
+
+    let started = Instant::now();
+    for _ in 0..LOOKUPS {
+        let i = rng.random_range(0..MEMBERS);
+        store.zrem(&key, &[member(i)]).unwrap();
+        store
+            .zadd(&key, vec![(1.0, member(i))], ZAddOptions::default())
+            .unwrap();
+    }
+    println!("ZREM + ZADD x{}: {:?}", LOOKUPS, started.elapsed());
+}